@@ -0,0 +1,229 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Reports problems in the formats that CI services show inline on the diff of
+//! a merge/pull request: workflow commands for GitHub Actions and a Code
+//! Quality report for GitLab CI.
+
+use std::{
+    env,
+    hash::{DefaultHasher, Hash, Hasher},
+    str::FromStr,
+    sync::{Mutex, OnceLock},
+};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use miette::{miette, Report, Severity};
+use serde_json::{json, Value as JsonValue};
+use tracing::{debug, warn};
+
+use crate::{git_output, repository_path, write_file};
+
+/// Where GitLab CI picks up the Code Quality report, once it is listed under
+/// `artifacts:reports:codequality` in the job.
+pub const CODE_QUALITY_REPORT: &str = "gl-code-quality-report.json";
+
+/// Which annotations to emit.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum AnnotationFormat {
+    /// Whichever the CI service running mergelog understands.
+    Ci,
+    GitHub,
+    GitLab,
+}
+
+impl FromStr for AnnotationFormat {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ci" => Ok(Self::Ci),
+            "github" => Ok(Self::GitHub),
+            "gitlab" => Ok(Self::GitLab),
+            other => Err(miette!("Failed to parse '{other}' as an annotation format. Options include 'ci', 'github', and 'gitlab'"))
+        }
+    }
+}
+
+enum Sink {
+    GitHub,
+    /// The Code Quality issues found so far, which are written out at the end
+    /// of the run.
+    GitLab(Mutex<Vec<JsonValue>>),
+}
+
+static SINK: OnceLock<Sink> = OnceLock::new();
+
+/// Held for the rest of the run. The GitLab Code Quality report is written
+/// when this is dropped, so it covers everything found before mergelog exits,
+/// including after an error.
+pub struct Annotations;
+
+impl Drop for Annotations {
+    fn drop(&mut self) {
+        let Some(Sink::GitLab(issues)) = SINK.get() else {
+            return;
+        };
+        let issues = issues.lock().expect("no panics while recording");
+        let report = JsonValue::Array(issues.clone()).to_string();
+        match write_file(Utf8Path::new(CODE_QUALITY_REPORT), report, true) {
+            Ok(()) => debug!(
+                "Wrote {} issue(s) to {}",
+                issues.len(),
+                CODE_QUALITY_REPORT
+            ),
+            Err(error) => {
+                warn!("Failed to write {}: {}", CODE_QUALITY_REPORT, error)
+            }
+        }
+    }
+}
+
+/// Starts annotating problems in `format`. `ci` is resolved from the
+/// variables that GitHub Actions and GitLab CI set in their jobs.
+pub fn init(format: AnnotationFormat) -> miette::Result<Annotations> {
+    let is_set = |name: &str| env::var_os(name).is_some_and(|v| !v.is_empty());
+    let sink = match format {
+        AnnotationFormat::GitHub => Sink::GitHub,
+        AnnotationFormat::GitLab => Sink::GitLab(Mutex::new(Vec::new())),
+        AnnotationFormat::Ci if is_set("GITHUB_ACTIONS") => Sink::GitHub,
+        AnnotationFormat::Ci if is_set("GITLAB_CI") => {
+            Sink::GitLab(Mutex::new(Vec::new()))
+        }
+        AnnotationFormat::Ci => {
+            return Err(miette!(
+                code = "annotate::unknown_ci",
+                help = "Run mergelog in GitHub Actions or GitLab CI, or pass `--annotate github` or `--annotate gitlab`.",
+                "Cannot tell which CI service mergelog is running in"
+            ));
+        }
+    };
+    let _ = SINK.set(sink);
+    Ok(Annotations)
+}
+
+/// Annotates the problem in `report`, if annotations were started, at the
+/// first line it labels in its source, or else at its source file.
+pub fn record(report: &Report) {
+    let Some(sink) = SINK.get() else {
+        return;
+    };
+    let location = locate(report);
+    let code = report
+        .code()
+        .map_or_else(|| "mergelog".to_string(), |code| code.to_string());
+    let mut message = report.to_string();
+    if let Some(help) = report.help() {
+        message = format!("{}\n{}", message, help);
+    }
+    let severity = report.severity().unwrap_or(Severity::Error);
+    match sink {
+        Sink::GitHub => {
+            let mut properties = Vec::new();
+            if let Some((path, line)) = &location {
+                properties
+                    .push(format!("file={}", escape_property(path.as_str())));
+                if let Some(line) = line {
+                    properties.push(format!("line={}", line));
+                }
+            }
+            properties.push(format!("title={}", escape_property(&code)));
+            eprintln!(
+                "::{} {}::{}",
+                match severity {
+                    Severity::Advice => "notice",
+                    Severity::Warning => "warning",
+                    Severity::Error => "error",
+                },
+                properties.join(","),
+                escape_data(&message)
+            );
+        }
+        Sink::GitLab(issues) => {
+            // A Code Quality issue has to be somewhere in the repository.
+            let Some((path, line)) = location else {
+                debug!("Not annotating {}, which has no file", code);
+                return;
+            };
+            // The hash only has to be stable between runs of one build, so
+            // that GitLab can tell which issues a merge request introduces.
+            let mut hasher = DefaultHasher::new();
+            (&code, &path, &message).hash(&mut hasher);
+            issues
+                .lock()
+                .expect("no panics while recording")
+                .push(json!({
+                    "description": message,
+                    "check_name": code,
+                    "fingerprint": format!("{:016x}", hasher.finish()),
+                    "severity": match severity {
+                        Severity::Advice => "info",
+                        Severity::Warning => "minor",
+                        Severity::Error => "major",
+                    },
+                    "location": {
+                        "path": path,
+                        "lines": { "begin": line.unwrap_or(1) },
+                    },
+                }));
+        }
+    }
+}
+
+/// The path of the source of `report` from the repository root, along with the
+/// one-based line of its first label, if it has one.
+fn locate(report: &Report) -> Option<(Utf8PathBuf, Option<usize>)> {
+    let source = report.source_code()?;
+    let span = report
+        .labels()
+        .and_then(|mut labels| labels.next())
+        .map(|label| *label.inner());
+    let contents = source
+        .read_span(&span.unwrap_or_else(|| (0, 0).into()), 0, 0)
+        .ok()?;
+    let path = Utf8Path::new(contents.name()?);
+    let line = span.map(|_| contents.line() + 1);
+    Some((from_repository_root(path), line))
+}
+
+/// `path`, which is relative to the current directory, relative to the root
+/// of the repository instead, as the CI services expect.
+fn from_repository_root(path: &Utf8Path) -> Utf8PathBuf {
+    let root = git_output(["rev-parse", "--show-toplevel"]).ok().flatten();
+    let current = env::current_dir()
+        .ok()
+        .and_then(|current| Utf8PathBuf::from_path_buf(current).ok());
+    match root.zip(current) {
+        Some((root, current)) => {
+            let path = repository_path(&current.join(path));
+            path.strip_prefix(&root)
+                .map(Utf8Path::to_path_buf)
+                .unwrap_or(path)
+        }
+        None => repository_path(path),
+    }
+}
+
+/// Escapes the message of a GitHub workflow command.
+fn escape_data(data: &str) -> String {
+    data.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escapes a property of a GitHub workflow command, such as its file.
+fn escape_property(property: &str) -> String {
+    escape_data(property)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
@@ -0,0 +1,424 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{
+    env, fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use comrak::{
+    nodes::{AstNode, NodeValue},
+    Arena, Options,
+};
+use miette::{miette, Context, IntoDiagnostic, Result};
+use reqwest::Method;
+use serde::Deserialize;
+use serde_json::{json, Value as JsonValue};
+use tracing::{debug, trace};
+use url::Url;
+
+use crate::{strip_provenance, WhateverContextExt};
+
+/// The service behind a webhook, which determines the payload format.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum AnnounceKind {
+    /// A Slack incoming webhook, sent mrkdwn.
+    Slack,
+    /// A Discord webhook, sent markdown with links inlined.
+    Discord,
+    /// A Matrix room, sent as an `m.room.message` event.
+    Matrix,
+    /// Any other endpoint, sent both the markdown and the rendered HTML.
+    Generic,
+}
+
+impl fmt::Display for AnnounceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Slack => "slack",
+            Self::Discord => "discord",
+            Self::Matrix => "matrix",
+            Self::Generic => "generic",
+        }
+        .fmt(f)
+    }
+}
+
+/// A webhook listed under `[[announce]]` in the config.
+#[derive(Deserialize)]
+pub struct AnnounceTarget {
+    kind: AnnounceKind,
+    /// The webhook URL, or the homeserver URL for Matrix.
+    #[serde(default)]
+    url: Option<String>,
+    /// Environment variable to read the URL from, so that secrets stay out of
+    /// the config.
+    #[serde(default, rename = "url-env")]
+    url_env: Option<String>,
+    /// Matrix room id to post to.
+    #[serde(default)]
+    room: Option<String>,
+    /// Environment variable holding the Matrix access token.
+    #[serde(default, rename = "token-env")]
+    token_env: Option<String>,
+    /// The most characters to send, overriding the limit of the service.
+    #[serde(default, rename = "max-length")]
+    max_length: Option<usize>,
+    /// Where the full notes can be read if they had to be truncated.
+    #[serde(default, rename = "changelog-url")]
+    changelog_url: Option<String>,
+}
+
+impl AnnounceTarget {
+    fn read_env(variable: &str, what: &str) -> Result<String> {
+        env::var(variable).ok().whatever_context(miette!(
+            code = "announce::missing_env",
+            help = format!("Set the environment variable {variable}."),
+            "Missing {} for announcement",
+            what
+        ))
+    }
+
+    fn url(&self) -> Result<Url> {
+        let url = match (&self.url, &self.url_env) {
+            (Some(url), _) => url.clone(),
+            (None, Some(variable)) => Self::read_env(variable, "webhook URL")?,
+            (None, None) => {
+                return Err(miette!(
+                    code = "announce::missing_url",
+                    help =
+                        "Set `url` or `url-env` on the `[[announce]]` entry.",
+                    "No URL configured for {} announcement",
+                    self.kind
+                ))
+            }
+        };
+        Url::parse(&url).into_diagnostic().wrap_err(format!(
+            "Invalid URL configured for {} announcement",
+            self.kind
+        ))
+    }
+
+    /// The method and URL to send the payload to, and the bearer token, if
+    /// any.
+    fn endpoint(&self) -> Result<(Method, Url, Option<String>)> {
+        let url = self.url()?;
+        match self.kind {
+            AnnounceKind::Matrix => {
+                let room = self.room.as_ref().whatever_context(miette!(
+                    code = "announce::missing_room",
+                    help = "Set `room` on the `[[announce]]` entry, e.g., `room = \"!abc:matrix.org\"`.",
+                    "No room configured for matrix announcement"
+                ))?;
+                let token_env = self.token_env.as_ref().whatever_context(miette!(
+                    code = "announce::missing_token",
+                    help = "Set `token-env` on the `[[announce]]` entry to the environment variable holding the access token.",
+                    "No access token configured for matrix announcement"
+                ))?;
+                let token = Self::read_env(token_env, "Matrix access token")?;
+                let transaction = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_nanos()
+                    .to_string();
+                let mut endpoint = url.clone();
+                endpoint
+                    .path_segments_mut()
+                    .map_err(|_| {
+                        miette!(
+                            code = "announce::invalid_homeserver",
+                            "Homeserver URL {} cannot have a path",
+                            url
+                        )
+                    })?
+                    .pop_if_empty()
+                    .extend([
+                        "_matrix",
+                        "client",
+                        "v3",
+                        "rooms",
+                        room,
+                        "send",
+                        "m.room.message",
+                        &transaction,
+                    ]);
+                Ok((Method::PUT, endpoint, Some(token)))
+            }
+            _ => Ok((Method::POST, url, None)),
+        }
+    }
+
+    /// The most characters the service accepts in one message, if it is
+    /// limited.
+    fn max_length(&self) -> Option<usize> {
+        self.max_length.or(match self.kind {
+            AnnounceKind::Slack => Some(3000),
+            AnnounceKind::Discord => Some(2000),
+            AnnounceKind::Matrix | AnnounceKind::Generic => None,
+        })
+    }
+
+    /// Cuts `text` down to the length limit at a line boundary, pointing to
+    /// the full changelog if there is one.
+    fn truncate(&self, text: String) -> String {
+        let Some(max_length) = self.max_length() else {
+            return text;
+        };
+        if text.chars().count() <= max_length {
+            return text;
+        }
+
+        let suffix = match (&self.changelog_url, self.kind) {
+            (Some(url), AnnounceKind::Slack) => {
+                format!("…\n<{url}|Read the full changelog>")
+            }
+            (Some(url), _) => {
+                format!("\n…\n[Read the full changelog](<{url}>)")
+            }
+            (None, AnnounceKind::Slack) => "…".to_string(),
+            (None, _) => "\n…".to_string(),
+        };
+        let budget = max_length.saturating_sub(suffix.chars().count());
+        let mut truncated = String::new();
+        let mut length = 0;
+        for line in text.lines() {
+            let line_length = line.chars().count() + 1;
+            if length + line_length > budget {
+                break;
+            }
+            truncated.push_str(line);
+            truncated.push('\n');
+            length += line_length;
+        }
+        if truncated.is_empty() {
+            truncated = text.chars().take(budget.saturating_sub(1)).collect();
+            truncated.push('\n');
+        }
+        truncated.push_str(&suffix);
+        truncated
+    }
+
+    fn payload(&self, notes: &str) -> Result<JsonValue> {
+        let options = Options::default();
+        Ok(match self.kind {
+            AnnounceKind::Slack => {
+                let text = self.truncate(render_slack(notes));
+                json!({
+                    "text": text,
+                    "blocks": [{
+                        "type": "section",
+                        "text": { "type": "mrkdwn", "text": text },
+                    }],
+                })
+            }
+            AnnounceKind::Discord => json!({
+                "content": self.truncate(render_inline_links(notes)?)
+            }),
+            AnnounceKind::Matrix | AnnounceKind::Generic => {
+                // Truncating could cut off link reference definitions at the
+                // end, so inline the links first.
+                let notes = match self.max_length() {
+                    Some(_) => self.truncate(render_inline_links(notes)?),
+                    None => notes.to_string(),
+                };
+                let html = comrak::markdown_to_html(&notes, &options);
+                match self.kind {
+                    AnnounceKind::Matrix => json!({
+                        "msgtype": "m.text",
+                        "body": notes,
+                        "format": "org.matrix.custom.html",
+                        "formatted_body": html,
+                    }),
+                    _ => json!({ "text": notes, "html": html }),
+                }
+            }
+        })
+    }
+
+    /// Builds the request announcing `notes` to this webhook, without any
+    /// provenance comments.
+    pub fn prepare(&self, notes: &str) -> Result<Announcement> {
+        let (method, endpoint, token) = self.endpoint()?;
+        Ok(Announcement {
+            description: match (&self.kind, &self.room) {
+                (AnnounceKind::Matrix, Some(room)) => {
+                    format!("matrix room {room}")
+                }
+                (kind, _) => format!("{kind} webhook"),
+            },
+            method,
+            endpoint,
+            token,
+            payload: self.payload(&strip_provenance(notes))?,
+        })
+    }
+}
+
+/// A request to a webhook that is ready to be sent.
+pub struct Announcement {
+    /// Where the request posts to, without any secrets.
+    pub description: String,
+    method: Method,
+    endpoint: Url,
+    token: Option<String>,
+    payload: JsonValue,
+}
+
+impl Announcement {
+    /// Prints the request instead of sending it.
+    pub fn print(&self) {
+        println!("{} {}", self.method, self.endpoint);
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&self.payload)
+                .expect("JSON values always serialize")
+        );
+    }
+
+    pub fn send(self) -> Result<()> {
+        debug!("Posting notes to {}", self.description);
+        trace!(
+            "Payload for {}: {}",
+            self.description,
+            serde_json::to_string(&self.payload)
+                .expect("JSON values always serialize")
+        );
+        let mut request = reqwest::blocking::Client::new()
+            .request(self.method, self.endpoint);
+        if let Some(token) = self.token {
+            request = request.bearer_auth(token);
+        }
+        request
+            .json(&self.payload)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .into_diagnostic()
+            .whatever_context(miette!(
+                code = "announce::request_failed",
+                "Failed to post notes to {}",
+                self.description
+            ))?;
+        Ok(())
+    }
+}
+
+/// Rewrites markdown so that reference-style links are written inline, since
+/// chat services do not resolve link reference definitions.
+fn render_inline_links(notes: &str) -> Result<String> {
+    let arena = Arena::new();
+    let options = Options::default();
+    let root = comrak::parse_document(&arena, notes, &options);
+    let mut result = Vec::new();
+    comrak::format_commonmark(root, &options, &mut result)
+        .into_diagnostic()
+        .wrap_err("Failed to format notes")?;
+    String::from_utf8(result)
+        .into_diagnostic()
+        .wrap_err("Formatted notes were not valid UTF-8")
+}
+
+/// Converts markdown to Slack's mrkdwn dialect.
+fn render_slack(notes: &str) -> String {
+    let arena = Arena::new();
+    let root = comrak::parse_document(&arena, notes, &Options::default());
+    let mut result = String::new();
+    slack_blocks(root, 0, &mut result);
+    result.trim_end().to_string()
+}
+
+fn slack_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn slack_blocks<'a>(node: &'a AstNode<'a>, depth: usize, out: &mut String) {
+    for child in node.children() {
+        match &child.data.borrow().value {
+            NodeValue::Heading(_) => {
+                if !out.is_empty() {
+                    out.push('\n');
+                }
+                out.push('*');
+                slack_inlines(child, out);
+                out.push_str("*\n");
+            }
+            NodeValue::Paragraph => {
+                slack_inlines(child, out);
+                out.push('\n');
+            }
+            NodeValue::Item(_) => {
+                out.push_str(&"    ".repeat(depth.saturating_sub(1)));
+                out.push_str("• ");
+                slack_blocks(child, depth + 1, out);
+            }
+            NodeValue::List(_) => slack_blocks(child, depth.max(1), out),
+            NodeValue::CodeBlock(code_block) => {
+                out.push_str("```\n");
+                out.push_str(&slack_escape(&code_block.literal));
+                out.push_str("```\n");
+            }
+            NodeValue::BlockQuote => {
+                let mut quote = String::new();
+                slack_blocks(child, depth, &mut quote);
+                for line in quote.lines() {
+                    out.push_str("> ");
+                    out.push_str(line);
+                    out.push('\n');
+                }
+            }
+            NodeValue::HtmlBlock(html) => {
+                out.push_str(&slack_escape(&html.literal))
+            }
+            _ => slack_blocks(child, depth, out),
+        }
+    }
+}
+
+fn slack_inlines<'a>(node: &'a AstNode<'a>, out: &mut String) {
+    for child in node.children() {
+        match &child.data.borrow().value {
+            NodeValue::Text(text) => out.push_str(&slack_escape(text)),
+            NodeValue::Code(code) => {
+                out.push('`');
+                out.push_str(&slack_escape(&code.literal));
+                out.push('`');
+            }
+            NodeValue::HtmlInline(html) => out.push_str(&slack_escape(html)),
+            NodeValue::SoftBreak => out.push(' '),
+            NodeValue::LineBreak => out.push('\n'),
+            NodeValue::Strong => {
+                out.push('*');
+                slack_inlines(child, out);
+                out.push('*');
+            }
+            NodeValue::Emph => {
+                out.push('_');
+                slack_inlines(child, out);
+                out.push('_');
+            }
+            NodeValue::Strikethrough => {
+                out.push('~');
+                slack_inlines(child, out);
+                out.push('~');
+            }
+            NodeValue::Link(link) => {
+                let mut text = String::new();
+                slack_inlines(child, &mut text);
+                out.push_str(&format!("<{}|{}>", link.url, text));
+            }
+            _ => slack_inlines(child, out),
+        }
+    }
+}
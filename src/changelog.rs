@@ -0,0 +1,1038 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Reads and edits existing changelog files. They are parsed into releases and
+//! their sections, which keep every line as written, so that everything an
+//! edit does not touch stays exactly as it was.
+
+use std::{cmp::Ordering, fmt};
+
+use miette::{miette, Result};
+
+use crate::{
+    headings_match, list_item, natural_cmp, query::release_heading,
+    strip_provenance, ListConfig, RenderedSection,
+};
+
+/// The level and text of `line` if it is an ATX heading.
+pub fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    let rest = &line[level..];
+    if !(1..=6).contains(&level)
+        || !(rest.is_empty() || rest.starts_with([' ', '\t']))
+    {
+        return None;
+    }
+    Some((level, rest.trim().trim_end_matches('#').trim_end()))
+}
+
+/// Tracks whether the lines read so far leave off within a fenced code block,
+/// where lines that look like headings are code.
+#[derive(Default)]
+struct Fences {
+    fenced: bool,
+}
+
+impl Fences {
+    /// Like [`heading`], but for the line after those read so far.
+    fn heading<'a>(&mut self, line: &'a str) -> Option<(usize, &'a str)> {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            self.fenced = !self.fenced;
+            None
+        } else if self.fenced {
+            None
+        } else {
+            heading(line)
+        }
+    }
+}
+
+/// The label of `line` if it is a link reference definition.
+pub fn link_definition(line: &str) -> Option<&str> {
+    let rest = line.strip_prefix('[')?;
+    rest.find("]:").map(|end| &rest[..end])
+}
+
+/// The lines of a changelog, split into the link reference definitions at the
+/// end and everything before them.
+struct Lines {
+    body: Vec<String>,
+    definitions: Vec<String>,
+}
+
+impl Lines {
+    fn new(contents: &str) -> Self {
+        let mut body = contents.lines().map(str::to_string).collect::<Vec<_>>();
+        let mut start = body.len();
+        for (index, line) in body.iter().enumerate().rev() {
+            if link_definition(line).is_some() {
+                start = index;
+            } else if !is_blank(line) {
+                break;
+            }
+        }
+        let definitions = body.split_off(start);
+        trim_blank_lines(&mut body);
+        Self { body, definitions }
+    }
+
+    fn into_string(mut self) -> String {
+        trim_blank_lines(&mut self.body);
+        let mut result = self.body.join("\n");
+        result.push('\n');
+        if !self.definitions.is_empty() {
+            result.push('\n');
+            result.push_str(&self.definitions.join("\n"));
+            result.push('\n');
+        }
+        result
+    }
+}
+
+fn is_blank(line: &str) -> bool {
+    line.trim().is_empty()
+}
+
+fn trim_blank_lines(lines: &mut Vec<String>) {
+    while lines.last().is_some_and(|line| is_blank(line)) {
+        lines.pop();
+    }
+}
+
+/// The end of the last non-blank line in `lines[start..end]`.
+fn content_end(lines: &[String], start: usize, end: usize) -> usize {
+    lines[start..end]
+        .iter()
+        .rposition(|line| !is_blank(line))
+        .map_or(start, |offset| start + offset + 1)
+}
+
+/// A changelog parsed into its releases, each with its sections. Every line is
+/// kept as written, so a changelog prints back exactly as it was read, apart
+/// from blank lines at the end, and edits only touch the lines they change.
+pub struct Changelog {
+    /// The title and anything else before the first release.
+    preamble: Vec<String>,
+    releases: Vec<Release>,
+    /// The link reference definitions at the end, as written.
+    definitions: Vec<String>,
+}
+
+/// A release, or the unreleased changes.
+pub struct Release {
+    /// `None` for the unreleased changes.
+    version: Option<String>,
+    /// The date in the heading, like `2024-01-01`, if it has one.
+    date: Option<String>,
+    /// The heading of the release and everything under it, with the sections
+    /// of the release as its subsections.
+    content: Section,
+}
+
+/// A heading and everything under it.
+struct Section {
+    heading: String,
+    level: usize,
+    /// The lines under the heading up to its first subsection.
+    lines: Vec<String>,
+    subsections: Vec<Section>,
+}
+
+impl Changelog {
+    pub fn parse(contents: &str) -> Self {
+        let Lines { body, definitions } = Lines::new(contents);
+        let mut changelog = Self {
+            preamble: Vec::new(),
+            releases: Vec::new(),
+            definitions,
+        };
+        let mut fences = Fences::default();
+        for line in body {
+            let Some((level, text)) = fences.heading(&line) else {
+                changelog.last_lines().push(line);
+                continue;
+            };
+            let version = release_heading(text);
+            let date = release_date(text);
+            let section = Section {
+                heading: line.clone(),
+                level,
+                lines: Vec::new(),
+                subsections: Vec::new(),
+            };
+            let current =
+                changelog.releases.last().map(|last| last.content.level);
+            match (version, changelog.releases.last_mut()) {
+                // A heading like `### 2.0 migration` within a release is one
+                // of its sections.
+                (Some(version), _)
+                    if current.is_none_or(|current| level <= current) =>
+                {
+                    changelog.releases.push(Release {
+                        version,
+                        date,
+                        content: section,
+                    });
+                }
+                (_, Some(last)) => last.content.insert(section),
+                (_, None) => changelog.preamble.push(line),
+            }
+        }
+        changelog
+    }
+
+    /// The lines that a line after all others so far belongs to.
+    fn last_lines(&mut self) -> &mut Vec<String> {
+        match self.releases.last_mut() {
+            Some(release) => release.content.last_lines(),
+            None => &mut self.preamble,
+        }
+    }
+
+    /// The releases, newest first as they are written, without the unreleased
+    /// changes.
+    pub fn releases(&self) -> impl Iterator<Item = &Release> {
+        self.releases
+            .iter()
+            .filter(|release| release.version.is_some())
+    }
+
+    /// The latest release, i.e., the first that is not Unreleased.
+    pub fn latest(&self) -> Option<&Release> {
+        self.releases().next()
+    }
+
+    /// The release of `version`, with or without a leading `v`.
+    pub fn release(&self, version: &str) -> Option<&Release> {
+        let version = version.trim_start_matches('v');
+        self.releases().find(|release| {
+            release.version().trim_start_matches('v') == version
+        })
+    }
+
+    /// The URL of the link reference definition labeled `label`.
+    fn link(&self, label: &str) -> Option<&str> {
+        self.definitions.iter().find_map(|line| {
+            let defined = link_definition(line)?;
+            defined
+                .eq_ignore_ascii_case(label)
+                .then(|| line[defined.len() + 3..].trim())
+        })
+    }
+
+    /// The URL of the `[Unreleased]` compare link and the revision it compares
+    /// from, e.g., `v1.3.0` in `.../compare/v1.3.0...HEAD`.
+    pub fn unreleased_compare_link(&self) -> Option<(String, String)> {
+        let url = self.link("Unreleased")?;
+        let from = url.strip_suffix("...HEAD")?.rsplit('/').next()?;
+        Some((url.to_string(), from.to_string()))
+    }
+
+    /// The contents of `release` without its heading, followed by the link
+    /// definitions they use.
+    pub fn notes(&self, release: &Release) -> String {
+        let mut notes = Vec::new();
+        release.content.write_contents(&mut notes);
+        trim_blank_lines(&mut notes);
+        let mut notes = notes
+            .into_iter()
+            .skip_while(|line| is_blank(line))
+            .collect::<Vec<_>>();
+        let text = notes.join("\n");
+        let definitions = self
+            .definitions
+            .iter()
+            .filter(|line| {
+                link_definition(line)
+                    .is_some_and(|label| text.contains(&format!("[{label}]")))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        if !definitions.is_empty() {
+            notes.push(String::new());
+            notes.extend(definitions);
+        }
+        notes.join("\n") + "\n"
+    }
+
+    /// Adds a link reference definition unless one with the same label
+    /// exists, placing it right before the one labeled `before`, if any.
+    fn define(&mut self, label: &str, url: &str, before: Option<&str>) {
+        if self.link(label).is_some() {
+            return;
+        }
+        let index = before
+            .and_then(|before| {
+                self.definitions.iter().position(|line| {
+                    link_definition(line)
+                        .is_some_and(|other| other.eq_ignore_ascii_case(before))
+                })
+            })
+            .unwrap_or(self.definitions.len());
+        self.definitions.insert(index, format!("[{label}]: {url}"));
+    }
+
+    /// Adds `sections` to the Unreleased section, creating it before the first
+    /// release if there is none, and adds any missing link definitions.
+    pub fn update_unreleased(
+        &mut self,
+        sections: &[RenderedSection],
+        definitions: &[(String, String)],
+        unreleased_link: &str,
+        list: &ListConfig,
+    ) {
+        let index = match self
+            .releases
+            .iter()
+            .position(|release| release.version.is_none())
+        {
+            Some(index) => index,
+            None => {
+                let level = self
+                    .releases
+                    .first()
+                    .map_or(2, |release| release.content.level);
+                if self.preamble.last().is_some_and(|line| !is_blank(line)) {
+                    self.preamble.push(String::new());
+                }
+                let blank_after = !self.releases.is_empty();
+                self.releases.insert(
+                    0,
+                    Release {
+                        version: None,
+                        date: None,
+                        content: Section {
+                            heading: format!(
+                                "{} [Unreleased]",
+                                "#".repeat(level)
+                            ),
+                            level,
+                            lines: blank_after
+                                .then(String::new)
+                                .into_iter()
+                                .collect(),
+                            subsections: Vec::new(),
+                        },
+                    },
+                );
+                0
+            }
+        };
+        let is_last_release = index + 1 == self.releases.len();
+        let unreleased = &mut self.releases[index].content;
+
+        // Sections deeper than the shallowest ones, e.g., subsections grouped
+        // by label, go under the section before them. Each entry is the index
+        // of the subsection that the next section one level deeper goes under.
+        let base = sections.iter().map(|section| section.level).min();
+        let mut path = Vec::<usize>::new();
+        for section in sections {
+            let depth =
+                usize::from(section.level - base.unwrap_or(section.level));
+            path.truncate(depth);
+            let is_last = is_last_release && unreleased.is_last(&path);
+            let parent = unreleased.descendant(&path);
+            let items = section.items.iter().map(|item| list_item(item, list));
+            let existing = parent.subsections.iter().position(|subsection| {
+                subsection.level == parent.level + 1
+                    && heading(&subsection.heading).is_some_and(|(_, text)| {
+                        headings_match(text, &section.heading)
+                    })
+            });
+            let index = match existing {
+                Some(index) => {
+                    // Before any subsections, so the items stay at this level.
+                    let lines = &mut parent.subsections[index].lines;
+                    let end = content_end(lines, 0, lines.len());
+                    lines.splice(end..end, items);
+                    index
+                }
+                None => {
+                    // The blank lines at the end of the parent go after the
+                    // new subsection instead.
+                    let last = parent.last_lines();
+                    let end = content_end(last, 0, last.len());
+                    let mut trailing = last.split_off(end);
+                    if trailing.is_empty() && !is_last {
+                        trailing.push(String::new());
+                    }
+                    parent.last_lines().push(String::new());
+                    parent.subsections.push(Section {
+                        heading: format!(
+                            "{} {}",
+                            "#".repeat(parent.level + 1),
+                            section.heading
+                        ),
+                        level: parent.level + 1,
+                        lines: items.chain(trailing).collect(),
+                        subsections: Vec::new(),
+                    });
+                    parent.subsections.len() - 1
+                }
+            };
+            path.push(index);
+        }
+
+        for (label, url) in definitions {
+            self.define(label, url, Some("Unreleased"));
+        }
+        if self.releases[index]
+            .content
+            .heading
+            .contains("[Unreleased]")
+        {
+            self.define("Unreleased", unreleased_link, None);
+        }
+    }
+
+    /// Turns the Unreleased section into one for `version`, leaving an empty
+    /// Unreleased section above it, and points the compare links at the
+    /// release.
+    pub fn promote(
+        &mut self,
+        version: &str,
+        date: &str,
+        (unreleased_link, version_link): (&str, &str),
+    ) -> Result<()> {
+        let index = self
+            .releases
+            .iter()
+            .position(|release| release.version.is_none())
+            .ok_or_else(|| {
+                miette!(
+                    code = "release::missing_unreleased",
+                    help = "Add a `## [Unreleased]` heading, e.g., with `mergelog merge --update <changelog>`.",
+                    "Changelog has no Unreleased section to release"
+                )
+            })?;
+        let unreleased = &mut self.releases[index].content;
+        let level = unreleased.level;
+        let bracketed = unreleased.heading.contains("[Unreleased]");
+        let heading = if bracketed {
+            format!("{} [{version}] - {date}", "#".repeat(level))
+        } else {
+            format!("{} {version} - {date}", "#".repeat(level))
+        };
+        let released = Section {
+            heading,
+            level,
+            lines: std::mem::replace(
+                &mut unreleased.lines,
+                vec![String::new()],
+            ),
+            subsections: std::mem::take(&mut unreleased.subsections),
+        };
+        self.releases.insert(
+            index + 1,
+            Release {
+                version: Some(version.to_string()),
+                date: Some(date.to_string()),
+                content: released,
+            },
+        );
+
+        if bracketed {
+            match self.definitions.iter().position(|line| {
+                link_definition(line).is_some_and(|label| {
+                    label.eq_ignore_ascii_case("unreleased")
+                })
+            }) {
+                Some(index) => {
+                    self.definitions[index] =
+                        format!("[Unreleased]: {unreleased_link}");
+                    self.definitions.insert(
+                        index + 1,
+                        format!("[{version}]: {version_link}"),
+                    );
+                }
+                None => {
+                    self.define("Unreleased", unreleased_link, None);
+                    self.define(version, version_link, None);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the releases, sections, and entries of `other` that this
+    /// changelog lacks, along with the link definitions they use, keeping
+    /// everything here as it is. Returns the labels, URL here, and URL in
+    /// `other` of the link definitions that point elsewhere in `other`, which
+    /// keep pointing where they do here.
+    pub fn combine(
+        &mut self,
+        other: Changelog,
+    ) -> Vec<(String, String, String)> {
+        for release in other.releases {
+            let existing = self.releases.iter_mut().find(|ours| {
+                match (&ours.version, &release.version) {
+                    (Some(ours), Some(theirs)) => {
+                        ours.trim_start_matches('v')
+                            == theirs.trim_start_matches('v')
+                    }
+                    (ours, theirs) => ours.is_none() && theirs.is_none(),
+                }
+            });
+            if let Some(ours) = existing {
+                ours.content.combine(release.content);
+                continue;
+            }
+            // Releases are newest first, after the unreleased changes.
+            let index = self
+                .releases
+                .iter()
+                .position(|ours| match (&release.version, &ours.version) {
+                    (None, _) => true,
+                    (Some(_), None) => false,
+                    (Some(theirs), Some(ours)) => {
+                        natural_cmp(
+                            theirs.trim_start_matches('v'),
+                            ours.trim_start_matches('v'),
+                        ) == Ordering::Greater
+                    }
+                })
+                .unwrap_or(self.releases.len());
+            let before = match index.checked_sub(1) {
+                Some(previous) => self.releases[previous].content.last_lines(),
+                None => &mut self.preamble,
+            };
+            ensure_blank_line(before);
+            let mut release = release;
+            ensure_blank_line(release.content.last_lines());
+            self.releases.insert(index, release);
+        }
+
+        let mut conflicts = Vec::new();
+        for line in other.definitions {
+            let Some(label) = link_definition(&line) else {
+                continue;
+            };
+            let url = line[label.len() + 3..].trim();
+            match self.link(label) {
+                None => {
+                    // Links to releases stay newest first after those of
+                    // the entries.
+                    let version = release_heading(label);
+                    let before = self.definitions.iter().find_map(|line| {
+                        let other = link_definition(line)?;
+                        let goes_before =
+                            match (&version, release_heading(other)) {
+                                (_, None) => false,
+                                (None, Some(_)) => true,
+                                (Some(None), _)
+                                | (Some(Some(_)), Some(None)) => false,
+                                (Some(Some(version)), Some(Some(other))) => {
+                                    natural_cmp(
+                                        version.trim_start_matches('v'),
+                                        other.trim_start_matches('v'),
+                                    ) == Ordering::Greater
+                                }
+                            };
+                        goes_before.then(|| other.to_string())
+                    });
+                    self.define(label, url, before.as_deref());
+                }
+                Some(ours) if ours != url => conflicts.push((
+                    label.to_string(),
+                    ours.to_string(),
+                    url.to_string(),
+                )),
+                Some(_) => {}
+            }
+        }
+        conflicts
+    }
+}
+
+impl fmt::Display for Changelog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut body = self.preamble.clone();
+        for release in &self.releases {
+            release.content.write(&mut body);
+        }
+        Lines {
+            body,
+            definitions: self.definitions.clone(),
+        }
+        .into_string()
+        .fmt(f)
+    }
+}
+
+impl Release {
+    pub fn version(&self) -> &str {
+        self.version.as_deref().unwrap_or("Unreleased")
+    }
+
+    pub fn date(&self) -> Option<&str> {
+        self.date.as_deref()
+    }
+}
+
+impl Section {
+    /// Adds `section`, which comes after everything else so far, as a
+    /// subsection of the last subsection that is above its level.
+    fn insert(&mut self, section: Section) {
+        match self.subsections.last_mut() {
+            Some(last) if last.level < section.level => last.insert(section),
+            _ => self.subsections.push(section),
+        }
+    }
+
+    /// The lines at the end of this section and its subsections.
+    fn last_lines(&mut self) -> &mut Vec<String> {
+        match self.subsections.last_mut() {
+            Some(last) => last.last_lines(),
+            None => &mut self.lines,
+        }
+    }
+
+    /// Adds the entries and subsections of `other`, a section with the same
+    /// heading, that this one lacks.
+    fn combine(&mut self, other: Section) {
+        let ours = entries(&self.lines)
+            .iter()
+            .map(|entry| entry_key(entry))
+            .collect::<Vec<_>>();
+        let missing = entries(&other.lines)
+            .into_iter()
+            .filter(|entry| !ours.contains(&entry_key(entry)))
+            .flatten()
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            let end = content_end(&self.lines, 0, self.lines.len());
+            let trailing = self.lines.split_off(end);
+            // Keeps the blank line after the heading of an empty section.
+            if end == 0 && !trailing.is_empty() {
+                self.lines.push(String::new());
+            }
+            self.lines.extend(missing);
+            self.lines.extend(trailing);
+        }
+
+        for subsection in other.subsections {
+            let existing = self.subsections.iter_mut().find(|ours| {
+                ours.level == subsection.level
+                    && heading(&ours.heading)
+                        .zip(heading(&subsection.heading))
+                        .is_some_and(|((_, ours), (_, theirs))| {
+                            headings_match(ours, theirs)
+                        })
+            });
+            match existing {
+                Some(ours) => ours.combine(subsection),
+                None => {
+                    ensure_blank_line(self.last_lines());
+                    let mut subsection = subsection;
+                    ensure_blank_line(subsection.last_lines());
+                    self.subsections.push(subsection);
+                }
+            }
+        }
+    }
+
+    /// The subsection found by following the indices in `path`.
+    fn descendant(&mut self, path: &[usize]) -> &mut Section {
+        let mut section = self;
+        for index in path {
+            section = &mut section.subsections[*index];
+        }
+        section
+    }
+
+    /// Whether the subsection at `path` ends this section.
+    fn is_last(&self, path: &[usize]) -> bool {
+        let mut section = self;
+        for index in path {
+            if *index + 1 != section.subsections.len() {
+                return false;
+            }
+            section = &section.subsections[*index];
+        }
+        true
+    }
+
+    fn write(&self, out: &mut Vec<String>) {
+        out.push(self.heading.clone());
+        self.write_contents(out);
+    }
+
+    /// Writes everything under the heading.
+    fn write_contents(&self, out: &mut Vec<String>) {
+        out.extend(self.lines.iter().cloned());
+        for subsection in &self.subsections {
+            subsection.write(out);
+        }
+    }
+}
+
+/// The entries in `lines`, like list items with everything nested under them
+/// and paragraphs, each without the blank lines after it.
+fn entries(lines: &[String]) -> Vec<Vec<String>> {
+    let mut entries = Vec::<Vec<String>>::new();
+    let mut after_blank = true;
+    for line in lines {
+        if is_blank(line) {
+            if let Some(last) = entries.last_mut() {
+                last.push(line.clone());
+            }
+            after_blank = true;
+            continue;
+        }
+        let starts_item = line.starts_with(['-', '*', '+'])
+            && line[1..].starts_with([' ', '\t'])
+            || line.split_once(['.', ')']).is_some_and(|(number, rest)| {
+                !number.is_empty()
+                    && number.chars().all(|c| c.is_ascii_digit())
+                    && rest.starts_with(' ')
+            });
+        let indented = line.starts_with([' ', '\t']);
+        match entries.last_mut() {
+            Some(last) if !starts_item && (indented || !after_blank) => {
+                last.push(line.clone())
+            }
+            _ => entries.push(vec![line.clone()]),
+        }
+        after_blank = false;
+    }
+    for entry in &mut entries {
+        trim_blank_lines(entry);
+    }
+    entries
+}
+
+/// What tells an entry apart from others: its text, without provenance
+/// comments or differences in whitespace.
+fn entry_key(entry: &[String]) -> String {
+    strip_provenance(&entry.join("\n"))
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Ends `lines` with a blank line, unless they already do or are empty.
+fn ensure_blank_line(lines: &mut Vec<String>) {
+    if lines.last().is_some_and(|line| !is_blank(line)) {
+        lines.push(String::new());
+    }
+}
+
+/// The date in a release heading like `[1.2.0] - 2024-01-01`.
+pub fn release_date(heading: &str) -> Option<String> {
+    heading.split_whitespace().skip(1).find_map(|word| {
+        let word = word.trim_matches(|c: char| !c.is_ascii_digit());
+        let is_date = word.len() == 10
+            && word.char_indices().all(|(index, c)| match index {
+                4 | 7 => c == '-',
+                _ => c.is_ascii_digit(),
+            });
+        is_date.then(|| word.to_string())
+    })
+}
+
+/// A heading and the lines up to the next one, or the lines before the first
+/// heading.
+#[derive(Clone, PartialEq)]
+struct Block {
+    /// Identifies the section across versions of the changelog: the text of
+    /// its heading and those it is under, with releases known by version
+    /// alone so that a changed date does not make a new one.
+    key: Vec<String>,
+    heading: Option<String>,
+    lines: Vec<String>,
+}
+
+fn blocks(body: &[String]) -> Vec<Block> {
+    let mut blocks = vec![Block {
+        key: Vec::new(),
+        heading: None,
+        lines: Vec::new(),
+    }];
+    let mut path = Vec::<(usize, String)>::new();
+    let mut fences = Fences::default();
+    for line in body {
+        match fences.heading(line) {
+            Some((level, text)) => {
+                path.retain(|(other, _)| *other < level);
+                let text = match release_heading(text) {
+                    Some(Some(version)) => {
+                        version.trim_start_matches('v').to_string()
+                    }
+                    Some(None) => "unreleased".to_string(),
+                    None => text.to_lowercase(),
+                };
+                path.push((level, text));
+                blocks.push(Block {
+                    key: path.iter().map(|(_, text)| text.clone()).collect(),
+                    heading: Some(line.clone()),
+                    lines: Vec::new(),
+                });
+            }
+            None => blocks
+                .last_mut()
+                .expect("there is always a first block")
+                .lines
+                .push(line.clone()),
+        }
+    }
+    blocks
+}
+
+/// A change made to a list of lines: `lines` in place of those in
+/// `start..end`.
+struct Hunk<'a> {
+    start: usize,
+    end: usize,
+    lines: &'a [String],
+}
+
+/// The changes that turn `base` into `other`, in order, found from their
+/// longest common subsequence.
+fn hunks<'a>(base: &[String], other: &'a [String]) -> Vec<Hunk<'a>> {
+    let mut lengths = vec![vec![0; other.len() + 1]; base.len() + 1];
+    for i in (0..base.len()).rev() {
+        for j in (0..other.len()).rev() {
+            lengths[i][j] = if base[i] == other[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut matches = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < base.len() && j < other.len() {
+        if base[i] == other[j] {
+            matches.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    matches.push((base.len(), other.len()));
+
+    let mut hunks = Vec::new();
+    let (mut start, mut other_start) = (0, 0);
+    for (end, other_end) in matches {
+        if end > start || other_end > other_start {
+            hunks.push(Hunk {
+                start,
+                end,
+                lines: &other[other_start..other_end],
+            });
+        }
+        start = end + 1;
+        other_start = other_end + 1;
+    }
+    hunks
+}
+
+/// Whether `lhs` and `rhs`, which starts no earlier, change the same lines.
+/// Insertions at the same place count as touching each other.
+fn overlap(lhs: &Hunk, rhs: &Hunk) -> bool {
+    if lhs.start == lhs.end && rhs.start == rhs.end {
+        lhs.start == rhs.start
+    } else {
+        rhs.start < lhs.end
+    }
+}
+
+/// Merges the changes that `ours` and `theirs` made to `base`, keeping the
+/// lines that both inserted at the same place, ours first. Returns `None` if
+/// they changed the same lines differently.
+fn merge_lines(
+    base: &[String],
+    ours: &[String],
+    theirs: &[String],
+) -> Option<Vec<String>> {
+    if ours == theirs || theirs == base {
+        return Some(ours.to_vec());
+    }
+    if ours == base {
+        return Some(theirs.to_vec());
+    }
+
+    let mut all_hunks = hunks(base, ours);
+    all_hunks.extend(hunks(base, theirs));
+    all_hunks.sort_by_key(|hunk| (hunk.start, hunk.end));
+    let mut all_hunks = all_hunks.into_iter().peekable();
+    let mut merged = Vec::new();
+    let mut next = 0;
+    while let Some(hunk) = all_hunks.next() {
+        let mut lines = hunk.lines.to_vec();
+        while let Some(other) = all_hunks.next_if(|other| overlap(&hunk, other))
+        {
+            if (other.start, other.end) == (hunk.start, hunk.end)
+                && other.lines == lines
+            {
+                continue;
+            }
+            if hunk.start != hunk.end || other.start != other.end {
+                return None;
+            }
+            lines.extend(
+                other
+                    .lines
+                    .iter()
+                    .filter(|line| is_blank(line) || !lines.contains(line))
+                    .cloned()
+                    .collect::<Vec<_>>(),
+            );
+        }
+        merged.extend_from_slice(&base[next..hunk.start]);
+        merged.extend(lines);
+        next = hunk.end;
+    }
+    merged.extend_from_slice(&base[next..]);
+    Some(merged)
+}
+
+/// Merges one section that ours and theirs both have.
+fn merge_block(
+    base: Option<&Block>,
+    ours: &Block,
+    theirs: &Block,
+) -> Option<Block> {
+    let heading = if ours.heading == theirs.heading
+        || base.is_some_and(|base| base.heading == theirs.heading)
+    {
+        ours.heading.clone()
+    } else if base.is_some_and(|base| base.heading == ours.heading) {
+        theirs.heading.clone()
+    } else {
+        return None;
+    };
+    Some(Block {
+        key: ours.key.clone(),
+        heading,
+        lines: merge_lines(
+            base.map_or(&[], |base| &base.lines),
+            &ours.lines,
+            &theirs.lines,
+        )?,
+    })
+}
+
+/// What is left of a section that one side removed, e.g., by releasing it,
+/// and `other` only added lines to: those lines, since the removal could not
+/// have accounted for them. Returns `None` if `other` changed it otherwise.
+fn additions_only(base: &Block, other: &Block) -> Option<Block> {
+    let hunks = hunks(&base.lines, &other.lines);
+    if other.heading != base.heading
+        || hunks.iter().any(|hunk| hunk.start != hunk.end)
+    {
+        return None;
+    }
+    Some(Block {
+        key: other.key.clone(),
+        heading: other.heading.clone(),
+        lines: hunks
+            .iter()
+            .flat_map(|hunk| hunk.lines)
+            .filter(|line| !is_blank(line))
+            .cloned()
+            .collect(),
+    })
+}
+
+/// Merges the changes that `ours` and `theirs` made to the changelog `base`,
+/// as for a git merge driver. The entries both added to a section are all
+/// kept, as are sections and releases either added, and entries one added to
+/// a section the other released. Returns `None` if both changed the same
+/// entry or heading differently, or one removed a section the other changed
+/// otherwise.
+pub fn merge(base: &str, ours: &str, theirs: &str) -> Option<String> {
+    let [base, ours, theirs] = [base, ours, theirs].map(Lines::new);
+    let definitions =
+        merge_lines(&base.definitions, &ours.definitions, &theirs.definitions)?;
+    let [base, ours, theirs] =
+        [base, ours, theirs].map(|lines| blocks(&lines.body));
+    // Sections could not be told apart by their headings otherwise.
+    for blocks in [&base, &ours, &theirs] {
+        for (index, block) in blocks.iter().enumerate() {
+            if blocks[..index].iter().any(|other| other.key == block.key) {
+                return None;
+            }
+        }
+    }
+    let find = |blocks: &[Block], key: &[String]| {
+        blocks.iter().position(|block| block.key == key)
+    };
+
+    // Whether each section came from theirs alone.
+    let mut merged = Vec::<(Block, bool)>::new();
+    for block in &ours {
+        let base_block = find(&base, &block.key).map(|index| &base[index]);
+        match find(&theirs, &block.key) {
+            Some(index) => merged
+                .push((merge_block(base_block, block, &theirs[index])?, false)),
+            // They removed it, which only stands if we left it alone.
+            None => match base_block {
+                Some(base_block) if base_block == block => {}
+                Some(base_block) => {
+                    merged.push((additions_only(base_block, block)?, true))
+                }
+                None => merged.push((block.clone(), false)),
+            },
+        }
+    }
+    for (index, block) in theirs.iter().enumerate() {
+        if find(&ours, &block.key).is_some() {
+            continue;
+        }
+        let block = match find(&base, &block.key) {
+            Some(base_index) if base[base_index] == *block => continue,
+            Some(base_index) => additions_only(&base[base_index], block)?,
+            None => block.clone(),
+        };
+        // After the section before it on their side and its subsections.
+        let mut position = theirs[..index]
+            .iter()
+            .rev()
+            .find_map(|previous| {
+                merged
+                    .iter()
+                    .position(|(other, _)| other.key == previous.key)
+            })
+            .map_or(0, |position| position + 1);
+        while merged
+            .get(position)
+            .is_some_and(|(other, _)| other.key.len() > block.key.len())
+        {
+            position += 1;
+        }
+        merged.insert(position, (block, true));
+    }
+
+    let mut body = Vec::new();
+    let mut previous_inserted = false;
+    for (block, inserted) in merged {
+        if let Some(heading) = block.heading {
+            if (inserted || previous_inserted)
+                && body.last().is_some_and(|line: &String| !is_blank(line))
+            {
+                body.push(String::new());
+            }
+            body.push(heading);
+        }
+        body.extend(block.lines);
+        previous_inserted = inserted;
+    }
+    trim_blank_lines(&mut body);
+    Some(Lines { body, definitions }.into_string())
+}
@@ -0,0 +1,666 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Shell completion scripts, generated from the command line definitions so
+//! that they never fall out of date.
+
+use std::{fmt::Write, str::FromStr};
+
+use argh::{CommandInfoWithArgs, FlagInfoKind, Optionality};
+use miette::{miette, Report};
+
+/// The command that prints the configured sections, one per line, which the
+/// scripts run to complete `--section`.
+const LIST_SECTIONS: &str = "mergelog --quiet config sections";
+
+/// A shell to generate completions for.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+const SHELLS: &[&str] = &["bash", "zsh", "fish", "powershell"];
+
+impl FromStr for Shell {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bash" => Ok(Self::Bash),
+            "zsh" => Ok(Self::Zsh),
+            "fish" => Ok(Self::Fish),
+            "powershell" | "pwsh" => Ok(Self::Powershell),
+            other => Err(miette!("Failed to parse '{other}' as a shell. Options include 'bash', 'zsh', 'fish', and 'powershell'"))
+        }
+    }
+}
+
+/// What the value of an option or positional argument is completed with.
+#[derive(Clone, Copy)]
+enum Values {
+    /// Nothing, since it is free-form.
+    Nothing,
+    /// Paths to files and directories.
+    Paths,
+    /// The sections in the config.
+    Sections,
+    /// One of a fixed set of words.
+    Choices(&'static [&'static str]),
+}
+
+impl Values {
+    /// The values for the option or positional argument called `name` of
+    /// `command`.
+    fn of(command: &str, name: &str) -> Self {
+        match name {
+            "section" => Self::Sections,
+            "shell" => Self::Choices(SHELLS),
+            "host" => Self::Choices(&["github", "gitlab"]),
+            "color" => Self::Choices(&["auto", "always", "never"]),
+            "annotate" => Self::Choices(&["ci", "github", "gitlab"]),
+            "sort" => Self::Choices(&[
+                "by-id",
+                "by-merge-date",
+                "by-title",
+                "alphabetical",
+                "input-order",
+            ]),
+            "group-by" => Self::Choices(&["section", "month"]),
+            "symlinks" => Self::Choices(&["follow", "skip", "error"]),
+            "format" if command == "merge" => Self::Choices(&[
+                "markdown", "asciidoc", "rst", "text", "debian", "rpm",
+            ]),
+            "format" => Self::Choices(&["text", "json"]),
+            "repo" | "version" | "pr" | "author" | "label" | "since"
+            | "until" | "package" | "description" | "tag" | "milestone"
+            | "remote" | "skip-label" | "emit" | "url" | "title" | "limit" => {
+                Self::Nothing
+            }
+            _ => Self::Paths,
+        }
+    }
+}
+
+/// A flag or option of a command.
+struct Flag {
+    long: &'static str,
+    short: Option<char>,
+    description: &'static str,
+    /// What its value is completed with, or `None` if it is a switch.
+    value: Option<Values>,
+    repeating: bool,
+}
+
+impl Flag {
+    /// Its spellings, e.g., `--section` and `-s`.
+    fn names(&self) -> Vec<String> {
+        [self.long.to_string()]
+            .into_iter()
+            .chain(self.short.map(|short| format!("-{}", short)))
+            .collect()
+    }
+}
+
+/// A positional argument of a command.
+struct Positional {
+    name: &'static str,
+    values: Values,
+    optional: bool,
+    repeating: bool,
+}
+
+/// A command or subcommand along with everything that can follow it.
+struct Command {
+    /// The names leading to it, starting with `mergelog`.
+    path: Vec<&'static str>,
+    description: &'static str,
+    flags: Vec<Flag>,
+    positionals: Vec<Positional>,
+    commands: Vec<Command>,
+}
+
+impl Command {
+    fn new(info: CommandInfoWithArgs, path: Vec<&'static str>) -> Self {
+        let flags = info
+            .flags
+            .iter()
+            .filter(|flag| !flag.hidden)
+            .map(|flag| Flag {
+                long: flag.long,
+                short: flag.short,
+                description: flag.description,
+                value: match flag.kind {
+                    FlagInfoKind::Switch => None,
+                    FlagInfoKind::Option { .. } => Some(Values::of(
+                        path.last().copied().unwrap_or_default(),
+                        flag.long.trim_start_matches('-'),
+                    )),
+                },
+                repeating: flag.optionality == Optionality::Repeating,
+            })
+            .collect();
+        let positionals = info
+            .positionals
+            .iter()
+            .filter(|positional| !positional.hidden)
+            .map(|positional| Positional {
+                name: positional.name,
+                values: Values::of(
+                    path.last().copied().unwrap_or_default(),
+                    positional.name,
+                ),
+                optional: positional.optionality == Optionality::Optional,
+                repeating: matches!(
+                    positional.optionality,
+                    Optionality::Repeating | Optionality::Greedy
+                ),
+            })
+            .collect();
+        let commands = info
+            .commands
+            .into_iter()
+            .map(|subcommand| {
+                let mut path = path.clone();
+                path.push(subcommand.name);
+                Command::new(subcommand.command, path)
+            })
+            .collect();
+        Self {
+            path,
+            description: info.description,
+            flags,
+            positionals,
+            commands,
+        }
+    }
+
+    /// It and all the commands under it.
+    fn all(&self) -> Vec<&Command> {
+        let mut commands = vec![self];
+        for command in &self.commands {
+            commands.extend(command.all());
+        }
+        commands
+    }
+
+    fn name(&self) -> &'static str {
+        self.path.last().expect("paths start with mergelog")
+    }
+
+    /// What the next word is completed with when it is not a flag.
+    fn argument_values(&self) -> Values {
+        self.positionals
+            .first()
+            .map_or(Values::Nothing, |positional| positional.values)
+    }
+
+    /// A name for it usable in shell function names, e.g., `mergelog_config`.
+    fn identifier(&self) -> String {
+        self.path.join("_").replace('-', "_")
+    }
+}
+
+/// The completion script for `shell`, covering the command line described by
+/// `info`.
+pub fn generate(shell: Shell, info: CommandInfoWithArgs) -> String {
+    let root = Command::new(info, vec!["mergelog"]);
+    match shell {
+        Shell::Bash => bash(&root),
+        Shell::Zsh => zsh(&root),
+        Shell::Fish => fish(&root),
+        Shell::Powershell => powershell(&root),
+    }
+}
+
+fn bash(root: &Command) -> String {
+    let commands = root.all();
+    let mut script = String::new();
+    script.push_str("# bash completion for mergelog\n\n");
+    writeln!(script, "_mergelog() {{").unwrap();
+    script.push_str(
+        "    local cur=\"${COMP_WORDS[COMP_CWORD]}\"
+    local prev=\"${COMP_WORDS[COMP_CWORD-1]}\"
+    local path=mergelog i
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        case \"$path ${COMP_WORDS[i]}\" in\n",
+    );
+    let subcommands = commands
+        .iter()
+        .skip(1)
+        .map(|command| format!("\"{}\"", command.path.join(" ")))
+        .collect::<Vec<_>>();
+    writeln!(
+        script,
+        "            {}) path=\"$path ${{COMP_WORDS[i]}}\" ;;",
+        subcommands.join(" | ")
+    )
+    .unwrap();
+    let value_flags = commands
+        .iter()
+        .flat_map(|command| {
+            command
+                .flags
+                .iter()
+                .filter(|flag| flag.value.is_some())
+                .flat_map(|flag| flag.names())
+                .map(|name| format!("\"{} {}\"", command.path.join(" "), name))
+        })
+        .collect::<Vec<_>>();
+    writeln!(
+        script,
+        "            {}) ((i++)) ;;",
+        value_flags.join(" | ")
+    )
+    .unwrap();
+    script.push_str("        esac\n    done\n\n    case \"$path\" in\n");
+    for command in &commands {
+        writeln!(script, "        \"{}\")", command.path.join(" ")).unwrap();
+        script.push_str("            case \"$prev\" in\n");
+        for flag in &command.flags {
+            let Some(values) = flag.value else {
+                continue;
+            };
+            writeln!(
+                script,
+                "                {}) {}; return ;;",
+                flag.names().join(" | "),
+                bash_reply(values)
+            )
+            .unwrap();
+        }
+        script.push_str("            esac\n");
+        let flags = command
+            .flags
+            .iter()
+            .flat_map(Flag::names)
+            .collect::<Vec<_>>()
+            .join(" ");
+        writeln!(
+            script,
+            "            if [[ $cur == -* ]]; then\n                COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+            flags
+        )
+        .unwrap();
+        let arguments = if command.commands.is_empty() {
+            bash_reply(command.argument_values())
+        } else {
+            format!(
+                "COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+                command
+                    .commands
+                    .iter()
+                    .map(Command::name)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            )
+        };
+        writeln!(
+            script,
+            "            else\n                {}\n            fi\n            ;;",
+            arguments
+        )
+        .unwrap();
+    }
+    script.push_str(
+        "    esac\n}\n\ncomplete -o filenames -F _mergelog mergelog\n",
+    );
+    script
+}
+
+/// The bash statement that completes `$cur` with `values`.
+fn bash_reply(values: Values) -> String {
+    match values {
+        Values::Nothing => "COMPREPLY=()".into(),
+        Values::Paths => "COMPREPLY=($(compgen -f -- \"$cur\"))".into(),
+        Values::Sections => format!(
+            "local IFS=$'\\n'; COMPREPLY=($(compgen -W \"$({} 2>/dev/null)\" -- \"$cur\"))",
+            LIST_SECTIONS
+        ),
+        Values::Choices(choices) => format!(
+            "COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+            choices.join(" ")
+        ),
+    }
+}
+
+fn zsh(root: &Command) -> String {
+    let mut script = String::new();
+    script.push_str("#compdef mergelog\n# zsh completion for mergelog\n\n");
+    writeln!(
+        script,
+        "_mergelog_sections() {{\n    local -a sections\n    sections=(\"${{(@f)$({} 2>/dev/null)}}\")\n    compadd -a sections\n}}\n",
+        LIST_SECTIONS
+    )
+    .unwrap();
+    for command in root.all() {
+        writeln!(script, "_{}() {{", command.identifier()).unwrap();
+        if !command.commands.is_empty() {
+            script
+                .push_str("    local curcontext=\"$curcontext\" state line\n");
+        }
+        let mut specs = Vec::new();
+        for flag in &command.flags {
+            let description = zsh_escape(flag.description);
+            let names = flag.names();
+            let prefix = if flag.repeating {
+                "'*'".to_string()
+            } else if names.len() > 1 {
+                format!("'({})'", names.join(" "))
+            } else {
+                String::new()
+            };
+            let names = if names.len() > 1 {
+                format!("{{{}}}", names.join(","))
+            } else {
+                names.join("")
+            };
+            let argument = match flag.value {
+                Some(values) => format!(
+                    ":{}:{}",
+                    flag.long.trim_start_matches('-'),
+                    zsh_action(values)
+                ),
+                None => String::new(),
+            };
+            specs.push(format!(
+                "{}{}'[{}]{}'",
+                prefix, names, description, argument
+            ));
+        }
+        if command.commands.is_empty() {
+            for (index, positional) in command.positionals.iter().enumerate() {
+                let position = if positional.repeating {
+                    "*:".to_string()
+                } else if positional.optional {
+                    format!("{}::", index + 1)
+                } else {
+                    format!("{}:", index + 1)
+                };
+                specs.push(format!(
+                    "'{}{}:{}'",
+                    position,
+                    positional.name,
+                    zsh_action(positional.values)
+                ));
+            }
+            writeln!(
+                script,
+                "    _arguments -s \\\n        {}\n}}\n",
+                specs.join(" \\\n        ")
+            )
+            .unwrap();
+            continue;
+        }
+        specs.push("'1:command:->command'".into());
+        specs.push("'*::argument:->argument'".into());
+        writeln!(
+            script,
+            "    _arguments -C -s \\\n        {}",
+            specs.join(" \\\n        ")
+        )
+        .unwrap();
+        script.push_str("    case $state in\n        command)\n            local -a commands\n            commands=(\n");
+        for subcommand in &command.commands {
+            writeln!(
+                script,
+                "                '{}:{}'",
+                subcommand.name(),
+                zsh_escape(subcommand.description)
+            )
+            .unwrap();
+        }
+        script.push_str("            )\n            _describe -t commands command commands\n            ;;\n        argument)\n            case $line[1] in\n");
+        for subcommand in &command.commands {
+            writeln!(
+                script,
+                "                {}) _{} ;;",
+                subcommand.name(),
+                subcommand.identifier()
+            )
+            .unwrap();
+        }
+        script.push_str("            esac\n            ;;\n    esac\n}\n\n");
+    }
+    script.push_str("_mergelog \"$@\"\n");
+    script
+}
+
+/// The `_arguments` action that completes `values`.
+fn zsh_action(values: Values) -> String {
+    match values {
+        Values::Nothing => " ".into(),
+        Values::Paths => "_files".into(),
+        Values::Sections => "_mergelog_sections".into(),
+        Values::Choices(choices) => format!("({})", choices.join(" ")),
+    }
+}
+
+/// Escapes `text` for a description inside a single-quoted `_arguments` spec
+/// or `_describe` entry.
+fn zsh_escape(text: &str) -> String {
+    text.replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+}
+
+fn fish(root: &Command) -> String {
+    let mut script = String::new();
+    script.push_str(
+        "# fish completion for mergelog\n\ncomplete -c mergelog -f\n",
+    );
+    for command in root.all() {
+        let condition = fish_condition(command);
+        script.push('\n');
+        for subcommand in &command.commands {
+            writeln!(
+                script,
+                "complete -c mergelog -n '{}' -a {} -d '{}'",
+                condition,
+                subcommand.name(),
+                fish_escape(subcommand.description)
+            )
+            .unwrap();
+        }
+        for flag in &command.flags {
+            let mut line = format!(
+                "complete -c mergelog -n '{}' -l {}",
+                condition,
+                flag.long.trim_start_matches('-')
+            );
+            if let Some(short) = flag.short {
+                write!(line, " -s {}", short).unwrap();
+            }
+            if let Some(values) = flag.value {
+                write!(line, " {}", fish_values(values)).unwrap();
+            }
+            write!(line, " -d '{}'", fish_escape(flag.description)).unwrap();
+            writeln!(script, "{}", line).unwrap();
+        }
+        if command.commands.is_empty() && !command.positionals.is_empty() {
+            writeln!(
+                script,
+                "complete -c mergelog -n '{}' {}",
+                condition,
+                fish_values(command.argument_values())
+            )
+            .unwrap();
+        }
+    }
+    script
+}
+
+/// The fish condition under which `command` is the one being completed.
+fn fish_condition(command: &Command) -> String {
+    let mut condition = match command.path.len() {
+        1 => "__fish_use_subcommand".to_string(),
+        _ => format!("__fish_seen_subcommand_from {}", command.name()),
+    };
+    if command.path.len() > 1 && !command.commands.is_empty() {
+        write!(
+            condition,
+            "; and not __fish_seen_subcommand_from {}",
+            command
+                .commands
+                .iter()
+                .map(Command::name)
+                .collect::<Vec<_>>()
+                .join(" ")
+        )
+        .unwrap();
+    }
+    condition
+}
+
+/// The `complete` arguments that complete `values`.
+fn fish_values(values: Values) -> String {
+    match values {
+        Values::Nothing => "-x".into(),
+        Values::Paths => "-r -F".into(),
+        Values::Sections => format!("-x -a '({} 2>/dev/null)'", LIST_SECTIONS),
+        Values::Choices(choices) => format!("-x -a '{}'", choices.join(" ")),
+    }
+}
+
+/// Escapes `text` for a single-quoted fish string.
+fn fish_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+fn powershell(root: &Command) -> String {
+    let commands = root.all();
+    let mut script = String::new();
+    script.push_str(
+        "# PowerShell completion for mergelog
+
+Register-ArgumentCompleter -Native -CommandName mergelog -ScriptBlock {
+    param($wordToComplete, $commandAst, $cursorPosition)
+
+    $words = @($commandAst.CommandElements |
+        Where-Object { $_.Extent.EndOffset -lt $cursorPosition } |
+        ForEach-Object { $_.ToString() })
+",
+    );
+    let subcommands = commands
+        .iter()
+        .skip(1)
+        .map(|command| format!("'{}'", command.path.join(" ")))
+        .collect::<Vec<_>>();
+    writeln!(script, "    $subcommands = @({})", subcommands.join(", "))
+        .unwrap();
+    let value_flags = commands
+        .iter()
+        .flat_map(|command| {
+            command
+                .flags
+                .iter()
+                .filter(|flag| flag.value.is_some())
+                .flat_map(|flag| flag.names())
+                .map(|name| format!("'{} {}'", command.path.join(" "), name))
+        })
+        .collect::<Vec<_>>();
+    writeln!(script, "    $valueFlags = @({})", value_flags.join(", "))
+        .unwrap();
+    script.push_str(
+        "    $path = 'mergelog'
+    for ($i = 1; $i -lt $words.Count; $i++) {
+        $word = \"$path $($words[$i])\"
+        if ($subcommands -contains $word) {
+            $path = $word
+        } elseif ($valueFlags -contains $word) {
+            $i++
+        }
+    }
+    $previous = if ($words.Count -gt 1) { $words[-1] } else { '' }
+
+    $candidates = switch -Exact (\"$path $previous\") {
+",
+    );
+    for command in &commands {
+        for flag in &command.flags {
+            let Some(values) = flag.value else {
+                continue;
+            };
+            for name in flag.names() {
+                writeln!(
+                    script,
+                    "        '{} {}' {{ {}; break }}",
+                    command.path.join(" "),
+                    name,
+                    powershell_values(values)
+                )
+                .unwrap();
+            }
+        }
+    }
+    script.push_str(
+        "        default {
+            switch -Exact ($path) {
+",
+    );
+    for command in &commands {
+        let flags = command
+            .flags
+            .iter()
+            .flat_map(Flag::names)
+            .map(|name| format!("'{}'", name))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let arguments = if command.commands.is_empty() {
+            powershell_values(command.argument_values())
+        } else {
+            command
+                .commands
+                .iter()
+                .map(|subcommand| format!("'{}'", subcommand.name()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        writeln!(
+            script,
+            "                '{}' {{ if ($wordToComplete -like '-*') {{ {} }} else {{ {} }}; break }}",
+            command.path.join(" "),
+            flags,
+            arguments
+        )
+        .unwrap();
+    }
+    script.push_str(
+        "            }
+        }
+    }
+
+    # Nothing to offer leaves PowerShell to complete paths.
+    $candidates | Where-Object { $_ -like \"$wordToComplete*\" } | ForEach-Object {
+        [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)
+    }
+}
+",
+    );
+    script
+}
+
+/// The PowerShell expression listing the candidates for `values`.
+fn powershell_values(values: Values) -> String {
+    match values {
+        Values::Nothing | Values::Paths => "@()".into(),
+        Values::Sections => format!("@({} 2>$null)", LIST_SECTIONS),
+        Values::Choices(choices) => choices
+            .iter()
+            .map(|choice| format!("'{}'", choice))
+            .collect::<Vec<_>>()
+            .join(", "),
+    }
+}
@@ -0,0 +1,125 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! What the terminal can display. Legacy Windows consoles understand neither
+//! ANSI colors nor most of the Unicode symbols used in prompts, so they get
+//! plain ASCII instead.
+
+use std::{env, str::FromStr, sync::OnceLock};
+
+use miette::{miette, MietteHandlerOpts, Report};
+
+/// When to color the output.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Only if standard error is a terminal that supports colors and
+    /// `NO_COLOR` is not set.
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            other => Err(miette!("Failed to parse '{other}' as a color choice. Options include 'auto', 'always', and 'never'"))
+        }
+    }
+}
+
+/// The symbols used to draw prompts, reports, and progress.
+pub struct Symbols {
+    pub check: &'static str,
+    pub top: &'static str,
+    pub side: &'static str,
+    pub branch: &'static str,
+    pub bottom: &'static str,
+    pub arrow: &'static str,
+    pub spinner: &'static str,
+    pub progress: &'static str,
+}
+
+const UNICODE: Symbols = Symbols {
+    check: "✓",
+    top: "╭─",
+    side: "│",
+    branch: "├─",
+    bottom: "╰─",
+    arrow: "→",
+    spinner: "⠁⠁⠉⠙⠚⠒⠂⠂⠒⠲⠴⠤⠄⠄⠤⠠⠠⠤⠦⠖⠒⠐⠐⠒⠓⠋⠉⠈⠈✓",
+    progress: "█░",
+};
+
+const ASCII: Symbols = Symbols {
+    check: "+",
+    top: ",-",
+    side: "|",
+    branch: "|-",
+    bottom: "`-",
+    arrow: "->",
+    spinner: "-\\|/+",
+    progress: "#-",
+};
+
+static LEGACY: OnceLock<bool> = OnceLock::new();
+
+/// Whether standard error is a Windows console that cannot process ANSI
+/// escape codes, which also means it likely lacks fonts for the Unicode
+/// symbols.
+#[cfg(windows)]
+fn detect_legacy_console() -> bool {
+    // This also turns on ANSI processing where the console supports it.
+    !ratatui::crossterm::ansi_support::supports_ansi()
+}
+
+#[cfg(not(windows))]
+fn detect_legacy_console() -> bool {
+    false
+}
+
+/// Detects what the terminal can display and decides whether to color the
+/// output following `color`. Call this before printing anything.
+pub fn init(color: ColorChoice) {
+    let legacy = *LEGACY.get_or_init(detect_legacy_console);
+    let colors = match color {
+        ColorChoice::Always => Some(true),
+        ColorChoice::Never => Some(false),
+        // Otherwise whether standard error supports colors is checked each
+        // time something is colored.
+        ColorChoice::Auto => (legacy
+            || env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()))
+        .then_some(false),
+    };
+    if let Some(colors) = colors {
+        owo_colors::set_override(colors);
+        // This only fails if a diagnostic was created before, which would
+        // keep the default colors.
+        let _ = miette::set_hook(Box::new(move |_| {
+            Box::new(MietteHandlerOpts::new().color(colors).build())
+        }));
+    }
+}
+
+/// The symbols the terminal can display.
+pub fn symbols() -> &'static Symbols {
+    if *LEGACY.get_or_init(detect_legacy_console) {
+        &ASCII
+    } else {
+        &UNICODE
+    }
+}
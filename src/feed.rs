@@ -0,0 +1,130 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Writes the releases in a changelog as an Atom feed, for readers to follow
+//! them.
+
+use std::fmt::Write;
+
+use comrak::Options;
+
+/// The feed as a whole.
+pub struct Feed {
+    pub title: String,
+    /// Identifies the feed to readers, so it must never change.
+    pub id: String,
+    /// Where the feed itself is published, if known.
+    pub url: Option<String>,
+    /// The page of the project.
+    pub homepage: String,
+    pub author: String,
+}
+
+/// A release in the feed.
+pub struct FeedEntry {
+    pub title: String,
+    /// The page of the release on the repository host, which is also its id.
+    pub url: String,
+    /// The day of the release as `YYYY-MM-DD`.
+    pub date: String,
+    /// The release notes in markdown.
+    pub notes: String,
+}
+
+/// Renders `feed` with `entries`, newest first, as an Atom document.
+pub fn atom(feed: &Feed, entries: &[FeedEntry]) -> String {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    out.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    writeln!(out, "  <title>{}</title>", escape(&feed.title)).unwrap();
+    writeln!(out, "  <id>{}</id>", escape(&feed.id)).unwrap();
+    if let Some(url) = &feed.url {
+        writeln!(out, "  <link rel=\"self\" href=\"{}\"/>", escape(url))
+            .unwrap();
+    }
+    writeln!(out, "  <link href=\"{}\"/>", escape(&feed.homepage)).unwrap();
+    // Dates sort like strings, so the newest is the greatest.
+    let updated = entries
+        .iter()
+        .map(|entry| entry.date.as_str())
+        .max()
+        .unwrap_or("1970-01-01");
+    writeln!(out, "  <updated>{}</updated>", timestamp(updated)).unwrap();
+    writeln!(
+        out,
+        "  <author>\n    <name>{}</name>\n  </author>",
+        escape(&feed.author)
+    )
+    .unwrap();
+    for entry in entries {
+        let html = comrak::markdown_to_html(&entry.notes, &Options::default());
+        out.push_str("  <entry>\n");
+        writeln!(out, "    <title>{}</title>", escape(&entry.title)).unwrap();
+        writeln!(out, "    <id>{}</id>", escape(&entry.url)).unwrap();
+        writeln!(out, "    <link href=\"{}\"/>", escape(&entry.url)).unwrap();
+        writeln!(out, "    <updated>{}</updated>", timestamp(&entry.date))
+            .unwrap();
+        writeln!(
+            out,
+            "    <content type=\"html\">{}</content>",
+            escape(&html)
+        )
+        .unwrap();
+        out.push_str("  </entry>\n");
+    }
+    out.push_str("</feed>\n");
+    out
+}
+
+/// Whether `date` is a day of the calendar as `YYYY-MM-DD`, which is all that
+/// [`atom`] accepts.
+pub fn is_date(date: &str) -> bool {
+    let mut parts = date.split('-');
+    let (Some(year), Some(month), Some(day), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return false;
+    };
+    let number = |part: &str, digits| {
+        (part.len() == digits && part.bytes().all(|c| c.is_ascii_digit()))
+            .then(|| part.parse::<u32>().ok())
+            .flatten()
+    };
+    let (Some(year), Some(month), Some(day)) =
+        (number(year, 4), number(month, 2), number(day, 2))
+    else {
+        return false;
+    };
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return false,
+    };
+    (1..=days).contains(&day)
+}
+
+/// The start of the day `date` in UTC, as Atom expects.
+fn timestamp(date: &str) -> String {
+    format!("{date}T00:00:00Z")
+}
+
+/// Escapes `text` for XML element content and attribute values.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,112 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Settings shared with GitHub's automatically generated release notes, so
+//! that both agree about which merge/pull requests count.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use miette::{miette, Context, IntoDiagnostic, Result};
+use serde::Deserialize;
+
+use crate::{read_file, PullRequest};
+
+/// Where GitHub looks for its release notes configuration.
+const RELEASE_CONFIG_PATHS: [&str; 2] =
+    [".github/release.yml", ".github/release.yaml"];
+
+#[derive(Deserialize)]
+struct ReleaseConfig {
+    #[serde(default)]
+    changelog: ReleaseChangelog,
+}
+
+#[derive(Default, Deserialize)]
+struct ReleaseChangelog {
+    #[serde(default)]
+    exclude: ReleaseExclude,
+}
+
+#[derive(Default, Deserialize)]
+struct ReleaseExclude {
+    #[serde(default)]
+    labels: Vec<String>,
+    #[serde(default)]
+    authors: Vec<String>,
+}
+
+/// The labels and authors whose merge/pull requests are left out of the
+/// release notes altogether, from `changelog.exclude` in a `release.yml`.
+///
+/// Exclusions under individual categories are not read since they only move
+/// merge/pull requests to another category.
+pub struct Exclusions {
+    path: Utf8PathBuf,
+    labels: Vec<String>,
+    authors: Vec<String>,
+}
+
+impl Exclusions {
+    /// Reads the exclusions from `path`, or from `.github/release.yml` if it
+    /// exists when `path` is `None`.
+    pub fn load(path: Option<&Utf8Path>) -> Result<Option<Self>> {
+        let Some(path) = path.map(Utf8Path::to_path_buf).or_else(|| {
+            RELEASE_CONFIG_PATHS
+                .into_iter()
+                .map(Utf8PathBuf::from)
+                .find(|path| path.is_file())
+        }) else {
+            return Ok(None);
+        };
+
+        let contents = read_file(&path).into_diagnostic().wrap_err(format!(
+            "Failed to read release notes configuration from {}",
+            path
+        ))?;
+        let config = serde_yaml::from_str::<ReleaseConfig>(&contents)
+            .map_err(|error| {
+                miette!(
+                    code = "github::invalid_release_config",
+                    help = "See https://docs.github.com/en/repositories/releasing-projects-on-github/automatically-generated-release-notes#configuration-options for the format.",
+                    "Failed to parse {}: {}",
+                    path,
+                    error
+                )
+            })?;
+        let ReleaseExclude { labels, authors } = config.changelog.exclude;
+        Ok(Some(Self {
+            path,
+            labels,
+            authors,
+        }))
+    }
+
+    /// Why `pull_request` is excluded, if it is.
+    pub fn reason(&self, pull_request: &PullRequest) -> Option<String> {
+        if let Some(label) = pull_request.labels.iter().find(|label| {
+            self.labels
+                .iter()
+                .any(|excluded| excluded == "*" || excluded == *label)
+        }) {
+            return Some(format!("label '{}' in {}", label, self.path));
+        }
+        pull_request
+            .author
+            .as_ref()
+            .filter(|author| {
+                self.authors
+                    .iter()
+                    .any(|excluded| excluded.eq_ignore_ascii_case(author))
+            })
+            .map(|author| format!("author '{}' in {}", author, self.path))
+    }
+}
@@ -0,0 +1,317 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Runs the commands configured under `hooks` at points of a merge, handing
+//! them what mergelog has so far as JSON on standard input and taking back
+//! what they print on standard output in its place.
+
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+    thread,
+};
+
+use camino::Utf8PathBuf;
+use miette::{miette, Context, IntoDiagnostic, Report, Result};
+use serde::Deserialize;
+use serde_json::{json, Value as JsonValue};
+use tracing::{debug, info};
+
+use crate::{ChangelogSections, Link, RenderedSection};
+
+/// Commands to run during a merge, each with `sh -c`.
+#[derive(Deserialize, Default)]
+pub struct HooksConfig {
+    /// Gets the changelogs as they were read, before they are parsed.
+    #[serde(default, rename = "pre-merge")]
+    pub pre_merge: Option<String>,
+    /// Gets the entries once they are matched to merge/pull requests.
+    #[serde(default, rename = "post-resolve")]
+    pub post_resolve: Option<String>,
+    /// Gets the sections as they are about to be written out.
+    #[serde(default, rename = "post-render")]
+    pub post_render: Option<String>,
+}
+
+/// Runs the hook `name`, which is `command`, with `input`, returning the JSON
+/// it printed.
+fn run(name: &str, command: &str, input: &JsonValue) -> Result<JsonValue> {
+    debug!("Running {} hook: {}", name, command);
+    let mut child = Command::new(if cfg!(windows) { "cmd" } else { "sh" })
+        .arg(if cfg!(windows) { "/C" } else { "-c" })
+        .arg(command)
+        .env("MERGELOG_HOOK", name)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .into_diagnostic()
+        .wrap_err(format!("Failed to run {} hook `{}`", name, command))?;
+    let input = serde_json::to_string_pretty(input)
+        .expect("JSON values always serialize");
+    // The input is written while the output is read, so that a hook that
+    // prints as it reads never waits on a full pipe. A hook that exits without
+    // reading its input only fails if it fails.
+    let mut stdin = child.stdin.take().expect("standard input is piped");
+    let writer = thread::spawn(move || {
+        let _ = stdin.write_all(input.as_bytes());
+    });
+    let output = child
+        .wait_with_output()
+        .into_diagnostic()
+        .wrap_err(format!("Failed to run {} hook `{}`", name, command))?;
+    writer.join().expect("writing never panics");
+    if !output.status.success() {
+        return Err(miette!(
+            code = "hooks::failed",
+            help = "Its standard error is above.",
+            "The {} hook `{}` failed with {}",
+            name,
+            command,
+            output.status
+        ));
+    }
+    let output = serde_json::from_slice(&output.stdout).map_err(|cause| {
+        miette!(
+            code = "hooks::invalid_json",
+            help = "Print the JSON the hook is given, changed as needed.",
+            "The {} hook `{}` printed invalid JSON: {}",
+            name,
+            command,
+            cause
+        )
+    })?;
+    info!("Ran {} hook", name);
+    Ok(output)
+}
+
+/// An error for a hook that printed JSON of the wrong shape, saying what was
+/// `expected` of it.
+fn invalid_output(name: &str, expected: &str) -> Report {
+    miette!(
+        code = "hooks::invalid_output",
+        help = "Print the JSON the hook is given, changed as needed.",
+        "The {} hook printed {}",
+        name,
+        expected
+    )
+}
+
+fn str_field<'a>(value: &'a JsonValue, key: &str) -> Option<&'a str> {
+    value.get(key)?.as_str()
+}
+
+/// Runs the `pre-merge` hook on `changelogs`, given as their paths and
+/// contents, which it may change, drop, or add to.
+pub fn pre_merge(
+    command: &str,
+    changelogs: Vec<(Utf8PathBuf, String)>,
+) -> Result<Vec<(Utf8PathBuf, String)>> {
+    let input = changelogs
+        .iter()
+        .map(|(path, contents)| json!({ "path": path, "contents": contents }))
+        .collect::<Vec<_>>();
+    let expected =
+        "something other than an array of objects with a `path` and `contents`";
+    run("pre-merge", command, &JsonValue::Array(input))?
+        .as_array()
+        .ok_or_else(|| invalid_output("pre-merge", expected))?
+        .iter()
+        .map(|changelog| {
+            Some((
+                Utf8PathBuf::from(str_field(changelog, "path")?),
+                str_field(changelog, "contents")?.to_string(),
+            ))
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| invalid_output("pre-merge", expected))
+}
+
+/// Runs the `post-resolve` hook on the entries in `sections`, each with an
+/// `id` that the hook keeps to say which entry it printed. Entries it leaves
+/// out are dropped, and those it moves to a section must go to one of
+/// `section_names`.
+pub fn post_resolve(
+    command: &str,
+    section_names: &[String],
+    sections: &mut ChangelogSections,
+) -> Result<()> {
+    let mut entries = Vec::new();
+    for name in section_names {
+        if let Some((_, section_entries)) = sections.get_mut(name) {
+            entries.extend(
+                section_entries.drain(..).map(|entry| (name.clone(), entry)),
+            );
+        }
+    }
+    let input = entries
+        .iter()
+        .enumerate()
+        .map(|(id, (section, entry))| {
+            let pull_request = entry.pull_request.as_ref();
+            json!({
+                "id": id,
+                "section": section,
+                "item": entry.item,
+                "details": entry.details,
+                "breaking": entry.breaking,
+                "authors": entry.authors,
+                "links": entry.links.iter().map(|link| json!({
+                    "shorthand": link.shorthand,
+                    "url": link.full,
+                })).collect::<Vec<_>>(),
+                "title": pull_request.map(|pull_request| &pull_request.title),
+                "author": pull_request.and_then(|pull_request| pull_request.author.as_ref()),
+                "labels": pull_request.map(|pull_request| pull_request.labels.as_slice()).unwrap_or_default(),
+                "merged_at": pull_request.and_then(|pull_request| pull_request.merged_at.as_ref()),
+            })
+        })
+        .collect::<Vec<_>>();
+    let output = run("post-resolve", command, &JsonValue::Array(input))?;
+    let expected = "something other than an array of entries, each with the `id` it was given";
+
+    let mut entries = entries.into_iter().map(Some).collect::<Vec<_>>();
+    for printed in output
+        .as_array()
+        .ok_or_else(|| invalid_output("post-resolve", expected))?
+    {
+        let (mut section, mut entry) = printed
+            .get("id")
+            .and_then(JsonValue::as_u64)
+            .and_then(|id| entries.get_mut(id as usize)?.take())
+            .ok_or_else(|| invalid_output("post-resolve", expected))?;
+        if let Some(name) = str_field(printed, "section") {
+            if !section_names.iter().any(|other| other == name) {
+                return Err(miette!(
+                    code = "hooks::unknown_section",
+                    help = format!(
+                        "Move it to one of {}.",
+                        section_names.join(", ")
+                    ),
+                    "The post-resolve hook moved an entry to section {}, which is not being merged",
+                    name
+                ));
+            }
+            section = name.to_string();
+        }
+        if let Some(item) = str_field(printed, "item") {
+            entry.item = item.to_string();
+        }
+        if let Some(details) = str_field(printed, "details") {
+            entry.details = details.to_string();
+        }
+        if let Some(breaking) =
+            printed.get("breaking").and_then(JsonValue::as_bool)
+        {
+            entry.breaking = breaking;
+        }
+        if let Some(authors) = printed.get("authors") {
+            entry.authors = strings(authors)
+                .ok_or_else(|| invalid_output("post-resolve", expected))?;
+        }
+        if let Some(links) = printed.get("links") {
+            entry.links = links
+                .as_array()
+                .and_then(|links| {
+                    links
+                        .iter()
+                        .map(|link| {
+                            Some(Link {
+                                shorthand: str_field(link, "shorthand")?
+                                    .to_string(),
+                                full: str_field(link, "url")?.to_string(),
+                            })
+                        })
+                        .collect()
+                })
+                .ok_or_else(|| invalid_output("post-resolve", expected))?;
+        }
+        let level = sections.get(&section).map_or(2, |(level, _)| *level);
+        sections
+            .entry(section)
+            .or_insert((level, Vec::new()))
+            .1
+            .push(entry);
+    }
+    // Like sections that never had entries, those it emptied are left out.
+    sections.retain(|name, (_, entries)| {
+        !entries.is_empty() || !section_names.contains(name)
+    });
+    Ok(())
+}
+
+/// Runs the `post-render` hook on the rendered `sections` and the link
+/// definitions they use, replacing them with what it printed.
+pub fn post_render(
+    command: &str,
+    sections: &mut Vec<RenderedSection>,
+    link_definitions: &mut Vec<(String, String)>,
+) -> Result<()> {
+    let input = json!({
+        "sections": sections.iter().map(|section| json!({
+            "level": section.level,
+            "heading": section.heading,
+            "items": section.items,
+        })).collect::<Vec<_>>(),
+        "links": link_definitions.iter().map(|(label, url)| json!({
+            "label": label,
+            "url": url,
+        })).collect::<Vec<_>>(),
+    });
+    let output = run("post-render", command, &input)?;
+    let expected = "something other than an object with `sections` and `links`";
+    *sections = output
+        .get("sections")
+        .and_then(JsonValue::as_array)
+        .and_then(|sections| {
+            sections
+                .iter()
+                .map(|section| {
+                    Some(RenderedSection {
+                        level: section
+                            .get("level")?
+                            .as_u64()
+                            .and_then(|level| u8::try_from(level).ok())
+                            .filter(|level| (1..=6).contains(level))?,
+                        heading: str_field(section, "heading")?.to_string(),
+                        items: strings(section.get("items")?)?,
+                    })
+                })
+                .collect::<Option<Vec<_>>>()
+        })
+        .ok_or_else(|| invalid_output("post-render", expected))?;
+    *link_definitions = output
+        .get("links")
+        .and_then(JsonValue::as_array)
+        .and_then(|links| {
+            links
+                .iter()
+                .map(|link| {
+                    Some((
+                        str_field(link, "label")?.to_string(),
+                        str_field(link, "url")?.to_string(),
+                    ))
+                })
+                .collect::<Option<Vec<_>>>()
+        })
+        .ok_or_else(|| invalid_output("post-render", expected))?;
+    Ok(())
+}
+
+/// The strings in `value`, if it is an array of only strings.
+fn strings(value: &JsonValue) -> Option<Vec<String>> {
+    value
+        .as_array()?
+        .iter()
+        .map(|value| value.as_str().map(str::to_string))
+        .collect()
+}
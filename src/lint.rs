@@ -0,0 +1,518 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Checks a changelog for the mistakes that creep in when it is edited by
+//! hand, pointing at each in the file.
+
+use std::ops::Range;
+
+use camino::Utf8Path;
+use miette::{Context, IntoDiagnostic, LabeledSpan, Result};
+use strsim::jaro_winkler;
+use tracing::info;
+
+use crate::{
+    canonical_section,
+    changelog::{heading, link_definition, release_date},
+    headings_match,
+    query::release_heading,
+    read_file,
+    validate::{self, Problem},
+    Config, KEEP_A_CHANGELOG_SECTIONS,
+};
+
+/// A line of the changelog, without its line ending, and where it starts in
+/// the file.
+struct Line<'a> {
+    start: usize,
+    text: &'a str,
+}
+
+impl Line<'_> {
+    /// Where `part`, which must be a slice of this line, is in the file.
+    fn span(&self, part: &str) -> Range<usize> {
+        let start =
+            self.start + (part.as_ptr() as usize - self.text.as_ptr() as usize);
+        start..start + part.len()
+    }
+}
+
+struct Release {
+    version: String,
+    span: Range<usize>,
+    date: Option<(String, Range<usize>)>,
+}
+
+struct Definition {
+    label: String,
+    destination: String,
+    span: Range<usize>,
+}
+
+/// A list item directly in a release or one of its sections.
+struct Entry {
+    /// The text of its first line.
+    span: Range<usize>,
+    linked: bool,
+}
+
+/// Checks the changelog at `path`, reporting every problem found.
+pub fn lint(path: &Utf8Path, config: &Config) -> Result<()> {
+    let contents = read_file(path)
+        .into_diagnostic()
+        .wrap_err(format!("Failed to read changelog {}", path))?;
+    let problems = problems(&contents, config);
+    if problems.is_empty() {
+        info!("{} has no problems", path);
+        return Ok(());
+    }
+    Err(validate::report(
+        path,
+        &contents,
+        "markdown",
+        problems,
+        "lint::failed",
+    ))
+}
+
+fn problems(contents: &str, config: &Config) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut releases = Vec::new();
+    let mut definitions = Vec::new();
+    let mut references = Vec::new();
+    let mut entries = Vec::new();
+    let mut release_level = None;
+    // Whether the lines so far are part of a release, which is where entries
+    // and sections go.
+    let mut in_release = false;
+    let mut entry: Option<Entry> = None;
+    for line in lines(contents) {
+        if let Some(label) = link_definition(line.text) {
+            let destination = line.text[label.len() + 3..].trim();
+            definitions.push(Definition {
+                label: label.to_string(),
+                destination: destination
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or_default()
+                    .to_string(),
+                span: line.span(line.text),
+            });
+            continue;
+        }
+        for (span, label) in links(line.text) {
+            references.push((label.to_string(), line.span(span)));
+        }
+
+        if line.text.trim().is_empty() {
+            continue;
+        }
+        let item = line
+            .text
+            .strip_prefix(['-', '*', '+'])
+            .filter(|rest| rest.is_empty() || rest.starts_with([' ', '\t']));
+        if line.text.starts_with([' ', '\t']) || item.is_some() {
+            match &mut entry {
+                Some(entry) if item.is_none() => {
+                    entry.linked |= is_linked(line.text)
+                }
+                _ => {
+                    entries.extend(entry.take());
+                    if let Some(item) = item.filter(|_| in_release) {
+                        entry = Some(Entry {
+                            span: line.span(item.trim()),
+                            linked: is_linked(item),
+                        });
+                    }
+                }
+            }
+            continue;
+        }
+        entries.extend(entry.take());
+
+        let Some((level, text)) = heading(line.text) else {
+            continue;
+        };
+        match release_heading(text) {
+            Some(version)
+                if release_level.is_none_or(|release| release == level) =>
+            {
+                release_level = Some(level);
+                in_release = true;
+                if let Some(version) = version {
+                    let start = text.find(&version).unwrap_or_default();
+                    releases.push(Release {
+                        span: line.span(&text[start..start + version.len()]),
+                        date: release_date(text).map(|date| {
+                            let start = text.find(&date).unwrap_or_default();
+                            let span =
+                                line.span(&text[start..start + date.len()]);
+                            (date, span)
+                        }),
+                        version,
+                    });
+                }
+            }
+            _ if release_level.is_some_and(|release| level <= release) => {
+                in_release = false;
+            }
+            _ if in_release
+                && release_level
+                    .is_some_and(|release| level == release + 1) =>
+            {
+                problems.extend(check_section(text, line.span(text), config));
+            }
+            _ => {}
+        }
+    }
+    entries.extend(entry);
+
+    check_releases(&releases, &mut problems);
+    check_links(&definitions, &references, &mut problems);
+    for entry in entries.into_iter().filter(|entry| !entry.linked) {
+        problems.push(Problem::new(
+            "lint::entry_without_link",
+            "Entry has no link".into(),
+            vec![LabeledSpan::at(entry.span, "no link")],
+            "Link the entry to the merge/pull request or issue it comes from."
+                .into(),
+        ));
+    }
+    problems
+}
+
+/// The lines of `contents` outside of fenced code blocks.
+fn lines(contents: &str) -> Vec<Line<'_>> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    let mut fenced = false;
+    for text in contents.split_inclusive('\n') {
+        let line = text.trim_end_matches(['\n', '\r']);
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fenced = !fenced;
+        } else if !fenced {
+            lines.push(Line { start, text: line });
+        }
+        start += text.len();
+    }
+    lines
+}
+
+/// The reference links in `line`, like `[text][label]`, `[label][]`, and
+/// `[label]`, each as where it is and the label it refers to. Inline links and
+/// anything in code spans are left out.
+fn links(line: &str) -> Vec<(&str, &str)> {
+    let bytes = line.as_bytes();
+    let mut links = Vec::new();
+    let mut open = None;
+    let mut in_code = false;
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'`' => in_code = !in_code,
+            b'\\' => index += 1,
+            _ if in_code => {}
+            b'[' => open = Some(index),
+            b']' => {
+                if let Some(start) = open.take() {
+                    let text = &line[start + 1..index];
+                    let rest = &line[index + 1..];
+                    if rest.starts_with('(') {
+                        // An inline link.
+                    } else if let Some(label) = rest
+                        .strip_prefix('[')
+                        .and_then(|rest| rest.split_once(']'))
+                        .map(|(label, _)| label)
+                        .filter(|label| !label.contains('['))
+                    {
+                        let end = index + label.len() + 3;
+                        let label = if label.is_empty() { text } else { label };
+                        links.push((&line[start..end], label));
+                        index = end - 1;
+                    } else if !matches!(text.trim(), "" | "x" | "X") {
+                        // Anything else is a shortcut reference, apart from the
+                        // boxes of task list items.
+                        links.push((&line[start..=index], text));
+                    }
+                }
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+    links
+}
+
+/// Whether `text` links to something, either with a link or with a shorthand
+/// like `#30` or `!30`.
+fn is_linked(text: &str) -> bool {
+    text.contains("](")
+        || text.contains("http://")
+        || text.contains("https://")
+        || !links(text).is_empty()
+        || text.match_indices(['#', '!']).any(|(index, _)| {
+            text[index + 1..].starts_with(|c: char| c.is_ascii_digit())
+        })
+}
+
+/// Labels are matched without regard to case or runs of whitespace.
+fn normalize_label(label: &str) -> String {
+    label
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Whether `version`, without a leading `v`, is a semantic version like
+/// `1.4.0` or `2.0.0-rc.1+build.5`.
+fn is_semver(version: &str) -> bool {
+    let identifiers = |text: &str| {
+        text.split('.').all(|identifier| {
+            !identifier.is_empty()
+                && identifier
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+    };
+    let (version, build) = match version.split_once('+') {
+        Some((version, build)) => (version, Some(build)),
+        None => (version, None),
+    };
+    let (core, pre_release) = match version.split_once('-') {
+        Some((core, pre_release)) => (core, Some(pre_release)),
+        None => (version, None),
+    };
+    let numbers = core.split('.').collect::<Vec<_>>();
+    numbers.len() == 3
+        && numbers.iter().all(|number| {
+            !number.is_empty()
+                && number.chars().all(|c| c.is_ascii_digit())
+                && (number.len() == 1 || !number.starts_with('0'))
+        })
+        && pre_release.is_none_or(identifiers)
+        && build.is_none_or(identifiers)
+}
+
+fn check_section(
+    heading: &str,
+    span: Range<usize>,
+    config: &Config,
+) -> Option<Problem> {
+    let mut sections = config.section_names();
+    if sections.is_empty() {
+        sections = KEEP_A_CHANGELOG_SECTIONS
+            .map(|section| section.into())
+            .to_vec();
+    }
+    sections.push(config.breaking.section.clone());
+    if canonical_section(heading, &sections, config).is_some() {
+        return None;
+    }
+    // Sections are also written with their configured heading and emoji.
+    let mut headings = vec![config.highlights.clone()];
+    headings.extend(
+        config
+            .new_contributors
+            .as_ref()
+            .and_then(|new_contributors| new_contributors.section.clone()),
+    );
+    for section in &config.sections {
+        let heading = section
+            .heading
+            .as_ref()
+            .unwrap_or(&config.heading)
+            .replace("{name}", &section.name);
+        if let Some(emoji) = &section.emoji {
+            headings.push(format!("{emoji} {heading}"));
+        }
+        headings.push(heading);
+    }
+    if headings.iter().any(|known| headings_match(known, heading)) {
+        return None;
+    }
+
+    let suggestion = sections
+        .iter()
+        .map(|section| (jaro_winkler(section, heading), section))
+        .filter(|(similarity, _)| *similarity >= 0.8)
+        .max_by(|lhs, rhs| lhs.0.total_cmp(&rhs.0));
+    let help = match suggestion {
+        Some((_, section)) => format!("Did you mean `{}`?", section),
+        None => format!(
+            "Use one of {}, or map the heading to one of them with `aliases` in the config.",
+            sections
+                .iter()
+                .map(|section| format!("`{}`", section))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    Some(Problem::new(
+        "lint::unknown_section",
+        format!("Unknown section `{}`", heading),
+        vec![LabeledSpan::at(span, "not a known section")],
+        help,
+    ))
+}
+
+fn check_releases(releases: &[Release], problems: &mut Vec<Problem>) {
+    for (index, release) in releases.iter().enumerate() {
+        let version = release.version.trim_start_matches('v');
+        if !is_semver(version) {
+            problems.push(Problem::new(
+                "lint::non_semver_version",
+                format!("Version `{}` is not a semantic version", version),
+                vec![LabeledSpan::at(release.span.clone(), "not MAJOR.MINOR.PATCH")],
+                "Versions look like `1.4.0`, optionally followed by a pre-release like `-rc.1`.".into(),
+            ));
+        }
+        if let Some(first) = releases[..index]
+            .iter()
+            .find(|first| first.version.trim_start_matches('v') == version)
+        {
+            problems.push(Problem::new(
+                "lint::duplicate_version",
+                format!("Version `{}` is released more than once", version),
+                vec![
+                    LabeledSpan::at(first.span.clone(), "first released here"),
+                    LabeledSpan::at(
+                        release.span.clone(),
+                        "released again here",
+                    ),
+                ],
+                "Combine the two releases, or fix the version of one of them."
+                    .into(),
+            ));
+        }
+    }
+
+    // Releases are listed newest first, so dates never increase going down.
+    let dated = releases
+        .iter()
+        .filter_map(|release| release.date.as_ref().map(|date| (release, date)))
+        .collect::<Vec<_>>();
+    for pair in dated.windows(2) {
+        let [(above, (above_date, above_span)), (release, (date, span))] = pair
+        else {
+            unreachable!("windows of two");
+        };
+        if date > above_date {
+            problems.push(Problem::new(
+                "lint::out_of_order_date",
+                format!(
+                    "Release {} is dated after {}, which is listed above it",
+                    release.version, above.version
+                ),
+                vec![
+                    LabeledSpan::at(above_span.clone(), "listed first"),
+                    LabeledSpan::at(span.clone(), "but released later"),
+                ],
+                "List releases newest first, or fix the date of one of them."
+                    .into(),
+            ));
+        }
+    }
+}
+
+fn check_links(
+    definitions: &[Definition],
+    references: &[(String, Range<usize>)],
+    problems: &mut Vec<Problem>,
+) {
+    for (index, definition) in definitions.iter().enumerate() {
+        let label = normalize_label(&definition.label);
+        if let Some(first) = definitions[..index]
+            .iter()
+            .find(|first| normalize_label(&first.label) == label)
+        {
+            problems.push(Problem::new(
+                "lint::duplicate_link_definition",
+                format!(
+                    "Link `[{}]` is defined more than once",
+                    definition.label
+                ),
+                vec![
+                    LabeledSpan::at(first.span.clone(), "first defined here"),
+                    LabeledSpan::at(
+                        definition.span.clone(),
+                        "defined again here",
+                    ),
+                ],
+                "Only the first definition is used, so remove the other."
+                    .into(),
+            ));
+        } else if definition.destination.is_empty() {
+            problems.push(Problem::new(
+                "lint::broken_link",
+                format!("Link `[{}]` has no destination", definition.label),
+                vec![LabeledSpan::at(definition.span.clone(), "defined here")],
+                format!(
+                    "Give the link a destination, like `[{}]: https://...`.",
+                    definition.label
+                ),
+            ));
+        } else if let Some(Some(version)) = release_heading(&definition.label) {
+            // A link for a release has to lead to that release.
+            let version = version.trim_start_matches('v');
+            if !definition.destination.contains(version) {
+                problems.push(Problem::new(
+                    "lint::broken_link",
+                    format!(
+                        "Link `[{}]` does not lead to release {}",
+                        definition.label, version
+                    ),
+                    vec![LabeledSpan::at(
+                        definition.span.clone(),
+                        "defined here",
+                    )],
+                    format!(
+                        "Point the link at the changes in {}, like a comparison with the release before it.",
+                        version
+                    ),
+                ));
+            }
+        }
+    }
+
+    let mut missing: Vec<(String, Vec<LabeledSpan>)> = Vec::new();
+    for (label, span) in references {
+        let normalized = normalize_label(label);
+        if definitions
+            .iter()
+            .any(|definition| normalize_label(&definition.label) == normalized)
+        {
+            continue;
+        }
+        let label_span = LabeledSpan::at(span.clone(), "used here");
+        match missing
+            .iter_mut()
+            .find(|(other, _)| normalize_label(other) == normalized)
+        {
+            Some((_, spans)) => spans.push(label_span),
+            None => missing.push((label.clone(), vec![label_span])),
+        }
+    }
+    for (label, spans) in missing {
+        problems.push(Problem::new(
+            "lint::missing_link_definition",
+            format!("Link `[{}]` has no definition", label),
+            spans,
+            format!(
+                "Define it at the end of the changelog, like `[{}]: https://...`.",
+                label
+            ),
+        ));
+    }
+}
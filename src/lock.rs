@@ -0,0 +1,91 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! An advisory lock that keeps two runs in the same repository from prompting
+//! at once and interleaving their writes.
+
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    io::{Read, Seek, Write},
+    process,
+};
+
+use camino::Utf8PathBuf;
+use miette::{miette, Context, IntoDiagnostic, Result};
+use tracing::debug;
+
+use crate::git_output;
+
+/// Held for as long as the run may write or ask something. The lock is
+/// released when this is dropped, including when the process exits.
+pub struct RunLock {
+    _file: File,
+}
+
+/// Where the lock lives: inside the git directory, where it stays out of the
+/// way, or in the current directory outside of a repository. The file is left
+/// behind afterward, since removing it would let a run waiting on the old
+/// file and a run creating a new one both hold a lock.
+fn lock_path() -> Result<Utf8PathBuf> {
+    Ok(match git_output(["rev-parse", "--absolute-git-dir"])? {
+        Some(git_dir) => Utf8PathBuf::from(git_dir).join("mergelog.lock"),
+        None => Utf8PathBuf::from(".mergelog.lock"),
+    })
+}
+
+/// Takes the lock, failing right away if another run holds it.
+pub fn acquire() -> Result<RunLock> {
+    let path = lock_path()?;
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .into_diagnostic()
+        .wrap_err(format!("Failed to open lock file {}", path))?;
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            let mut holder = String::new();
+            let _ = file.read_to_string(&mut holder);
+            let holder = holder.trim();
+            return Err(miette!(
+                code = "lock::held",
+                help = format!(
+                    "Wait for it to finish and try again. Its lock on {} is released as soon as it exits.",
+                    path
+                ),
+                "Another mergelog run is in progress{}",
+                if holder.is_empty() {
+                    String::new()
+                } else {
+                    format!(" (process {holder})")
+                }
+            ));
+        }
+        Err(TryLockError::Error(error)) => {
+            return Err(error)
+                .into_diagnostic()
+                .wrap_err(format!("Failed to lock {}", path));
+        }
+    }
+    debug!("Locked {}", path);
+
+    // Only for the diagnostic above, so failing to record it is harmless.
+    let _ = file
+        .set_len(0)
+        .and_then(|()| file.rewind())
+        .and_then(|()| writeln!(file, "{}", process::id()));
+    Ok(RunLock { _file: file })
+}
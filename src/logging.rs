@@ -0,0 +1,222 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Status messages, warnings, and traces, which go to standard error through
+//! `tracing` so that `-v` and `--quiet` can choose how much of them to show.
+//! They are written around the progress bars rather than over them.
+
+use std::{
+    cell::Cell,
+    fmt::{self, Write as _},
+    io::{self, IsTerminal, Write},
+    sync::OnceLock,
+};
+
+use indicatif::{MultiProgress, ProgressDrawTarget};
+use owo_colors::{OwoColorize, Stream::Stderr};
+use tracing::{
+    field::{Field, Visit},
+    level_filters::LevelFilter,
+    Event, Level, Subscriber,
+};
+use tracing_subscriber::{
+    filter::Targets,
+    fmt::{
+        self as subscriber_fmt, format, FmtContext, FormatEvent, FormatFields,
+        MakeWriter,
+    },
+    layer::SubscriberExt,
+    registry::LookupSpan,
+    util::SubscriberInitExt,
+};
+
+use crate::console;
+
+/// How much is written to standard error besides prompts and errors.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Verbosity {
+    /// Nothing.
+    Quiet,
+    /// Status messages, warnings, and progress bars.
+    Normal,
+    /// Also the decisions made while reading changelogs and the requests
+    /// sent, instead of progress bars.
+    Verbose,
+    /// Also the responses received.
+    Trace,
+}
+
+impl Verbosity {
+    /// The verbosity for `-v` passed `verbose` times, or `--quiet`.
+    pub fn new(verbose: u8, quiet: bool) -> Self {
+        match (verbose, quiet) {
+            (_, true) => Self::Quiet,
+            (0, false) => Self::Normal,
+            (1, false) => Self::Verbose,
+            (_, false) => Self::Trace,
+        }
+    }
+
+    fn level_filter(self) -> LevelFilter {
+        match self {
+            Self::Quiet => LevelFilter::ERROR,
+            Self::Normal => LevelFilter::INFO,
+            Self::Verbose => LevelFilter::DEBUG,
+            Self::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+static PROGRESS: OnceLock<MultiProgress> = OnceLock::new();
+
+thread_local! {
+    /// Whether the progress bars are currently suspended on this thread, in
+    /// which case they must not be suspended again.
+    static SUSPENDED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// The fields of an event, written out as is. The default formatter escapes
+/// ANSI codes, which would garble the colors of diagnostics.
+struct Message(String);
+
+impl Visit for Message {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, "{value:?}");
+        } else {
+            let _ = write!(self.0, " {}={value:?}", field.name());
+        }
+    }
+}
+
+/// Formats status messages like the rest of the output, and the finer
+/// details in gray.
+struct Format;
+
+impl<S, N> FormatEvent<S, N> for Format
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        _context: &FmtContext<'_, S, N>,
+        mut writer: format::Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut message = Message(String::new());
+        event.record(&mut message);
+        let Message(message) = message;
+        match *event.metadata().level() {
+            Level::INFO => writeln!(
+                writer,
+                "{} {}",
+                console::symbols().check,
+                message.if_supports_color(Stderr, |text| text.green())
+            ),
+            Level::DEBUG | Level::TRACE => writeln!(
+                writer,
+                "{}",
+                format!(
+                    "{} {}: {}",
+                    event.metadata().level().as_str().to_lowercase(),
+                    event.metadata().target(),
+                    message
+                )
+                .if_supports_color(Stderr, |text| text
+                    .fg_rgb::<128, 128, 128>())
+            ),
+            _ => writeln!(writer, "{message}"),
+        }
+    }
+}
+
+/// Collects one formatted event and writes it out once it is complete.
+struct EventWriter(Vec<u8>);
+
+impl Write for EventWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for EventWriter {
+    fn drop(&mut self) {
+        let write = || {
+            let _ = io::stderr().write_all(&self.0);
+        };
+        if SUSPENDED.get() {
+            write();
+        } else {
+            progress().suspend(write);
+        }
+    }
+}
+
+struct MakeEventWriter;
+
+impl MakeWriter<'_> for MakeEventWriter {
+    type Writer = EventWriter;
+
+    fn make_writer(&self) -> Self::Writer {
+        EventWriter(Vec::new())
+    }
+}
+
+/// Starts showing messages at `verbosity`. Call this before logging anything.
+pub fn init(verbosity: Verbosity) {
+    PROGRESS.get_or_init(|| {
+        if verbosity == Verbosity::Normal && io::stderr().is_terminal() {
+            MultiProgress::new()
+        } else {
+            // The bars would only get in the way of traces, have no place in
+            // quiet output, and garble logs when standard error is not a
+            // terminal.
+            MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+        }
+    });
+    tracing_subscriber::registry()
+        .with(
+            subscriber_fmt::layer()
+                .with_writer(MakeEventWriter)
+                .event_format(Format),
+        )
+        // The traces of dependencies such as the HTTP client would drown out
+        // our own.
+        .with(
+            Targets::new().with_target(
+                env!("CARGO_CRATE_NAME"),
+                verbosity.level_filter(),
+            ),
+        )
+        .init();
+}
+
+/// Where progress bars are drawn.
+pub fn progress() -> &'static MultiProgress {
+    PROGRESS.get_or_init(MultiProgress::new)
+}
+
+/// Hides the progress bars while `f` runs, e.g., to ask the user something.
+pub fn suspend<R>(f: impl FnOnce() -> R) -> R {
+    progress().suspend(|| {
+        SUSPENDED.set(true);
+        let result = f();
+        SUSPENDED.set(false);
+        result
+    })
+}
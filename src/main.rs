@@ -11,33 +11,86 @@
 // You should have received a copy of the GNU General Public License along with
 // this program.  If not, see <https://www.gnu.org/licenses/>.
 
+mod annotate;
+mod announce;
+mod changelog;
+mod completions;
+mod console;
+mod feed;
+mod github;
+mod hooks;
+mod lint;
+mod lock;
+mod logging;
+mod man;
+mod markup;
+mod packaging;
+mod picker;
+mod prompter;
+mod publish;
+mod query;
+mod validate;
+mod version_file;
+
 use core::str;
 use std::{
-    collections::{HashMap, HashSet},
+    cmp::{Ordering, Reverse},
+    collections::{hash_map, BTreeMap, HashMap, HashSet},
     env,
     error::Error,
-    fmt, fs,
-    io::{self, Write},
-    process::Command,
+    fmt::{self, Write as _},
+    fs::{self, File},
+    io::{self, IsTerminal, Write},
+    iter,
+    ops::Range,
+    path::Path,
+    process::{self, Command},
+    slice,
     str::FromStr,
-    time::Duration,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use argh::FromArgs;
-use camino::{Utf8Path, Utf8PathBuf};
+use annotate::AnnotationFormat;
+use announce::AnnounceTarget;
+use argh::{ArgsInfo, FlagInfoKind, FromArgs};
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+use changelog::Changelog;
+use console::{ColorChoice, Symbols};
 use edit_distance::edit_distance;
+use feed::{Feed, FeedEntry};
+use github::Exclusions;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use hooks::HooksConfig;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use indicatif::{ProgressBar, ProgressStyle};
+use markup::MarkupFormat;
 use miette::{
     miette, Context, Diagnostic, IntoDiagnostic, LabeledSpan, NamedSource,
-    Report, Result, SourceOffset,
+    Report, Result, Severity, SourceOffset, SourceSpan,
 };
-use owo_colors::OwoColorize;
+use owo_colors::{OwoColorize, Stream::Stderr};
+use packaging::{PackageFormat, Release};
+use prompter::{Prompter, Scripted, Terminal};
+use query::{QueryFormat, Record};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use regex::Regex;
 use serde::Deserialize;
 use serde_json::Value as JsonValue;
-use url::Url;
+use similar::{ChangeTag, TextDiff};
+use strsim::jaro_winkler;
+use tracing::{debug, info, trace, warn, Level};
+use unicode_normalization::UnicodeNormalization;
+use url::{Position, Url};
 
 trait WhateverContextExt<T> {
     fn whatever_context(self, new_parent: Report) -> Result<T>;
+
+    /// Like [`WhateverContextExt::whatever_context`], but only creates the
+    /// parent on failure, since creating a diagnostic is not free.
+    fn with_whatever_context(
+        self,
+        new_parent: impl FnOnce() -> Report,
+    ) -> Result<T>;
 }
 
 #[derive(Debug)]
@@ -92,9 +145,16 @@ impl Diagnostic for DiagnosticWithSource {
 
 impl<T> WhateverContextExt<T> for std::result::Result<T, Report> {
     fn whatever_context(self, new_parent: Report) -> Result<T> {
+        self.with_whatever_context(|| new_parent)
+    }
+
+    fn with_whatever_context(
+        self,
+        new_parent: impl FnOnce() -> Report,
+    ) -> Result<T> {
         self.map_err(|cause| {
             Report::new(DiagnosticWithSource {
-                parent: new_parent,
+                parent: new_parent(),
                 cause,
             })
         })
@@ -105,9 +165,16 @@ impl<T> WhateverContextExt<T> for Option<T> {
     fn whatever_context(self, new_parent: Report) -> Result<T> {
         self.ok_or(new_parent)
     }
+
+    fn with_whatever_context(
+        self,
+        new_parent: impl FnOnce() -> Report,
+    ) -> Result<T> {
+        self.ok_or_else(new_parent)
+    }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 enum RepositoryHost {
     GitHub,
     GitLab,
@@ -126,661 +193,8326 @@ impl FromStr for RepositoryHost {
     }
 }
 
-/// Merges changelog files into a single changelog
-#[derive(FromArgs)]
-struct Opts {
-    /// link to the repository to resolve merge/pull requests at; omit to infer
-    /// from the current repo
-    #[argh(option, long = "repo")]
-    repo_url: Option<Url>,
+impl RepositoryHost {
+    /// Where the host serves repositories, e.g., `gitlab.com`.
+    fn domain(self) -> &'static str {
+        match self {
+            Self::GitHub => "github.com",
+            Self::GitLab => "gitlab.com",
+            Self::Infer => unreachable!(),
+        }
+    }
 
-    /// the repository host; omit to infer from the repo URL
-    #[argh(option, default = "RepositoryHost::Infer")]
+    /// The error for a request to the API of the host, which mergelog can
+    /// only send to GitLab for now.
+    fn unsupported(self) -> Report {
+        miette!(
+            code = "api::unsupported_host",
+            help = "Only the GitLab API is supported for now. `mergelog --offline merge` reads merge/pull requests from the local history instead.",
+            "Cannot send API requests to {}",
+            self.domain()
+        )
+    }
+}
+
+/// A repository on its host, which links to its merge/pull requests, commits,
+/// and revisions point into.
+#[derive(Clone, Copy)]
+struct Repository<'a> {
     host: RepositoryHost,
+    owner: &'a str,
+    name: &'a str,
+    /// Where links to merge/pull requests point instead of the host, from the
+    /// config.
+    link_template: Option<&'a str>,
+}
 
-    /// changelog sections in order
-    #[argh(option, short = 's')]
-    section: Vec<String>,
+impl<'a> Repository<'a> {
+    fn new(
+        host: RepositoryHost,
+        owner: &'a str,
+        name: &'a str,
+        config: &'a Config,
+    ) -> Self {
+        Self {
+            host,
+            owner,
+            name,
+            link_template: config.link_template(host),
+        }
+    }
 
-    /// path to optional config file
-    #[argh(option)]
-    config: Option<Utf8PathBuf>,
+    /// Fills in the placeholders of a link template, with `id` for `{id}`.
+    fn fill(self, template: &str, id: &str) -> String {
+        template
+            .replace("{id}", id)
+            .replace("{owner}", self.owner)
+            .replace("{repo}", self.name)
+    }
 
-    /// directory containing changelogs and a mergelog.toml
-    #[argh(positional)]
-    changelog_directory: Utf8PathBuf,
-}
+    /// The web page of the repository. A link template that places the
+    /// repository before the id, as for a self-hosted instance, places it
+    /// there; one that does not, as for an issue tracker, leaves it on the
+    /// host.
+    fn url(self) -> String {
+        let prefix = self.link_template.and_then(|template| {
+            let end = template.find("{repo}")? + "{repo}".len();
+            let prefix = &template[..end];
+            (!prefix.contains("{id}")).then_some(prefix)
+        });
+        match prefix {
+            Some(prefix) => self.fill(prefix, ""),
+            None => format!(
+                "https://{}/{}/{}",
+                self.host.domain(),
+                self.owner,
+                self.name
+            ),
+        }
+    }
 
-fn default_config_format() -> String {
-    "{item} ({link_name})".into()
-}
+    /// The shorthand of the merge/pull request `id`, e.g., `!12` on GitLab.
+    fn shorthand(self, id: u64) -> String {
+        match self.host {
+            RepositoryHost::GitHub => format!("#{id}"),
+            RepositoryHost::GitLab => format!("!{id}"),
+            RepositoryHost::Infer => unreachable!(),
+        }
+    }
 
-#[derive(Deserialize)]
-struct Config {
-    #[serde(default)]
-    sections: Vec<String>,
-    #[serde(default = "default_config_format")]
-    format: String,
-    #[serde(default, rename = "short-links")]
-    short_links: bool,
-}
+    /// The page at `path` in the repository, e.g., `commit/{sha}`, which
+    /// GitLab serves under `-/`.
+    fn page(self, path: &str) -> String {
+        match self.host {
+            RepositoryHost::GitHub => format!("{}/{path}", self.url()),
+            RepositoryHost::GitLab => format!("{}/-/{path}", self.url()),
+            RepositoryHost::Infer => unreachable!(),
+        }
+    }
 
-struct PullRequest {
-    id: u64,
-    link: String,
-    title: String,
+    /// The page of the release for `tag`.
+    fn release_page(self, tag: &str) -> String {
+        match self.host {
+            RepositoryHost::GitHub => self.page(&format!("releases/tag/{tag}")),
+            RepositoryHost::GitLab => self.page(&format!("releases/{tag}")),
+            RepositoryHost::Infer => unreachable!(),
+        }
+    }
 }
 
-impl PullRequest {
-    fn try_from_gitlab(value: &JsonValue) -> Result<Self> {
-        let id = value
-            .get("iid")
-            .and_then(|value| value.as_u64())
-            .wrap_err("Missing 'iid' field on merge request")?;
-        let name = value
-            .get("title")
-            .and_then(|value| value.as_str())
-            .wrap_err("Missing 'name' field on merge request")?;
-        Ok(Self {
-            id,
-            link: format!("!{}", id),
-            title: name.to_string(),
-        })
-    }
+/// How changelogs that are not named after a merge/pull request are resolved.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Interaction {
+    /// Ask on the command line.
+    Prompt,
+    /// Pick in a terminal UI.
+    Tui,
+    /// Pick the most likely one without asking.
+    Automatic,
 }
 
-/// # Safety
-///
-/// `substring` must start after `source`, although this function only makes
-/// sense if the start of `substring` is within the range of `source`.
-unsafe fn start_in(source: &str, substring: &str) -> usize {
-    substring.as_ptr().offset_from(source.as_ptr()) as usize
+/// How symbolic links in the changelog directory are treated.
+#[derive(Clone, Copy)]
+enum SymlinkPolicy {
+    Follow,
+    Skip,
+    Error,
 }
 
-fn infer_host(repo_url: &Url) -> Result<RepositoryHost> {
-    if let Some(domain) = repo_url.domain() {
-        match domain {
-            "github.com" => Ok(RepositoryHost::GitHub),
-            "gitlab.com" => Ok(RepositoryHost::GitLab),
-            _ => {
-                let start = unsafe { start_in(domain, repo_url.as_str()) };
-                Err(miette!(
-                    code = "infer_host::unknown_domain",
-                    labels = vec![LabeledSpan::new_with_span(None, (start, domain.len()))],
-                    help = "Please use a known repository host like github.com or gitlab.com.",
-                    "Unknown host domain"
-                )
-                .with_source_code(NamedSource::new("url",repo_url.to_string())))
-            }
+impl FromStr for SymlinkPolicy {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "follow" => Ok(Self::Follow),
+            "skip" => Ok(Self::Skip),
+            "error" => Ok(Self::Error),
+            other => Err(miette!("Failed to parse '{other}' as a symlink policy. Options include 'follow', 'skip', and 'error'"))
         }
-    } else {
-        Err(miette!(
-            code = "infer_host::missing_domain",
-            "Provided URL missing domain"
-        )
-        .with_source_code(NamedSource::new("url", repo_url.to_string())))
     }
 }
 
-fn parse_owner_and_name(
-    url: Url,
-    host: RepositoryHost,
-) -> Result<(String, String)> {
-    match host {
-        RepositoryHost::GitHub => todo!(),
-        RepositoryHost::GitLab => {
-            let components = url
-                .path_segments()
-                .wrap_err("Repository URL missing path segments")?
-                .collect::<Vec<_>>();
-            if components.len() < 2
-                || (components.len() == 2
-                    && (components[0].is_empty() || components[1].is_empty()))
-            {
-                let start = if components.is_empty() {
-                    0
-                } else {
-                    unsafe { start_in(url.as_str(), components[0]) }
-                };
-                let length = url.as_str().len() - start;
-                return Err(miette!(
-                    code = "parse_owner_and_name::incorrect_format",
-                    labels = vec![LabeledSpan::at(
-                        (start, length),
-                        "less than two path segments"
-                    )],
-                    help = "The URL should be of the form: https://gitlab.com/{owner}/{name}",
-                    "URL does not point to a repository"
-                )
-                .with_source_code(NamedSource::new("url", url.to_string())));
-            }
-            Ok((components[0].to_string(), components[1].to_string()))
-        }
-        RepositoryHost::Infer => unreachable!(),
-    }
+/// How the merged entries are grouped under headings.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Grouping {
+    /// Under their sections only.
+    Section,
+    /// Under the calendar month they were merged in, newest first, and then
+    /// their sections.
+    Month,
 }
 
-fn fetch_merge_requests(
-    owner: &str,
-    name: &str,
-    host: RepositoryHost,
-) -> Result<Vec<PullRequest>> {
-    match host {
-        RepositoryHost::GitHub => todo!(),
-        RepositoryHost::GitLab => {
-            let request = format!("https://gitlab.com/api/v4/projects/{}%2F{}/merge_requests?state=merged&view=simple&per_page=100", owner, name);
-            let response = reqwest::blocking::get(&request)
-                .into_diagnostic()
-                .whatever_context(miette!(
-                    code = "fetch_merge_requests::api_error",
-                    "Failed to obtain merge requests from {}/{}",
-                    owner,
-                    name
-                ))?
-                .text()
-                .into_diagnostic()
-                .whatever_context(miette!(
-                    "Failed to extract GitLab API response text"
-                ))?;
-            let response_json: JsonValue = serde_json::from_str(&response)
-                .map_err(|cause| {
-                    miette!(
-                        code = "fetch_merge_requests::serde_json_error",
-                        labels = vec![LabeledSpan::at(
-                            SourceOffset::from_location(
-                                &response,
-                                cause.line(),
-                                cause.column()
-                            ),
-                            cause.to_string()
-                        )],
-                        "Failed to parse GitLab API response text"
-                    )
-                    .with_source_code(
-                        NamedSource::new(request.as_str(), response.clone())
-                            .with_language("json"),
-                    )
-                })?;
-            let merge_requests = response_json.as_array().whatever_context(
-                miette!(
-                    code = "fetch_merge_requests::malformed_json",
-                    labels = vec![LabeledSpan::at(
-                        (0, 0),
-                        "Expected array of merge request details"
-                    )],
-                    "Failed to parse GitLab API response text"
-                )
-                .with_source_code(
-                    NamedSource::new(request, response).with_language("json"),
-                ),
-            )?;
-            merge_requests
-                .iter()
-                .map(PullRequest::try_from_gitlab)
-                .collect::<Result<Vec<_>>>()
+impl FromStr for Grouping {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "section" => Ok(Self::Section),
+            "month" => Ok(Self::Month),
+            other => Err(miette!("Failed to parse '{other}' as a grouping. Options include 'section' and 'month'"))
         }
-        RepositoryHost::Infer => unreachable!(),
     }
 }
 
-fn prompt<'a>(
-    prompt: impl Fn(),
-    validate: impl Fn(&str) -> bool,
-    exit: impl Fn(&str),
-    default: impl Into<Option<&'a str>>,
-) -> Result<String> {
-    let default = default.into().map(Into::into);
-    let mut buffer = String::new();
-    loop {
-        prompt();
-        io::stdout()
-            .flush()
-            .into_diagnostic()
-            .wrap_err("Failed to flush standard output")?;
-        io::stderr()
-            .flush()
-            .into_diagnostic()
-            .wrap_err("Failed to flush standard input")?;
-        io::stdin()
-            .read_line(&mut buffer)
-            .into_diagnostic()
-            .wrap_err("Failed to read user input")?;
-        let buffer = buffer.trim();
-        if buffer.is_empty() {
-            if let Some(default) = default {
-                exit(default);
-                return Ok(default.to_string());
-            }
-        };
-        if validate(buffer) {
-            exit(buffer);
-            return Ok(buffer.to_string());
+/// A format the merged changelog can be printed in.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    Markup(MarkupFormat),
+    /// The changelog of a distribution package, released as `--version`.
+    Package(PackageFormat),
+}
+
+impl FromStr for OutputFormat {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "debian" => Ok(Self::Package(PackageFormat::Debian)),
+            "rpm" => Ok(Self::Package(PackageFormat::Rpm)),
+            other => Ok(Self::Markup(other.parse().map_err(|_| miette!("Failed to parse '{other}' as an output format. Options include 'markdown', 'asciidoc', 'rst', 'text', 'debian', and 'rpm'"))?)),
         }
     }
 }
 
-fn guess_pull_request<'a>(
-    name: &str,
-    pull_requests: &'a [PullRequest],
-) -> Option<Vec<&'a PullRequest>> {
-    let mut costs = pull_requests
-        .iter()
-        .enumerate()
-        .map(|(i, pr)| {
-            let words = pr.title.split_ascii_whitespace().collect::<Vec<_>>();
-            let distance = words
-                .iter()
-                .map(|word| {
-                    if name.to_lowercase().contains(&word.to_lowercase())
-                        && word.len() > 1
-                    {
-                        pr.title.len() * 10
-                    } else {
-                        edit_distance(&pr.title, name)
-                    }
-                })
-                .sum::<usize>() as f64;
-            let normalizer = if words.is_empty() { 1 } else { pr.title.len() };
-            (i, distance / (normalizer as f64))
-        })
-        .collect::<Vec<_>>();
-    if costs.is_empty() {
-        return None;
-    }
-    costs.sort_by(|lhs, rhs| {
-        lhs.1
-            .partial_cmp(&rhs.1)
-            .expect("we should not have created NaNs")
-            .reverse()
-    });
-    Some(
-        costs
-            .into_iter()
-            .take(5)
-            .map(|(index, _)| &pull_requests[index])
-            .collect(),
-    )
+/// A format the merged changelog can be written out in.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum EmitFormat {
+    Output(OutputFormat),
+    /// The entries as records like `mergelog query --format json` prints.
+    Json,
 }
 
+/// Where to write the merged changelog in what format, as in `md=notes.md`.
 #[derive(Clone)]
-struct Link {
-    shorthand: String,
-    full: String,
+struct Emit {
+    format: EmitFormat,
+    /// The file to write to, or `None` for standard output.
+    path: Option<Utf8PathBuf>,
 }
 
-fn make_pull_request_link(
-    id: String,
-    link: String,
-    host: RepositoryHost,
-    repo_owner: &str,
-    repo_name: &str,
-) -> Link {
-    let full_link = match host {
-        RepositoryHost::GitHub => todo!(),
-        RepositoryHost::GitLab => {
-            format!(
-                "https://gitlab.com/{repo_owner}/{repo_name}/-/merge_requests/{id}"
-            )
-        }
-        RepositoryHost::Infer => unreachable!(),
-    };
-    Link {
-        shorthand: link,
-        full: full_link,
+impl FromStr for Emit {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (format, path) = s.split_once('=').unwrap_or((s, "-"));
+        let format = match format {
+            "json" => EmitFormat::Json,
+            other => EmitFormat::Output(other.parse().map_err(|_| miette!("Failed to parse '{other}' as an output format. Options include 'md', 'asciidoc', 'rst', 'text', 'debian', 'rpm', and 'json'"))?),
+        };
+        Ok(Self {
+            format,
+            path: (path != "-").then(|| Utf8PathBuf::from(path)),
+        })
     }
 }
 
-/// Determines the link for the changelog entry. If the entry name is not a
-/// number, it tries to guess from the pull requests and asks the user.
-fn resolve_changelog_pr_interactive(
-    name: &str,
-    contents: &str,
-    pull_requests: &[PullRequest],
-    repo_owner: &str,
-    repo_name: &str,
+/// Merges changelog files into a single changelog
+#[derive(FromArgs, ArgsInfo)]
+#[argh(
+    example = "Merge the changelogs in changes/ and print the result:\n$ {command_name} merge changes"
+)]
+#[argh(
+    example = "Create a changelog for the current branch:\n$ {command_name} new -s Fixed \"Fix a crash on empty input\""
+)]
+struct Opts {
+    /// link to the repository to resolve merge/pull requests at; omit to infer
+    /// from the current repo
+    #[argh(option, long = "repo")]
+    repo_url: Option<Url>,
+
+    /// git remote to infer the repository from; omit to use the one the
+    /// current branch pulls from, or else `upstream`, or else `origin`
+    #[argh(option)]
+    remote: Option<String>,
+
+    /// the repository host; omit to infer from the repo URL
+    #[argh(option, default = "RepositoryHost::Infer")]
     host: RepositoryHost,
-) -> Result<Link> {
-    if let Ok(id) = name.parse::<u64>() {
-        let link = if let Some(link) = pull_requests
-            .iter()
-            .find(|pr| pr.id == id)
+
+    /// base URL of the API of the repository host to send requests to; omit
+    /// to use https://gitlab.com/api/v4 or https://api.github.com
+    #[argh(option)]
+    api_url: Option<Url>,
+
+    /// never send API requests; read merge/pull requests from the merge
+    /// commits in the local history instead
+    #[argh(switch)]
+    offline: bool,
+
+    /// path to optional config file
+    #[argh(option)]
+    config: Option<Utf8PathBuf>,
+
+    /// never prompt; match changelogs to merge/pull requests automatically
+    /// and report how confident each match is
+    #[argh(switch)]
+    non_interactive: bool,
+
+    /// file to answer prompts from, one answer per line, instead of standard
+    /// input
+    #[argh(option)]
+    answers: Option<Utf8PathBuf>,
+
+    /// pick merge/pull requests for changelogs in a terminal UI instead of
+    /// answering prompts
+    #[argh(switch)]
+    tui: bool,
+
+    /// answer every prompt that has a default with it instead of asking, and
+    /// fail on those that have none
+    #[argh(switch)]
+    yes: bool,
+
+    /// write files in place instead of renaming a temporary file over them,
+    /// for filesystems that cannot rename atomically
+    #[argh(switch)]
+    no_atomic: bool,
+
+    /// also show how changelogs are read and which requests are sent; pass
+    /// twice (`-vv`) to show the responses too
+    #[argh(switch, short = 'v')]
+    verbose: u8,
+
+    /// show nothing but the output and errors
+    #[argh(switch, short = 'q')]
+    quiet: bool,
+
+    /// when to color the output: 'auto' (default), 'always', or 'never'
+    #[argh(option, default = "ColorChoice::Auto")]
+    color: ColorChoice,
+
+    /// also report problems as annotations on the merge/pull request diff:
+    /// 'ci' for whichever CI service is running mergelog, 'github', or
+    /// 'gitlab'
+    #[argh(option)]
+    annotate: Option<AnnotationFormat>,
+
+    #[argh(subcommand)]
+    command: Subcommand,
+}
+
+#[derive(FromArgs, ArgsInfo)]
+#[argh(subcommand)]
+enum Subcommand {
+    Merge(MergeOpts),
+    New(NewOpts),
+    Announce(AnnounceOpts),
+    Query(QueryOpts),
+    Release(ReleaseOpts),
+    Backfill(BackfillOpts),
+    Show(ShowOpts),
+    Combine(CombineOpts),
+    Lint(LintOpts),
+    Publish(PublishOpts),
+    Feed(FeedOpts),
+    Enforce(EnforceOpts),
+    MergeDriver(MergeDriverOpts),
+    Config(ConfigOpts),
+    Completions(CompletionsOpts),
+    Man(ManOpts),
+}
+
+impl Subcommand {
+    /// The changelog directory given on the command line, which the config is
+    /// searched for from.
+    fn changelog_directory(&self) -> Option<Utf8PathBuf> {
+        match self {
+            Self::Merge(opts) => opts.changelogs.iter().find_map(|path| {
+                if path.is_dir() {
+                    Some(path.clone())
+                } else if path.is_file() {
+                    path.parent().map(Utf8Path::to_path_buf)
+                } else {
+                    None
+                }
+            }),
+            Self::New(opts) => opts.directory.clone(),
+            Self::Query(opts) => opts.changelog_directory.clone(),
+            Self::Enforce(opts) => opts.directory.clone(),
+            Self::Announce(_)
+            | Self::Release(_)
+            | Self::Backfill(_)
+            | Self::Show(_)
+            | Self::Combine(_)
+            | Self::Lint(_)
+            | Self::Publish(_)
+            | Self::Feed(_)
+            | Self::MergeDriver(_)
+            | Self::Config(_)
+            | Self::Completions(_)
+            | Self::Man(_) => None,
+        }
+    }
+}
+
+/// Merges changelog files into a single changelog
+#[derive(FromArgs, ArgsInfo, Clone)]
+#[argh(subcommand, name = "merge")]
+#[argh(
+    example = "Print the merged changelogs in changes/:\n$ {command_name} changes"
+)]
+#[argh(
+    example = "Add them to CHANGELOG.md under Unreleased instead:\n$ {command_name} --update CHANGELOG.md changes"
+)]
+#[argh(
+    example = "Release them as 1.4.0 in the Keep a Changelog format:\n$ {command_name} --keep-a-changelog --version 1.4.0 changes"
+)]
+#[argh(
+    example = "List what went into milestone v1.4 by label instead:\n$ {command_name} --milestone v1.4"
+)]
+struct MergeOpts {
+    /// changelog sections in order
+    #[argh(option, short = 's')]
+    section: Vec<String>,
+
+    /// file to write the recommended semver bump to
+    #[argh(option)]
+    bump_output: Option<Utf8PathBuf>,
+
+    /// how to order entries within sections: 'by-id', 'by-merge-date',
+    /// 'by-title', 'alphabetical', or 'input-order'; overrides the config
+    #[argh(option)]
+    sort: Option<SortOrder>,
+
+    /// interactively pick entries to repeat in a leading highlights section
+    #[argh(switch)]
+    highlights: bool,
+
+    /// also merge changelogs in subdirectories
+    #[argh(switch)]
+    recursive: bool,
+
+    /// how to group entries: 'section' (default), or 'month' to put them
+    /// under the month their merge/pull request was merged in
+    #[argh(option, default = "Grouping::Section")]
+    group_by: Grouping,
+
+    /// follow each entry with an HTML comment naming the changelog it came
+    /// from and how its merge/pull request was found
+    #[argh(switch)]
+    provenance: bool,
+
+    /// follow the Keep a Changelog format: release headings, the standard
+    /// sections in their order, and compare links
+    #[argh(switch)]
+    keep_a_changelog: bool,
+
+    /// version to release the entries as with `--keep-a-changelog` or the
+    /// packaging formats; omit to put them under Unreleased
+    #[argh(option)]
+    version: Option<String>,
+
+    /// changelog whose newest release to propose the next version from, by
+    /// applying the recommended bump, when `--version` is omitted
+    #[argh(option)]
+    version_from_changelog: Option<Utf8PathBuf>,
+
+    /// changelog to add the entries to under its Unreleased section, instead
+    /// of printing them
+    #[argh(option)]
+    update: Option<Utf8PathBuf>,
+
+    /// format to write the merged changelog in: 'markdown' (default),
+    /// 'asciidoc', 'rst', 'text', or the packaging changelogs 'debian' and
+    /// 'rpm'
+    #[argh(option, default = "OutputFormat::Markup(MarkupFormat::Markdown)")]
+    format: OutputFormat,
+
+    /// format and file to write the merged changelog to, like
+    /// `md=CHANGELOG.md` or `json=notes.json`, where `-` is standard output,
+    /// instead of printing it; can be repeated
+    #[argh(option)]
+    emit: Vec<Emit>,
+
+    /// file with the expected output to compare with, e.g., a committed
+    /// changelog, failing with a diff if they differ; nothing is printed, nor
+    /// is the changelog written with `--update`
+    #[argh(option)]
+    check_against: Option<Utf8PathBuf>,
+
+    /// package from the `packages` config to merge the changelogs of into its
+    /// own changelog, instead of the given directories; can be repeated
+    #[argh(option)]
+    package: Vec<String>,
+
+    /// merge the changelogs of every package in the `packages` config into
+    /// their own changelogs
+    #[argh(switch)]
+    all_packages: bool,
+
+    /// what to do with symbolic links: 'follow' (default), 'skip', or
+    /// 'error'
+    #[argh(option, default = "SymlinkPolicy::Follow")]
+    symlinks: SymlinkPolicy,
+
+    /// read the changelog files to merge from standard input, one per line,
+    /// e.g., from `git diff --name-only`; other files are skipped
+    #[argh(switch)]
+    stdin_paths: bool,
+
+    /// milestone to list the merged merge/pull requests and closed issues of,
+    /// each under the section one of its labels names, instead of merging
+    /// changelogs
+    #[argh(option)]
+    milestone: Option<String>,
+
+    /// fail instead of merging if any content would be left out, e.g., under
+    /// a heading that is not one of the sections
+    #[argh(switch)]
+    strict: bool,
+
+    /// changelog files, directories containing them, or glob patterns like
+    /// 'changes/*/' matching directories
+    #[argh(positional)]
+    changelogs: Vec<Utf8PathBuf>,
+}
+
+impl MergeOpts {
+    /// Whether the merged changelog is written out as the changelog of a
+    /// distribution package, which needs a version to release it as.
+    fn releases_package(&self) -> bool {
+        matches!(self.format, OutputFormat::Package(_))
+            || self.emit.iter().any(|emit| {
+                matches!(
+                    emit.format,
+                    EmitFormat::Output(OutputFormat::Package(_))
+                )
+            })
+    }
+}
+
+/// Creates a changelog file for the current change
+#[derive(FromArgs, ArgsInfo)]
+#[argh(subcommand, name = "new")]
+#[argh(
+    example = "Describe the change on the current branch under Fixed:\n$ {command_name} -s Fixed \"Fix a crash on empty input\""
+)]
+#[argh(
+    example = "Name the changelog after merge/pull request 42:\n$ {command_name} --pr 42 \"Support GitLab subgroups\""
+)]
+struct NewOpts {
+    /// section to put the entry under; omit to use the first configured
+    /// section
+    #[argh(option, short = 's')]
+    section: Option<String>,
+
+    /// merge/pull request id to name the changelog after; omit to look it up
+    /// from the current branch
+    #[argh(option)]
+    pr: Option<u64>,
+
+    /// directory to create the changelog in; omit to use the `directory`
+    /// config option
+    #[argh(option)]
+    directory: Option<Utf8PathBuf>,
+
+    /// short description of the change
+    #[argh(positional)]
+    description: String,
+}
+
+/// Posts release notes to the webhooks configured under `announce`
+#[derive(FromArgs, ArgsInfo)]
+#[argh(subcommand, name = "announce")]
+#[argh(
+    example = "Announce the merged changelogs:\n$ mergelog merge changes | {command_name}"
+)]
+#[argh(
+    example = "Show what would be sent for notes.md:\n$ {command_name} --dry-run notes.md"
+)]
+struct AnnounceOpts {
+    /// print the requests instead of sending them
+    #[argh(switch)]
+    dry_run: bool,
+
+    /// markdown file with the notes, e.g., as printed by `mergelog merge`;
+    /// omit to read standard input
+    #[argh(positional)]
+    notes: Option<Utf8PathBuf>,
+}
+
+/// Lists changelog entries, released or not, that match the given filters
+#[derive(FromArgs, ArgsInfo)]
+#[argh(subcommand, name = "query")]
+#[argh(
+    example = "List the fixes by octocat since 1.2.0:\n$ {command_name} -s Fixed --author octocat --since 1.2.0 --changelog CHANGELOG.md"
+)]
+#[argh(
+    example = "List the unreleased entries as JSON:\n$ {command_name} --format json changes"
+)]
+struct QueryOpts {
+    /// only list entries in this section; can be repeated
+    #[argh(option, short = 's')]
+    section: Vec<String>,
+
+    /// only list entries from merge/pull requests opened by this user
+    #[argh(option)]
+    author: Option<String>,
+
+    /// only list entries from merge/pull requests with this label; can be
+    /// repeated to require several
+    #[argh(option)]
+    label: Vec<String>,
+
+    /// only list entries released after this version, or unreleased
+    #[argh(option)]
+    since: Option<String>,
+
+    /// only list entries released in or before this version
+    #[argh(option)]
+    until: Option<String>,
+
+    /// how to print the entries: 'text' (default) or 'json'
+    #[argh(option, default = "QueryFormat::Text")]
+    format: QueryFormat,
+
+    /// existing changelog to read released entries from
+    #[argh(option)]
+    changelog: Option<Utf8PathBuf>,
+
+    /// also read changelogs in subdirectories
+    #[argh(switch)]
+    recursive: bool,
+
+    /// directory containing unreleased changelogs; omit to use the
+    /// `directory` config option
+    #[argh(positional)]
+    changelog_directory: Option<Utf8PathBuf>,
+}
+
+/// Turns the Unreleased section of a changelog into a release
+#[derive(FromArgs, ArgsInfo)]
+#[argh(subcommand, name = "release")]
+#[argh(
+    example = "Release the Unreleased section as 1.4.0:\n$ {command_name} 1.4.0"
+)]
+#[argh(
+    example = "Release 1.4.0 in Cargo.toml too, then commit and tag it:\n$ {command_name} 1.4.0 --bump-cargo --commit --tag"
+)]
+struct ReleaseOpts {
+    /// changelog to release; defaults to CHANGELOG.md
+    #[argh(option, default = "Utf8PathBuf::from(\"CHANGELOG.md\")")]
+    changelog: Utf8PathBuf,
+
+    /// also set the package version in Cargo.toml to the release
+    #[argh(switch)]
+    bump_cargo: bool,
+
+    /// commit the changed files with the `release.commit-message` config
+    /// option as the message
+    #[argh(switch)]
+    commit: bool,
+
+    /// create an annotated tag for the release, with its notes as the
+    /// message, on the release commit or else on HEAD
+    #[argh(switch)]
+    tag: bool,
+
+    /// version being released, e.g., 1.4.0
+    #[argh(positional)]
+    version: String,
+}
+
+/// Generates the changelog of releases that are already tagged from the
+/// merge/pull requests merged between their tags
+#[derive(FromArgs, ArgsInfo)]
+#[argh(subcommand, name = "backfill")]
+#[argh(
+    example = "Write a changelog for every release from v0.1.0 to v1.3.0:\n$ {command_name} --from v0.1.0 --to v1.3.0 --output CHANGELOG.md"
+)]
+#[argh(
+    example = "Put merge requests without a section label under Changed:\n$ {command_name} --default-section Changed"
+)]
+struct BackfillOpts {
+    /// tag of the oldest release to include; defaults to the first tag
+    #[argh(option)]
+    from: Option<String>,
+
+    /// tag of the newest release to include; defaults to the latest tag
+    #[argh(option)]
+    to: Option<String>,
+
+    /// changelog sections in order; defaults to those in the config, or else
+    /// to those of Keep a Changelog
+    #[argh(option, short = 's')]
+    section: Vec<String>,
+
+    /// section for merge/pull requests without a label naming one; without
+    /// it, they are left out
+    #[argh(option)]
+    default_section: Option<String>,
+
+    /// file to write the changelog to instead of printing it
+    #[argh(option)]
+    output: Option<Utf8PathBuf>,
+}
+
+/// Prints the notes of a release in the changelog
+#[derive(FromArgs, ArgsInfo)]
+#[argh(subcommand, name = "show")]
+#[argh(example = "Print the notes of 1.4.0:\n$ {command_name} 1.4.0")]
+#[argh(
+    example = "Save the notes of the latest release for a release job:\n$ {command_name} latest > notes.md"
+)]
+struct ShowOpts {
+    /// changelog to take the notes from; defaults to CHANGELOG.md
+    #[argh(option, default = "Utf8PathBuf::from(\"CHANGELOG.md\")")]
+    changelog: Utf8PathBuf,
+
+    /// version of the release, with or without a leading `v`, or 'latest'
+    #[argh(positional)]
+    version: String,
+}
+
+/// Combines two changelogs release by release and section by section
+#[derive(FromArgs, ArgsInfo)]
+#[argh(subcommand, name = "combine")]
+#[argh(
+    example = "Bring the releases of a fork back into CHANGELOG.md:\n$ {command_name} CHANGELOG.md fork/CHANGELOG.md --output CHANGELOG.md"
+)]
+struct CombineOpts {
+    /// file to write the combined changelog to instead of printing it
+    #[argh(option)]
+    output: Option<Utf8PathBuf>,
+
+    /// changelog to add to, whose title, entries, and links are kept as they
+    /// are
+    #[argh(positional)]
+    changelog: Utf8PathBuf,
+
+    /// changelog to add the releases, sections, and entries that the first
+    /// lacks from
+    #[argh(positional)]
+    other: Utf8PathBuf,
+}
+
+/// Checks a changelog for duplicate versions, out-of-order dates, unknown
+/// sections, broken links, and entries without links
+#[derive(FromArgs, ArgsInfo)]
+#[argh(subcommand, name = "lint")]
+#[argh(example = "Check CHANGELOG.md:\n$ {command_name}")]
+#[argh(
+    example = "Check the changelog of a package against the config of the repository:\n$ {command_name} packages/core/CHANGELOG.md"
+)]
+struct LintOpts {
+    /// changelog to check; defaults to CHANGELOG.md
+    #[argh(positional, default = "Utf8PathBuf::from(\"CHANGELOG.md\")")]
+    changelog: Utf8PathBuf,
+}
+
+/// Creates or updates the release for a tag on the repository host, with the
+/// notes of its version in the changelog
+#[derive(FromArgs, ArgsInfo)]
+#[argh(subcommand, name = "publish")]
+#[argh(
+    example = "Publish the notes of 1.4.0 as the release for tag v1.4.0:\n$ GITLAB_TOKEN=<token> {command_name} --tag v1.4.0"
+)]
+#[argh(
+    example = "Publish it on GitHub instead:\n$ GITHUB_TOKEN=<token> {command_name} --tag v1.4.0"
+)]
+struct PublishOpts {
+    /// tag to publish the release for, e.g., v1.4.0
+    #[argh(option)]
+    tag: String,
+
+    /// changelog to take the notes from; defaults to CHANGELOG.md
+    #[argh(option, default = "Utf8PathBuf::from(\"CHANGELOG.md\")")]
+    changelog: Utf8PathBuf,
+
+    /// file to upload and link in the release, e.g., a binary or its
+    /// checksums, besides those under `publish.assets` in the config
+    #[argh(option)]
+    asset: Vec<Utf8PathBuf>,
+
+    /// print the requests instead of sending them
+    #[argh(switch)]
+    dry_run: bool,
+}
+
+/// Prints an Atom feed of the releases in a changelog
+#[derive(FromArgs, ArgsInfo)]
+#[argh(subcommand, name = "feed")]
+#[argh(
+    example = "Write a feed of the releases in CHANGELOG.md for a website:\n$ {command_name} --url https://example.com/releases.xml --output public/releases.xml"
+)]
+struct FeedOpts {
+    /// changelog to read the releases from; defaults to CHANGELOG.md
+    #[argh(option, default = "Utf8PathBuf::from(\"CHANGELOG.md\")")]
+    changelog: Utf8PathBuf,
+
+    /// file to write the feed to instead of printing it
+    #[argh(option)]
+    output: Option<Utf8PathBuf>,
+
+    /// URL the feed is published at, which also identifies it to readers
+    #[argh(option)]
+    url: Option<String>,
+
+    /// title of the feed; defaults to the repository name followed by
+    /// "releases"
+    #[argh(option)]
+    title: Option<String>,
+
+    /// how many of the latest releases to include; defaults to 20
+    #[argh(option, default = "20")]
+    limit: usize,
+}
+
+/// Fails unless a merge/pull request adds a changelog; for use as a CI check
+#[derive(FromArgs, ArgsInfo)]
+#[argh(subcommand, name = "enforce")]
+#[argh(
+    example = "Check that merge request !1234 adds a changelog:\n$ {command_name} --pr 1234"
+)]
+#[argh(
+    example = "Check the merge request of a GitLab CI pipeline:\n$ {command_name} --pr \"$CI_MERGE_REQUEST_IID\""
+)]
+struct EnforceOpts {
+    /// merge/pull request id to check
+    #[argh(option)]
+    pr: u64,
+
+    /// directory the changelog must be added to; omit to use the `directory`
+    /// config option
+    #[argh(option)]
+    directory: Option<Utf8PathBuf>,
+
+    /// label that exempts a merge/pull request from needing a changelog;
+    /// defaults to skip-changelog
+    #[argh(option, default = "String::from(\"skip-changelog\")")]
+    skip_label: String,
+}
+
+/// Resolves git conflicts in a changelog; for use as a merge driver
+#[derive(FromArgs, ArgsInfo)]
+#[argh(subcommand, name = "merge-driver")]
+#[argh(
+    example = "Register it with git:\n$ git config merge.mergelog.driver \"{command_name} %O %A %B %P\""
+)]
+struct MergeDriverOpts {
+    /// the common ancestor's version (%O)
+    #[argh(positional)]
+    base: Utf8PathBuf,
+
+    /// our version, which is replaced by the merged one (%A)
+    #[argh(positional)]
+    ours: Utf8PathBuf,
+
+    /// their version (%B)
+    #[argh(positional)]
+    theirs: Utf8PathBuf,
+
+    /// the path of the changelog in the repository, for messages (%P)
+    #[argh(positional)]
+    path: Option<Utf8PathBuf>,
+}
+
+/// Inspects the config
+#[derive(FromArgs, ArgsInfo)]
+#[argh(subcommand, name = "config")]
+struct ConfigOpts {
+    #[argh(subcommand)]
+    command: ConfigSubcommand,
+}
+
+#[derive(FromArgs, ArgsInfo)]
+#[argh(subcommand)]
+enum ConfigSubcommand {
+    Validate(ValidateOpts),
+    Sections(SectionsOpts),
+}
+
+/// Checks the config for unknown keys, unknown placeholders, and conflicting
+/// options
+#[derive(FromArgs, ArgsInfo)]
+#[argh(subcommand, name = "validate")]
+#[argh(example = "Check the config that mergelog would use:\n$ {command_name}")]
+#[argh(
+    example = "Check the config in a pyproject.toml:\n$ {command_name} pyproject.toml"
+)]
+struct ValidateOpts {
+    /// config file to check; omit to check the one mergelog would use
+    #[argh(positional)]
+    path: Option<Utf8PathBuf>,
+}
+
+/// Lists the sections in the config, one per line
+#[derive(FromArgs, ArgsInfo)]
+#[argh(subcommand, name = "sections")]
+struct SectionsOpts {}
+
+/// Prints a completion script for a shell
+#[derive(FromArgs, ArgsInfo)]
+#[argh(subcommand, name = "completions")]
+#[argh(
+    example = "Install completions for fish:\n$ {command_name} fish > ~/.config/fish/completions/mergelog.fish"
+)]
+struct CompletionsOpts {
+    /// the shell: 'bash', 'zsh', 'fish', or 'powershell'
+    #[argh(positional)]
+    shell: completions::Shell,
+}
+
+/// Prints the man page
+#[derive(FromArgs, ArgsInfo)]
+#[argh(subcommand, name = "man")]
+#[argh(
+    example = "Install the man page:\n$ {command_name} > ~/.local/share/man/man1/mergelog.1"
+)]
+struct ManOpts {}
+
+fn default_config_format() -> String {
+    "{item} ({link_short})".into()
+}
+
+fn default_config_heading() -> String {
+    "{name}".into()
+}
+
+fn default_highlights_section() -> String {
+    "Highlights".into()
+}
+
+fn default_fragment_patterns() -> Vec<String> {
+    vec!["*.md".into(), "*.markdown".into(), "*.txt".into()]
+}
+
+fn default_major_sections() -> Vec<String> {
+    vec!["Removed".into()]
+}
+
+fn default_minor_sections() -> Vec<String> {
+    vec!["Added".into(), "Changed".into(), "Deprecated".into()]
+}
+
+#[derive(Deserialize)]
+struct BumpConfig {
+    #[serde(default = "default_major_sections")]
+    major: Vec<String>,
+    #[serde(default = "default_minor_sections")]
+    minor: Vec<String>,
+}
+
+impl Default for BumpConfig {
+    fn default() -> Self {
+        Self {
+            major: default_major_sections(),
+            minor: default_minor_sections(),
+        }
+    }
+}
+
+fn default_breaking_heading() -> String {
+    "Breaking".into()
+}
+
+fn default_breaking_prefix() -> String {
+    "**BREAKING**".into()
+}
+
+fn default_breaking_section() -> String {
+    "Breaking Changes".into()
+}
+
+fn default_breaking_badge() -> String {
+    "💥".into()
+}
+
+/// What `--format debian` and `--format rpm` release the entries as.
+#[derive(Deserialize)]
+struct PackagingConfig {
+    /// The name of the package, or else of the repository.
+    #[serde(default)]
+    name: Option<String>,
+    /// Like `Jane Doe <jane@example.com>`, or else the git author.
+    #[serde(default)]
+    maintainer: Option<String>,
+    #[serde(default = "default_packaging_revision")]
+    revision: String,
+    #[serde(default = "default_packaging_distribution")]
+    distribution: String,
+    #[serde(default = "default_packaging_urgency")]
+    urgency: String,
+}
+
+impl Default for PackagingConfig {
+    fn default() -> Self {
+        Self {
+            name: None,
+            maintainer: None,
+            revision: default_packaging_revision(),
+            distribution: default_packaging_distribution(),
+            urgency: default_packaging_urgency(),
+        }
+    }
+}
+
+fn default_packaging_revision() -> String {
+    "1".into()
+}
+
+fn default_packaging_distribution() -> String {
+    "unstable".into()
+}
+
+fn default_packaging_urgency() -> String {
+    "medium".into()
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+enum BreakingMode {
+    /// Moves breaking entries into a dedicated section at the top.
+    #[default]
+    Hoist,
+    /// Keeps breaking entries in place and marks them with a badge.
+    Annotate,
+}
+
+#[derive(Deserialize)]
+struct BreakingConfig {
+    #[serde(default = "default_breaking_heading")]
+    heading: String,
+    #[serde(default = "default_breaking_prefix")]
+    prefix: String,
+    #[serde(default)]
+    mode: BreakingMode,
+    #[serde(default = "default_breaking_section")]
+    section: String,
+    #[serde(default = "default_breaking_badge")]
+    badge: String,
+}
+
+impl Default for BreakingConfig {
+    fn default() -> Self {
+        Self {
+            heading: default_breaking_heading(),
+            prefix: default_breaking_prefix(),
+            mode: BreakingMode::default(),
+            section: default_breaking_section(),
+            badge: default_breaking_badge(),
+        }
+    }
+}
+
+/// How entries within a section are ordered.
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "kebab-case")]
+enum SortOrder {
+    /// By link shorthand, in natural order.
+    #[default]
+    ById,
+    /// By when the merge/pull request was merged, oldest first.
+    ByMergeDate,
+    /// By merge/pull request title.
+    ByTitle,
+    /// By entry text.
+    Alphabetical,
+    /// In the order the changelogs were read.
+    InputOrder,
+}
+
+impl FromStr for SortOrder {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "by-id" => Ok(Self::ById),
+            "by-merge-date" => Ok(Self::ByMergeDate),
+            "by-title" => Ok(Self::ByTitle),
+            "alphabetical" => Ok(Self::Alphabetical),
+            "input-order" => Ok(Self::InputOrder),
+            other => Err(miette!("Failed to parse '{other}' as a sort order. Options include 'by-id', 'by-merge-date', 'by-title', 'alphabetical', and 'input-order'"))
+        }
+    }
+}
+
+const MONTH_NAMES: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// The year and month of a merge date like `2024-10-03T12:00:00Z`.
+fn merge_month(merged_at: &str) -> Option<(u32, u32)> {
+    let (year, rest) = merged_at.split_once('-')?;
+    let month = rest.get(..2)?.parse().ok()?;
+    (1..=12)
+        .contains(&month)
+        .then_some((year.parse().ok()?, month))
+}
+
+/// Sorts entries stably, so ties keep the order the changelogs were read in.
+fn sort_entries(entries: &mut [Entry], sort: SortOrder) {
+    match sort {
+        SortOrder::ById => entries.sort_by(|lhs, rhs| {
+            natural_cmp(&lhs.links[0].shorthand, &rhs.links[0].shorthand)
+        }),
+        SortOrder::ByMergeDate => entries.sort_by(|lhs, rhs| {
+            let merged_at = |entry: &Entry| {
+                entry
+                    .pull_request
+                    .as_ref()
+                    .and_then(|pull_request| pull_request.merged_at.clone())
+            };
+            match (merged_at(lhs), merged_at(rhs)) {
+                (Some(lhs), Some(rhs)) => lhs.cmp(&rhs),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            }
+        }),
+        SortOrder::ByTitle => entries.sort_by(|lhs, rhs| {
+            let title = |entry: &Entry| {
+                entry
+                    .pull_request
+                    .as_ref()
+                    .map(|pull_request| pull_request.title.clone())
+                    .unwrap_or_else(|| entry.item.clone())
+            };
+            natural_cmp(&title(lhs), &title(rhs))
+        }),
+        SortOrder::Alphabetical => {
+            entries.sort_by(|lhs, rhs| natural_cmp(&lhs.item, &rhs.item))
+        }
+        SortOrder::InputOrder => {}
+    }
+}
+
+/// What to do with near-identical entries in a section, e.g., from a
+/// cherry-picked fix.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+enum DuplicateMode {
+    /// Collapses them into the first one, which then links to all of them.
+    #[default]
+    Merge,
+    /// Keeps all of them but warns about each duplicate.
+    Warn,
+    /// Keeps all of them silently.
+    Keep,
+}
+
+/// What to do with paragraphs and code blocks in changelogs that are not in
+/// a list item.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+enum ProseMode {
+    /// Leaves them out with a warning.
+    #[default]
+    Drop,
+    /// Makes each paragraph an entry and adds each code block to the entry
+    /// before it.
+    Entries,
+}
+
+/// How links to merge/pull requests are written in entries.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+enum LinkStyle {
+    /// `{link_short}` expands to the bare shorthand, e.g., `!30`.
+    #[default]
+    Inline,
+    /// `{link_short}` expands to a reference link, e.g., `[!30]`, whose
+    /// definition is printed at the end.
+    Reference,
+}
+
+/// Escapes the characters that would end or break a markdown link label.
+fn escape_link_label(label: &str) -> String {
+    let mut escaped = String::with_capacity(label.len());
+    for c in label.chars() {
+        if matches!(c, '\\' | '[' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// A case transformation applied to section names in headings.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+enum HeadingCase {
+    Upper,
+    Lower,
+    Title,
+}
+
+impl HeadingCase {
+    fn apply(self, text: &str) -> String {
+        match self {
+            Self::Upper => text.to_uppercase(),
+            Self::Lower => text.to_lowercase(),
+            Self::Title => text
+                .split(' ')
+                .map(|word| {
+                    let mut chars = word.chars();
+                    chars
+                        .next()
+                        .map(|first| {
+                            first.to_uppercase().chain(chars).collect()
+                        })
+                        .unwrap_or_default()
+                })
+                .collect::<Vec<String>>()
+                .join(" "),
+        }
+    }
+}
+
+/// What the `emoji` of a section is put before.
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+enum Decoration {
+    /// Its heading, like `## ✨ Added`.
+    #[default]
+    Heading,
+    /// Each of its entries, like `- ✨ Add traits (!30)`.
+    Entries,
+}
+
+/// Where the order of the sections in the output comes from.
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+enum SectionOrder {
+    /// The order of the `sections` config option.
+    Config,
+    /// The order they are passed in with `-s`, or else that of the config.
+    #[default]
+    Cli,
+    /// The order they first appear in across the changelogs.
+    Fragment,
+}
+
+/// What happens to a listed section that has no entries.
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+enum EmptySection {
+    /// Leave it out.
+    #[default]
+    Omit,
+    /// Write its heading with a "No changes" entry.
+    Placeholder,
+    /// Fail, for sections that every release should have.
+    Error,
+}
+
+/// The level of a markdown heading, from 1 for `#` to 6 for `######`.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(try_from = "u8")]
+struct HeadingLevel(u8);
+
+impl TryFrom<u8> for HeadingLevel {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        if (1..=6).contains(&value) {
+            Ok(Self(value))
+        } else {
+            Err(format!(
+                "heading level must be between 1 and 6, but is {value}"
+            ))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SectionConfigRepr {
+    Name(String),
+    Table {
+        name: String,
+        #[serde(default)]
+        level: Option<HeadingLevel>,
+        #[serde(default, alias = "prefix")]
+        emoji: Option<String>,
+        #[serde(default)]
+        decorate: Option<Decoration>,
+        #[serde(default)]
+        empty: Option<EmptySection>,
+        #[serde(default)]
+        sort: Option<SortOrder>,
+        #[serde(default)]
+        format: Option<String>,
+        #[serde(default)]
+        heading: Option<String>,
+        #[serde(default, rename = "heading-case")]
+        heading_case: Option<HeadingCase>,
+    },
+}
+
+/// A section listed in the config, either by name alone or as a table of
+/// settings that override the global ones.
+#[derive(Deserialize)]
+#[serde(from = "SectionConfigRepr")]
+struct SectionConfig {
+    name: String,
+    level: Option<HeadingLevel>,
+    /// Text, usually an emoji, to put before its heading or entries.
+    emoji: Option<String>,
+    decorate: Option<Decoration>,
+    empty: Option<EmptySection>,
+    sort: Option<SortOrder>,
+    format: Option<String>,
+    heading: Option<String>,
+    heading_case: Option<HeadingCase>,
+}
+
+impl From<SectionConfigRepr> for SectionConfig {
+    fn from(value: SectionConfigRepr) -> Self {
+        match value {
+            SectionConfigRepr::Name(name) => Self {
+                name,
+                level: None,
+                emoji: None,
+                decorate: None,
+                empty: None,
+                sort: None,
+                format: None,
+                heading: None,
+                heading_case: None,
+            },
+            SectionConfigRepr::Table {
+                name,
+                level,
+                emoji,
+                decorate,
+                empty,
+                sort,
+                format,
+                heading,
+                heading_case,
+            } => Self {
+                name,
+                level,
+                emoji,
+                decorate,
+                empty,
+                sort,
+                format,
+                heading,
+                heading_case,
+            },
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Config {
+    #[serde(default)]
+    sections: Vec<SectionConfig>,
+    #[serde(default)]
+    directory: Option<Utf8PathBuf>,
+    #[serde(default = "default_config_format")]
+    format: String,
+    #[serde(default = "default_config_heading")]
+    heading: String,
+    #[serde(default, rename = "heading-case")]
+    heading_case: Option<HeadingCase>,
+    #[serde(default, rename = "heading-level")]
+    heading_level: Option<HeadingLevel>,
+    #[serde(default)]
+    decorate: Decoration,
+    #[serde(default)]
+    empty: EmptySection,
+    #[serde(default, rename = "section-order")]
+    section_order: SectionOrder,
+    #[serde(default)]
+    sort: SortOrder,
+    #[serde(default, rename = "short-links")]
+    short_links: bool,
+    #[serde(default, rename = "link-style")]
+    link_style: LinkStyle,
+    /// Where links to merge/pull requests point instead of the repository
+    /// host, e.g., `https://jira.example.com/browse/{id}`.
+    #[serde(default, rename = "link-template")]
+    link_template: Option<String>,
+    /// Like `link-template`, but only for the repository host it is keyed by,
+    /// e.g., `gitlab`, which it takes precedence for.
+    #[serde(default, rename = "link-templates")]
+    link_templates: HashMap<String, String>,
+    #[serde(default = "default_fragment_patterns")]
+    fragments: Vec<String>,
+    #[serde(default)]
+    aliases: HashMap<String, String>,
+    /// Sections to put merge/pull requests and issues in by their labels,
+    /// e.g., `"kind/bug" = "Fixed"`.
+    #[serde(default)]
+    labels: HashMap<String, String>,
+    #[serde(default = "default_highlights_section")]
+    highlights: String,
+    #[serde(default)]
+    bump: BumpConfig,
+    #[serde(default)]
+    breaking: BreakingConfig,
+    #[serde(default)]
+    duplicates: DuplicateMode,
+    #[serde(default)]
+    announce: Vec<AnnounceTarget>,
+    #[serde(default, rename = "release-config")]
+    release_config: Option<Utf8PathBuf>,
+    #[serde(default)]
+    release: ReleaseConfig,
+    #[serde(default)]
+    publish: PublishConfig,
+    #[serde(default)]
+    hooks: HooksConfig,
+    /// Files whose version `mergelog release` sets, keyed by their path.
+    #[serde(default, rename = "version-files")]
+    version_files: BTreeMap<Utf8PathBuf, VersionFileConfig>,
+    #[serde(default)]
+    subsections: Option<SubsectionConfig>,
+    #[serde(default, rename = "new-contributors")]
+    new_contributors: Option<NewContributorsConfig>,
+    #[serde(default)]
+    list: ListConfig,
+    #[serde(default)]
+    packaging: PackagingConfig,
+    #[serde(default)]
+    packages: BTreeMap<String, PackageConfig>,
+    #[serde(default, rename = "stale-after")]
+    stale_after: Option<u64>,
+    #[serde(default)]
+    exclude: Vec<String>,
+    /// Labels of merge/pull requests to leave out, e.g., `dependencies`.
+    #[serde(default, rename = "exclude-labels")]
+    exclude_labels: Vec<String>,
+    /// Regular expression matching titles of merge/pull requests to leave
+    /// out, e.g., `^chore:`.
+    #[serde(default, rename = "exclude-title-pattern")]
+    exclude_title_pattern: Option<String>,
+    #[serde(
+        default,
+        rename = "preserve-formatting",
+        alias = "preserve_formatting"
+    )]
+    preserve_formatting: bool,
+    #[serde(default)]
+    prose: ProseMode,
+}
+
+impl Config {
+    fn section_names(&self) -> Vec<String> {
+        self.sections
+            .iter()
+            .map(|section| section.name.clone())
+            .collect()
+    }
+
+    fn section(&self, name: &str) -> Option<&SectionConfig> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+
+    /// The template of links to merge/pull requests on `host`, if they do
+    /// not point at the host itself.
+    fn link_template(&self, host: RepositoryHost) -> Option<&str> {
+        let host = match host {
+            RepositoryHost::GitHub => "github",
+            RepositoryHost::GitLab => "gitlab",
+            RepositoryHost::Infer => unreachable!(),
+        };
+        self.link_templates
+            .get(host)
+            .or(self.link_template.as_ref())
+            .map(String::as_str)
+    }
+
+    /// What happens to section `name` if it has no entries.
+    fn empty_section(&self, name: &str) -> EmptySection {
+        self.section(name)
+            .and_then(|section| section.empty)
+            .unwrap_or(self.empty)
+    }
+
+    /// Whether the format or that of any section uses `placeholder`, e.g.,
+    /// `{issues}`.
+    fn formats_use(&self, placeholder: &str) -> bool {
+        self.sections
+            .iter()
+            .filter_map(|section| section.format.as_ref())
+            .chain([&self.format])
+            .any(|format| format.contains(placeholder))
+    }
+
+    /// Makes the relative paths in the config relative to the current
+    /// directory instead of `directory`, where it was found, except under
+    /// the top-level keys in `unchanged`.
+    fn resolve_paths(&mut self, directory: &Utf8Path, unchanged: &[&str]) {
+        let resolve = |key: &str, path: &mut Utf8PathBuf| {
+            if path.is_relative() && !unchanged.contains(&key) {
+                *path = directory.join(&*path);
+            }
+        };
+        if let Some(path) = &mut self.directory {
+            resolve("directory", path);
+        }
+        if let Some(path) = &mut self.release_config {
+            resolve("release-config", path);
+        }
+        for package in self.packages.values_mut() {
+            resolve("packages", &mut package.directory);
+            resolve("packages", &mut package.changelog);
+        }
+        for path in &mut self.publish.assets {
+            resolve("publish", path);
+        }
+        self.version_files = std::mem::take(&mut self.version_files)
+            .into_iter()
+            .map(|(mut path, file)| {
+                resolve("version-files", &mut path);
+                (path, file)
+            })
+            .collect();
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            sections: vec![],
+            directory: None,
+            format: default_config_format(),
+            heading: default_config_heading(),
+            heading_case: None,
+            heading_level: None,
+            decorate: Decoration::default(),
+            empty: EmptySection::default(),
+            section_order: SectionOrder::default(),
+            sort: SortOrder::default(),
+            short_links: false,
+            link_style: LinkStyle::default(),
+            link_template: None,
+            link_templates: HashMap::new(),
+            fragments: default_fragment_patterns(),
+            aliases: HashMap::new(),
+            labels: HashMap::new(),
+            highlights: default_highlights_section(),
+            bump: BumpConfig::default(),
+            breaking: BreakingConfig::default(),
+            duplicates: DuplicateMode::default(),
+            announce: vec![],
+            release_config: None,
+            release: ReleaseConfig::default(),
+            publish: PublishConfig::default(),
+            hooks: HooksConfig::default(),
+            version_files: BTreeMap::new(),
+            subsections: None,
+            new_contributors: None,
+            list: ListConfig::default(),
+            packaging: PackagingConfig::default(),
+            packages: BTreeMap::new(),
+            stale_after: None,
+            exclude: Vec::new(),
+            exclude_labels: vec![],
+            exclude_title_pattern: None,
+            preserve_formatting: false,
+            prose: ProseMode::default(),
+        }
+    }
+}
+
+/// A package in a workspace that keeps its own changelogs and changelog.
+#[derive(Deserialize)]
+struct PackageConfig {
+    /// Where its changelogs are.
+    directory: Utf8PathBuf,
+    /// Where its entries are added under Unreleased.
+    changelog: Utf8PathBuf,
+}
+
+/// Splits sections into subsections by the labels in one namespace, e.g.,
+/// `area/cli` and `area/parser` for the prefix `area/`.
+#[derive(Deserialize)]
+struct SubsectionConfig {
+    #[serde(rename = "label-prefix")]
+    label_prefix: String,
+    /// Headings for label suffixes, which are otherwise title-cased.
+    #[serde(default)]
+    names: HashMap<String, String>,
+}
+
+impl SubsectionConfig {
+    /// The subsection for a merge/pull request, from the first of its labels
+    /// in the namespace.
+    fn heading(&self, pull_request: &PullRequest) -> Option<String> {
+        let suffix = pull_request
+            .labels
+            .iter()
+            .find_map(|label| label.strip_prefix(&self.label_prefix))
+            .filter(|suffix| !suffix.is_empty())?;
+        Some(self.names.get(suffix).cloned().unwrap_or_else(|| {
+            HeadingCase::Title.apply(&suffix.replace(['-', '_'], " "))
+        }))
+    }
+}
+
+/// How `mergelog release --commit` records a release in git.
+#[derive(Deserialize)]
+struct ReleaseConfig {
+    /// The message of the release commit, which may use `{version}` and
+    /// `{tag}`.
+    #[serde(
+        default = "default_release_commit_message",
+        rename = "commit-message"
+    )]
+    commit_message: String,
+}
+
+impl Default for ReleaseConfig {
+    fn default() -> Self {
+        Self {
+            commit_message: default_release_commit_message(),
+        }
+    }
+}
+
+fn default_release_commit_message() -> String {
+    "Release {version}".into()
+}
+
+/// What `mergelog publish` links in a release besides its notes.
+#[derive(Deserialize, Default)]
+struct PublishConfig {
+    /// Files to upload and link, e.g., `dist/checksums.txt`.
+    #[serde(default)]
+    assets: Vec<Utf8PathBuf>,
+    /// URLs to link, keyed by the name of the link.
+    #[serde(default)]
+    links: BTreeMap<String, String>,
+}
+
+/// Where the version is in a file listed under `version-files`: at `key` of a
+/// TOML file, in the first group of `pattern`, or else the whole file.
+#[derive(Deserialize)]
+struct VersionFileConfig {
+    /// A dotted key, e.g., `package.version`.
+    #[serde(default)]
+    key: Option<String>,
+    /// A regular expression, e.g., `__version__ = "(.*)"`.
+    #[serde(default)]
+    pattern: Option<String>,
+}
+
+/// Marks the entries of merge/pull requests that are the first of their
+/// author's to be merged, and lists those authors in a section of their own.
+#[derive(Deserialize)]
+struct NewContributorsConfig {
+    /// What `{new_contributor}` in the format is for such entries.
+    #[serde(default)]
+    badge: Option<String>,
+    /// The heading of a section listing the new contributors after all other
+    /// sections.
+    #[serde(default)]
+    section: Option<String>,
+}
+
+/// The marker that list items are written out with.
+#[derive(Deserialize, Clone, Copy, Default)]
+enum Bullet {
+    #[default]
+    #[serde(rename = "-")]
+    Dash,
+    #[serde(rename = "*")]
+    Asterisk,
+    #[serde(rename = "+")]
+    Plus,
+}
+
+impl Bullet {
+    fn as_char(self) -> char {
+        match self {
+            Self::Dash => '-',
+            Self::Asterisk => '*',
+            Self::Plus => '+',
+        }
+    }
+}
+
+/// How many spaces each level of nested list items is indented by. At least
+/// the width of a list marker and its space, and less than what would make
+/// the next level an indented code block.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(try_from = "u8")]
+struct ListIndent(u8);
+
+impl TryFrom<u8> for ListIndent {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        if (2..=5).contains(&value) {
+            Ok(Self(value))
+        } else {
+            Err(format!(
+                "list indentation must be between 2 and 5, but is {value}"
+            ))
+        }
+    }
+}
+
+impl Default for ListIndent {
+    fn default() -> Self {
+        Self(2)
+    }
+}
+
+/// How entries are written out as markdown list items.
+#[derive(Deserialize, Default)]
+struct ListConfig {
+    #[serde(default)]
+    bullet: Bullet,
+    #[serde(default)]
+    indent: ListIndent,
+    /// The column to hard-wrap entries at, if any.
+    #[serde(default)]
+    wrap: Option<usize>,
+}
+
+/// A semantic versioning bump, ordered by severity.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Bump {
+    Patch,
+    Minor,
+    Major,
+}
+
+impl fmt::Display for Bump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Patch => "patch",
+            Self::Minor => "minor",
+            Self::Major => "major",
+        }
+        .fmt(f)
+    }
+}
+
+/// Recommends a semver bump from the sections that received entries, or
+/// `None` if there are no entries at all.
+fn recommend_bump<'a>(
+    sections: impl IntoIterator<Item = (&'a str, &'a [Entry])>,
+    config: &BumpConfig,
+) -> Option<Bump> {
+    sections
+        .into_iter()
+        .filter(|(_, entries)| !entries.is_empty())
+        .map(|(section, entries)| {
+            if entries.iter().any(|entry| entry.breaking)
+                || config.major.iter().any(|major| major == section)
+            {
+                Bump::Major
+            } else if config.minor.iter().any(|minor| minor == section) {
+                Bump::Minor
+            } else {
+                Bump::Patch
+            }
+        })
+        .max()
+}
+
+/// The version after `previous` given `bump`, keeping any `v` prefix, or
+/// `None` if `previous` is not a `MAJOR.MINOR.PATCH` version. Before 1.0.0,
+/// breaking changes only bump the minor version, as is conventional.
+fn next_version(previous: &str, bump: Bump) -> Option<String> {
+    let (prefix, version) = match previous.strip_prefix('v') {
+        Some(version) => ("v", version),
+        None => ("", previous),
+    };
+    // Pre-release and build metadata do not carry over to the next version.
+    let version = version.split(['-', '+']).next()?;
+    let [major, minor, patch] = version
+        .split('.')
+        .map(|part| part.parse::<u64>().ok())
+        .collect::<Option<Vec<_>>>()?
+        .try_into()
+        .ok()?;
+    let (major, minor, patch) = match bump {
+        Bump::Major if major == 0 => (0, minor + 1, 0),
+        Bump::Major => (major + 1, 0, 0),
+        Bump::Minor => (major, minor + 1, 0),
+        Bump::Patch => (major, minor, patch + 1),
+    };
+    Some(format!("{prefix}{major}.{minor}.{patch}"))
+}
+
+/// Whether two entries say the same thing, ignoring case, spacing, trailing
+/// punctuation, and small typos.
+fn is_near_duplicate(lhs: &str, rhs: &str) -> bool {
+    fn normalize(item: &str) -> String {
+        item.split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .trim_end_matches(['.', '!'])
+            .to_lowercase()
+    }
+
+    let lhs = normalize(lhs);
+    let rhs = normalize(rhs);
+    lhs == rhs || edit_distance(&lhs, &rhs) * 10 <= lhs.len().max(rhs.len())
+}
+
+/// Finds near-identical entries in `section` and, depending on `mode`,
+/// collapses each into the first of them or warns about it.
+fn handle_duplicate_entries(
+    section: &str,
+    entries: &mut Vec<Entry>,
+    mode: DuplicateMode,
+) {
+    if mode == DuplicateMode::Keep {
+        return;
+    }
+    let mut kept = Vec::<Entry>::with_capacity(entries.len());
+    for entry in entries.drain(..) {
+        let Some(original) = kept
+            .iter_mut()
+            .find(|original| is_near_duplicate(&original.item, &entry.item))
+        else {
+            kept.push(entry);
+            continue;
+        };
+        if mode == DuplicateMode::Warn {
+            emit_warning(miette!(
+                severity = Severity::Warning,
+                code = "main::duplicate_entry",
+                help = "Set `duplicates = \"merge\"` in the config to collapse them into one entry.",
+                "Entry from {} in section {} duplicates one from {}: {}",
+                entry.links[0].shorthand,
+                section,
+                original.links[0].shorthand,
+                entry.item
+            ));
+            kept.push(entry);
+            continue;
+        }
+        debug!(
+            "Merging duplicate entry from {} in section {} into the one from {}",
+            entry.links[0].shorthand, section, original.links[0].shorthand
+        );
+        for link in entry.links {
+            if !original.links.contains(&link) {
+                original.links.push(link);
+            }
+        }
+        for author in entry.authors {
+            if !original.authors.contains(&author) {
+                original.authors.push(author);
+            }
+        }
+        original.provenance.extend(entry.provenance);
+        original.breaking |= entry.breaking;
+    }
+    *entries = kept;
+}
+
+/// Concatenates the text inside `node`, ignoring formatting, e.g., `Added`
+/// for a heading written as ``## **`Added`**``.
+fn node_text<'a>(node: &'a comrak::nodes::AstNode<'a>) -> String {
+    node.descendants()
+        .filter_map(|descendant| match &descendant.data.borrow().value {
+            comrak::nodes::NodeValue::Text(text) => Some(text.clone()),
+            comrak::nodes::NodeValue::Code(code) => Some(code.literal.clone()),
+            comrak::nodes::NodeValue::SoftBreak
+            | comrak::nodes::NodeValue::LineBreak => Some(" ".to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Strips the list marker from a formatted markdown list item.
+fn item_text(content: &str) -> &str {
+    let item = content.trim();
+    item.strip_prefix("-").unwrap_or(item).trim()
+}
+
+/// Indents the lines of `block` after the first to sit under the `- ` an
+/// entry is written out with, and all of them if `first` is false.
+fn indent_block(block: &str, first: bool) -> String {
+    block
+        .lines()
+        .enumerate()
+        .map(|(index, line)| {
+            if line.trim().is_empty() {
+                String::new()
+            } else if index == 0 && first {
+                line.to_string()
+            } else {
+                format!("  {line}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Writes out `item`, whose lines after the first are indented by two spaces
+/// per level of nesting as [`indent_block`] leaves them, as a list item in the
+/// style of `list`.
+fn list_item(item: &str, list: &ListConfig) -> String {
+    let bullet = list.bullet.as_char();
+    let indent = list.indent.0 as usize;
+    // The closing fence and how far the code block was moved, while in one.
+    let mut fence: Option<(String, usize)> = None;
+    let mut lines = Vec::new();
+    for (index, line) in item.lines().enumerate() {
+        let text = line.trim_start_matches(' ');
+        let spaces = line.len() - text.len();
+        if let Some((marker, shift)) = &fence {
+            // Code keeps its indentation relative to its fences.
+            lines.push(if line.is_empty() {
+                String::new()
+            } else {
+                format!("{}{}", " ".repeat(*shift), line)
+            });
+            if text.trim_end() == marker {
+                fence = None;
+            }
+            continue;
+        }
+        let (spaces, text) = if index == 0 {
+            (0, format!("{} {}", bullet, text))
+        } else {
+            let reindented = spaces / 2 * indent + spaces % 2;
+            if let Some(fence_char) =
+                ['`', '~'].into_iter().find(|c| text.starts_with([*c; 3]))
+            {
+                let length =
+                    text.len() - text.trim_start_matches(fence_char).len();
+                fence = Some((text[..length].to_string(), reindented - spaces));
+            }
+            let text = match text.split_at_checked(2) {
+                Some(("- " | "* " | "+ ", rest)) if fence.is_none() => {
+                    format!("{} {}", bullet, rest)
+                }
+                _ => text.to_string(),
+            };
+            (reindented, text)
+        };
+        if text.is_empty() {
+            lines.push(String::new());
+            continue;
+        }
+        let line = format!("{}{}", " ".repeat(spaces), text);
+        match list.wrap {
+            Some(width) if fence.is_none() && !text.starts_with('|') => {
+                lines.extend(wrap_line(&line, spaces, width))
+            }
+            _ => lines.push(line),
+        }
+    }
+    lines.join("\n")
+}
+
+/// Breaks `line`, which is indented by `spaces`, at spaces so that its lines
+/// fit within `width` columns where possible. The lines after the first are
+/// indented to line up with the text after any list marker.
+fn wrap_line(line: &str, spaces: usize, width: usize) -> Vec<String> {
+    let text = &line[spaces..];
+    let hang = spaces
+        + match text.split_at_checked(2) {
+            Some(("- " | "* " | "+ ", _)) => 2,
+            _ => 0,
+        };
+    let mut words = line[hang..].split(' ');
+    let mut current =
+        format!("{}{}", &line[..hang], words.next().unwrap_or_default());
+    let mut lines = Vec::new();
+    for word in words {
+        // A line starting with the word would start a different block, e.g.,
+        // a list item or a heading, so it stays on this one.
+        if current.chars().count() + 1 + word.chars().count() <= width
+            || word.is_empty()
+            || starts_block(word)
+        {
+            current.push(' ');
+            current.push_str(word);
+        } else {
+            lines.push(current);
+            current = format!("{}{}", " ".repeat(hang), word);
+        }
+    }
+    lines.push(current);
+    lines
+}
+
+/// Whether a line starting with `word` would start a markdown block other
+/// than a paragraph.
+fn starts_block(word: &str) -> bool {
+    let mut chars = word.chars();
+    let first = chars.next().unwrap_or(' ');
+    let digits = word.trim_start_matches(|c: char| c.is_ascii_digit());
+    ("-*+=_#".contains(first) && chars.all(|c| c == first))
+        || word.starts_with(['>', '<', '|'])
+        || word.starts_with("```")
+        || word.starts_with("~~~")
+        || (digits.len() < word.len() && matches!(digits, "." | ")"))
+}
+
+/// A list item formatted as CommonMark, without its list marker, split into
+/// its first block and the rest (see [`ParsedItem::details`]).
+fn item_markdown<'a>(
+    item: &'a comrak::nodes::AstNode<'a>,
+) -> Result<(String, String)> {
+    let tight = item.parent().is_none_or(|list| {
+        matches!(
+            list.data.borrow().value,
+            comrak::nodes::NodeValue::List(list) if list.tight
+        )
+    });
+    let mut blocks = Vec::new();
+    for block in item.children() {
+        let mut result = Vec::new();
+        comrak::format_commonmark(
+            block,
+            &comrak::Options::default(),
+            &mut result,
+        )
+        .into_diagnostic()
+        .wrap_err("Failed to format document")?;
+        blocks.push(
+            String::from_utf8(result)
+                .into_diagnostic()
+                .wrap_err("Markdown list item was not valid UTF-8")?,
+        );
+    }
+    let mut blocks = blocks.iter();
+    let lead = blocks
+        .next()
+        .map(|block| indent_block(block.trim(), true))
+        .unwrap_or_default();
+    let details = blocks
+        .map(|block| {
+            format!(
+                "{}{}",
+                if tight { "\n" } else { "\n\n" },
+                indent_block(block.trim_end(), false)
+            )
+        })
+        .collect();
+    Ok((lead, details))
+}
+
+/// Like [`item_markdown`], but with the item exactly as written in
+/// `contents`.
+fn item_source<'a>(
+    contents: &str,
+    item: &'a comrak::nodes::AstNode<'a>,
+    list: comrak::nodes::NodeList,
+) -> (String, String) {
+    let sourcepos = item.data.borrow().sourcepos;
+    let lead_end = item.first_child().map_or(sourcepos.end.line, |block| {
+        block.data.borrow().sourcepos.end.line
+    });
+    let indent = sourcepos.start.column - 1 + list.padding;
+    let mut lead = Vec::new();
+    let mut details = String::new();
+    for (number, line) in contents
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line))
+        .skip(sourcepos.start.line - 1)
+        .take(sourcepos.end.line + 1 - sourcepos.start.line)
+    {
+        let line = if number == sourcepos.end.line {
+            line.get(..sourcepos.end.column).unwrap_or(line)
+        } else {
+            line
+        };
+        let line = if number == sourcepos.start.line {
+            line.get(indent..).unwrap_or_default()
+        } else {
+            let whitespace = line.len() - line.trim_start_matches(' ').len();
+            &line[whitespace.min(indent)..]
+        };
+        let line = if line.is_empty() || number == sourcepos.start.line {
+            line.to_string()
+        } else {
+            format!("  {line}")
+        };
+        if number <= lead_end {
+            lead.push(line);
+        } else {
+            details.push('\n');
+            details.push_str(&line);
+        }
+    }
+    (
+        lead.join("\n").trim().to_string(),
+        details.trim_end().to_string(),
+    )
+}
+
+#[derive(Clone)]
+struct PullRequest {
+    id: u64,
+    link: String,
+    title: String,
+    merged_at: Option<String>,
+    /// Username of whoever opened it.
+    author: Option<String>,
+    labels: Vec<String>,
+    /// The ids of the issues it closes, which are only fetched if a format
+    /// uses `{issues}`.
+    closes_issues: Vec<u64>,
+    /// The commits it was squashed and merged into, whichever there are.
+    commits: Vec<String>,
+    /// Whether it is the first of its author's to be merged, which is only
+    /// fetched if the `new-contributors` config table is set.
+    first_contribution: bool,
+}
+
+impl PullRequest {
+    fn try_from_gitlab(value: &JsonValue) -> Result<Self> {
+        let id = value
+            .get("iid")
+            .and_then(|value| value.as_u64())
+            .wrap_err("Missing 'iid' field on merge request")?;
+        let name = value
+            .get("title")
+            .and_then(|value| value.as_str())
+            .wrap_err("Missing 'name' field on merge request")?;
+        let merged_at = value
+            .get("merged_at")
+            .and_then(|value| value.as_str())
+            .map(str::to_string);
+        let author = value
+            .pointer("/author/username")
+            .and_then(|value| value.as_str())
+            .map(str::to_string);
+        let labels = value
+            .get("labels")
+            .and_then(|value| value.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|label| label.as_str().map(str::to_string))
+            .collect();
+        Ok(Self {
+            id,
+            link: format!("!{}", id),
+            title: name.to_string(),
+            merged_at,
+            author,
+            labels,
+            closes_issues: Vec::new(),
+            commits: ["squash_commit_sha", "merge_commit_sha"]
+                .into_iter()
+                .filter_map(|key| value.get(key)?.as_str())
+                .map(str::to_string)
+                .collect(),
+            first_contribution: false,
+        })
+    }
+
+    /// Reads a closed issue like a merge request, linked as `#id` and dated
+    /// by when it was closed.
+    fn try_from_gitlab_issue(value: &JsonValue) -> Result<Self> {
+        let issue = Self::try_from_gitlab(value)?;
+        Ok(Self {
+            link: format!("#{}", issue.id),
+            merged_at: value
+                .get("closed_at")
+                .and_then(|value| value.as_str())
+                .map(str::to_string),
+            ..issue
+        })
+    }
+}
+
+/// The span of `substring` in `source`, taking its first occurrence at or after
+/// byte `from`, so that an earlier one, e.g., of a domain in the username of a
+/// URL, is not mistaken for it.
+fn find_span(source: &str, substring: &str, from: usize) -> Option<SourceSpan> {
+    let start = from + source.get(from..)?.find(substring)?;
+    Some((start, substring.len()).into())
+}
+
+fn infer_host(repo_url: &Url) -> Result<RepositoryHost> {
+    if let Some(domain) = repo_url.domain() {
+        match domain {
+            "github.com" => Ok(RepositoryHost::GitHub),
+            "gitlab.com" => Ok(RepositoryHost::GitLab),
+            _ => {
+                let span = find_span(
+                    repo_url.as_str(),
+                    domain,
+                    repo_url[..Position::BeforeHost].len(),
+                );
+                Err(miette!(
+                    code = "infer_host::unknown_domain",
+                    labels = span
+                        .map(|span| LabeledSpan::new_with_span(None, span))
+                        .into_iter()
+                        .collect::<Vec<_>>(),
+                    help = "Please use a known repository host like github.com or gitlab.com.",
+                    "Unknown host domain"
+                )
+                .with_source_code(NamedSource::new("url",repo_url.to_string())))
+            }
+        }
+    } else {
+        Err(miette!(
+            code = "infer_host::missing_domain",
+            "Provided URL missing domain"
+        )
+        .with_source_code(NamedSource::new("url", repo_url.to_string())))
+    }
+}
+
+fn parse_owner_and_name(
+    url: Url,
+    host: RepositoryHost,
+) -> Result<(String, String)> {
+    match host {
+        RepositoryHost::GitHub | RepositoryHost::GitLab => {
+            let components = url
+                .path_segments()
+                .wrap_err("Repository URL missing path segments")?
+                .collect::<Vec<_>>();
+            if components.len() < 2
+                || (components.len() == 2
+                    && (components[0].is_empty() || components[1].is_empty()))
+            {
+                let start = components
+                    .first()
+                    .and_then(|component| {
+                        find_span(
+                            url.as_str(),
+                            component,
+                            url[..Position::BeforePath].len(),
+                        )
+                    })
+                    .map_or(0, |span| span.offset());
+                let length = url.as_str().len() - start;
+                return Err(miette!(
+                    code = "parse_owner_and_name::incorrect_format",
+                    labels = vec![LabeledSpan::at(
+                        (start, length),
+                        "less than two path segments"
+                    )],
+                    help = format!(
+                        "The URL should be of the form: https://{}/{{owner}}/{{name}}",
+                        host.domain()
+                    ),
+                    "URL does not point to a repository"
+                )
+                .with_source_code(NamedSource::new("url", url.to_string())));
+            }
+            let name = components[1];
+            Ok((
+                components[0].to_string(),
+                name.strip_suffix(".git").unwrap_or(name).to_string(),
+            ))
+        }
+        RepositoryHost::Infer => unreachable!(),
+    }
+}
+
+/// The GitLab API that merge request details are fetched from, the GitHub API
+/// that releases may be published to, and the client that sends the requests.
+struct Api {
+    /// The base URL, without a trailing slash.
+    gitlab_url: String,
+    /// The base URL of the GitHub API, without a trailing slash, which only
+    /// `mergelog publish` sends requests to.
+    github_url: String,
+    client: reqwest::blocking::Client,
+    /// Whether requests are refused, so that merge/pull requests are read from
+    /// the local history instead.
+    offline: bool,
+}
+
+impl Api {
+    const GITLAB_URL: &str = "https://gitlab.com/api/v4";
+    const GITHUB_URL: &str = "https://api.github.com";
+
+    /// The API at `api_url`, or else at gitlab.com and github.com, which
+    /// refuses all requests if `offline`.
+    fn new(api_url: Option<&Url>, offline: bool) -> Self {
+        let base_url = |default| {
+            api_url
+                .map_or(default, Url::as_str)
+                .trim_end_matches('/')
+                .to_string()
+        };
+        Self {
+            gitlab_url: base_url(Self::GITLAB_URL),
+            github_url: base_url(Self::GITHUB_URL),
+            client: reqwest::blocking::Client::new(),
+            offline,
+        }
+    }
+
+    /// Fails if `request` may not be sent because of `--offline`.
+    fn check_online(&self, request: &str) -> Result<()> {
+        if self.offline {
+            return Err(miette!(
+                code = "api::offline",
+                help = "Drop `--offline`, or leave out what needs the API, e.g., `{issues}` in the format.",
+                "Cannot send GitLab API request {} with `--offline`",
+                request
+            ));
+        }
+        Ok(())
+    }
+
+    /// Sends a GET request for `endpoint`, e.g., `projects/1/merge_requests`,
+    /// to the GitLab API and parses the JSON response.
+    fn fetch_gitlab_json(&self, endpoint: &str) -> Result<JsonValue> {
+        Ok(self.fetch_gitlab_page(endpoint)?.0)
+    }
+
+    /// Like [`Api::fetch_gitlab_json`], but also returns the number of the
+    /// page after the one in the response, if there is one.
+    fn fetch_gitlab_page(
+        &self,
+        endpoint: &str,
+    ) -> Result<(JsonValue, Option<u64>)> {
+        let request = format!("{}/{}", self.gitlab_url, endpoint);
+        self.check_online(&request)?;
+        debug!("GET {}", request);
+        let response = self
+            .client
+            .get(&request)
+            .send()
+            .into_diagnostic()
+            .whatever_context(miette!(
+                code = "fetch_gitlab_json::api_error",
+                "Failed to send GitLab API request {}",
+                request
+            ))?;
+        // GitLab leaves it empty on the last page.
+        let next_page = response
+            .headers()
+            .get("x-next-page")
+            .and_then(|page| page.to_str().ok()?.parse().ok());
+        let response = response.text().into_diagnostic().whatever_context(
+            miette!("Failed to extract GitLab API response text"),
+        )?;
+        trace!("Response to {}: {}", request, response);
+        let json = serde_json::from_str(&response).map_err(|cause| {
+            miette!(
+                code = "fetch_gitlab_json::serde_json_error",
+                labels = vec![LabeledSpan::at(
+                    SourceOffset::from_location(
+                        &response,
+                        cause.line(),
+                        cause.column()
+                    ),
+                    cause.to_string()
+                )],
+                "Failed to parse GitLab API response text"
+            )
+            .with_source_code(
+                NamedSource::new(request.as_str(), response.clone())
+                    .with_language("json"),
+            )
+        })?;
+        Ok((json, next_page))
+    }
+
+    /// Like [`Api::fetch_gitlab_json`], but expects an array of merge
+    /// requests, spread over `pages`.
+    fn fetch_gitlab_merge_requests(
+        &self,
+        endpoint: &str,
+        pages: Pages,
+    ) -> Result<Vec<PullRequest>> {
+        self.fetch_gitlab_array(
+            endpoint,
+            pages,
+            "Expected array of merge request details",
+            PullRequest::try_from_gitlab,
+        )
+    }
+
+    /// Like [`Api::fetch_gitlab_json`], but expects an array of issues, spread
+    /// over `pages`.
+    fn fetch_gitlab_issues(
+        &self,
+        endpoint: &str,
+        pages: Pages,
+    ) -> Result<Vec<PullRequest>> {
+        self.fetch_gitlab_array(
+            endpoint,
+            pages,
+            "Expected array of issue details",
+            PullRequest::try_from_gitlab_issue,
+        )
+    }
+
+    /// Fetches `pages` of the array at `endpoint` and reads each element with
+    /// `parse`, labeling a response with `expected` if it is not an array.
+    fn fetch_gitlab_array<T>(
+        &self,
+        endpoint: &str,
+        pages: Pages,
+        expected: &str,
+        parse: fn(&JsonValue) -> Result<T>,
+    ) -> Result<Vec<T>> {
+        let mut items = Vec::new();
+        let mut page_endpoint = endpoint.to_string();
+        loop {
+            let (response_json, next_page) =
+                self.fetch_gitlab_page(&page_endpoint)?;
+            let elements = response_json.as_array().whatever_context(
+                miette!(
+                    code = "fetch_merge_requests::malformed_json",
+                    labels = vec![LabeledSpan::at((0, 0), expected)],
+                    "Failed to parse GitLab API response text"
+                )
+                .with_source_code(
+                    NamedSource::new(
+                        format!("{}/{}", self.gitlab_url, page_endpoint),
+                        response_json.to_string(),
+                    )
+                    .with_language("json"),
+                ),
+            )?;
+            for element in elements {
+                items.push(parse(element)?);
+            }
+            match (pages, next_page) {
+                (Pages::All, Some(page)) => {
+                    let separator =
+                        if endpoint.contains('?') { '&' } else { '?' };
+                    page_endpoint = format!("{endpoint}{separator}page={page}");
+                }
+                _ => return Ok(items),
+            }
+        }
+    }
+}
+
+/// Which pages of an array to fetch from the GitLab API, which returns at most
+/// 100 elements at a time.
+#[derive(Clone, Copy)]
+enum Pages {
+    /// Only the first, when that has all that is needed, e.g., the oldest
+    /// merge request of an author.
+    First,
+    /// Every page, following the `x-next-page` header.
+    All,
+}
+
+fn fetch_merge_requests(
+    api: &Api,
+    owner: &str,
+    name: &str,
+    host: RepositoryHost,
+) -> Result<Vec<PullRequest>> {
+    if api.offline {
+        return merge_requests_from_history();
+    }
+    match host {
+        RepositoryHost::GitHub => Err(host.unsupported()),
+        RepositoryHost::GitLab => {
+            let endpoint = format!(
+                "projects/{}%2F{}/merge_requests?state=merged&per_page=100",
+                owner, name
+            );
+            api.fetch_gitlab_merge_requests(&endpoint, Pages::All)
+                .whatever_context(miette!(
+                    code = "fetch_merge_requests::api_error",
+                    "Failed to obtain merge requests from {}/{}",
+                    owner,
+                    name
+                ))
+        }
+        RepositoryHost::Infer => unreachable!(),
+    }
+}
+
+/// The merge/pull requests that the merge commits in the local history name,
+/// newest first, e.g., `See merge request group/project!123` from GitLab or
+/// `Merge pull request #123 from ...` from GitHub. Each is titled by the first
+/// line of the commit message after its subject, as both hosts write it.
+fn merge_requests_from_history() -> Result<Vec<PullRequest>> {
+    git_output(["rev-parse", "--git-dir"])?.whatever_context(miette!(
+        code = "main::offline_without_history",
+        help = "Run mergelog in the repository, or drop `--offline`.",
+        "Cannot read merge/pull requests from the local history outside of a git repository"
+    ))?;
+    // git log fails in a repository without commits, which has no history.
+    let log = git_output(["log", "--merges", "--format=%H%x1f%cI%x1f%B%x1e"])?
+        .unwrap_or_default();
+    let mut pull_requests = Vec::<PullRequest>::new();
+    for commit in log.split('\x1e') {
+        let mut fields = commit.trim().splitn(3, '\x1f');
+        let (Some(sha), Some(merged_at), Some(message)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let mut lines = message.lines();
+        let subject = lines.next().unwrap_or_default();
+        let link = message
+            .lines()
+            .find_map(|line| {
+                let (_, id) = line
+                    .strip_prefix("See merge request ")?
+                    .rsplit_once('!')?;
+                Some(format!("!{}", id.trim_end_matches('.')))
+            })
+            .or_else(|| {
+                let rest = subject.strip_prefix("Merge pull request #")?;
+                let id = rest.split(' ').next()?;
+                Some(format!("#{id}"))
+            });
+        let Some((link, id)) = link.and_then(|link| {
+            let id = link[1..].parse::<u64>().ok()?;
+            Some((link, id))
+        }) else {
+            continue;
+        };
+        if pull_requests
+            .iter()
+            .any(|pull_request| pull_request.id == id)
+        {
+            continue;
+        }
+        let title = lines
+            .map(str::trim)
+            .find(|line| {
+                !line.is_empty() && !line.starts_with("See merge request ")
+            })
+            .unwrap_or(subject);
+        pull_requests.push(PullRequest {
+            id,
+            link,
+            title: title.to_string(),
+            merged_at: Some(merged_at.to_string()),
+            author: None,
+            labels: Vec::new(),
+            closes_issues: Vec::new(),
+            commits: vec![sha.to_string()],
+            first_contribution: false,
+        });
+    }
+    debug!(
+        "Read {} merge/pull requests from the local history",
+        pull_requests.len()
+    );
+    Ok(pull_requests)
+}
+
+/// The merge/pull requests merged and the issues closed in the milestone
+/// titled `milestone`, with the issues linked as such.
+fn fetch_milestone(
+    api: &Api,
+    owner: &str,
+    name: &str,
+    host: RepositoryHost,
+    milestone: &str,
+) -> Result<Vec<PullRequest>> {
+    match host {
+        RepositoryHost::GitHub => Err(host.unsupported()),
+        RepositoryHost::GitLab => {
+            let milestone =
+                url::form_urlencoded::byte_serialize(milestone.as_bytes())
+                    .collect::<String>();
+            let error = || {
+                miette!(
+                    code = "fetch_milestone::api_error",
+                    "Failed to obtain milestone {} from {}/{}",
+                    milestone,
+                    owner,
+                    name
+                )
+            };
+            let mut items = api
+                .fetch_gitlab_merge_requests(&format!(
+                    "projects/{}%2F{}/merge_requests?state=merged&milestone={}&per_page=100",
+                    owner, name, milestone
+                ), Pages::All)
+                .whatever_context(error())?;
+            items.extend(
+                api.fetch_gitlab_issues(&format!(
+                    "projects/{}%2F{}/issues?state=closed&milestone={}&per_page=100",
+                    owner, name, milestone
+                ), Pages::All)
+                .whatever_context(error())?,
+            );
+            Ok(items)
+        }
+        RepositoryHost::Infer => unreachable!(),
+    }
+}
+
+/// Records the issues that each merge/pull request of the entries in
+/// `sections` closes.
+fn fetch_closed_issues(
+    api: &Api,
+    owner: &str,
+    name: &str,
+    host: RepositoryHost,
+    sections: &mut ChangelogSections,
+) -> Result<()> {
+    let mut closed_issues = HashMap::<u64, Vec<u64>>::new();
+    for (_, entries) in sections.values_mut() {
+        for pull_request in entries
+            .iter_mut()
+            .filter_map(|entry| entry.pull_request.as_mut())
+            // Issues from a milestone close nothing themselves.
+            .filter(|pull_request| !pull_request.link.starts_with('#'))
+        {
+            if let Some(issues) = closed_issues.get(&pull_request.id) {
+                pull_request.closes_issues = issues.clone();
+                continue;
+            }
+            let issues = match host {
+                RepositoryHost::GitHub => return Err(host.unsupported()),
+                RepositoryHost::GitLab => api
+                    .fetch_gitlab_issues(
+                        &format!(
+                            "projects/{}%2F{}/merge_requests/{}/closes_issues",
+                            owner, name, pull_request.id
+                        ),
+                        Pages::All,
+                    )
+                    .whatever_context(miette!(
+                        code = "fetch_closed_issues::api_error",
+                        "Failed to obtain the issues closed by {} in {}/{}",
+                        pull_request.link,
+                        owner,
+                        name
+                    ))?,
+                RepositoryHost::Infer => unreachable!(),
+            };
+            pull_request.closes_issues =
+                issues.iter().map(|issue| issue.id).collect();
+            closed_issues
+                .insert(pull_request.id, pull_request.closes_issues.clone());
+        }
+    }
+    Ok(())
+}
+
+/// Records which merge/pull requests of the entries in `sections` are the first
+/// of their author's to be merged.
+fn fetch_first_contributions(
+    api: &Api,
+    owner: &str,
+    name: &str,
+    host: RepositoryHost,
+    sections: &mut ChangelogSections,
+) -> Result<()> {
+    let mut first_contributions = HashMap::<String, Option<u64>>::new();
+    for (_, entries) in sections.values_mut() {
+        for pull_request in entries
+            .iter_mut()
+            .filter_map(|entry| entry.pull_request.as_mut())
+            .filter(|pull_request| !pull_request.link.starts_with('#'))
+        {
+            let Some(author) = &pull_request.author else {
+                continue;
+            };
+            if let hash_map::Entry::Vacant(vacant) =
+                first_contributions.entry(author.clone())
+            {
+                let first = match host {
+                    RepositoryHost::GitHub => return Err(host.unsupported()),
+                    RepositoryHost::GitLab => api
+                        .fetch_gitlab_merge_requests(&format!(
+                            "projects/{}%2F{}/merge_requests?state=merged&author_username={}&order_by=created_at&sort=asc&per_page=1",
+                            owner,
+                            name,
+                            url::form_urlencoded::byte_serialize(
+                                author.as_bytes()
+                            )
+                            .collect::<String>()
+                        ), Pages::First)
+                        .whatever_context(miette!(
+                            code = "fetch_first_contributions::api_error",
+                            "Failed to obtain the first merge request of {} in {}/{}",
+                            author,
+                            owner,
+                            name
+                        ))?,
+                    RepositoryHost::Infer => unreachable!(),
+                };
+                vacant.insert(first.first().map(|first| first.id));
+            }
+            pull_request.first_contribution =
+                first_contributions[author] == Some(pull_request.id);
+        }
+    }
+    Ok(())
+}
+
+/// Credits the people named in `Co-authored-by:` trailers of the commits that
+/// the merge/pull requests of the entries in `sections` were merged as, unless
+/// the changelog of an entry names its authors itself.
+fn fetch_co_authors(
+    api: &Api,
+    owner: &str,
+    name: &str,
+    host: RepositoryHost,
+    sections: &mut ChangelogSections,
+) -> Result<()> {
+    let mut co_authors = HashMap::<u64, Vec<String>>::new();
+    for (_, entries) in sections.values_mut() {
+        for entry in entries.iter_mut() {
+            let Some(pull_request) = &entry.pull_request else {
+                continue;
+            };
+            if entry.authors != Vec::from_iter(pull_request.author.clone()) {
+                continue;
+            }
+            if let hash_map::Entry::Vacant(vacant) =
+                co_authors.entry(pull_request.id)
+            {
+                let mut names = Vec::new();
+                for sha in &pull_request.commits {
+                    let message = commit_message(api, owner, name, host, sha)?;
+                    names.extend(message.lines().filter_map(|line| {
+                        let (key, value) = line.split_once(':')?;
+                        let name =
+                            value.split('<').next().unwrap_or_default().trim();
+                        (key.trim().eq_ignore_ascii_case("co-authored-by")
+                            && !name.is_empty())
+                        .then(|| name.to_string())
+                    }));
+                }
+                vacant.insert(names);
+            }
+            for co_author in &co_authors[&pull_request.id] {
+                if !entry
+                    .authors
+                    .iter()
+                    .any(|author| author.eq_ignore_ascii_case(co_author))
+                {
+                    entry.authors.push(co_author.clone());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The message of the commit `sha`, from the local history if it has the
+/// commit, or else from the repository host.
+fn commit_message(
+    api: &Api,
+    owner: &str,
+    name: &str,
+    host: RepositoryHost,
+    sha: &str,
+) -> Result<String> {
+    if let Some(message) = git_output(["show", "-s", "--format=%B", sha])? {
+        return Ok(message);
+    }
+    match host {
+        RepositoryHost::GitHub => Err(host.unsupported()),
+        RepositoryHost::GitLab => {
+            let commit = api
+                .fetch_gitlab_json(&format!(
+                    "projects/{}%2F{}/repository/commits/{}",
+                    owner, name, sha
+                ))
+                .whatever_context(miette!(
+                    code = "fetch_co_authors::api_error",
+                    "Failed to obtain commit {} from {}/{}",
+                    sha,
+                    owner,
+                    name
+                ))?;
+            Ok(commit
+                .get("message")
+                .and_then(|message| message.as_str())
+                .unwrap_or_default()
+                .to_string())
+        }
+        RepositoryHost::Infer => unreachable!(),
+    }
+}
+
+/// Drops the merge/pull requests that the `exclude-labels` and
+/// `exclude-title-pattern` config options leave out, e.g., those opened by
+/// bots, so that changelogs are never matched to them.
+fn drop_excluded(
+    pull_requests: &mut Vec<PullRequest>,
+    config: &Config,
+) -> Result<()> {
+    let title_pattern = config
+        .exclude_title_pattern
+        .as_deref()
+        .map(Regex::new)
+        .transpose()
+        .map_err(|error| {
+            miette!(
+                code = "main::invalid_title_pattern",
+                help = "Fix `exclude-title-pattern` in the config; it is a regular expression, e.g., `^chore:`.",
+                "Invalid title pattern: {}",
+                error
+            )
+        })?;
+    pull_requests.retain(|pull_request| {
+        let excluded = pull_request
+            .labels
+            .iter()
+            .any(|label| config.exclude_labels.contains(label))
+            || title_pattern
+                .as_ref()
+                .is_some_and(|pattern| pattern.is_match(&pull_request.title));
+        if excluded {
+            debug!("Excluded {} '{}'", pull_request.link, pull_request.title);
+        }
+        !excluded
+    });
+    Ok(())
+}
+
+/// Like [`fetch_merge_requests`], but shows a spinner while waiting, unless
+/// reading the local history with `--offline`.
+fn fetch_merge_requests_with_spinner(
+    api: &Api,
+    owner: &str,
+    name: &str,
+    host: RepositoryHost,
+) -> Result<Vec<PullRequest>> {
+    if api.offline {
+        return merge_requests_from_history();
+    }
+    with_fetch_spinner(|| fetch_merge_requests(api, owner, name, host))
+}
+
+/// Runs `fetch` while showing a spinner.
+fn with_fetch_spinner<T>(fetch: impl FnOnce() -> Result<T>) -> Result<T> {
+    let spinner = logging::progress()
+        .add(ProgressBar::new_spinner())
+        .with_message("Fetching information from remote repository")
+        .with_style(
+            ProgressStyle::default_spinner()
+                .tick_chars(console::symbols().spinner),
+        );
+    spinner.enable_steady_tick(Duration::from_millis(100));
+    let fetched = fetch()?;
+    spinner.finish_and_clear();
+    info!("Fetched information from remote repository");
+    Ok(fetched)
+}
+
+/// The tag a release of `version` gets, following whether `previous_tag` has
+/// a `v` prefix.
+fn release_tag(version: &str, previous_tag: Option<&str>) -> String {
+    match previous_tag {
+        Some(previous_tag)
+            if previous_tag.starts_with('v') && !version.starts_with('v') =>
+        {
+            format!("v{version}")
+        }
+        _ => version.to_string(),
+    }
+}
+
+/// The tag of the release before the Unreleased changes in `changelog`: its
+/// latest release, spelled like the git tags, or else the latest git tag.
+fn previous_release_tag(changelog: &Changelog) -> Result<Option<String>> {
+    let latest_tag = git_output(["describe", "--tags", "--abbrev=0"])?;
+    Ok(match changelog.latest() {
+        Some(release) => {
+            Some(release_tag(release.version(), latest_tag.as_deref()))
+        }
+        None => latest_tag,
+    })
+}
+
+/// Links to the changes between two revisions, or to the revision `to` alone
+/// if there is nothing to compare with.
+fn make_compare_link(from: Option<&str>, to: &str, repo: Repository) -> String {
+    match from {
+        Some(from) => repo.page(&format!("compare/{from}...{to}")),
+        None => repo.page(&format!("tree/{to}")),
+    }
+}
+
+/// Finds the open merge/pull request whose source branch is `branch`.
+fn fetch_branch_merge_request(
+    api: &Api,
+    owner: &str,
+    name: &str,
+    host: RepositoryHost,
+    branch: &str,
+) -> Result<Option<PullRequest>> {
+    match host {
+        RepositoryHost::GitHub => Err(host.unsupported()),
+        RepositoryHost::GitLab => {
+            let branch =
+                url::form_urlencoded::byte_serialize(branch.as_bytes())
+                    .collect::<String>();
+            let endpoint = format!("projects/{}%2F{}/merge_requests?state=opened&view=simple&source_branch={}", owner, name, branch);
+            Ok(api
+                .fetch_gitlab_merge_requests(&endpoint, Pages::First)
+                .whatever_context(miette!(
+                    code = "fetch_branch_merge_request::api_error",
+                    "Failed to obtain merge requests from {}/{}",
+                    owner,
+                    name
+                ))?
+                .into_iter()
+                .next())
+        }
+        RepositoryHost::Infer => unreachable!(),
+    }
+}
+
+/// Asks `question` until the answer is valid, using `default` if nothing is
+/// entered or there are no more answers.
+/// If `assume_default`, answers with `default` right away instead, failing if
+/// there is none.
+fn prompt<'a>(
+    prompter: &mut dyn Prompter,
+    question: &str,
+    validate: impl Fn(&str) -> bool,
+    exit: impl Fn(&str),
+    default: impl Into<Option<&'a str>>,
+    assume_default: bool,
+) -> Result<String> {
+    let default = default.into();
+    if assume_default {
+        let Some(default) = default else {
+            prompter.show(question);
+            return Err(miette!(
+                code = "main::prompt_without_default",
+                help = "Run without `--yes` to answer it, or pass `--non-interactive` to match changelogs to merge/pull requests without asking.",
+                "This prompt has no default answer to assume"
+            ));
+        };
+        if tracing::enabled!(Level::INFO) {
+            prompter.show(&format!("{question}{default}"));
+        }
+        exit(default);
+        return Ok(default.to_string());
+    }
+    loop {
+        // No more answers will come, so asking again would never end.
+        let Some(answer) = prompter.ask(question)? else {
+            let Some(default) = default else {
+                return Err(miette!(
+                    code = "main::prompt_eof",
+                    help = "Pass `--non-interactive` to match changelogs to merge/pull requests without asking, or `--yes` to assume default answers.",
+                    "Input ended before the prompt was answered"
+                ));
+            };
+            exit(default);
+            return Ok(default.to_string());
+        };
+        let answer = answer.trim();
+        if answer.is_empty() {
+            if let Some(default) = default {
+                exit(default);
+                return Ok(default.to_string());
+            }
+        };
+        if validate(answer) {
+            exit(answer);
+            return Ok(answer.to_string());
+        }
+    }
+}
+
+/// Guesses which merge/pull requests a changelog named `name` with `contents`
+/// belongs to, best first, or returns `None` if none resemble it.
+fn guess_pull_request<'a>(
+    name: &str,
+    contents: &str,
+    pull_requests: &'a [PullRequest],
+) -> Option<Vec<&'a PullRequest>> {
+    let mut scores = pull_requests
+        .iter()
+        .map(|pr| (pr, match_confidence(name, contents, &pr.title)))
+        .filter(|(_, score)| *score > 0.0)
+        .collect::<Vec<_>>();
+    if scores.is_empty() {
+        return None;
+    }
+    scores.sort_by(|lhs, rhs| {
+        rhs.1
+            .partial_cmp(&lhs.1)
+            .expect("we should not have created NaNs")
+    });
+    Some(scores.into_iter().take(5).map(|(pr, _)| pr).collect())
+}
+
+/// How sure [`guess_commits`] must be that a commit is described by a
+/// changelog to offer it, since most commits are not.
+const COMMIT_CONFIDENCE: f64 = 0.5;
+
+/// Guesses which of the recent commits in the local history that are not
+/// merges a changelog named `name` with `contents` describes, best first, for
+/// changes that were pushed without a merge/pull request. Returns each as its
+/// hash and subject.
+fn guess_commits(name: &str, contents: &str) -> Result<Vec<(String, String)>> {
+    let Some(log) =
+        git_output(["log", "--no-merges", "--format=%H %s", "-n", "500"])?
+    else {
+        return Ok(Vec::new());
+    };
+    let mut scores = log
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(sha, subject)| {
+            (sha, subject, match_confidence(name, contents, subject))
+        })
+        .filter(|(_, _, score)| *score >= COMMIT_CONFIDENCE)
+        .collect::<Vec<_>>();
+    scores.sort_by(|lhs, rhs| {
+        rhs.2
+            .partial_cmp(&lhs.2)
+            .expect("we should not have created NaNs")
+    });
+    Ok(scores
+        .into_iter()
+        .take(3)
+        .map(|(sha, subject, _)| (sha.to_string(), subject.to_string()))
+        .collect())
+}
+
+/// The link to the commit `sha`, shortened to its first seven characters.
+fn make_commit_link(sha: &str, repo: Repository) -> Link {
+    Link {
+        shorthand: sha[..sha.len().min(7)].to_string(),
+        full: repo.page(&format!("commit/{sha}")),
+    }
+}
+
+/// Compares strings so that runs of digits are ordered by their numeric
+/// value, e.g., `!20` before `!100`, and other text case-insensitively.
+fn natural_cmp(lhs: &str, rhs: &str) -> Ordering {
+    fn chunks(string: &str) -> impl Iterator<Item = &str> {
+        let mut rest = string;
+        iter::from_fn(move || {
+            let first = rest.chars().next()?;
+            let end = rest
+                .find(|c: char| c.is_ascii_digit() != first.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let (chunk, remaining) = rest.split_at(end);
+            rest = remaining;
+            Some(chunk)
+        })
+    }
+
+    let mut lhs_chunks = chunks(lhs);
+    let mut rhs_chunks = chunks(rhs);
+    loop {
+        let ordering = match (lhs_chunks.next(), rhs_chunks.next()) {
+            (None, None) => return lhs.cmp(rhs),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(lhs_chunk), Some(rhs_chunk)) => {
+                if lhs_chunk.starts_with(|c: char| c.is_ascii_digit())
+                    && rhs_chunk.starts_with(|c: char| c.is_ascii_digit())
+                {
+                    let lhs_digits = lhs_chunk.trim_start_matches('0');
+                    let rhs_digits = rhs_chunk.trim_start_matches('0');
+                    lhs_digits
+                        .len()
+                        .cmp(&rhs_digits.len())
+                        .then_with(|| lhs_digits.cmp(rhs_digits))
+                } else {
+                    lhs_chunk.to_lowercase().cmp(&rhs_chunk.to_lowercase())
+                }
+            }
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Link {
+    shorthand: String,
+    full: String,
+}
+
+/// A section of the merged changelog, ready to be written out.
+struct RenderedSection {
+    level: u8,
+    heading: String,
+    /// The formatted entries, without list markers.
+    items: Vec<String>,
+}
+
+impl RenderedSection {
+    /// The section with the `items` that have no subsection, followed by a
+    /// section one level deeper for each subsection, in alphabetical order.
+    fn with_subsections(
+        level: u8,
+        heading: String,
+        items: Vec<(Option<String>, String)>,
+    ) -> Vec<Self> {
+        let mut direct = Vec::new();
+        let mut subsections = BTreeMap::<String, Vec<String>>::new();
+        for (subsection, item) in items {
+            match subsection {
+                Some(subsection) => {
+                    subsections.entry(subsection).or_default().push(item)
+                }
+                None => direct.push(item),
+            }
+        }
+        iter::once(Self {
+            level,
+            heading,
+            items: direct,
+        })
+        .chain(subsections.into_iter().map(|(heading, items)| Self {
+            level: (level + 1).min(6),
+            heading,
+            items,
+        }))
+        .collect()
+    }
+}
+
+/// A single item in the merged changelog.
+#[derive(Clone)]
+struct Entry {
+    item: String,
+    /// What goes after the formatted item; see [`ParsedItem::details`].
+    details: String,
+    /// Links to every merge/pull request the item came from, which is more
+    /// than one only if duplicates were merged.
+    links: Vec<Link>,
+    breaking: bool,
+    pull_request: Option<PullRequest>,
+    /// Who made the change, from the front matter of its changelog or else
+    /// the author of its merge/pull request.
+    authors: Vec<String>,
+    /// The changelogs the item came from, which is empty if it was read from
+    /// an existing changelog.
+    provenance: Vec<Provenance>,
+}
+
+/// How the merge/pull request a changelog belongs to was found.
+#[derive(Clone, Copy)]
+enum Resolution {
+    /// The changelog is named after its id.
+    FileName,
+    /// The user picked or entered it.
+    Prompt,
+    /// It was guessed without asking, with this confidence.
+    Guess(f64),
+    /// The changelog gives it in its front matter.
+    FrontMatter,
+}
+
+/// Which changelog an entry came from and how its merge/pull request was
+/// found, as recorded in the output with `--provenance`.
+#[derive(Clone)]
+struct Provenance {
+    path: Utf8PathBuf,
+    resolution: Resolution,
+}
+
+impl fmt::Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.resolution {
+            Resolution::FileName => {
+                write!(f, "{}, matched by file name", self.path)
+            }
+            Resolution::Prompt => write!(f, "{}, matched at prompt", self.path),
+            Resolution::FrontMatter => {
+                write!(f, "{}, matched by front matter", self.path)
+            }
+            Resolution::Guess(confidence) => write!(
+                f,
+                "{}, guessed with confidence {confidence:.2}",
+                self.path
+            ),
+        }
+    }
+}
+
+/// How the HTML comments recording provenance start.
+const PROVENANCE_PREFIX: &str = "<!-- from ";
+
+/// The HTML comment recording where an entry came from, if it is known.
+fn provenance_comment(provenance: &[Provenance]) -> Option<String> {
+    (!provenance.is_empty()).then(|| {
+        format!(
+            "{PROVENANCE_PREFIX}{} -->",
+            provenance
+                .iter()
+                .map(Provenance::to_string)
+                .collect::<Vec<_>>()
+                .join("; ")
+        )
+    })
+}
+
+/// Removes the provenance comments from `text`, e.g., before it is published
+/// somewhere that does not hide HTML comments.
+fn strip_provenance(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(PROVENANCE_PREFIX) {
+        let Some(length) = rest[start..].find("-->") else {
+            break;
+        };
+        result.push_str(rest[..start].trim_end_matches([' ', '\t']));
+        rest = &rest[start + length + "-->".len()..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// The link to the merge/pull request `id`, shown as `link`, which points at
+/// the link template of `repo` if it has one.
+fn make_pull_request_link(id: String, link: String, repo: Repository) -> Link {
+    let full_link = match (repo.link_template, repo.host) {
+        (Some(template), _) => repo.fill(template, &id),
+        (None, RepositoryHost::GitHub) => repo.page(&format!("pull/{id}")),
+        (None, RepositoryHost::GitLab) => {
+            repo.page(&format!("merge_requests/{id}"))
+        }
+        (None, RepositoryHost::Infer) => unreachable!(),
+    };
+    Link {
+        shorthand: link,
+        full: full_link,
+    }
+}
+
+/// The name of the changelog at `path`, which may be the id of its merge/pull
+/// request.
+fn changelog_name(path: &Utf8Path) -> &str {
+    path.file_stem().unwrap_or(path.as_str())
+}
+
+/// Determines the link for the changelog at `path`. If its name is not a
+/// number, it tries to guess from the pull requests and asks the user.
+fn resolve_changelog_pr_interactive(
+    path: &Utf8Path,
+    contents: &str,
+    pull_requests: &[PullRequest],
+    repo: Repository,
+    tui: bool,
+    yes: bool,
+    prompter: &mut dyn Prompter,
+) -> Result<Link> {
+    if let Ok(id) = changelog_name(path).parse::<u64>() {
+        let link = if let Some(link) = pull_requests
+            .iter()
+            .find(|pr| pr.id == id)
             .map(|pr| pr.link.clone())
         {
-            eprintln!(
-                "✓ {}",
-                format!("Processing changelog for {}", link).green()
+            info!("Processing changelog for {}", link);
+            link
+        } else {
+            let link = repo.shorthand(id);
+            emit_warning(miette!(
+                severity = Severity::Warning,
+                code = "main::unknown_pull_request",
+                help = "Check that the changelog is named after the right merge/pull request, and that it is merged.",
+                "Changelog '{}' is named after {}, which is not among the merged merge/pull requests",
+                path.file_name().unwrap_or(path.as_str()),
+                link
+            ));
+            link
+        };
+        Ok(make_pull_request_link(id.to_string(), link, repo))
+    } else {
+        resolve_changelog_pr_manually(
+            path,
+            contents,
+            pull_requests,
+            repo,
+            tui,
+            yes,
+            prompter,
+        )
+    }
+}
+
+/// Asks the user which merge/pull request the changelog at `path` with
+/// `contents` belongs to, offering guesses, either at a prompt or in the
+/// terminal UI if `tui`.
+fn resolve_changelog_pr_manually(
+    path: &Utf8Path,
+    contents: &str,
+    pull_requests: &[PullRequest],
+    repo: Repository,
+    tui: bool,
+    yes: bool,
+    prompter: &mut dyn Prompter,
+) -> Result<Link> {
+    let name = changelog_name(path);
+    let file_name = path.file_name().unwrap_or(path.as_str());
+    let guessed_prs = guess_pull_request(name, contents, pull_requests);
+    // The picker has no default to assume.
+    if tui && !yes {
+        if let Some(pr) = picker::pick_pull_request(
+            file_name,
+            contents,
+            pull_requests,
+            guessed_prs.as_deref().unwrap_or_default(),
+        )? {
+            info!("Processing changelog for {}", pr.link);
+            return Ok(make_pull_request_link(
+                pr.id.to_string(),
+                pr.link.clone(),
+                repo,
+            ));
+        }
+    }
+
+    let Symbols {
+        top,
+        side,
+        branch,
+        bottom,
+        ..
+    } = console::symbols();
+    prompter.show(&format!(
+            "{top} {}:",
+            format!("Cannot automatically determine pull request for changelog '{}', if it even has one", file_name).if_supports_color(Stderr, |text| text.red()),
+        ));
+    prompter.show(side);
+    for line in contents.lines() {
+        prompter.show(&format!(
+            "{side} {}",
+            line.if_supports_color(Stderr, |text| text
+                .fg_rgb::<128, 128, 128>())
+        ));
+    }
+    prompter.show(side);
+    if let Some(guessed_prs) = guessed_prs {
+        prompter.show(&format!(
+            "{branch} {}: Is it one of:",
+            "help".if_supports_color(Stderr, |text| text.cyan())
+        ));
+        for guessed_pr in guessed_prs {
+            prompter.show(&format!(
+                "{side}          {}: {}",
+                guessed_pr.link, guessed_pr.title
+            ));
+        }
+        prompter.show(side);
+    }
+    let guessed_commits = guess_commits(name, contents)?;
+    if !guessed_commits.is_empty() {
+        prompter.show(&format!(
+            "{branch} {}: Or, if it was pushed without one, the commit:",
+            "help".if_supports_color(Stderr, |text| text.cyan())
+        ));
+        for (sha, subject) in &guessed_commits {
+            prompter.show(&format!(
+                "{side}          {}: {}",
+                &sha[..7],
+                subject
+            ));
+        }
+        prompter.show(side);
+    }
+    let full_link = loop {
+        let value = prompt(
+            prompter,
+            &format!("{bottom} Please enter the desired link (can also be a link like !30 in GitLab, or a commit hash), or words to search for: "),
+            |value| !value.is_empty(),
+            |_| {},
+            None,
+            yes,
+        )?;
+        if value.starts_with(['!', '#']) || Url::parse(&value).is_ok() {
+            break value;
+        }
+        if let Some(sha) = resolve_commit(&value)? {
+            let link = make_commit_link(&sha, repo);
+            info!("Processing changelog for commit {}", link.shorthand);
+            return Ok(link);
+        }
+
+        let matches = fuzzy_search(&value, pull_requests)
+            .into_iter()
+            .take(5)
+            .map(|index| &pull_requests[index])
+            .collect::<Vec<_>>();
+        if matches.is_empty() {
+            prompter
+                .show(&format!("   No merge/pull requests match '{value}'."));
+            continue;
+        }
+        for (number, pr) in matches.iter().enumerate() {
+            prompter.show(&format!(
+                "   {}. {}: {}",
+                number + 1,
+                pr.link,
+                pr.title
+            ));
+        }
+        let choice = prompt(
+            prompter,
+            "   Pick one by number, or press enter to search again: ",
+            |value| {
+                value
+                    .parse::<usize>()
+                    .is_ok_and(|number| (1..=matches.len()).contains(&number))
+            },
+            |_| {},
+            "",
+            yes,
+        )?;
+        if let Ok(number) = choice.parse::<usize>() {
+            let pr = matches[number - 1];
+            info!("Processing changelog for {}", pr.link);
+            return Ok(make_pull_request_link(
+                pr.id.to_string(),
+                pr.link.clone(),
+                repo,
+            ));
+        }
+    };
+    info!("Processing changelog for {}", full_link);
+    if let Some(id) = match repo.host {
+        RepositoryHost::GitHub => full_link.strip_prefix("#"),
+        RepositoryHost::GitLab => full_link.strip_prefix("!"),
+        RepositoryHost::Infer => unreachable!(),
+    } {
+        Ok(make_pull_request_link(id.to_string(), full_link, repo))
+    } else {
+        let shorthand = prompt(
+            prompter,
+            "   Please provide the markdown shorthand name for the link: ",
+            |value| !value.is_empty(),
+            |_| {},
+            None,
+            yes,
+        )?;
+        Ok(Link {
+            shorthand,
+            full: full_link,
+        })
+    }
+}
+
+/// The full hash of the commit that `value` abbreviates, if it is a hash of at
+/// least seven characters of a commit in the local history.
+fn resolve_commit(value: &str) -> Result<Option<String>> {
+    if value.len() < 7 || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(None);
+    }
+    git_output([
+        "rev-parse",
+        "--verify",
+        "--quiet",
+        &format!("{value}^{{commit}}"),
+    ])
+}
+
+/// Scores how well `query` matches `text` as a subsequence, ignoring case and
+/// favoring runs of consecutive characters and characters that start words,
+/// or returns `None` if it does not match at all.
+fn fuzzy_score(query: &str, text: &str) -> Option<i64> {
+    let text = text.to_lowercase().chars().collect::<Vec<_>>();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous_match = None;
+    for query_char in query.to_lowercase().chars() {
+        if query_char.is_whitespace() {
+            continue;
+        }
+        let offset = text[position..].iter().position(|c| *c == query_char)?;
+        let index = position + offset;
+        score += 1;
+        if previous_match.is_some_and(|previous| previous + 1 == index) {
+            score += 5;
+        }
+        if index == 0 || !text[index - 1].is_alphanumeric() {
+            score += 3;
+        }
+        score -= offset.min(3) as i64;
+        previous_match = Some(index);
+        position = index + 1;
+    }
+    Some(score)
+}
+
+/// The indices of the merge/pull requests whose id and title match `query`,
+/// best match first.
+fn fuzzy_search(query: &str, pull_requests: &[PullRequest]) -> Vec<usize> {
+    let mut scored = pull_requests
+        .iter()
+        .enumerate()
+        .filter_map(|(index, pr)| {
+            fuzzy_score(query, &format!("{} {}", pr.link, pr.title))
+                .map(|score| (index, score))
+        })
+        .collect::<Vec<_>>();
+    scored.sort_by_key(|(_, score)| Reverse(*score));
+    scored.into_iter().map(|(index, _)| index).collect()
+}
+
+/// Words so common in changelogs and merge/pull request titles that they say
+/// nothing about which change is meant.
+const COMMON_WORDS: &[&str] = &[
+    "a", "add", "added", "adds", "an", "and", "change", "changed", "fix",
+    "fixed", "fixes", "for", "from", "in", "is", "it", "of", "on", "remove",
+    "removed", "support", "the", "to", "update", "updated", "use", "when",
+    "with",
+];
+
+/// The lowercase words in `text`, ignoring single characters and
+/// [`COMMON_WORDS`].
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() > 1)
+        .map(str::to_lowercase)
+        .filter(|word| !COMMON_WORDS.contains(&word.as_str()))
+        .collect()
+}
+
+/// How well the words in `needles` are found among those in `haystack`, from 0
+/// to 1: the average similarity of each to its closest counterpart. Words count
+/// as similar once their Jaro-Winkler similarity reaches 0.85, so that typos
+/// and inflections like `parse` and `parsing` still match.
+fn word_coverage(needles: &[String], haystack: &[String]) -> f64 {
+    if needles.is_empty() {
+        return 0.0;
+    }
+    needles
+        .iter()
+        .map(|needle| {
+            haystack
+                .iter()
+                .map(|word| jaro_winkler(needle, word))
+                .filter(|similarity| *similarity >= 0.85)
+                .fold(0.0, f64::max)
+        })
+        .sum::<f64>()
+        / needles.len() as f64
+}
+
+/// The text of the first list item in `contents`, which usually mirrors the
+/// title of its merge/pull request, or all of `contents` if there is none.
+fn first_item(contents: &str) -> &str {
+    contents
+        .lines()
+        .map(str::trim)
+        .find_map(|line| {
+            ["- ", "* ", "+ "]
+                .into_iter()
+                .find_map(|marker| line.strip_prefix(marker))
+        })
+        .unwrap_or(contents)
+}
+
+/// How sure we can be that a changelog named `name` with `contents` belongs to
+/// a merge/pull request titled `title`, from 0 to 1. Both the name and the
+/// first list item are compared with the title both ways, with the item
+/// counting for more, since it is usually worded much like the title.
+fn match_confidence(name: &str, contents: &str, title: &str) -> f64 {
+    let title = words(title);
+    let similarity = |words: &[String]| {
+        (word_coverage(words, &title) + word_coverage(&title, words)) / 2.0
+    };
+    0.4 * similarity(&words(name))
+        + 0.6 * similarity(&words(first_item(contents)))
+}
+
+/// How a changelog without an id for a name was matched in non-interactive
+/// mode.
+struct AutomaticMatch {
+    path: Utf8PathBuf,
+    /// The chosen merge/pull request and the runner-up, if any, with their
+    /// confidence.
+    candidates: Vec<(PullRequest, f64)>,
+}
+
+/// The link to the merge/pull request `id`, which need not be one of
+/// `pull_requests`.
+fn pull_request_link_by_id(
+    id: u64,
+    pull_requests: &[PullRequest],
+    repo: Repository,
+) -> Link {
+    let link = pull_requests
+        .iter()
+        .find(|pr| pr.id == id)
+        .map_or_else(|| repo.shorthand(id), |pr| pr.link.clone());
+    make_pull_request_link(id.to_string(), link, repo)
+}
+
+/// Like [`resolve_changelog_pr_interactive`], but picks the most likely merge/
+/// pull request instead of asking, returning how it decided if it had to
+/// guess.
+fn resolve_changelog_pr_automatic(
+    path: &Utf8Path,
+    name: &str,
+    contents: &str,
+    pull_requests: &[PullRequest],
+    repo: Repository,
+) -> (Option<Link>, Option<AutomaticMatch>) {
+    if let Ok(id) = name.parse::<u64>() {
+        return (Some(pull_request_link_by_id(id, pull_requests, repo)), None);
+    }
+
+    let mut candidates = pull_requests
+        .iter()
+        .map(|pr| (pr.clone(), match_confidence(name, contents, &pr.title)))
+        .filter(|(_, confidence)| *confidence > 0.0)
+        .collect::<Vec<_>>();
+    candidates.sort_by(|lhs, rhs| {
+        rhs.1
+            .partial_cmp(&lhs.1)
+            .expect("we should not have created NaNs")
+    });
+    candidates.truncate(2);
+    let Some((pr, _)) = candidates.first() else {
+        return (None, None);
+    };
+    (
+        Some(make_pull_request_link(
+            pr.id.to_string(),
+            pr.link.clone(),
+            repo,
+        )),
+        Some(AutomaticMatch {
+            path: path.to_path_buf(),
+            candidates,
+        }),
+    )
+}
+
+/// Prints how changelogs were matched without asking, so that risky matches
+/// can be checked afterward.
+fn report_automatic_matches(automatic_matches: &[AutomaticMatch]) {
+    if automatic_matches.is_empty() || !tracing::enabled!(Level::INFO) {
+        return;
+    }
+    let Symbols {
+        top,
+        side,
+        bottom,
+        arrow,
+        ..
+    } = console::symbols();
+    eprintln!(
+        "{top} {}:",
+        "Automatically matched changelogs"
+            .if_supports_color(Stderr, |text| text.cyan())
+    );
+    eprintln!("{side}");
+    for automatic_match in automatic_matches {
+        let (chosen, confidence) = &automatic_match.candidates[0];
+        let runner_up = match automatic_match.candidates.get(1) {
+            Some((runner_up, runner_up_confidence)) => format!(
+                "runner-up {} {} at {:.2}",
+                runner_up.link, runner_up.title, runner_up_confidence
+            ),
+            None => "no runner-up".to_string(),
+        };
+        let confidence_text = format!("{confidence:.2}");
+        eprintln!(
+            "{side} {} {arrow} {} {} (confidence {}; {})",
+            automatic_match.path,
+            chosen.link,
+            chosen.title,
+            if *confidence < 0.5 {
+                confidence_text
+                    .if_supports_color(Stderr, |text| text.yellow())
+                    .to_string()
+            } else {
+                confidence_text
+                    .if_supports_color(Stderr, |text| text.green())
+                    .to_string()
+            },
+            runner_up.if_supports_color(Stderr, |text| text
+                .fg_rgb::<128, 128, 128>())
+        );
+    }
+    eprintln!("{bottom}");
+}
+
+/// Parses a comma-separated list of numbers from 1 to `count`, or returns
+/// `None` if any is not one.
+fn parse_choices(value: &str, count: usize) -> Option<Vec<usize>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|choice| !choice.is_empty())
+        .map(|choice| {
+            choice
+                .parse::<usize>()
+                .ok()
+                .filter(|index| (1..=count).contains(index))
+        })
+        .collect()
+}
+
+/// Shows which merge/pull request each changelog named after one belongs to
+/// and asks once whether they are all correct, unless `yes`, instead of
+/// reporting them one by one. Returns the link for each of these changelogs,
+/// or `None` for those the user says are wrong.
+fn confirm_resolved_by_id(
+    paths: &[&Utf8Path],
+    pull_requests: &[PullRequest],
+    yes: bool,
+    prompter: &mut dyn Prompter,
+    repo: Repository,
+) -> Result<HashMap<Utf8PathBuf, Option<Link>>> {
+    let resolved = paths
+        .iter()
+        .filter_map(|path| {
+            let id = path.file_stem()?.parse::<u64>().ok()?;
+            pull_requests
+                .iter()
+                .find(|pr| pr.id == id)
+                .map(|pr| (path, pr))
+        })
+        .collect::<Vec<_>>();
+    if resolved.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let Symbols {
+        top,
+        side,
+        bottom,
+        arrow,
+        ..
+    } = console::symbols();
+    // With `yes`, nothing is asked, so the table is only a status message.
+    let show_table = !yes || tracing::enabled!(Level::INFO);
+    if show_table {
+        prompter.show(&format!(
+            "{top} {}:",
+            "Changelogs named after merge/pull requests"
+                .if_supports_color(Stderr, |text| text.cyan())
+        ));
+        prompter.show(side);
+        for (number, (path, pr)) in resolved.iter().enumerate() {
+            prompter.show(&format!(
+                "{side} {:>3}. {} {arrow} {} {}",
+                number + 1,
+                path,
+                pr.link,
+                pr.title.if_supports_color(Stderr, |text| text
+                    .fg_rgb::<128, 128, 128>())
+            ));
+        }
+        prompter.show(side);
+    }
+    let wrong = if yes {
+        if show_table {
+            prompter.show(&format!("{bottom} Assuming they are all correct"));
+        }
+        vec![]
+    } else {
+        let choices = prompt(
+            prompter,
+            &format!("{bottom} Press enter if they are all correct, or enter the numbers of wrong ones separated by commas: "),
+            |value| parse_choices(value, resolved.len()).is_some(),
+            |_| {},
+            "",
+            false,
+        )?;
+        parse_choices(&choices, resolved.len()).unwrap_or_default()
+    };
+
+    Ok(resolved
+        .into_iter()
+        .enumerate()
+        .map(|(index, (path, pr))| {
+            let link = (!wrong.contains(&(index + 1))).then(|| {
+                make_pull_request_link(pr.id.to_string(), pr.link.clone(), repo)
+            });
+            (path.to_path_buf(), link)
+        })
+        .collect())
+}
+
+/// Asks the user which of the merged entries to feature in the highlights
+/// section.
+fn pick_highlights(
+    section_names: &[String],
+    sections: &HashMap<String, (u8, Vec<Entry>)>,
+    yes: bool,
+    prompter: &mut dyn Prompter,
+) -> Result<Vec<Entry>> {
+    let candidates = section_names
+        .iter()
+        .filter_map(|name| sections.get(name).map(|section| (name, section)))
+        .flat_map(|(name, (_, entries))| {
+            entries.iter().map(move |entry| (name, entry))
+        })
+        .collect::<Vec<_>>();
+    if candidates.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let Symbols {
+        top, side, bottom, ..
+    } = console::symbols();
+    prompter.show(&format!(
+        "{top} {}:",
+        "Pick the entries to highlight"
+            .if_supports_color(Stderr, |text| text.cyan())
+    ));
+    prompter.show(side);
+    for (i, (section, entry)) in candidates.iter().enumerate() {
+        prompter.show(&format!(
+            "{side} {:>3}. [{}] {} ({})",
+            i + 1,
+            section,
+            entry.item.lines().next().unwrap_or_default(),
+            entry
+                .links
+                .iter()
+                .map(|link| link.shorthand.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    prompter.show(side);
+    let choices = prompt(
+        prompter,
+        &format!("{bottom} Enter entry numbers separated by commas (default: none): "),
+        |value| parse_choices(value, candidates.len()).is_some(),
+        |_| {},
+        "",
+        yes,
+    )?;
+    Ok(parse_choices(&choices, candidates.len())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|index| candidates[index - 1].1.clone())
+        .collect())
+}
+
+/// Whether two headings name the same section, ignoring case in any language
+/// and differences in how accented characters are encoded.
+fn headings_match(lhs: &str, rhs: &str) -> bool {
+    fn normalize(heading: &str) -> String {
+        heading.trim().nfc().collect::<String>().to_lowercase()
+    }
+
+    normalize(lhs) == normalize(rhs)
+}
+
+/// Normalizes a heading from a changelog to the name of a known section,
+/// resolving aliases, or returns `None` if it is unknown.
+fn canonical_section(
+    heading: &str,
+    sections: &[String],
+    config: &Config,
+) -> Option<String> {
+    sections
+        .iter()
+        .chain([&config.breaking.heading])
+        .find(|section| headings_match(section, heading))
+        .or_else(|| {
+            config
+                .aliases
+                .iter()
+                .find(|(alias, _)| headings_match(alias, heading))
+                .map(|(_, section)| section)
+        })
+        .cloned()
+}
+
+/// Prints a non-fatal diagnostic.
+fn emit_warning(report: Report) {
+    annotate::record(&report);
+    warn!("{:?}", report);
+}
+
+/// Warns about each of the `unused_changelogs`, in order of their paths.
+fn report_unused_changelogs(
+    mut unused_changelogs: Vec<(Utf8PathBuf, UnusedChangelogReason)>,
+) {
+    unused_changelogs.sort_by(|lhs, rhs| lhs.0.cmp(&rhs.0));
+    for (path, reason) in unused_changelogs {
+        emit_warning(
+            miette!(
+                severity = Severity::Warning,
+                code = "main::unused_changelog",
+                help = "Its entries will not appear in the merged changelog.",
+                "Changelog {} contributed nothing because {}",
+                path,
+                reason
+            )
+            // Points annotations at the changelog.
+            .with_source_code(NamedSource::new(path.as_str(), String::new())),
+        );
+    }
+}
+
+/// Why a changelog file ended up contributing nothing to the output.
+enum UnusedChangelogReason {
+    Unreadable(io::Error),
+    Empty,
+    Filtered,
+    UnknownSections(Vec<String>),
+    Unmatched,
+    Excluded(String),
+}
+
+impl fmt::Display for UnusedChangelogReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unreadable(error) => {
+                write!(f, "it could not be read ({error})")
+            }
+            Self::Empty => "it has no list items".fmt(f),
+            Self::Filtered => {
+                "its list items are not under any section heading".fmt(f)
+            }
+            Self::UnknownSections(sections) => write!(
+                f,
+                "its list items are only under unselected sections: {}",
+                sections.join(", ")
+            ),
+            Self::Unmatched => {
+                "no merge/pull request resembles its name".fmt(f)
+            }
+            Self::Excluded(reason) => {
+                write!(f, "its merge/pull request is excluded by the {reason}")
+            }
+        }
+    }
+}
+
+/// The changelogs among the paths listed on standard input, skipping those
+/// that no longer exist or are not named like changelogs, so that the output
+/// of `git diff --name-only` can be passed as is.
+fn read_stdin_paths(config: &Config) -> Result<Vec<Utf8PathBuf>> {
+    let fragment_matcher = build_fragment_matcher(&config.fragments)?;
+    let mut paths = Vec::new();
+    for line in io::stdin().lines() {
+        let line = line
+            .into_diagnostic()
+            .wrap_err("Failed to read changelog paths from standard input")?;
+        let path = Utf8PathBuf::from(line.trim());
+        if path.as_str().is_empty() {
+            continue;
+        }
+        if !path
+            .file_name()
+            .is_some_and(|name| fragment_matcher.is_match(name))
+        {
+            debug!("Skipping {}, which is not named like a changelog", path);
+        } else if !path.is_file() {
+            debug!("Skipping {}, which no longer exists", path);
+        } else {
+            paths.push(path);
+        }
+    }
+    Ok(paths)
+}
+
+/// The directories matching a glob `pattern` given on the command line, for
+/// shells that do not expand it, sorted by path.
+fn expand_directory_pattern(pattern: &Utf8Path) -> Result<Vec<Utf8PathBuf>> {
+    let paths =
+        glob::glob(pattern.as_str())
+            .into_diagnostic()
+            .wrap_err(format!(
+                "Invalid changelog directory pattern '{}'",
+                pattern
+            ))?;
+    let mut directories = Vec::new();
+    for path in paths {
+        let path = path.into_diagnostic().wrap_err(format!(
+            "Failed to expand changelog directory pattern '{}'",
+            pattern
+        ))?;
+        match Utf8PathBuf::try_from(path) {
+            Ok(path) if path.is_dir() => directories.push(path),
+            Ok(path) => debug!(
+                "Skipping {}, which matches {} but is not a directory",
+                path, pattern
+            ),
+            Err(error) => emit_warning(miette!(
+                severity = Severity::Warning,
+                code = "main::non_utf8_directory",
+                "Skipping {}, whose path is not valid UTF-8",
+                error.as_path().display()
+            )),
+        }
+    }
+    if directories.is_empty() {
+        return Err(miette!(
+            code = "main::no_matching_directories",
+            help = "Check the pattern, or pass the directories themselves.",
+            "No changelog directories match '{}'",
+            pattern
+        ));
+    }
+    Ok(directories)
+}
+
+/// The files to skip in the changelog `directory`: those listed in its
+/// `.mergelogignore` or in `exclude`, both in gitignore syntax.
+fn ignore_rules(directory: &Utf8Path, exclude: &[String]) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(directory);
+    let ignore_file = directory.join(".mergelogignore");
+    if ignore_file.is_file() {
+        if let Some(error) = builder.add(&ignore_file) {
+            return Err(error)
+                .into_diagnostic()
+                .wrap_err(format!("Failed to read {}", ignore_file));
+        }
+    }
+    for pattern in exclude {
+        builder
+            .add_line(None, pattern)
+            .into_diagnostic()
+            .wrap_err(format!("Invalid exclude pattern '{}'", pattern))?;
+    }
+    builder.build().into_diagnostic().wrap_err(format!(
+        "Failed to compile the ignore rules for {}",
+        directory
+    ))
+}
+
+/// Finds the changelog files in `directories` whose names match one of
+/// `patterns` and that are not ignored, sorted by path. Files in more than one
+/// of them are only found once.
+fn collect_changelogs(
+    directories: &[Utf8PathBuf],
+    patterns: &[String],
+    exclude: &[String],
+    recursive: bool,
+    symlinks: SymlinkPolicy,
+) -> Result<Vec<Utf8PathBuf>> {
+    fn visit(
+        directory: &Utf8Path,
+        matcher: &GlobSet,
+        ignore: &Gitignore,
+        recursive: bool,
+        symlinks: SymlinkPolicy,
+        visited: &mut HashSet<Utf8PathBuf>,
+        paths: &mut Vec<Utf8PathBuf>,
+    ) -> Result<()> {
+        let canonical =
+            directory.canonicalize_utf8().into_diagnostic().wrap_err(
+                format!("Failed to resolve changelog directory {}", directory),
+            )?;
+        if !visited.insert(canonical) {
+            return Ok(());
+        }
+
+        let read_dir = directory
+            .read_dir_utf8()
+            .into_diagnostic()
+            .whatever_context(miette!(
+                code = "collect_changelogs::read_dir",
+                "Failed to read changelog directory {}",
+                directory
+            ))?;
+        for entry in read_dir {
+            let entry =
+                entry.into_diagnostic().with_whatever_context(|| {
+                    miette!(
+                        code = "collect_changelogs::read_entry",
+                        "Failed to read an entry of changelog directory {}",
+                        directory
+                    )
+                })?;
+            let path = entry.path();
+            let mut file_type = entry
+                .file_type()
+                .into_diagnostic()
+                .with_whatever_context(|| {
+                    miette!(
+                        code = "collect_changelogs::file_type",
+                        "Failed to determine the file type of {}",
+                        path
+                    )
+                })?;
+            if file_type.is_symlink() {
+                match symlinks {
+                    SymlinkPolicy::Follow => {
+                        file_type = fs::metadata(path)
+                            .into_diagnostic()
+                            .with_whatever_context(|| miette!(
+                                code = "collect_changelogs::broken_symlink",
+                                help = "Pass `--symlinks skip` to ignore symbolic links.",
+                                "Failed to follow symbolic link {}",
+                                path
+                            ))?
+                            .file_type();
+                    }
+                    SymlinkPolicy::Skip => {
+                        debug!("Skipping symbolic link {}", path);
+                        continue;
+                    }
+                    SymlinkPolicy::Error => {
+                        return Err(miette!(
+                            code = "collect_changelogs::symlink",
+                            help = "Pass `--symlinks follow` or `--symlinks skip` to allow symbolic links.",
+                            "Changelog directory contains symbolic link {}",
+                            path
+                        ));
+                    }
+                }
+            }
+
+            if ignore.matched(path, file_type.is_dir()).is_ignore() {
+                debug!("Skipping {}, which is ignored", path);
+                continue;
+            }
+
+            if file_type.is_dir() {
+                if recursive {
+                    visit(
+                        path, matcher, ignore, recursive, symlinks, visited,
+                        paths,
+                    )?;
+                }
+            } else if file_type.is_file() && matcher.is_match(entry.file_name())
+            {
+                paths.push(path.to_path_buf());
+            } else if file_type.is_file() {
+                debug!(
+                    "Skipping {}, which is not named like a changelog",
+                    path
+                );
+            }
+        }
+        Ok(())
+    }
+
+    let matcher = build_fragment_matcher(patterns)?;
+    let mut visited = HashSet::new();
+    let mut paths = Vec::new();
+    for directory in directories {
+        let already_visited = directory
+            .canonicalize_utf8()
+            .is_ok_and(|canonical| visited.contains(&canonical));
+        let found = paths.len();
+        visit(
+            directory,
+            &matcher,
+            &ignore_rules(directory, exclude)?,
+            recursive,
+            symlinks,
+            &mut visited,
+            &mut paths,
+        )?;
+        if paths.len() == found && !already_visited {
+            emit_warning(miette!(
+                severity = Severity::Warning,
+                code = "collect_changelogs::empty_directory",
+                help = format!(
+                    "Changelogs are files named like {}, as set by the `fragments` config option{}.",
+                    patterns.join(", "),
+                    if recursive {
+                        ""
+                    } else {
+                        "; pass `--recursive` to also read subdirectories"
+                    }
+                ),
+                "Changelog directory {} contains no changelogs",
+                directory
+            ));
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Compiles the configured changelog file name patterns.
+fn build_fragment_matcher(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern).into_diagnostic().wrap_err(format!(
+            "Invalid changelog file pattern '{}'",
+            pattern
+        ))?);
+    }
+    builder
+        .build()
+        .into_diagnostic()
+        .wrap_err("Failed to compile changelog file patterns")
+}
+
+/// A table that may hold a config under `mergelog`, like `[tool]` in a
+/// `pyproject.toml`.
+#[derive(Deserialize)]
+struct EmbeddedConfig {
+    mergelog: Option<Config>,
+}
+
+#[derive(Deserialize)]
+struct PyProject {
+    tool: Option<EmbeddedConfig>,
+}
+
+/// The `[package]` or `[workspace]` table of a `Cargo.toml`.
+#[derive(Deserialize)]
+struct CargoTable {
+    metadata: Option<EmbeddedConfig>,
+}
+
+#[derive(Deserialize)]
+struct CargoManifest {
+    workspace: Option<CargoTable>,
+    package: Option<CargoTable>,
+}
+
+/// The files searched for a config in each directory, in order. The config
+/// can also be embedded under `[tool.mergelog]` in a `pyproject.toml` and
+/// under `[workspace.metadata.mergelog]` or `[package.metadata.mergelog]` in
+/// a `Cargo.toml`.
+const CONFIG_FILE_NAMES: [&str; 3] =
+    ["mergelog.toml", "pyproject.toml", "Cargo.toml"];
+
+/// Reads the config in the file at `path`, which is `None` if it is a
+/// `pyproject.toml` or `Cargo.toml` without one.
+fn load_config(path: &Utf8Path) -> Result<Option<Config>> {
+    let contents = read_file(path)
+        .into_diagnostic()
+        .wrap_err(format!("Failed to read config file from {}", path))?;
+    let config = match path.file_name() {
+        Some("pyproject.toml") => toml::from_str::<PyProject>(&contents)
+            .map(|pyproject| pyproject.tool.and_then(|tool| tool.mergelog)),
+        Some("Cargo.toml") => {
+            toml::from_str::<CargoManifest>(&contents).map(|manifest| {
+                [manifest.workspace, manifest.package]
+                    .into_iter()
+                    .flatten()
+                    .find_map(|table| table.metadata?.mergelog)
+            })
+        }
+        _ => toml::from_str::<Config>(&contents).map(Some),
+    };
+    config.map_err(|cause| {
+        let labels = cause
+            .span()
+            .into_iter()
+            .map(|span| LabeledSpan::at(span, cause.to_string()))
+            .collect::<Vec<_>>();
+        miette!(
+            code = "load_config::toml_error",
+            labels = labels,
+            "Failed to parse config file"
+        )
+        .with_source_code(
+            NamedSource::new(path, contents).with_language("toml"),
+        )
+    })
+}
+
+/// The directories to look for a config in: `start` and each directory above
+/// it, up to the root of the repository it is in.
+fn config_directories(
+    start: &Utf8Path,
+    git_root: Option<&Utf8Path>,
+) -> Vec<Utf8PathBuf> {
+    let mut directories = Vec::new();
+    let mut directory = start.to_path_buf();
+    loop {
+        directories.push(directory.clone());
+        let absolute = (if directory.as_str().is_empty() {
+            Utf8Path::new(".")
+        } else {
+            &directory
+        })
+        .canonicalize_utf8();
+        let Ok(absolute) = absolute else {
+            break;
+        };
+        if git_root.is_none_or(|git_root| {
+            absolute == git_root || !absolute.starts_with(git_root)
+        }) {
+            break;
+        }
+        // Relative paths stay relative, so that paths in the config can be
+        // shown relative to the current directory.
+        directory = match directory.file_name() {
+            Some(_) => directory
+                .parent()
+                .map_or_else(|| directory.join(".."), Utf8Path::to_path_buf),
+            None => directory.join(".."),
+        };
+    }
+    directories
+}
+
+/// Finds and reads the config closest to `start`, or else to the current
+/// directory, along with where it was found. Its relative paths are still
+/// relative to the directory it is in.
+fn find_config(
+    start: Option<&Utf8Path>,
+) -> Result<Option<(Utf8PathBuf, Config)>> {
+    let git_root = git_output(["rev-parse", "--show-toplevel"])?
+        .map(Utf8PathBuf::from)
+        .and_then(|git_root| git_root.canonicalize_utf8().ok());
+    let mut directories = start
+        .map(|start| config_directories(start, git_root.as_deref()))
+        .unwrap_or_default();
+    directories
+        .extend(config_directories(Utf8Path::new(""), git_root.as_deref()));
+    for directory in directories {
+        for name in CONFIG_FILE_NAMES {
+            let path = directory.join(name);
+            if !path.is_file() {
+                continue;
+            }
+            // Only parse manifests that could embed a config, so that a
+            // broken one is left for the tools it belongs to.
+            if name != "mergelog.toml"
+                && !read_file(&path)
+                    .is_ok_and(|contents| contents.contains("mergelog"))
+            {
+                continue;
+            }
+            if let Some(config) = load_config(&path)? {
+                return Ok(Some((path, config)));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// The prefix of the environment variables that set config keys and options.
+const ENV_PREFIX: &str = "MERGELOG_";
+
+/// The environment variables that stand in for options on the command line
+/// rather than config keys.
+const ENV_OPTIONS: [&str; 4] = [
+    "MERGELOG_REPO",
+    "MERGELOG_HOST",
+    "MERGELOG_API_URL",
+    "MERGELOG_CONFIG",
+];
+
+/// A config key set by an environment variable.
+struct EnvOverride {
+    variable: String,
+    /// The key, after the tables it is in.
+    key: Vec<String>,
+    value: toml::Value,
+}
+
+/// The config keys set by `MERGELOG_*` environment variables, e.g.,
+/// `MERGELOG_LINK_STYLE` for `link-style` and `MERGELOG_BREAKING__MODE` for
+/// `mode` in the `breaking` table, in order of the variables. Values are read
+/// as TOML, or as a string if they are not valid TOML.
+fn env_overrides() -> Vec<EnvOverride> {
+    let mut overrides = env::vars_os()
+        .filter_map(|(variable, value)| {
+            let variable = variable.into_string().ok()?;
+            let value = value.into_string().ok()?;
+            let name = variable.strip_prefix(ENV_PREFIX)?;
+            if ENV_OPTIONS.contains(&variable.as_str()) || name.is_empty() {
+                return None;
+            }
+            let key = name
+                .split("__")
+                .map(|part| part.to_lowercase().replace('_', "-"))
+                .collect();
+            let value =
+                toml::from_str::<toml::Table>(&format!("value = {value}"))
+                    .ok()
+                    .and_then(|mut table| table.remove("value"))
+                    .unwrap_or(toml::Value::String(value));
+            Some(EnvOverride {
+                variable,
+                key,
+                value,
+            })
+        })
+        .collect::<Vec<_>>();
+    overrides.sort_by(|lhs, rhs| lhs.variable.cmp(&rhs.variable));
+    overrides
+}
+
+/// The table holding the config in the file at `path`, which [`load_config`]
+/// has already read successfully.
+fn config_table(path: &Utf8Path) -> Result<toml::Table> {
+    let contents = read_file(path)
+        .into_diagnostic()
+        .wrap_err(format!("Failed to read config file from {}", path))?;
+    let table = toml::from_str::<toml::Table>(&contents)
+        .into_diagnostic()
+        .wrap_err(format!("Failed to parse config file {}", path))?;
+    let keys: &[&[&str]] = match path.file_name() {
+        Some("pyproject.toml") => &[&["tool", "mergelog"]],
+        Some("Cargo.toml") => &[
+            &["workspace", "metadata", "mergelog"],
+            &["package", "metadata", "mergelog"],
+        ],
+        _ => return Ok(table),
+    };
+    Ok(keys
+        .iter()
+        .find_map(|keys| {
+            keys.iter()
+                .try_fold(&table, |table, key| table.get(*key)?.as_table())
+        })
+        .cloned()
+        .unwrap_or_default())
+}
+
+/// The config from `path`, or else the closest one to `start` (see
+/// [`find_config`]), with the `MERGELOG_*` environment variables applied over
+/// it.
+fn load_configuration(
+    path: Option<&Utf8Path>,
+    start: Option<&Utf8Path>,
+) -> Result<Config> {
+    let (found, directory) = match path {
+        Some(path) => (
+            Some((
+                path.to_path_buf(),
+                load_config(path)?.ok_or_else(|| {
+                    miette!(
+                        code = "load_config::missing_table",
+                        help = "Put the config under `[tool.mergelog]` in a `pyproject.toml`, or under `[workspace.metadata.mergelog]` or `[package.metadata.mergelog]` in a `Cargo.toml`.",
+                        "{} has no mergelog config",
+                        path
+                    )
+                })?,
+            )),
+            // A config passed explicitly keeps paths relative to the current
+            // directory, as it always has.
+            None,
+        ),
+        None => {
+            let found = find_config(start)?;
+            let directory = found.as_ref().and_then(|(path, _)| {
+                path.parent().map(Utf8Path::to_path_buf)
+            });
+            (found, directory)
+        }
+    };
+    let (path, mut config) = match found {
+        Some((path, config)) => {
+            info!("Loaded config from {}", path);
+            (Some(path), config)
+        }
+        None => (None, Config::default()),
+    };
+
+    let overrides = env_overrides();
+    if !overrides.is_empty() {
+        let mut table = match &path {
+            Some(path) => config_table(path)?,
+            None => toml::Table::new(),
+        };
+        for EnvOverride {
+            variable,
+            key,
+            value,
+        } in &overrides
+        {
+            debug!("Setting {} from {}", key.join("."), variable);
+            let (last, tables) = key.split_last().expect("keys are not empty");
+            let table = tables.iter().fold(&mut table, |table, name| {
+                let entry = table
+                    .entry(name.clone())
+                    .or_insert_with(|| toml::Table::new().into());
+                if !entry.is_table() {
+                    *entry = toml::Table::new().into();
+                }
+                entry.as_table_mut().expect("just made it a table")
+            });
+            table.insert(last.clone(), value.clone());
+        }
+        config = toml::Value::Table(table).try_into().map_err(|error| {
+            miette!(
+                code = "load_config::env_error",
+                help = format!(
+                    "Check the values of {}. Quote strings that would otherwise be read as TOML, e.g., `'\"1\"'`.",
+                    overrides
+                        .iter()
+                        .map(|env_override| env_override.variable.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                "Failed to apply config from the environment: {}",
+                error
+            )
+        })?;
+    }
+
+    if let Some(directory) = directory {
+        // Paths from the environment are relative to the current directory.
+        let from_env = overrides
+            .iter()
+            .filter_map(|env_override| env_override.key.first())
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        config.resolve_paths(&directory, &from_env);
+    }
+    Ok(config)
+}
+
+/// Fills in the options that are not passed on the command line from their
+/// `MERGELOG_*` environment variables.
+fn apply_env_options(opts: &mut Opts) -> Result<()> {
+    let var =
+        |name: &str| env::var(name).ok().filter(|value| !value.is_empty());
+    if opts.repo_url.is_none() {
+        if let Some(value) = var("MERGELOG_REPO") {
+            opts.repo_url = Some(value.parse().map_err(|error| {
+                miette!(
+                    code = "main::invalid_env",
+                    help = "Set it to a repository URL like https://gitlab.com/owner/name.",
+                    "MERGELOG_REPO is not a URL: {}",
+                    error
+                )
+            })?);
+        }
+    }
+    if opts.host == RepositoryHost::Infer {
+        if let Some(value) = var("MERGELOG_HOST") {
+            opts.host = value
+                .parse()
+                .wrap_err("MERGELOG_HOST is not a repository host")?;
+        }
+    }
+    if opts.api_url.is_none() {
+        if let Some(value) = var("MERGELOG_API_URL") {
+            opts.api_url = Some(value.parse().map_err(|error| {
+                miette!(
+                    code = "main::invalid_env",
+                    help = "Set it to the base URL of an API like https://gitlab.com/api/v4 or https://api.github.com.",
+                    "MERGELOG_API_URL is not a URL: {}",
+                    error
+                )
+            })?);
+        }
+    }
+    if opts.config.is_none() {
+        opts.config = var("MERGELOG_CONFIG").map(Utf8PathBuf::from);
+    }
+    Ok(())
+}
+
+/// The command line mergelog was run with, quoted as a shell would need it, and
+/// where each argument is in it, so that diagnostics can point at arguments.
+struct CommandLine {
+    text: String,
+    arguments: Vec<(String, SourceSpan)>,
+}
+
+impl CommandLine {
+    fn current() -> Self {
+        let mut text = String::new();
+        let mut arguments = Vec::new();
+        for argument in env::args_os() {
+            let argument = argument.to_string_lossy().into_owned();
+            if !text.is_empty() {
+                text.push(' ');
+            }
+            let quoted = shell_quote(&argument);
+            arguments.push((argument, (text.len(), quoted.len()).into()));
+            text.push_str(&quoted);
+        }
+        Self { text, arguments }
+    }
+
+    /// Where `argument` was passed, preferring an occurrence that is not the
+    /// value of an option if it was passed several times.
+    fn span_of(&self, argument: &str) -> Option<SourceSpan> {
+        let mut occurrences = self
+            .arguments
+            .iter()
+            .enumerate()
+            .skip(1)
+            .filter(|(_, (passed, _))| passed == argument)
+            .peekable();
+        let first = occurrences.peek().map(|(_, (_, span))| *span);
+        occurrences
+            .find(|(index, _)| !self.arguments[index - 1].0.starts_with('-'))
+            .map(|(_, (_, span))| *span)
+            .or(first)
+    }
+}
+
+/// `argument` as it would be typed into a POSIX shell.
+fn shell_quote(argument: &str) -> String {
+    if !argument.is_empty()
+        && argument
+            .chars()
+            .all(|c| c.is_alphanumeric() || "_-./:=@%+,".contains(c))
+    {
+        argument.to_string()
+    } else {
+        format!("'{}'", argument.replace('\'', "'\\''"))
+    }
+}
+
+/// `args` with `merge` put after the top-level options if they are not
+/// followed by a command, so that `mergelog <changelog_directory>` keeps
+/// working as it did before merging became the `merge` command.
+fn imply_merge(args: Vec<&str>) -> Vec<&str> {
+    let info = Opts::get_args_info();
+    let top_level = |arg: &str| {
+        info.flags.iter().find(|flag| {
+            flag.long == arg
+                || flag.short.is_some_and(|short| arg == format!("-{short}"))
+        })
+    };
+    let mut options = Vec::new();
+    let mut rest = Vec::new();
+    let mut iter = args.iter().copied();
+    while let Some(arg) = iter.next() {
+        match top_level(arg) {
+            Some(flag) => {
+                options.push(arg);
+                if let FlagInfoKind::Option { .. } = flag.kind {
+                    options.extend(iter.next());
+                }
+            }
+            None => rest.push(arg),
+        }
+    }
+    let implied = rest.first().is_some_and(|first| {
+        *first != "help"
+            && !info.commands.iter().any(|command| command.name == *first)
+    });
+    if !implied {
+        return args;
+    }
+    options.push("merge");
+    options.extend(rest);
+    options
+}
+
+/// Parses the command line like [`argh::from_env`], but also accepts repeated
+/// short switches written together, like `-vv`.
+fn parse_args() -> Opts {
+    let args = env::args().collect::<Vec<_>>();
+    let command = Path::new(&args[0])
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(&args[0]);
+    let args = args[1..]
+        .iter()
+        .flat_map(|arg| match arg.strip_prefix('-') {
+            Some(switches)
+                if switches.len() > 1 && switches.chars().all(|c| c == 'v') =>
+            {
+                vec!["-v"; switches.len()]
+            }
+            _ => vec![arg.as_str()],
+        })
+        .collect::<Vec<_>>();
+    let args = imply_merge(args);
+    Opts::from_args(&[command], &args).unwrap_or_else(|early_exit| {
+        process::exit(match early_exit.status {
+            Ok(()) => {
+                println!("{}", early_exit.output);
+                0
+            }
+            Err(()) => {
+                eprintln!(
+                    "{}\nRun {} --help for more information.",
+                    early_exit.output, command
+                );
+                1
+            }
+        })
+    })
+}
+
+fn main() -> Result<()> {
+    let mut opts = parse_args();
+    console::init(opts.color);
+    if opts.verbose > 0 && opts.quiet {
+        return Err(miette!(
+            code = "main::verbose_quiet",
+            help = "Pass only one of `-v` and `--quiet`.",
+            "Cannot be verbose and quiet at once"
+        ));
+    }
+    logging::init(logging::Verbosity::new(opts.verbose, opts.quiet));
+    let _annotations = opts.annotate.map(annotate::init).transpose()?;
+
+    if let Some(remote) = &opts.remote {
+        if opts.repo_url.is_some() {
+            return Err(miette!(
+                code = "main::repo_and_remote",
+                help = "Pass only one of `--repo` and `--remote`.",
+                "Cannot infer the repository from a remote when it is given"
+            ));
+        }
+        opts.repo_url = Some(remote_url(remote)?);
+    }
+    apply_env_options(&mut opts)?;
+    // None of these need the config, and validating reads it itself, so a
+    // broken one is reported rather than stopping mergelog before it starts.
+    match &opts.command {
+        Subcommand::Config(ConfigOpts {
+            command: ConfigSubcommand::Validate(validate_opts),
+        }) => {
+            let path = match validate_opts.path.clone().or(opts.config.clone())
+            {
+                Some(path) => path,
+                None => {
+                    find_config(None)?.map(|(path, _)| path).ok_or_else(
+                        || {
+                            miette!(
+                                code = "config::not_found",
+                                help = "Pass the config file to check, or create a `mergelog.toml`.",
+                                "No config file found"
+                            )
+                        },
+                    )?
+                }
+            };
+            return validate::validate(&path);
+        }
+        Subcommand::Completions(completions_opts) => {
+            print!(
+                "{}",
+                completions::generate(
+                    completions_opts.shell,
+                    Opts::get_args_info()
+                )
+            );
+            return Ok(());
+        }
+        Subcommand::Man(_) => {
+            print!("{}", man::generate(&Opts::get_args_info()));
+            return Ok(());
+        }
+        _ => {}
+    }
+    let config = load_configuration(
+        opts.config.as_deref(),
+        opts.command.changelog_directory().as_deref(),
+    )?;
+
+    let interaction = match (opts.non_interactive, opts.tui) {
+        (false, false) => Interaction::Prompt,
+        (false, true) => Interaction::Tui,
+        (true, false) => Interaction::Automatic,
+        (true, true) => {
+            return Err(miette!(
+                code = "main::tui_non_interactive",
+                help = "Pass only one of `--tui` and `--non-interactive`.",
+                "A terminal UI cannot be used without interaction"
+            ));
+        }
+    };
+    if interaction == Interaction::Tui
+        && !(io::stdin().is_terminal() && io::stderr().is_terminal())
+    {
+        return Err(miette!(
+            code = "main::tui_without_terminal",
+            help = "Run mergelog in a terminal or drop `--tui`.",
+            "The terminal UI needs standard input and standard error to be a terminal"
+        ));
+    }
+
+    let _lock = match opts.command {
+        // Publishing writes nothing locally, but edits the release on the
+        // host, which two runs must not do at once either.
+        Subcommand::Merge(_)
+        | Subcommand::New(_)
+        | Subcommand::Release(_)
+        | Subcommand::Backfill(_)
+        | Subcommand::Combine(_)
+        | Subcommand::Publish(_)
+        | Subcommand::Feed(_)
+        | Subcommand::MergeDriver(_) => Some(lock::acquire()?),
+        // These write no files, so they can run alongside anything.
+        Subcommand::Announce(_)
+        | Subcommand::Query(_)
+        | Subcommand::Show(_)
+        | Subcommand::Lint(_)
+        | Subcommand::Enforce(_)
+        | Subcommand::Config(_)
+        | Subcommand::Completions(_)
+        | Subcommand::Man(_) => None,
+    };
+
+    let api = Api::new(opts.api_url.as_ref(), opts.offline);
+    let mut prompter: Box<dyn Prompter> = match &opts.answers {
+        Some(path) => Box::new(Scripted::read(path)?),
+        None => Box::new(Terminal),
+    };
+    let prompter = prompter.as_mut();
+    match opts.command {
+        Subcommand::Merge(merge_opts) => merge(
+            merge_opts,
+            opts.repo_url,
+            opts.host,
+            api,
+            config,
+            interaction,
+            opts.yes,
+            prompter,
+            !opts.no_atomic,
+        ),
+        Subcommand::New(new_opts) => new_changelog(
+            new_opts,
+            opts.repo_url,
+            opts.host,
+            api,
+            config,
+            interaction,
+            opts.yes,
+            prompter,
+            !opts.no_atomic,
+        ),
+        Subcommand::Announce(announce_opts) => {
+            announce_notes(announce_opts, config)
+        }
+        Subcommand::Query(query_opts) => query(
+            query_opts,
+            opts.repo_url,
+            opts.host,
+            api,
+            config,
+            interaction,
+            opts.yes,
+            prompter,
+        ),
+        Subcommand::Release(release_opts) => release(
+            release_opts,
+            opts.repo_url,
+            opts.host,
+            &config,
+            !opts.no_atomic,
+        ),
+        Subcommand::Backfill(backfill_opts) => backfill(
+            backfill_opts,
+            opts.repo_url,
+            opts.host,
+            api,
+            &config,
+            !opts.no_atomic,
+        ),
+        Subcommand::Show(show_opts) => show_release(show_opts),
+        Subcommand::Combine(combine_opts) => {
+            combine_changelogs(combine_opts, !opts.no_atomic)
+        }
+        Subcommand::Lint(lint_opts) => {
+            lint::lint(&lint_opts.changelog, &config)
+        }
+        Subcommand::Publish(publish_opts) => {
+            publish_release(publish_opts, opts.repo_url, opts.host, api, config)
+        }
+        Subcommand::Feed(feed_opts) => release_feed(
+            feed_opts,
+            opts.repo_url,
+            opts.host,
+            &config,
+            !opts.no_atomic,
+        ),
+        Subcommand::Enforce(enforce_opts) => {
+            enforce(enforce_opts, opts.repo_url, opts.host, api, config)
+        }
+        Subcommand::MergeDriver(merge_driver_opts) => {
+            merge_driver(merge_driver_opts, !opts.no_atomic)
+        }
+        Subcommand::Config(ConfigOpts {
+            command: ConfigSubcommand::Sections(_),
+        }) => {
+            for section in config.section_names() {
+                println!("{}", section);
+            }
+            Ok(())
+        }
+        Subcommand::Config(ConfigOpts {
+            command: ConfigSubcommand::Validate(_),
+        })
+        | Subcommand::Completions(_)
+        | Subcommand::Man(_) => {
+            unreachable!("handled before loading the config")
+        }
+    }
+}
+
+/// Drops the byte order mark that some Windows editors put at the start of
+/// text files, so that it is not mistaken for part of the text.
+fn strip_byte_order_mark(text: String) -> String {
+    match text.strip_prefix('\u{feff}') {
+        Some(rest) => rest.to_string(),
+        None => text,
+    }
+}
+
+/// Reads the text file at `path` without its byte order mark, if any.
+fn read_file(path: impl AsRef<Path>) -> io::Result<String> {
+    fs::read_to_string(path).map(strip_byte_order_mark)
+}
+
+/// Writes `contents` to `path`. If `atomic`, they are written to a temporary
+/// file in the same directory that is then renamed over `path`, so that
+/// concurrent runs and crashes never leave it partially written.
+fn write_file(
+    path: &Utf8Path,
+    contents: impl AsRef<[u8]>,
+    atomic: bool,
+) -> io::Result<()> {
+    if !atomic {
+        return fs::write(path, contents);
+    }
+
+    let temporary = path.with_file_name(format!(
+        ".{}.{}.tmp",
+        path.file_name().unwrap_or("mergelog"),
+        process::id()
+    ));
+    let result = (|| {
+        let mut file = File::create(&temporary)?;
+        file.write_all(contents.as_ref())?;
+        file.sync_all()?;
+        if let Ok(metadata) = fs::metadata(path) {
+            fs::set_permissions(&temporary, metadata.permissions())?;
+        }
+        fs::rename(&temporary, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+    result
+}
+
+/// Compares `output` with the contents of `expected`, showing a diff from them
+/// on standard error if they differ. Returns whether they are the same.
+fn check_against(expected: &Utf8Path, output: &str) -> Result<bool> {
+    let contents = read_file(expected)
+        .into_diagnostic()
+        .wrap_err(format!("Failed to read expected output {}", expected))?;
+    if contents == output {
+        info!("Output matches {}", expected);
+        return Ok(true);
+    }
+
+    eprintln!(
+        "{}",
+        format!("--- {expected}").if_supports_color(Stderr, |text| text.red())
+    );
+    eprintln!(
+        "{}",
+        "+++ output".if_supports_color(Stderr, |text| text.green())
+    );
+    for hunk in TextDiff::from_lines(contents.as_str(), output)
+        .unified_diff()
+        .iter_hunks()
+    {
+        eprintln!(
+            "{}",
+            hunk.header().if_supports_color(Stderr, |text| text.cyan())
+        );
+        for change in hunk.iter_changes() {
+            let line = change.to_string_lossy();
+            let line = line.trim_end_matches('\n');
+            match change.tag() {
+                ChangeTag::Equal => eprintln!(" {line}"),
+                ChangeTag::Delete => eprintln!(
+                    "{}",
+                    format!("-{line}")
+                        .if_supports_color(Stderr, |text| text.red())
+                ),
+                ChangeTag::Insert => eprintln!(
+                    "{}",
+                    format!("+{line}")
+                        .if_supports_color(Stderr, |text| text.green())
+                ),
+            }
+            if change.missing_newline() {
+                eprintln!("\\ No newline at end of file");
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// Runs `git` with the given arguments and returns its trimmed standard
+/// output, or `None` if it failed.
+fn git_output<const N: usize>(args: [&str; N]) -> Result<Option<String>> {
+    debug!("Running git {}", args.join(" "));
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .into_diagnostic()
+        .wrap_err("Failed to run git")?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    let stdout = String::from_utf8(output.stdout)
+        .into_diagnostic()
+        .wrap_err("Failed to decode git output as UTF-8")?;
+    trace!("git {} printed: {}", args.join(" "), stdout.trim());
+    Ok(Some(stdout.trim().to_string()))
+}
+
+/// Runs git with `args`, failing with what it printed if it fails.
+fn git_run<'a>(args: impl IntoIterator<Item = &'a str>) -> Result<()> {
+    let args = args.into_iter().collect::<Vec<_>>();
+    debug!("Running git {}", args.join(" "));
+    let output = Command::new("git")
+        .args(&args)
+        .output()
+        .into_diagnostic()
+        .wrap_err("Failed to run git")?;
+    if !output.status.success() {
+        return Err(miette!(
+            code = "main::git_failed",
+            "git {} failed: {}",
+            args.first().copied().unwrap_or_default(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Seconds since the Unix epoch, or `SOURCE_DATE_EPOCH` when it is set so that
+/// the output can be reproduced.
+fn now() -> u64 {
+    env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.trim().parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+        })
+}
+
+/// Today's date in UTC as `YYYY-MM-DD`.
+fn today() -> String {
+    let (year, month, day) = civil_from_days((now() / 86400) as i64);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// The year, month, and day `days` days after the Unix epoch, following
+/// Howard Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524
+        - day_of_era / 146096)
+        / 365;
+    let day_of_year =
+        day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn package_without_version() -> Report {
+    miette!(
+        code = "main::package_without_version",
+        help = "Pass `--version`, or `--version-from-changelog` to propose one from the entries.",
+        "Packaging changelogs need a version to release the entries as"
+    )
+}
+
+/// What to release the entries as in packaging changelogs, from `opts` and
+/// the `packaging` config.
+fn package_release(
+    opts: &MergeOpts,
+    config: &Config,
+    repo_name: &str,
+) -> Result<Release> {
+    // `--version-from-changelog` proposes nothing without entries to bump for.
+    let version = opts
+        .version
+        .as_deref()
+        .ok_or_else(package_without_version)?;
+    let maintainer = match &config.packaging.maintainer {
+        Some(maintainer) => maintainer.clone(),
+        None => {
+            let name = git_output(["config", "user.name"])?;
+            let email = git_output(["config", "user.email"])?;
+            match name.zip(email) {
+                Some((name, email)) => format!("{} <{}>", name, email),
+                None => {
+                    return Err(miette!(
+                        code = "main::package_without_maintainer",
+                        help = "Set `maintainer` in the `packaging` config table, or `user.name` and `user.email` in the git config.",
+                        "Packaging changelogs need a maintainer to sign the release"
+                    ));
+                }
+            }
+        }
+    };
+    Ok(Release {
+        package: config
+            .packaging
+            .name
+            .clone()
+            .unwrap_or_else(|| repo_name.to_string()),
+        version: version.trim_start_matches('v').to_string(),
+        revision: config.packaging.revision.clone(),
+        distribution: config.packaging.distribution.clone(),
+        urgency: config.packaging.urgency.clone(),
+        maintainer,
+        timestamp: now(),
+    })
+}
+
+/// Turns a branch name into something usable as a file name.
+fn sanitize_branch_name(branch: &str) -> String {
+    branch
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+/// Determines the repository host, owner, and name, inferring whatever was
+/// not specified from the current repository.
+fn resolve_repository(
+    repo_url: Option<Url>,
+    host: RepositoryHost,
+) -> Result<(RepositoryHost, String, String)> {
+    let mut repo_url = match repo_url {
+        Some(repo_url) => repo_url,
+        None => remote_url(&default_remote()?)?,
+    };
+    // Credentials, e.g., in `https://token@gitlab.com/...`, must not end up in
+    // diagnostics or links.
+    let _ = repo_url.set_username("");
+    let _ = repo_url.set_password(None);
+    let host = match host {
+        RepositoryHost::Infer => infer_host(&repo_url)?,
+        specified => specified,
+    };
+
+    let (repo_owner, repo_name) = parse_owner_and_name(repo_url, host)?;
+    Ok((host, repo_owner, repo_name))
+}
+
+/// The remote the repository is inferred from without `--remote`: the one the
+/// current branch pulls from, or else `upstream`, so that a fork resolves
+/// merge/pull requests against the project it was forked from, or else
+/// `origin`.
+fn default_remote() -> Result<String> {
+    if let Some(branch) =
+        git_output(["symbolic-ref", "--quiet", "--short", "HEAD"])?
+    {
+        let key = format!("branch.{branch}.remote");
+        // A branch tracking another local branch has the remote `.`.
+        if let Some(remote) = git_output(["config", "--get", key.as_str()])?
+            .filter(|remote| remote != ".")
+        {
+            return Ok(remote);
+        }
+    }
+    let remotes = git_output(["remote"])?.unwrap_or_default();
+    Ok(if remotes.lines().any(|remote| remote == "upstream") {
+        "upstream"
+    } else {
+        "origin"
+    }
+    .to_string())
+}
+
+/// The URL of the git remote `remote`, after any `url.<base>.insteadOf`
+/// rewrites in the git config.
+fn remote_url(remote: &str) -> Result<Url> {
+    let url = git_output(["ls-remote", "--get-url", remote])?
+        // Git prints the name back for a remote that does not exist.
+        .filter(|url| url != remote)
+        .unwrap_or_default();
+    parse_git_url(&url).map_err(|inner| {
+        let help = if url.is_empty() {
+            format!("Add a valid remote {remote} URL with `git remote add {remote} <url>`, or pick another remote with `--remote`. You can also specify the URL manually by passing `--repo`")
+        } else {
+            format!("Remove the current remote {remote} with `git remote remove {remote}` and readd a correct one. You can also specify the URL manually by passing `--repo`")
+        };
+        miette!(
+            code = "main::parse_url",
+            labels = vec![LabeledSpan::at((0, url.len()), inner.to_string())],
+            help = help,
+            "Failed to parse {}{} URL",
+            if url.is_empty() { "empty " } else { "" },
+            remote
+        )
+        .with_source_code(NamedSource::new("url", url))
+    })
+}
+
+/// Parses a URL as git accepts it, including the scp-like syntax
+/// `user@host:path`, which is read as `ssh://user@host/path`.
+fn parse_git_url(url: &str) -> Result<Url, url::ParseError> {
+    Url::parse(url).or_else(|error| match url.split_once(':') {
+        Some((host, path))
+            if !host.is_empty()
+                && !host.contains('/')
+                && !path.starts_with("//") =>
+        {
+            Url::parse(&format!("ssh://{host}/{path}"))
+        }
+        _ => Err(error),
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn new_changelog(
+    opts: NewOpts,
+    repo_url: Option<Url>,
+    host: RepositoryHost,
+    api: Api,
+    config: Config,
+    interaction: Interaction,
+    yes: bool,
+    prompter: &mut dyn Prompter,
+    atomic: bool,
+) -> Result<()> {
+    let directory =
+        opts.directory
+            .or(config.directory.clone())
+            .whatever_context(miette!(
+        code = "new::missing_directory",
+        help = "Pass `--directory <path>` or set `directory` in the config.",
+        "No changelog directory provided"
+    ))?;
+    if !directory.is_dir() {
+        return Err(miette!(
+            code = "new::missing_changelogs",
+            "Changelog directory {} either does not exist or is not a directory",
+            directory
+        ));
+    }
+
+    let section_names = config.section_names();
+    let section = match opts.section {
+        Some(section) => {
+            if !section_names.is_empty() && !section_names.contains(&section) {
+                return Err(miette!(
+                    code = "new::unknown_section",
+                    help = format!(
+                        "The configured sections are: {}",
+                        section_names.join(", ")
+                    ),
+                    "Unknown section '{}'",
+                    section
+                ));
+            }
+            section
+        }
+        None => section_names.first().cloned().whatever_context(miette!(
+            code = "new::missing_section",
+            help = "Pass `--section <name>` or set `sections` in the config.",
+            "No changelog section provided"
+        ))?,
+    };
+
+    let name = if let Some(pr) = opts.pr {
+        pr.to_string()
+    } else {
+        let branch = git_output(["symbolic-ref", "--short", "HEAD"])?
+            .whatever_context(miette!(
+                code = "new::missing_branch",
+                help = "Pass `--pr <id>` to name the changelog explicitly.",
+                "Failed to determine the current branch"
+            ))?;
+        let merge_request = resolve_repository(repo_url, host).and_then(
+            |(host, repo_owner, repo_name)| {
+                fetch_branch_merge_request(
+                    &api,
+                    &repo_owner,
+                    &repo_name,
+                    host,
+                    &branch,
+                )
+            },
+        );
+        match merge_request {
+            Ok(Some(merge_request)) => {
+                info!(
+                    "Found {} for branch {}: {}",
+                    merge_request.link, branch, merge_request.title
+                );
+                merge_request.id.to_string()
+            }
+            result => {
+                if let Err(error) = result {
+                    emit_warning(
+                        Err::<(), _>(error)
+                            .whatever_context(miette!(
+                                severity = Severity::Warning,
+                                code = "new::branch_lookup",
+                                "Failed to look up the merge/pull request for branch {}",
+                                branch
+                            ))
+                            .unwrap_err(),
+                    );
+                }
+                let default = sanitize_branch_name(&branch);
+                if interaction == Interaction::Automatic {
+                    default
+                } else {
+                    prompt(
+                        prompter,
+                        &format!("No open merge/pull request found for branch {branch}. Please enter its id (default: {default}): "),
+                        |value| value.parse::<u64>().is_ok(),
+                        |_| {},
+                        default.as_str(),
+                        yes,
+                    )?
+                }
+            }
+        }
+    };
+
+    let path = directory.join(format!("{name}.md"));
+    if path.exists() {
+        return Err(miette!(
+            code = "new::changelog_exists",
+            help = "Edit the existing changelog instead.",
+            "Changelog {} already exists",
+            path
+        ));
+    }
+    write_file(
+        &path,
+        format!("## {section}\n- {}\n", opts.description.trim()),
+        atomic,
+    )
+    .into_diagnostic()
+    .wrap_err(format!("Failed to write changelog to {}", path))?;
+    info!("Created changelog at {}", path);
+
+    Ok(())
+}
+
+fn announce_notes(opts: AnnounceOpts, config: Config) -> Result<()> {
+    if config.announce.is_empty() {
+        return Err(miette!(
+            code = "announce::missing_targets",
+            help = "Add an `[[announce]]` entry with a `kind` and `url` to the config.",
+            "No webhooks configured to announce to"
+        ));
+    }
+
+    let notes = if let Some(path) = opts.notes {
+        read_file(&path)
+            .into_diagnostic()
+            .wrap_err(format!("Failed to read notes from {}", path))?
+    } else {
+        io::read_to_string(io::stdin())
+            .map(strip_byte_order_mark)
+            .into_diagnostic()
+            .wrap_err("Failed to read notes from standard input")?
+    };
+
+    // Build every request up front so that a misconfigured webhook does not
+    // leave the announcement half-sent.
+    let announcements = config
+        .announce
+        .iter()
+        .map(|target| target.prepare(&notes))
+        .collect::<Result<Vec<_>>>()?;
+    for announcement in announcements {
+        if opts.dry_run {
+            announcement.print();
+        } else {
+            let description = announcement.description.clone();
+            announcement.send()?;
+            info!("Announced to {}", description);
+        }
+    }
+
+    Ok(())
+}
+
+/// Entries read from changelog files, by section, with the heading level they
+/// were found at.
+type ChangelogSections = HashMap<String, (u8, Vec<Entry>)>;
+
+/// What [`read_changelogs`] found.
+struct ReadChangelogs {
+    sections: ChangelogSections,
+    /// The sections in the order they first appear in the changelogs.
+    section_order: Vec<String>,
+    unused_changelogs: Vec<(Utf8PathBuf, UnusedChangelogReason)>,
+    /// How changelogs were matched to merge/pull requests without asking, in
+    /// non-interactive mode.
+    automatic_matches: Vec<AutomaticMatch>,
+    /// Changelogs added longer ago than the `stale-after` config option
+    /// allows, with their age in days.
+    stale_changelogs: Vec<(Utf8PathBuf, u64)>,
+    /// How many warnings about content in the changelogs that is left out,
+    /// e.g., under unknown headings, were reported.
+    content_warnings: usize,
+}
+
+/// The `changelogs` that git history says were added more than `days` days
+/// ago, with their age in days, oldest first. Changelogs that are not
+/// committed yet are never stale.
+fn find_stale_changelogs(
+    directories: &[Utf8PathBuf],
+    changelogs: &[(Utf8PathBuf, String)],
+    days: u64,
+) -> Result<Vec<(Utf8PathBuf, u64)>> {
+    let now = now();
+    let mut added = HashMap::new();
+    for directory in directories {
+        // One run per directory rather than per changelog. The log is newest
+        // first, so a changelog that was deleted and added again is dated by
+        // its last addition.
+        let Some(log) = git_output([
+            "-C",
+            directory.as_str(),
+            "log",
+            "--diff-filter=A",
+            "--relative",
+            "--format=%x00%ct",
+            "--name-only",
+            "--",
+            ".",
+        ])?
+        else {
+            debug!("Not checking for stale changelogs outside of git history");
+            continue;
+        };
+        let mut timestamp = None;
+        for line in log.lines() {
+            if let Some(commit_time) = line.strip_prefix('\0') {
+                timestamp = commit_time.parse::<u64>().ok();
+            } else if let (Some(timestamp), false) =
+                (timestamp, line.is_empty())
+            {
+                added.entry(directory.join(line)).or_insert(timestamp);
+            }
+        }
+    }
+
+    let mut stale = changelogs
+        .iter()
+        .filter_map(|(path, _)| {
+            let age = now.saturating_sub(*added.get(path)?) / 86400;
+            (age > days).then(|| (path.clone(), age))
+        })
+        .collect::<Vec<_>>();
+    stale.sort_by_key(|(_, age)| Reverse(*age));
+    Ok(stale)
+}
+
+/// An item of a changelog, with the section it goes in.
+struct ParsedItem {
+    section: String,
+    /// The level of the heading it was under.
+    level: u8,
+    /// The first paragraph (or other block) of the item.
+    item: String,
+    /// The blocks after the first, such as nested lists and further
+    /// paragraphs, indented to go under it and each preceded by the line
+    /// breaks that separate it from the one before.
+    details: String,
+    breaking: bool,
+}
+
+/// The fields a changelog can set between `---` lines at its top, which take
+/// precedence over what is inferred from its name and headings.
+#[derive(Default, Deserialize)]
+struct FrontMatter {
+    /// The id of the merge/pull request it belongs to.
+    pr: Option<u64>,
+    /// The section all of its items go in, whatever they are under.
+    section: Option<String>,
+    #[serde(default)]
+    authors: Vec<String>,
+}
+
+impl ParsedItem {
+    /// The `item` under `section`, which is hoisted into the breaking changes
+    /// section if it is marked as breaking and the config says to.
+    fn new(
+        section: &str,
+        level: u8,
+        item: &str,
+        details: String,
+        config: &Config,
+    ) -> Self {
+        let (item, breaking) = match item.strip_prefix(&config.breaking.prefix)
+        {
+            Some(rest) => (rest.trim_start_matches(':').trim(), true),
+            None => (item, section == config.breaking.heading),
+        };
+        let section = if breaking && config.breaking.mode == BreakingMode::Hoist
+        {
+            &config.breaking.section
+        } else {
+            section
+        };
+        Self {
+            section: section.to_string(),
+            level,
+            item: item.to_string(),
+            details,
+            breaking,
+        }
+    }
+}
+
+/// Something in a changelog that is read differently than its author likely
+/// meant.
+enum FragmentProblem {
+    /// A heading that is not a section.
+    UnknownHeading(String),
+    /// A heading with no text to read a section from, e.g., one with only
+    /// inline HTML, and whether it is empty.
+    UnsupportedHeading(bool),
+    /// A list item before any section heading, which is left out.
+    ItemOutsideSection,
+    /// A paragraph, or a code block if true, under a section that is left
+    /// out because it is not part of a list item.
+    DroppedBlock(bool),
+}
+
+impl FragmentProblem {
+    /// The warning for this problem at `span`.
+    fn report(&self, span: Range<usize>, section_names: &[String]) -> Report {
+        match self {
+            Self::UnknownHeading(heading) => miette!(
+                severity = Severity::Warning,
+                code = "main::unknown_heading",
+                labels = vec![LabeledSpan::at(span, "not a known section")],
+                help = format!(
+                    "Use one of the sections ({}) or map this heading to one with the `aliases` config option.",
+                    section_names.join(", ")
+                ),
+                "Unknown section heading '{}'",
+                heading
+            ),
+            Self::UnsupportedHeading(empty) => miette!(
+                severity = Severity::Warning,
+                code = "main::unsupported_heading",
+                labels = vec![LabeledSpan::at(
+                    span,
+                    if *empty {
+                        "empty heading"
+                    } else {
+                        "only unsupported inline content, such as HTML"
+                    }
+                )],
+                help = "Write the section name as text, e.g., `## Added`; emphasis, code spans, and links around it are fine.",
+                "Section heading has no text to read a section from"
+            ),
+            Self::ItemOutsideSection => miette!(
+                severity = Severity::Warning,
+                code = "main::item_outside_section",
+                labels = vec![LabeledSpan::at(span, "left out")],
+                help = "Put it under a section heading, or set `section` in the front matter.",
+                "List item outside any section"
+            ),
+            Self::DroppedBlock(code) => miette!(
+                severity = Severity::Warning,
+                code = "main::dropped_block",
+                labels = vec![LabeledSpan::at(span, "left out")],
+                help = if *code {
+                    "Put it in the list item it belongs to, or set `prose = \"entries\"` in the config to add it to the entry before it."
+                } else {
+                    "Make it a list item, or set `prose = \"entries\"` in the config to make paragraphs entries."
+                },
+                "{} outside a list item",
+                if *code { "Code block" } else { "Paragraph" }
+            ),
+        }
+    }
+}
+
+/// The byte range of `sourcepos` in `contents`, without the blank lines that
+/// comrak counts as part of a list item.
+fn source_span(
+    contents: &str,
+    sourcepos: comrak::nodes::Sourcepos,
+) -> Range<usize> {
+    let start = SourceOffset::from_location(
+        contents,
+        sourcepos.start.line,
+        sourcepos.start.column,
+    );
+    let end = SourceOffset::from_location(
+        contents,
+        sourcepos.end.line,
+        sourcepos.end.column,
+    );
+    let end = (end.offset() + 1).min(contents.len());
+    start.offset()
+        ..start.offset() + contents[start.offset()..end].trim_end().len()
+}
+
+/// A changelog read and parsed, but not yet resolved to a merge/pull request.
+struct ParsedChangelog {
+    path: Utf8PathBuf,
+    contents: String,
+    front_matter: FrontMatter,
+    items: Vec<ParsedItem>,
+    /// Whether there were items outside of any section.
+    filtered_items: bool,
+    /// What is wrong with it, with where. Problems are only reported once
+    /// the changelog is known to be used.
+    problems: Vec<(FragmentProblem, Range<usize>)>,
+    /// The result of [`resolve_changelog_pr_automatic`], if it was used.
+    automatic: Option<(Option<Link>, Option<AutomaticMatch>)>,
+}
+
+/// Parses the items in the changelog at `path` into the sections they belong
+/// to.
+fn parse_changelog(
+    path: Utf8PathBuf,
+    contents: String,
+    section_names: &[String],
+    config: &Config,
+) -> Result<ParsedChangelog> {
+    let arena = comrak::Arena::new();
+    let mut options = comrak::Options::default();
+    options.extension.front_matter_delimiter = Some("---".into());
+    let mut front_matter = FrontMatter::default();
+    let mut current_section = None;
+    let mut items = Vec::new();
+    let mut filtered_items = false;
+    let mut problems = Vec::new();
+    // The item that prose after it in the same section can be attached to.
+    let mut last_item_in_section = None;
+    for node in
+        comrak::parse_document(&arena, &contents, &options).descendants()
+    {
+        match node.data.borrow().value {
+            comrak::nodes::NodeValue::FrontMatter(ref text) => {
+                let yaml = text.trim().trim_matches('-');
+                front_matter = serde_yaml::from_str::<Option<FrontMatter>>(
+                    yaml,
+                )
+                .map_err(|error| {
+                    miette!(
+                        code = "main::invalid_front_matter",
+                        labels = vec![LabeledSpan::at(
+                            0..text.trim_end().len(),
+                            "front matter"
+                        )],
+                        help = "Front matter may set `pr` (a number), `section`, and `authors` (a list).",
+                        "Invalid front matter in {}: {}",
+                        path,
+                        error
+                    )
+                    .with_source_code(
+                        NamedSource::new(path.as_str(), contents.clone())
+                            .with_language("markdown"),
+                    )
+                })?
+                .unwrap_or_default();
+                if let Some(section) = &front_matter.section {
+                    let section =
+                        canonical_section(section, section_names, config)
+                            .unwrap_or_else(|| section.clone());
+                    // The level sections are written at in most changelogs.
+                    current_section = Some((section, 2));
+                }
+            }
+            comrak::nodes::NodeValue::Heading(_)
+                if front_matter.section.is_some() => {}
+            comrak::nodes::NodeValue::Heading(heading) => {
+                let heading_string = node_text(node);
+                let heading_string = heading_string.trim();
+                let span = source_span(&contents, node.data.borrow().sourcepos);
+                if heading_string.is_empty() {
+                    problems.push((
+                        FragmentProblem::UnsupportedHeading(
+                            node.first_child().is_none(),
+                        ),
+                        span,
+                    ));
+                    current_section = None;
+                    continue;
+                }
+                let section =
+                    canonical_section(heading_string, section_names, config)
+                        .unwrap_or_else(|| {
+                            problems.push((
+                                FragmentProblem::UnknownHeading(
+                                    heading_string.to_string(),
+                                ),
+                                span,
+                            ));
+                            heading_string.to_string()
+                        });
+                if section != heading_string {
+                    debug!(
+                        "Reading heading '{}' in {} as section {}",
+                        heading_string, path, section
+                    );
+                }
+                current_section = Some((section, heading.level));
+                last_item_in_section = None;
+            }
+            comrak::nodes::NodeValue::Paragraph
+            | comrak::nodes::NodeValue::CodeBlock(_)
+                if node.parent().is_some_and(|parent| {
+                    matches!(
+                        parent.data.borrow().value,
+                        comrak::nodes::NodeValue::Document
+                    )
+                }) =>
+            {
+                let Some((section, level)) = current_section.as_ref() else {
+                    continue;
+                };
+                let code = matches!(
+                    node.data.borrow().value,
+                    comrak::nodes::NodeValue::CodeBlock(_)
+                );
+                let span = source_span(&contents, node.data.borrow().sourcepos);
+                if config.prose == ProseMode::Drop {
+                    problems.push((FragmentProblem::DroppedBlock(code), span));
+                    continue;
+                }
+                let block = if config.preserve_formatting {
+                    contents[span.clone()].to_string()
+                } else {
+                    let mut result = Vec::new();
+                    comrak::format_commonmark(
+                        node,
+                        &comrak::Options::default(),
+                        &mut result,
+                    )
+                    .into_diagnostic()
+                    .wrap_err("Failed to format document")?;
+                    String::from_utf8(result)
+                        .into_diagnostic()
+                        .wrap_err("Markdown block was not valid UTF-8")?
+                };
+                if !code {
+                    items.push(ParsedItem::new(
+                        section,
+                        *level,
+                        &indent_block(block.trim(), true),
+                        String::new(),
+                        config,
+                    ));
+                    last_item_in_section = Some(items.len() - 1);
+                } else if let Some(index) = last_item_in_section {
+                    // A code block cannot be followed by the link on its
+                    // last line, so it goes with the entry it illustrates.
+                    let details = &mut items[index].details;
+                    details.push_str("\n\n");
+                    details.push_str(&indent_block(block.trim_end(), false));
+                } else {
+                    problems.push((FragmentProblem::DroppedBlock(code), span));
+                }
+            }
+            comrak::nodes::NodeValue::Item(list) => {
+                // Nested items are part of the item they are nested in.
+                if node.ancestors().skip(1).any(|ancestor| {
+                    matches!(
+                        ancestor.data.borrow().value,
+                        comrak::nodes::NodeValue::Item(_)
+                    )
+                }) {
+                    continue;
+                }
+                let (item, details) = if config.preserve_formatting {
+                    item_source(&contents, node, list)
+                } else {
+                    item_markdown(node)?
+                };
+                if let Some((section, level)) = current_section.as_ref() {
+                    items.push(ParsedItem::new(
+                        section, *level, &item, details, config,
+                    ));
+                    last_item_in_section = Some(items.len() - 1);
+                } else {
+                    problems.push((
+                        FragmentProblem::ItemOutsideSection,
+                        source_span(&contents, node.data.borrow().sourcepos),
+                    ));
+                    filtered_items = true;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(ParsedChangelog {
+        path,
+        contents,
+        front_matter,
+        items,
+        filtered_items,
+        problems,
+        automatic: None,
+    })
+}
+
+/// Reads the changelogs in `directories` and the changelog `files` into
+/// entries under `section_names`, resolving which merge/pull request each
+/// belongs to, and reports the files that contributed nothing.
+#[allow(clippy::too_many_arguments)]
+fn read_changelogs(
+    (directories, files): (&[Utf8PathBuf], &[Utf8PathBuf]),
+    section_names: &[String],
+    recursive: bool,
+    symlinks: SymlinkPolicy,
+    config: &Config,
+    pull_requests: &[PullRequest],
+    repo: Repository,
+    interaction: Interaction,
+    yes: bool,
+    prompter: &mut dyn Prompter,
+) -> Result<ReadChangelogs> {
+    let mut sections = ChangelogSections::new();
+    let mut section_order = Vec::new();
+    let mut unused_changelogs = Vec::new();
+    let mut automatic_matches = Vec::new();
+    let mut content_warnings = 0;
+
+    let exclusions = Exclusions::load(config.release_config.as_deref())?;
+    let mut changelogs = Vec::new();
+    let mut paths = collect_changelogs(
+        directories,
+        &config.fragments,
+        &config.exclude,
+        recursive,
+        symlinks,
+    )?;
+    paths.extend_from_slice(files);
+    paths.sort();
+    paths.dedup();
+    for (path, contents) in paths
+        .into_par_iter()
+        .map(|path| {
+            let contents = read_file(&path);
+            (path, contents)
+        })
+        .collect::<Vec<_>>()
+    {
+        match contents {
+            Ok(contents) => changelogs.push((path, contents)),
+            Err(error) => unused_changelogs
+                .push((path, UnusedChangelogReason::Unreadable(error))),
+        }
+    }
+
+    if let Some(command) = &config.hooks.pre_merge {
+        changelogs = hooks::pre_merge(command, changelogs)?;
+    }
+
+    let stale_changelogs = match config.stale_after {
+        Some(days) => {
+            // Explicit files are looked up in the history of their directory.
+            let mut directories = directories.to_vec();
+            directories.extend(
+                files
+                    .iter()
+                    .filter_map(|file| file.parent())
+                    .map(Utf8Path::to_path_buf),
+            );
+            directories.sort();
+            directories.dedup();
+            find_stale_changelogs(&directories, &changelogs, days)?
+        }
+        None => Vec::new(),
+    };
+
+    let progress_bar = logging::progress()
+        .add(ProgressBar::new(changelogs.len() as u64))
+        .with_style(
+            ProgressStyle::with_template("{bar:20} {pos}/{len} changelogs")
+                .expect("progress bar template is valid")
+                .progress_chars(console::symbols().progress),
+        );
+    // Everything but asking the user is independent for each changelog, so
+    // only the prompts are left for afterward.
+    let parsed = changelogs
+        .into_par_iter()
+        .map(|(path, contents)| {
+            let parsed = parse_changelog(path, contents, section_names, config)
+                .map(|mut parsed| {
+                    if interaction == Interaction::Automatic
+                        && parsed.front_matter.pr.is_none()
+                    {
+                        parsed.automatic =
+                            parsed.path.file_stem().map(|file_stem| {
+                                resolve_changelog_pr_automatic(
+                                    &parsed.path,
+                                    file_stem,
+                                    &parsed.contents,
+                                    pull_requests,
+                                    repo,
+                                )
+                            });
+                    }
+                    parsed
+                });
+            progress_bar.inc(1);
+            parsed
+        })
+        .collect::<Result<Vec<_>>>()?;
+    progress_bar.finish_and_clear();
+
+    let mut confirmed = if interaction == Interaction::Automatic {
+        HashMap::new()
+    } else {
+        confirm_resolved_by_id(
+            &parsed
+                .iter()
+                .filter(|parsed| parsed.front_matter.pr.is_none())
+                .map(|parsed| parsed.path.as_path())
+                .collect::<Vec<_>>(),
+            pull_requests,
+            yes,
+            prompter,
+            repo,
+        )?
+    };
+
+    let count = parsed.len();
+    for ParsedChangelog {
+        path,
+        contents,
+        front_matter,
+        items,
+        filtered_items,
+        problems,
+        automatic,
+    } in parsed
+    {
+        let Some(file_stem) = path.file_stem() else {
+            continue;
+        };
+
+        let (link, resolution) = if let Some(id) = front_matter.pr {
+            (
+                pull_request_link_by_id(id, pull_requests, repo),
+                Resolution::FrontMatter,
+            )
+        } else if let Some(link) = confirmed.remove(&path) {
+            match link {
+                Some(link) => (link, Resolution::FileName),
+                None => (
+                    logging::suspend(|| {
+                        resolve_changelog_pr_manually(
+                            &path,
+                            &contents,
+                            pull_requests,
+                            repo,
+                            interaction == Interaction::Tui,
+                            yes,
+                            prompter,
+                        )
+                    })?,
+                    Resolution::Prompt,
+                ),
+            }
+        } else if let Some((link, automatic_match)) = automatic {
+            let resolution = match &automatic_match {
+                Some(automatic_match) => {
+                    Resolution::Guess(automatic_match.candidates[0].1)
+                }
+                None => Resolution::FileName,
+            };
+            automatic_matches.extend(automatic_match);
+            let Some(link) = link else {
+                unused_changelogs
+                    .push((path.clone(), UnusedChangelogReason::Unmatched));
+                continue;
+            };
+            (link, resolution)
+        } else {
+            let resolution = if file_stem.parse::<u64>().is_ok() {
+                Resolution::FileName
+            } else {
+                Resolution::Prompt
+            };
+            // The progress bars would draw over the prompts otherwise.
+            let link = logging::suspend(|| {
+                resolve_changelog_pr_interactive(
+                    &path,
+                    &contents,
+                    pull_requests,
+                    repo,
+                    interaction == Interaction::Tui,
+                    yes,
+                    prompter,
+                )
+            })?;
+            (link, resolution)
+        };
+        debug!("Resolved {} to {}", path, link.shorthand);
+        let pull_request = pull_requests
+            .iter()
+            .find(|pull_request| pull_request.link == link.shorthand)
+            .cloned();
+        if let Some(reason) =
+            exclusions.as_ref().zip(pull_request.as_ref()).and_then(
+                |(exclusions, pull_request)| exclusions.reason(pull_request),
+            )
+        {
+            unused_changelogs
+                .push((path.clone(), UnusedChangelogReason::Excluded(reason)));
+            continue;
+        }
+
+        content_warnings += problems.len();
+        for (problem, span) in problems {
+            emit_warning(
+                problem.report(span, section_names).with_source_code(
+                    NamedSource::new(path.as_str(), contents.clone())
+                        .with_language("markdown"),
+                ),
+            );
+        }
+        let mut item_sections = Vec::new();
+        for ParsedItem {
+            section,
+            level,
+            item,
+            details,
+            breaking,
+        } in items
+        {
+            if !sections.contains_key(&section) {
+                section_order.push(section.clone());
+            }
+            sections
+                .entry(section.clone())
+                .or_insert((level, vec![]))
+                .1
+                .push(Entry {
+                    item,
+                    details,
+                    links: vec![link.clone()],
+                    breaking,
+                    pull_request: pull_request.clone(),
+                    authors: if front_matter.authors.is_empty() {
+                        pull_request
+                            .as_ref()
+                            .and_then(|pull_request| {
+                                pull_request.author.clone()
+                            })
+                            .into_iter()
+                            .collect()
+                    } else {
+                        front_matter.authors.clone()
+                    },
+                    provenance: vec![Provenance {
+                        path: path.clone(),
+                        resolution,
+                    }],
+                });
+            if !item_sections.contains(&section) {
+                item_sections.push(section);
+            }
+        }
+
+        let reason = if item_sections.is_empty() {
+            Some(if filtered_items {
+                UnusedChangelogReason::Filtered
+            } else {
+                UnusedChangelogReason::Empty
+            })
+        } else if !item_sections
+            .iter()
+            .any(|section| section_names.contains(section))
+        {
+            Some(UnusedChangelogReason::UnknownSections(item_sections))
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            unused_changelogs.push((path.clone(), reason));
+        }
+    }
+    info!("Read {} changelogs", count);
+
+    Ok(ReadChangelogs {
+        sections,
+        section_order,
+        unused_changelogs,
+        automatic_matches,
+        stale_changelogs,
+        content_warnings,
+    })
+}
+
+fn release(
+    opts: ReleaseOpts,
+    repo_url: Option<Url>,
+    host: RepositoryHost,
+    config: &Config,
+    atomic: bool,
+) -> Result<()> {
+    let contents = read_file(&opts.changelog)
+        .into_diagnostic()
+        .wrap_err(format!("Failed to read changelog {}", opts.changelog))?;
+    let mut changelog = Changelog::parse(&contents);
+    let version = opts.version.trim_start_matches('v');
+
+    // Prefer rewriting the existing compare link over building one, so that
+    // the changelog keeps pointing wherever it already did.
+    let (tag, unreleased_link, version_link) = match changelog
+        .unreleased_compare_link()
+    {
+        Some((url, previous_tag)) => {
+            let tag = release_tag(&opts.version, Some(&previous_tag));
+            (
+                tag.clone(),
+                url.replace(
+                    &format!("{previous_tag}...HEAD"),
+                    &format!("{tag}...HEAD"),
+                ),
+                url.replace("...HEAD", &format!("...{tag}")),
+            )
+        }
+        None => {
+            let (host, repo_owner, repo_name) =
+                resolve_repository(repo_url, host)?;
+            let repo = Repository::new(host, &repo_owner, &repo_name, config);
+            let previous_tag = previous_release_tag(&changelog)?;
+            let tag = release_tag(&opts.version, previous_tag.as_deref());
+            (
+                tag.clone(),
+                make_compare_link(Some(&tag), "HEAD", repo),
+                make_compare_link(previous_tag.as_deref(), &tag, repo),
+            )
+        }
+    };
+    if opts.tag
+        && git_output([
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("refs/tags/{tag}"),
+        ])?
+        .is_some()
+    {
+        return Err(miette!(
+            code = "release::tag_exists",
+            help = format!(
+                "Delete the tag with `git tag -d {tag}` if it is wrong, or release another version."
+            ),
+            "Tag {} already exists",
+            tag
+        ));
+    }
+
+    changelog.promote(version, &today(), (&unreleased_link, &version_link))?;
+    // Set the versions before writing anything, so that a file without one
+    // leaves the changelog as it was.
+    let manifest = Utf8PathBuf::from("Cargo.toml");
+    let cargo_manifest = VersionFileConfig {
+        key: Some("package.version".to_string()),
+        pattern: None,
+    };
+    let version_files = config.version_files.iter().chain(
+        (opts.bump_cargo && !config.version_files.contains_key(&manifest))
+            .then_some((&manifest, &cargo_manifest)),
+    );
+    let mut bumped_files = Vec::new();
+    for (path, file) in version_files {
+        bumped_files.push((path, bump_version_file(path, file, version)?));
+    }
+    write_file(&opts.changelog, changelog.to_string(), atomic)
+        .into_diagnostic()
+        .wrap_err(format!("Failed to write changelog {}", opts.changelog))?;
+    info!("Released {} in {}", version, opts.changelog);
+    let mut changed = vec![opts.changelog.as_str()];
+    for (path, contents) in bumped_files {
+        write_file(path, contents, atomic)
+            .into_diagnostic()
+            .wrap_err(format!("Failed to write {}", path))?;
+        info!("Set the version in {} to {}", path, version);
+        changed.push(path.as_str());
+    }
+
+    if opts.commit {
+        let message = config
+            .release
+            .commit_message
+            .replace("{version}", version)
+            .replace("{tag}", &tag);
+        git_run(["add", "--"].into_iter().chain(changed.iter().copied()))?;
+        git_run(
+            ["commit", "--quiet", "--message", &message, "--"]
+                .into_iter()
+                .chain(changed.iter().copied()),
+        )?;
+        info!("Committed the release of {}", version);
+    }
+    if opts.tag {
+        let release = changelog
+            .release(version)
+            .expect("the release was just promoted");
+        // Verbatim, since git would otherwise strip the headings as comments.
+        git_run([
+            "tag",
+            "--annotate",
+            "--cleanup=verbatim",
+            "--message",
+            &changelog.notes(release),
+            &tag,
+        ])?;
+        info!("Tagged the release of {} as {}", version, tag);
+    }
+
+    Ok(())
+}
+
+/// The contents of the file at `path` with the version where `file` says it
+/// is set to `version`.
+fn bump_version_file(
+    path: &Utf8Path,
+    file: &VersionFileConfig,
+    version: &str,
+) -> Result<String> {
+    let contents = read_file(path)
+        .into_diagnostic()
+        .wrap_err(format!("Failed to read {}", path))?;
+    let pattern = file
+        .pattern
+        .as_deref()
+        .map(Regex::new)
+        .transpose()
+        .into_diagnostic()
+        .wrap_err(format!("Invalid version pattern for {}", path))?;
+    let target = match (&file.key, &pattern) {
+        (Some(_), Some(_)) => {
+            return Err(miette!(
+                code = "release::conflicting_version_target",
+                help = "Keep only one of `key` and `pattern`.",
+                "Version file {} has both a key and a pattern",
+                path
+            ));
+        }
+        (Some(key), None) => version_file::Target::Key(key),
+        (None, Some(pattern)) => version_file::Target::Pattern(pattern),
+        (None, None) => version_file::Target::Whole,
+    };
+    version_file::set_version(&contents, &target, version)
+        .wrap_err(format!("Failed to set the version in {}", path))
+}
+
+/// The tags of releases in the repository, oldest first, each with the day it
+/// was tagged as `YYYY-MM-DD`.
+fn release_tags() -> Result<Vec<(String, String)>> {
+    let refs = git_output([
+        "for-each-ref",
+        "--format=%(refname:short) %(creatordate:short)",
+        "refs/tags",
+    ])?
+    .whatever_context(miette!(
+        code = "backfill::outside_repository",
+        help = "Run mergelog in the repository whose releases to backfill.",
+        "Cannot read the release tags outside of a git repository"
+    ))?;
+    let mut tags = refs
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .filter(|(tag, _)| matches!(query::release_heading(tag), Some(Some(_))))
+        .map(|(tag, date)| (tag.to_string(), date.to_string()))
+        .collect::<Vec<_>>();
+    tags.sort_by(|lhs, rhs| {
+        natural_cmp(
+            lhs.0.trim_start_matches('v'),
+            rhs.0.trim_start_matches('v'),
+        )
+    });
+    Ok(tags)
+}
+
+/// Writes a changelog with a release for each tag from `opts.from` to
+/// `opts.to`, made of the merge/pull requests merged since the tag before it.
+/// Those with a known commit go in the first release whose tag contains it,
+/// and the others in the first release tagged on or after the day they were
+/// merged.
+fn backfill(
+    mut opts: BackfillOpts,
+    repo_url: Option<Url>,
+    host: RepositoryHost,
+    api: Api,
+    config: &Config,
+    atomic: bool,
+) -> Result<()> {
+    if opts.section.is_empty() {
+        opts.section = config.section_names();
+    }
+    if opts.section.is_empty() {
+        opts.section = KEEP_A_CHANGELOG_SECTIONS.map(String::from).to_vec();
+    }
+    if let Some(section) = &opts.default_section {
+        if !opts.section.contains(section) {
+            opts.section.push(section.clone());
+        }
+    }
+
+    let tags = release_tags()?;
+    let position = |tag: &Option<String>, default: usize| match tag {
+        Some(tag) => tags
+            .iter()
+            .position(|(other, _)| other == tag)
+            .whatever_context(miette!(
+                code = "backfill::unknown_tag",
+                help = "Pass one of the tags that `git tag` lists.",
+                "Tag {} is not a release tag of the repository",
+                tag
+            )),
+        None => Ok(default),
+    };
+    if tags.is_empty() {
+        return Err(miette!(
+            code = "backfill::no_tags",
+            help = "Tag the commit of each release, e.g., with `git tag v1.0.0 <commit>`.",
+            "The repository has no release tags to backfill"
+        ));
+    }
+    let from = position(&opts.from, 0)?;
+    let to = position(&opts.to, tags.len() - 1)?;
+    if from > to {
+        return Err(miette!(
+            code = "backfill::reversed_range",
+            help = "Swap `--from` and `--to`.",
+            "Release {} comes after {}",
+            tags[from].0,
+            tags[to].0
+        ));
+    }
+
+    let (host, repo_owner, repo_name) = resolve_repository(repo_url, host)?;
+    let repo = Repository::new(host, &repo_owner, &repo_name, config);
+    let mut pull_requests =
+        fetch_merge_requests_with_spinner(&api, &repo_owner, &repo_name, host)?;
+    drop_excluded(&mut pull_requests, config)?;
+
+    let mut changelog = Changelog::parse("# Changelog\n");
+    for index in from..=to {
+        let (tag, date) = &tags[index];
+        let previous = index.checked_sub(1).map(|index| &tags[index]);
+        let range = match previous {
+            Some((previous, _)) => format!("{previous}..{tag}"),
+            None => tag.clone(),
+        };
+        let commits = git_output(["rev-list", &range])?.unwrap_or_default();
+        let commits = commits.lines().collect::<HashSet<_>>();
+        let released = pull_requests
+            .iter()
+            .filter(|pull_request| {
+                if !pull_request.commits.is_empty() {
+                    return pull_request
+                        .commits
+                        .iter()
+                        .any(|commit| commits.contains(commit.as_str()));
+                }
+                pull_request.merged_at.as_deref().is_some_and(|merged_at| {
+                    let day = merged_at.get(..10).unwrap_or(merged_at);
+                    day <= date.as_str()
+                        && previous
+                            .is_none_or(|(_, previous)| day > previous.as_str())
+                })
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let ReadChangelogs {
+            mut sections,
+            section_order,
+            ..
+        } = read_milestone(
+            &released,
+            &opts.section,
+            config,
+            repo,
+            opts.default_section.as_deref(),
+        );
+        let mut section_names = opts.section.clone();
+        order_sections(&mut section_names, config, &section_order);
+        let (rendered_sections, link_definitions) = render_sections(
+            &section_names,
+            &mut sections,
+            config,
+            None,
+            Some(3),
+            Grouping::Section,
+            false,
+        );
+        let previous = previous.map(|(previous, _)| previous.as_str());
+        changelog.update_unreleased(
+            &rendered_sections,
+            &link_definitions,
+            &make_compare_link(previous, "HEAD", repo),
+            &config.list,
+        );
+        changelog.promote(
+            tag.trim_start_matches('v'),
+            date,
+            (
+                &make_compare_link(Some(tag), "HEAD", repo),
+                &make_compare_link(previous, tag, repo),
+            ),
+        )?;
+        info!(
+            "Backfilled {} with {} merge/pull request(s)",
+            tag,
+            released.len()
+        );
+    }
+
+    match &opts.output {
+        Some(output) => {
+            write_file(output, changelog.to_string(), atomic)
+                .into_diagnostic()
+                .wrap_err(format!("Failed to write changelog {}", output))?;
+            info!("Wrote {}", output);
+        }
+        None => print!("{changelog}"),
+    }
+    Ok(())
+}
+
+fn show_release(opts: ShowOpts) -> Result<()> {
+    let contents = read_file(&opts.changelog)
+        .into_diagnostic()
+        .wrap_err(format!("Failed to read changelog {}", opts.changelog))?;
+    let changelog = Changelog::parse(&contents);
+    let release = match opts.version.as_str() {
+        "latest" => changelog.latest(),
+        version => changelog.release(version),
+    }
+    .whatever_context(miette!(
+        code = "show::missing_release",
+        help = "Release the Unreleased section first with `mergelog release <version>`.",
+        "Changelog {} has no release {}",
+        opts.changelog,
+        opts.version
+    ))?;
+    print!("{}", strip_provenance(&changelog.notes(release)));
+    Ok(())
+}
+
+fn combine_changelogs(opts: CombineOpts, atomic: bool) -> Result<()> {
+    let [contents, other] = [&opts.changelog, &opts.other].map(|path| {
+        read_file(path)
+            .into_diagnostic()
+            .wrap_err(format!("Failed to read changelog {}", path))
+    });
+    let mut changelog = Changelog::parse(&contents?);
+    for (label, ours, theirs) in changelog.combine(Changelog::parse(&other?)) {
+        emit_warning(miette!(
+            severity = Severity::Warning,
+            code = "combine::conflicting_link",
+            help = format!(
+                "The combined changelog keeps the link from {}; fix the definition there if the other is right.",
+                opts.changelog
+            ),
+            "Link [{}] points to {} in {} but to {} in {}",
+            label,
+            ours,
+            opts.changelog,
+            theirs,
+            opts.other
+        ));
+    }
+    let combined = changelog.to_string();
+    match &opts.output {
+        Some(output) => {
+            write_file(output, combined, atomic)
+                .into_diagnostic()
+                .wrap_err(format!("Failed to write changelog {}", output))?;
+            info!("Combined {} into {}", opts.other, output);
+        }
+        None => print!("{combined}"),
+    }
+    Ok(())
+}
+
+fn publish_release(
+    opts: PublishOpts,
+    repo_url: Option<Url>,
+    host: RepositoryHost,
+    api: Api,
+    config: Config,
+) -> Result<()> {
+    let contents = read_file(&opts.changelog)
+        .into_diagnostic()
+        .wrap_err(format!("Failed to read changelog {}", opts.changelog))?;
+    let changelog = Changelog::parse(&contents);
+    let release =
+        changelog.release(&opts.tag).whatever_context(miette!(
+            code = "publish::missing_release",
+            help = "Release the Unreleased section first with `mergelog release <version>`.",
+            "Changelog {} has no release for tag {}",
+            opts.changelog,
+            opts.tag
+        ))?;
+    let notes = changelog.notes(release);
+    // Every file is read before anything is published, so that a missing one
+    // does not leave a release half-published.
+    let mut assets = Vec::new();
+    for path in config.publish.assets.iter().chain(&opts.asset) {
+        let contents = fs::read(path).map_err(|error| {
+            miette!(
+                code = "publish::unreadable_asset",
+                help = "Check the paths under `publish.assets` and passed to `--asset`.",
+                "Failed to read asset {}: {}",
+                path,
+                error
+            )
+        })?;
+        assets.push(publish::Asset::File {
+            name: path.file_name().unwrap_or(path.as_str()).to_string(),
+            contents,
+        });
+    }
+    assets.extend(
+        config
+            .publish
+            .links
+            .into_iter()
+            .map(|(name, url)| publish::Asset::Link { name, url }),
+    );
+    let (host, repo_owner, repo_name) = resolve_repository(repo_url, host)?;
+    publish::publish(
+        &api,
+        (host, &repo_owner, &repo_name),
+        &opts.tag,
+        &strip_provenance(&notes),
+        &assets,
+        opts.dry_run,
+    )
+}
+
+fn release_feed(
+    opts: FeedOpts,
+    repo_url: Option<Url>,
+    host: RepositoryHost,
+    config: &Config,
+    atomic: bool,
+) -> Result<()> {
+    let contents = read_file(&opts.changelog)
+        .into_diagnostic()
+        .wrap_err(format!("Failed to read changelog {}", opts.changelog))?;
+    let (host, repo_owner, repo_name) = resolve_repository(repo_url, host)?;
+    let repository = Repository::new(host, &repo_owner, &repo_name, config);
+    let homepage = repository.url();
+    let latest_tag = git_output(["describe", "--tags", "--abbrev=0"])?;
+    let changelog = Changelog::parse(&contents);
+    let entries = changelog
+        .releases()
+        .take(opts.limit)
+        .map(|release| {
+            let version = release.version();
+            let notes = changelog.notes(release);
+            let date = match release.date() {
+                Some(date) if feed::is_date(date) => date.to_string(),
+                Some(date) => {
+                    emit_warning(miette!(
+                        severity = Severity::Warning,
+                        code = "feed::invalid_date",
+                        help = "Date the release heading like `## [1.4.0] - 2024-10-03`.",
+                        "Release {} in {} is dated {}, which is not a day, so the feed dates it today",
+                        version,
+                        opts.changelog,
+                        date
+                    ));
+                    today()
+                }
+                None => {
+                    emit_warning(miette!(
+                        severity = Severity::Warning,
+                        code = "feed::undated_release",
+                        help = "Date the release heading like `## [1.4.0] - 2024-10-03`.",
+                        "Release {} in {} has no date, so the feed dates it today",
+                        version,
+                        opts.changelog
+                    ));
+                    today()
+                }
+            };
+            let tag = release_tag(version, latest_tag.as_deref());
+            FeedEntry {
+                title: format!("{} {}", repo_name, version),
+                url: repository.release_page(&tag),
+                date,
+                notes: strip_provenance(&notes),
+            }
+        })
+        .collect::<Vec<_>>();
+    let feed = Feed {
+        title: opts
+            .title
+            .unwrap_or_else(|| format!("{} releases", repo_name)),
+        id: opts
+            .url
+            .clone()
+            .unwrap_or_else(|| repository.page("releases")),
+        url: opts.url,
+        homepage,
+        author: format!("{}/{}", repo_owner, repo_name),
+    };
+    let atom = feed::atom(&feed, &entries);
+    match &opts.output {
+        Some(output) => {
+            write_file(output, atom, atomic)
+                .into_diagnostic()
+                .wrap_err(format!("Failed to write {}", output))?;
+            info!("Wrote {} release(s) to {}", entries.len(), output);
+        }
+        None => print!("{atom}"),
+    }
+    Ok(())
+}
+
+fn enforce(
+    opts: EnforceOpts,
+    repo_url: Option<Url>,
+    host: RepositoryHost,
+    api: Api,
+    config: Config,
+) -> Result<()> {
+    let directory =
+        opts.directory
+            .or(config.directory.clone())
+            .whatever_context(miette!(
+        code = "enforce::missing_directory",
+        help = "Pass `--directory <path>` or set `directory` in the config.",
+        "No changelog directory provided"
+    ))?;
+    // The host lists paths from the repository root, not from here.
+    let prefix =
+        git_output(["rev-parse", "--show-prefix"])?.unwrap_or_default();
+    let directory = repository_path(&Utf8Path::new(&prefix).join(&directory));
+    let fragment_matcher = build_fragment_matcher(&config.fragments)?;
+
+    let (host, repo_owner, repo_name) = resolve_repository(repo_url, host)?;
+    let added_files = match host {
+        RepositoryHost::GitHub => return Err(host.unsupported()),
+        RepositoryHost::GitLab => {
+            let endpoint = format!(
+                "projects/{}%2F{}/merge_requests/{}",
+                repo_owner, repo_name, opts.pr
             );
-            link
+            let pull_request = api
+                .fetch_gitlab_json(&endpoint)
+                .and_then(|response| PullRequest::try_from_gitlab(&response))
+                .whatever_context(miette!(
+                    code = "enforce::api_error",
+                    "Failed to obtain !{} in {}/{}",
+                    opts.pr,
+                    repo_owner,
+                    repo_name
+                ))?;
+            if pull_request.labels.contains(&opts.skip_label) {
+                info!(
+                    "!{} is labeled {}, so it needs no changelog",
+                    opts.pr, opts.skip_label
+                );
+                return Ok(());
+            }
+            // Large merge requests change more files than fit on a page.
+            let added_files = api
+                .fetch_gitlab_array(
+                    &format!("{}/diffs?per_page=100", endpoint),
+                    Pages::All,
+                    "Expected an array of diffs",
+                    |diff| {
+                        let added =
+                            diff.get("new_file").and_then(JsonValue::as_bool)
+                                == Some(true);
+                        Ok(added
+                            .then(|| diff.get("new_path")?.as_str())
+                            .flatten()
+                            .map(Utf8PathBuf::from))
+                    },
+                )
+                .whatever_context(miette!(
+                    code = "enforce::api_error",
+                    "Failed to obtain the changes of !{} in {}/{}",
+                    opts.pr,
+                    repo_owner,
+                    repo_name
+                ))?;
+            added_files.into_iter().flatten().collect::<Vec<_>>()
+        }
+        RepositoryHost::Infer => unreachable!(),
+    };
+
+    let changelog = added_files.iter().find(|path| {
+        path.parent() == Some(directory.as_path())
+            && path
+                .file_name()
+                .is_some_and(|name| fragment_matcher.is_match(name))
+    });
+    match changelog {
+        Some(changelog) => {
+            info!("!{} adds changelog {}", opts.pr, changelog);
+            Ok(())
+        }
+        None => Err(miette!(
+            code = "enforce::missing_changelog",
+            help = format!(
+                "Add one with `mergelog new --pr {} <description>`, or label the merge request {} if it needs none.",
+                opts.pr, opts.skip_label
+            ),
+            "!{} adds no changelog to {}",
+            opts.pr,
+            if directory.as_str().is_empty() {
+                "the repository root"
+            } else {
+                directory.as_str()
+            }
+        )),
+    }
+}
+
+/// `path` without `.` components, and with `..` components folded into their
+/// parent directories, so it can be compared with paths listed by the host.
+fn repository_path(path: &Utf8Path) -> Utf8PathBuf {
+    let mut normalized = Utf8PathBuf::new();
+    for component in path.components() {
+        match component {
+            Utf8Component::CurDir => {}
+            Utf8Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+fn merge_driver(opts: MergeDriverOpts, atomic: bool) -> Result<()> {
+    let path = opts.path.as_ref().unwrap_or(&opts.ours);
+    let [base, ours, theirs] =
+        [&opts.base, &opts.ours, &opts.theirs].map(|version| {
+            read_file(version)
+                .into_diagnostic()
+                .wrap_err(format!("Failed to read {}", version))
+        });
+    if let Some(merged) = changelog::merge(&base?, &ours?, &theirs?) {
+        write_file(&opts.ours, merged, atomic)
+            .into_diagnostic()
+            .wrap_err(format!("Failed to write merged {}", path))?;
+        info!("Merged {}", path);
+        return Ok(());
+    }
+
+    // Leave the usual conflict markers for the parts that need a person.
+    debug!("Falling back to git merge-file for {}", path);
+    Command::new("git")
+        .args(["merge-file", "-L", "ours", "-L", "base", "-L", "theirs"])
+        .args([&opts.ours, &opts.base, &opts.theirs])
+        .status()
+        .into_diagnostic()
+        .wrap_err("Failed to run git merge-file")?;
+    Err(miette!(
+        code = "merge_driver::conflict",
+        help = "Both sides changed the same entry or heading differently, or one changed a section the other removed. Resolve the conflict markers by hand.",
+        "Could not merge {} automatically",
+        path
+    ))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn query(
+    opts: QueryOpts,
+    repo_url: Option<Url>,
+    host: RepositoryHost,
+    api: Api,
+    config: Config,
+    interaction: Interaction,
+    yes: bool,
+    prompter: &mut dyn Prompter,
+) -> Result<()> {
+    let directory = opts
+        .changelog_directory
+        .clone()
+        .or(config.directory.clone());
+    if directory.is_none() && opts.changelog.is_none() {
+        return Err(miette!(
+            code = "query::missing_changelogs",
+            help = "Pass a changelog directory, set `directory` in the config, or pass `--changelog <path>`.",
+            "No changelogs to query"
+        ));
+    }
+
+    let (host, repo_owner, repo_name) = resolve_repository(repo_url, host)?;
+    let mut pull_requests = if directory.is_some()
+        || opts.author.is_some()
+        || !opts.label.is_empty()
+    {
+        fetch_merge_requests_with_spinner(&api, &repo_owner, &repo_name, host)?
+    } else {
+        vec![]
+    };
+    drop_excluded(&mut pull_requests, &config)?;
+
+    let mut records = Vec::new();
+    if let Some(directory) = directory {
+        let mut section_names = if config.sections.is_empty() {
+            opts.section.clone()
         } else {
-            prompt(
-                || {
-                    eprint!("TODO: fix gitlab api requests to do pagination.\nfor now just tell me if it's ok (y/n):");
-                },
-                |value| ["y", "n"].contains(&value),
-                |value| {
-                    eprintln!(
-                        "✓ {}",
-                        format!("Processing changelog for {}", value).green()
-                    )
-                },
-                "y",
-            )?
+            config.section_names()
         };
-        Ok(make_pull_request_link(
-            id.to_string(),
-            link,
+        if config.breaking.mode == BreakingMode::Hoist {
+            section_names.insert(0, config.breaking.section.clone());
+        }
+        let ReadChangelogs { mut sections, .. } = read_changelogs(
+            (&[directory], &[]),
+            &section_names,
+            opts.recursive,
+            SymlinkPolicy::Follow,
+            &config,
+            &pull_requests,
+            Repository::new(host, &repo_owner, &repo_name, &config),
+            interaction,
+            yes,
+            prompter,
+        )?;
+        for section in section_names {
+            if let Some((_, mut entries)) = sections.remove(&section) {
+                handle_duplicate_entries(
+                    &section,
+                    &mut entries,
+                    config.duplicates,
+                );
+                sort_entries(&mut entries, config.sort);
+                records.extend(entries.into_iter().map(|entry| Record {
+                    version: None,
+                    section: section.clone(),
+                    entry,
+                }));
+            }
+        }
+    }
+    if let Some(changelog) = &opts.changelog {
+        let contents = read_file(changelog)
+            .into_diagnostic()
+            .wrap_err(format!("Failed to read changelog {}", changelog))?;
+        records.extend(query::parse_released(
+            &contents,
+            &config,
+            &pull_requests,
+            Repository::new(host, &repo_owner, &repo_name, &config),
+        )?);
+    }
+
+    records.retain(|record| query::matches(&opts, record));
+    query::print(&records, opts.format);
+
+    Ok(())
+}
+
+/// The sections Keep a Changelog allows, in the order it lists them.
+const KEEP_A_CHANGELOG_SECTIONS: [&str; 6] = [
+    "Added",
+    "Changed",
+    "Deprecated",
+    "Removed",
+    "Fixed",
+    "Security",
+];
+
+/// The packages picked with `--package` or `--all-packages`, by name.
+fn select_packages<'a>(
+    opts: &MergeOpts,
+    config: &'a Config,
+) -> Result<Vec<(&'a str, &'a PackageConfig)>> {
+    if opts.all_packages {
+        if !opts.package.is_empty() {
+            return Err(miette!(
+                code = "main::package_with_all_packages",
+                help = "Pass only one of `--package` and `--all-packages`.",
+                "Cannot merge some packages and all packages at once"
+            ));
+        }
+        if config.packages.is_empty() {
+            return Err(miette!(
+                code = "main::no_packages",
+                help = "List them in a `[packages]` table in the config.",
+                "No packages are configured"
+            ));
+        }
+        return Ok(config
+            .packages
+            .iter()
+            .map(|(name, package)| (name.as_str(), package))
+            .collect());
+    }
+    opts.package
+        .iter()
+        .map(|name| {
+            config
+                .packages
+                .get_key_value(name)
+                .map(|(name, package)| (name.as_str(), package))
+                .with_whatever_context(|| {
+                    miette!(
+                        code = "main::unknown_package",
+                        help = format!(
+                            "Use one of the packages in the config ({}).",
+                            config
+                                .packages
+                                .keys()
+                                .map(String::as_str)
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ),
+                        "Unknown package '{}'",
+                        name
+                    )
+                })
+        })
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn merge(
+    mut opts: MergeOpts,
+    repo_url: Option<Url>,
+    host: RepositoryHost,
+    api: Api,
+    mut config: Config,
+    interaction: Interaction,
+    yes: bool,
+    prompter: &mut dyn Prompter,
+    atomic: bool,
+) -> Result<()> {
+    if opts.section.is_empty() {
+        opts.section = config.section_names();
+    }
+
+    if opts.keep_a_changelog {
+        if let Some(section) = opts.section.iter().find(|section| {
+            !KEEP_A_CHANGELOG_SECTIONS.contains(&section.as_str())
+        }) {
+            return Err(miette!(
+                code = "main::keep_a_changelog_section",
+                help = format!(
+                    "Keep a Changelog only allows the sections {}. Map other headings to them with the `aliases` config option.",
+                    KEEP_A_CHANGELOG_SECTIONS.join(", ")
+                ),
+                "Section '{}' is not part of Keep a Changelog",
+                section
+            ));
+        }
+        opts.section = KEEP_A_CHANGELOG_SECTIONS
+            .iter()
+            .filter(|section| {
+                opts.section.is_empty()
+                    || opts.section.iter().any(|other| other == *section)
+            })
+            .map(|section| section.to_string())
+            .collect();
+        if opts.highlights {
+            return Err(miette!(
+                code = "main::keep_a_changelog_highlights",
+                help = "Drop `--highlights` or `--keep-a-changelog`.",
+                "Keep a Changelog has no highlights section"
+            ));
+        }
+        // There is no breaking changes section to hoist entries into.
+        config.breaking.mode = BreakingMode::Annotate;
+    }
+    let packages = select_packages(&opts, &config)?;
+    let updates = opts.update.is_some() || !packages.is_empty();
+    if opts.highlights && interaction == Interaction::Automatic {
+        return Err(miette!(
+            code = "main::highlights_non_interactive",
+            help = "Drop `--highlights` or `--non-interactive`.",
+            "Picking highlights requires answering a prompt"
+        ));
+    }
+    if opts.version.is_some() && updates {
+        return Err(miette!(
+            code = "main::version_with_update",
+            help = "Run `mergelog release <version>` after updating the changelog.",
+            "Entries added to a changelog always go under Unreleased"
+        ));
+    }
+    if !opts.keep_a_changelog
+        && !opts.releases_package()
+        && opts.version.is_some()
+    {
+        return Err(miette!(
+            code = "main::version_without_keep_a_changelog",
+            help = "Pass `--keep-a-changelog` as well.",
+            "Only Keep a Changelog output has release headings to put the version in"
+        ));
+    }
+    if opts.releases_package()
+        && opts.version.is_none()
+        && opts.version_from_changelog.is_none()
+    {
+        return Err(package_without_version());
+    }
+    if opts.format != OutputFormat::Markup(MarkupFormat::Markdown) && updates {
+        return Err(miette!(
+            code = "main::format_with_update",
+            help = "Drop `--format`, or print the merged changelog on its own.",
+            "Only markdown changelogs can be updated"
+        ));
+    }
+    if !opts.emit.is_empty() && (updates || opts.check_against.is_some()) {
+        return Err(miette!(
+            code = "main::emit_with_update",
+            help = "Drop `--emit`, or write the changelog with `--emit md=<path>` instead.",
+            "Merged changelogs are either emitted, added to a changelog, or checked"
+        ));
+    }
+    if opts.group_by == Grouping::Month && (opts.keep_a_changelog || updates) {
+        return Err(miette!(
+            code = "main::group_by_month_release",
+            help = "Drop `--group-by month`, or print the digest on its own.",
+            "Grouping by month makes a digest, not a release"
+        ));
+    }
+
+    let command_line = CommandLine::current();
+
+    let mut changelog_directories = Vec::new();
+    let mut changelog_files = Vec::new();
+    if opts.milestone.is_some() {
+        if !opts.changelogs.is_empty()
+            || opts.stdin_paths
+            || !packages.is_empty()
+        {
+            return Err(miette!(
+                code = "main::milestone_with_changelogs",
+                help = "Drop the changelogs, `--stdin-paths`, and the packages, or `--milestone`.",
+                "A milestone is listed without changelogs"
+            ));
+        }
+    } else if packages.is_empty() {
+        if opts.changelogs.is_empty() && !opts.stdin_paths {
+            return Err(miette!(
+                code = "main::no_changelogs",
+                help = "Pass the directory with the changelogs to merge, e.g., `mergelog merge changes -s Added`, or the changelogs themselves.",
+                "No changelogs provided"
+            ));
+        }
+        for argument in &opts.changelogs {
+            if argument.is_dir() {
+                changelog_directories.push(argument.clone());
+            } else if argument.is_file() {
+                changelog_files.push(argument.clone());
+            } else if argument.as_str().contains(['*', '?', '[']) {
+                changelog_directories
+                    .extend(expand_directory_pattern(argument)?);
+            } else {
+                let span = command_line.span_of(argument.as_str());
+                return Err(miette!(
+                    code = "main::missing_changelogs",
+                    labels = span
+                        .map(|span| {
+                            LabeledSpan::at(span, "Changelog specified here")
+                        })
+                        .into_iter()
+                        .collect::<Vec<_>>(),
+                    "Changelog or directory specified does not exist"
+                )
+                .with_source_code(command_line.text));
+            }
+        }
+        if opts.stdin_paths {
+            changelog_files.extend(read_stdin_paths(&config)?);
+        }
+    } else {
+        if !opts.changelogs.is_empty()
+            || opts.stdin_paths
+            || opts.update.is_some()
+        {
+            return Err(miette!(
+                code = "main::package_with_changelogs",
+                help = "Drop the changelogs, `--stdin-paths`, and `--update`, since they are set for each package in the config.",
+                "Packages bring their own changelog directory and changelog"
+            ));
+        }
+        if opts.bump_output.is_some() && packages.len() > 1 {
+            return Err(miette!(
+                code = "main::packages_bump_output",
+                help = "Merge the packages one at a time with `--package` to get each bump.",
+                "Several packages have several recommended bumps, which cannot all be written to one file"
+            ));
+        }
+        if opts.check_against.is_some() && packages.len() > 1 {
+            return Err(miette!(
+                code = "main::packages_check_against",
+                help = "Check the packages one at a time with `--package`.",
+                "Several packages have several changelogs, which cannot all be compared with one file"
+            ));
+        }
+        for (name, package) in &packages {
+            if !package.directory.is_dir() {
+                return Err(miette!(
+                    code = "main::missing_package_directory",
+                    help = "Fix `directory` for the package in the config.",
+                    "Changelog directory {} of package '{}' either does not exist or is not a directory",
+                    package.directory,
+                    name
+                ));
+            }
+        }
+    }
+
+    if opts.section.is_empty() {
+        return Err(miette!(
+            code = "main::missing_sections",
+            labels = vec![LabeledSpan::at(0..command_line.text.len(), "Missing section option(s)")],
+            help = "Provide a changelog section by passing the option `-s`/--section` multiple times, e.g., `-s Added`.\n\nThese sections correspond to markdown headings in the changelog files, and the order in which you pass the sections is the order in which they will be generated in the changelog.", 
+            "No changelog sections provided"
+        ).with_source_code(command_line.text));
+    }
+
+    if config.breaking.mode == BreakingMode::Hoist
+        && !opts.section.contains(&config.breaking.section)
+    {
+        opts.section.insert(0, config.breaking.section.clone());
+    }
+
+    let (host, repo_owner, repo_name) = resolve_repository(repo_url, host)?;
+
+    let mut pull_requests = match &opts.milestone {
+        Some(milestone) => with_fetch_spinner(|| {
+            fetch_milestone(&api, &repo_owner, &repo_name, host, milestone)
+        })?,
+        None => fetch_merge_requests_with_spinner(
+            &api,
+            &repo_owner,
+            &repo_name,
             host,
-            repo_owner,
-            repo_name,
-        ))
+        )?,
+    };
+    drop_excluded(&mut pull_requests, &config)?;
+
+    if packages.is_empty() {
+        return merge_changelogs(
+            opts,
+            (&changelog_directories, &changelog_files),
+            &config,
+            &pull_requests,
+            &api,
+            (host, &repo_owner, &repo_name),
+            interaction,
+            yes,
+            prompter,
+            atomic,
+        );
+    }
+    for (name, package) in packages {
+        info!("Merging changelogs of package {}", name);
+        let mut opts = opts.clone();
+        opts.update = Some(package.changelog.clone());
+        merge_changelogs(
+            opts,
+            (slice::from_ref(&package.directory), &[]),
+            &config,
+            &pull_requests,
+            &api,
+            (host, &repo_owner, &repo_name),
+            interaction,
+            yes,
+            prompter,
+            atomic,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Reads the merge/pull requests and issues of a milestone as entries titled
+/// like them, each under the first of `section_names` that one of its labels
+/// maps to in the `labels` config table, or else that one of them names,
+/// resolving aliases. Those without such a label go under `default_section`,
+/// or are reported and left out without one.
+fn read_milestone(
+    pull_requests: &[PullRequest],
+    section_names: &[String],
+    config: &Config,
+    repo: Repository,
+    default_section: Option<&str>,
+) -> ReadChangelogs {
+    let mut sections = ChangelogSections::new();
+    let mut section_order = Vec::new();
+    let mut content_warnings = 0;
+    for pull_request in pull_requests {
+        // Mapped labels take precedence over labels that name a section.
+        let Some(section) = pull_request
+            .labels
+            .iter()
+            .filter_map(|label| config.labels.get(label))
+            .chain(&pull_request.labels)
+            .find_map(|name| {
+                canonical_section(name, section_names, config)
+                    .filter(|section| section_names.contains(section))
+            })
+            .or_else(|| default_section.map(str::to_string))
+        else {
+            content_warnings += 1;
+            emit_warning(miette!(
+                severity = Severity::Warning,
+                code = "main::unlabeled_milestone_item",
+                help = format!(
+                    "Label it with one of the sections, {}, or map its labels to them with the `labels` config table.",
+                    section_names.join(", ")
+                ),
+                "{} '{}' has no label naming a section and was left out",
+                pull_request.link,
+                pull_request.title
+            ));
+            continue;
+        };
+        let id = pull_request.id.to_string();
+        let link = if pull_request.link.starts_with('#') {
+            Link {
+                shorthand: pull_request.link.clone(),
+                full: repo.page(&format!("issues/{id}")),
+            }
+        } else {
+            make_pull_request_link(id, pull_request.link.clone(), repo)
+        };
+        let ParsedItem {
+            section,
+            level,
+            item,
+            details,
+            breaking,
+        } = ParsedItem::new(
+            &section,
+            2,
+            &pull_request.title,
+            String::new(),
+            config,
+        );
+        if !sections.contains_key(&section) {
+            section_order.push(section.clone());
+        }
+        sections
+            .entry(section)
+            .or_insert((level, vec![]))
+            .1
+            .push(Entry {
+                item,
+                details,
+                links: vec![link],
+                breaking,
+                pull_request: Some(pull_request.clone()),
+                authors: pull_request.author.clone().into_iter().collect(),
+                provenance: Vec::new(),
+            });
+    }
+    ReadChangelogs {
+        sections,
+        section_order,
+        unused_changelogs: Vec::new(),
+        automatic_matches: Vec::new(),
+        stale_changelogs: Vec::new(),
+        content_warnings,
+    }
+}
+
+/// Orders `sections` following the `section-order` config option, where
+/// `section_order` is the order they first appear in across the changelogs.
+/// Sections missing from the order go last, and the hoisted breaking changes
+/// section stays first.
+fn order_sections(
+    sections: &mut [String],
+    config: &Config,
+    section_order: &[String],
+) {
+    let order = match config.section_order {
+        SectionOrder::Cli => return,
+        SectionOrder::Config => config.section_names(),
+        SectionOrder::Fragment => section_order.to_vec(),
+    };
+    sections.sort_by_key(|section| {
+        let hoisted = config.breaking.mode == BreakingMode::Hoist
+            && *section == config.breaking.section;
+        (
+            !hoisted,
+            order
+                .iter()
+                .position(|other| other == section)
+                .unwrap_or(order.len()),
+        )
+    });
+}
+
+/// Merges the changelogs in the given directories and files once the options
+/// are checked, either printing them or adding them to `opts.update`.
+#[allow(clippy::too_many_arguments)]
+fn merge_changelogs(
+    mut opts: MergeOpts,
+    changelogs: (&[Utf8PathBuf], &[Utf8PathBuf]),
+    config: &Config,
+    pull_requests: &[PullRequest],
+    api: &Api,
+    (host, repo_owner, repo_name): (RepositoryHost, &str, &str),
+    interaction: Interaction,
+    yes: bool,
+    prompter: &mut dyn Prompter,
+    atomic: bool,
+) -> Result<()> {
+    let repo = Repository::new(host, repo_owner, repo_name, config);
+    let ReadChangelogs {
+        mut sections,
+        section_order,
+        unused_changelogs,
+        automatic_matches,
+        stale_changelogs,
+        content_warnings,
+    } = if opts.milestone.is_some() {
+        read_milestone(pull_requests, &opts.section, config, repo, None)
     } else {
-        eprintln!(
-            "╭─ {}:",
-            format!("Cannot automatically determine pull request for changelog '{}.md', if it even has one", name).red(),
+        read_changelogs(
+            changelogs,
+            &opts.section,
+            opts.recursive,
+            opts.symlinks,
+            config,
+            pull_requests,
+            repo,
+            interaction,
+            yes,
+            prompter,
+        )?
+    };
+
+    if opts.strict {
+        // Excluded changelogs are left out on purpose.
+        let dropped = unused_changelogs
+            .iter()
+            .filter(|(_, reason)| {
+                !matches!(reason, UnusedChangelogReason::Excluded(_))
+            })
+            .count();
+        if content_warnings + dropped > 0 {
+            report_unused_changelogs(unused_changelogs);
+            return Err(miette!(
+                code = "main::strict",
+                help = "Fix the warnings above, or merge without `--strict` to leave the content out.",
+                "Some content in the changelogs would be left out"
+            ));
+        }
+    }
+
+    if config.formats_use("{authors}") {
+        with_fetch_spinner(|| {
+            fetch_co_authors(api, repo_owner, repo_name, host, &mut sections)
+        })?;
+    }
+    if config.new_contributors.is_some() {
+        with_fetch_spinner(|| {
+            fetch_first_contributions(
+                api,
+                repo_owner,
+                repo_name,
+                host,
+                &mut sections,
+            )
+        })?;
+    }
+    if config.formats_use("{issues}") {
+        with_fetch_spinner(|| {
+            fetch_closed_issues(api, repo_owner, repo_name, host, &mut sections)
+        })?;
+    }
+    if let Some(command) = &config.hooks.post_resolve {
+        hooks::post_resolve(command, &opts.section, &mut sections)?;
+    }
+
+    // Keep a Changelog has an order of its own.
+    if !opts.keep_a_changelog {
+        order_sections(&mut opts.section, config, &section_order);
+    }
+
+    for section in &opts.section {
+        if let Some((_, entries)) = sections.get_mut(section) {
+            handle_duplicate_entries(section, entries, config.duplicates);
+        }
+    }
+
+    if opts.highlights {
+        let highlights =
+            pick_highlights(&opts.section, &sections, yes, prompter)?;
+        if !highlights.is_empty() {
+            let level = sections.values().map(|(level, _)| *level).min();
+            sections.insert(
+                config.highlights.clone(),
+                (level.unwrap_or(2), highlights),
+            );
+            opts.section.insert(0, config.highlights.clone());
+        }
+    }
+
+    let bump = recommend_bump(
+        opts.section.iter().filter_map(|section| {
+            sections
+                .get(section)
+                .map(|(_, contents)| (section.as_str(), contents.as_slice()))
+        }),
+        &config.bump,
+    );
+
+    if let (None, Some(changelog), Some(bump)) =
+        (&opts.version, &opts.version_from_changelog, bump)
+    {
+        let contents = read_file(changelog)
+            .into_diagnostic()
+            .wrap_err(format!("Failed to read changelog {}", changelog))?;
+        let previous = Changelog::parse(&contents)
+            .latest()
+            .map(|release| release.version().to_string())
+            .whatever_context(miette!(
+                code = "main::no_previous_release",
+                help = "Pass `--version` for the first release.",
+                "Changelog {} has no release to propose the next version from",
+                changelog
+            ))?;
+        let next = next_version(&previous, bump).whatever_context(miette!(
+            code = "main::invalid_previous_version",
+            help = "Pass `--version` instead.",
+            "Cannot bump version {} from {}, which is not of the form MAJOR.MINOR.PATCH",
+            previous,
+            changelog
+        ))?;
+        info!(
+            "Proposed version {} after {} for a {} bump",
+            next, previous, bump
         );
-        eprintln!("│");
-        for line in contents.lines() {
-            eprintln!("│ {}", line.fg_rgb::<128, 128, 128>());
+        if opts.keep_a_changelog || opts.releases_package() {
+            opts.version = Some(next);
         }
-        eprintln!("│");
-        if let Some(guessed_prs) = guess_pull_request(name, pull_requests) {
-            eprintln!("├─ {}: Is it one of:", "help".cyan());
-            for guessed_pr in guessed_prs {
-                eprintln!(
-                    "│          {}: {}",
-                    guessed_pr.link, guessed_pr.title
-                );
-            }
-            eprintln!("│");
+    }
+
+    // Hoisting fills the breaking section from the others, so it is only
+    // there when there is something to hoist.
+    for section in opts.section.iter().filter(|section| {
+        config.breaking.mode != BreakingMode::Hoist
+            || **section != config.breaking.section
+    }) {
+        if sections
+            .get(section)
+            .is_some_and(|(_, entries)| !entries.is_empty())
+        {
+            continue;
         }
-        let full_link = prompt(
-            || {
-                eprint!("╰─ Please enter the desired link (can also be a link like !30 in GitLab): ")
-            },
-            |value| !value.is_empty(),
-            |value| {
-                eprintln!(
-                    "✓ {}",
-                    format!("Processing changelog for {}", value).green()
-                )
-            },
-            None,
-        )?;
-        if let Some(id) = match host {
-            RepositoryHost::GitHub => todo!(),
-            RepositoryHost::GitLab => full_link.strip_prefix("!"),
-            RepositoryHost::Infer => unreachable!(),
-        } {
-            Ok(make_pull_request_link(
-                id.to_string(),
-                full_link,
-                host,
-                repo_owner,
-                repo_name,
-            ))
-        } else {
-            let shorthand = prompt(
-                || {
-                    eprint!("   Please provide the markdown shorthand name for the link: ")
-                },
-                |value| !value.is_empty(),
-                |_| {},
-                None,
-            )?;
-            Ok(Link {
-                shorthand,
-                full: full_link,
-            })
+        match config.empty_section(section) {
+            EmptySection::Omit => {}
+            EmptySection::Placeholder => {
+                sections
+                    .entry(section.to_string())
+                    .or_insert((2, Vec::new()));
+            }
+            EmptySection::Error => {
+                return Err(miette!(
+                    code = "main::empty_section",
+                    help = "Add a changelog with an entry in it, or set `empty` to \"omit\" or \"placeholder\" for the section in the config.",
+                    "Section {} has no entries",
+                    section
+                ));
+            }
         }
     }
-}
 
-fn load_config(path: Utf8PathBuf) -> Result<Config> {
-    let contents = fs::read_to_string(&path)
-        .into_diagnostic()
-        .wrap_err(format!("Failed to read config file from {}", path))?;
-    toml::from_str(&contents).map_err(|cause| {
-        let labels = cause
-            .span()
-            .into_iter()
-            .map(|span| LabeledSpan::at(span, cause.to_string()))
-            .collect::<Vec<_>>();
-        miette!(
-            code = "load_config::toml_error",
-            labels = labels,
-            "Failed to parse config file"
-        )
-        .with_source_code(
-            NamedSource::new(path, contents).with_language("toml"),
-        )
-    })
-}
+    let (mut rendered_sections, mut link_definitions) = render_sections(
+        &opts.section,
+        &mut sections,
+        config,
+        opts.sort,
+        opts.keep_a_changelog.then_some(3),
+        opts.group_by,
+        opts.provenance,
+    );
+    if let Some(command) = &config.hooks.post_render {
+        hooks::post_render(
+            command,
+            &mut rendered_sections,
+            &mut link_definitions,
+        )?;
+    }
 
-fn main() -> Result<()> {
-    let mut opts = argh::from_env::<Opts>();
+    let output = if let Some(update) = &opts.update {
+        let contents = if update.exists() {
+            read_file(update)
+                .into_diagnostic()
+                .wrap_err(format!("Failed to read changelog {}", update))?
+        } else {
+            "# Changelog\n".to_string()
+        };
+        let mut changelog = Changelog::parse(&contents);
+        let previous_tag = previous_release_tag(&changelog)?;
+        let unreleased_link =
+            make_compare_link(previous_tag.as_deref(), "HEAD", repo);
+        changelog.update_unreleased(
+            &rendered_sections,
+            &link_definitions,
+            &unreleased_link,
+            &config.list,
+        );
+        changelog.to_string()
+    } else {
+        let mut output = String::new();
+        let mut printed_any_section = false;
+        let mut compare_links = Vec::new();
+        if opts.keep_a_changelog {
+            let previous_tag =
+                git_output(["describe", "--tags", "--abbrev=0"])?;
+            let tag = opts
+                .version
+                .as_ref()
+                .map(|version| release_tag(version, previous_tag.as_deref()));
 
-    let (format, short_links) = if let Some(config_path) =
-        opts.config.or_else(|| {
-            if Utf8Path::new("mergelog.toml").is_file() {
-                Some(Utf8Path::new("mergelog.toml").to_path_buf())
-            } else {
-                None
+            writeln!(output, "## [Unreleased]").unwrap();
+            compare_links.push((
+                "Unreleased".to_string(),
+                make_compare_link(
+                    tag.as_ref().or(previous_tag.as_ref()).map(String::as_str),
+                    "HEAD",
+                    repo,
+                ),
+            ));
+            if let (Some(version), Some(tag)) = (&opts.version, &tag) {
+                let version = version.trim_start_matches('v');
+                output.push('\n');
+                writeln!(output, "## [{version}] - {}", today()).unwrap();
+                compare_links.push((
+                    version.to_string(),
+                    make_compare_link(previous_tag.as_deref(), tag, repo),
+                ));
+            }
+            printed_any_section = true;
+        }
+        for section in &rendered_sections {
+            if printed_any_section {
+                output.push('\n');
+            }
+            printed_any_section = true;
+            writeln!(
+                output,
+                "{} {}",
+                "#".repeat(section.level as usize),
+                section.heading
+            )
+            .unwrap();
+            for item in &section.items {
+                writeln!(output, "{}", list_item(item, &config.list)).unwrap();
+            }
+        }
+        if !link_definitions.is_empty() || !compare_links.is_empty() {
+            output.push('\n');
+            for (label, full_link) in
+                link_definitions.into_iter().chain(compare_links)
+            {
+                writeln!(output, "[{label}]: {full_link}").unwrap();
             }
-        }) {
-        let config = load_config(config_path.clone())?;
-        eprintln!(
-            "✓ {}",
-            format!("Loaded config from {}", config_path).green()
-        );
-        if opts.section.is_empty() {
-            opts.section = config.sections;
         }
-        (config.format, config.short_links)
+        output
+    };
+    let markdown = output;
+    let release = if opts.releases_package() {
+        Some(package_release(&opts, config, repo_name)?)
+    } else {
+        None
+    };
+    let render = |format| match format {
+        OutputFormat::Markup(format) => markup::render(&markdown, format),
+        OutputFormat::Package(format) => packaging::render(
+            &markdown,
+            format,
+            release.as_ref().expect("packaging formats have a release"),
+        ),
+    };
+    let output = if opts.update.is_none() {
+        render(opts.format)
     } else {
-        (default_config_format(), false)
+        markdown.clone()
+    };
+    let outdated = match (&opts.check_against, &opts.update) {
+        (Some(expected), _) => !check_against(expected, &output)?,
+        (None, Some(update)) => {
+            write_file(update, output, atomic)
+                .into_diagnostic()
+                .wrap_err(format!("Failed to write changelog {}", update))?;
+            info!("Added entries to Unreleased in {}", update);
+            false
+        }
+        (None, None) if !opts.emit.is_empty() => {
+            let records = opts
+                .section
+                .iter()
+                .filter_map(|section| Some((section, sections.get(section)?)))
+                .flat_map(|(section, (_, entries))| {
+                    entries.iter().map(|entry| Record {
+                        version: opts.version.clone(),
+                        section: section.clone(),
+                        entry: entry.clone(),
+                    })
+                })
+                .collect::<Vec<_>>();
+            for emit in &opts.emit {
+                let contents = match emit.format {
+                    EmitFormat::Output(format) => render(format),
+                    EmitFormat::Json => {
+                        query::render(&records, QueryFormat::Json)
+                    }
+                };
+                match &emit.path {
+                    Some(path) => {
+                        write_file(path, contents, atomic)
+                            .into_diagnostic()
+                            .wrap_err(format!("Failed to write {}", path))?;
+                        info!("Wrote {}", path);
+                    }
+                    None => print!("{contents}"),
+                }
+            }
+            false
+        }
+        (None, None) => {
+            print!("{output}");
+            false
+        }
     };
 
-    // TODO: bad if there are escaped characters
-    let command_as_string = env::args().collect::<Vec<_>>().join(" ");
+    report_unused_changelogs(unused_changelogs);
+    for (path, days) in stale_changelogs {
+        emit_warning(miette!(
+            severity = Severity::Warning,
+            code = "main::stale_changelog",
+            help = "If the change it describes was reverted or abandoned, delete it.",
+            "Changelog {} was added {} days ago and never released",
+            path,
+            days
+        ));
+    }
+
+    report_automatic_matches(&automatic_matches);
 
-    if !opts.changelog_directory.is_dir() {
-        let dir_string = opts.changelog_directory.as_str();
-        let start = command_as_string
-            .find(dir_string)
-            .expect("TODO: handle escapes. you get no pretty error but TLDR the changelog directory you specified does not exist :(");
-        return Err(miette!(
-            code = "main::missing_changelogs",
-            labels = vec![LabeledSpan::at(
-                (start, dir_string.len()),
-                "Directory specified here"
-            )],
-            "Changelog directory specified either does not exist or is not a directory"
-        )
-        .with_source_code(command_as_string));
+    let bump = bump.map_or_else(|| "none".to_string(), |bump| bump.to_string());
+    info!("Recommended version bump: {}", bump);
+    if let Some(bump_output) = opts.bump_output {
+        write_file(&bump_output, format!("{bump}\n"), atomic)
+            .into_diagnostic()
+            .wrap_err(format!(
+                "Failed to write version bump to {}",
+                bump_output
+            ))?;
     }
 
-    if opts.section.is_empty() {
+    if outdated {
         return Err(miette!(
-            code = "main::missing_sections",
-            labels = vec![LabeledSpan::at(0..command_as_string.len(), "Missing section option(s)")],
-            help = "Provide a changelog section by passing the option `-s`/--section` multiple times, e.g., `-s Added`.\n\nThese sections correspond to markdown headings in the changelog files, and the order in which you pass the sections is the order in which they will be generated in the changelog.", 
-            "No changelog sections provided"
-        ).with_source_code(command_as_string));
+            code = "main::check_against",
+            help = "Merge again without `--check-against` to see the new output, and commit it if it is right.",
+            "The output differs from {}",
+            opts.check_against.unwrap_or_default()
+        ));
     }
+    Ok(())
+}
 
-    let repo_url = if let Some(repo_url) = opts.repo_url {
-        repo_url
-    } else {
-        let git_output = Command::new("git")
-            .args(["config", "--get", "remote.origin.url"])
-            .output()
-            .into_diagnostic()
-            .wrap_err("Failed to determine origin URL in current repository")?;
-        let origin_string = String::from_utf8(git_output.stdout)
-            .into_diagnostic()
-            .wrap_err("Failed to decode origin URL as UTF-8")?;
-        Url::parse(&origin_string).map_err(|inner| {
-            let help = if origin_string.is_empty() {
-                "Add a valid remote origin URL with `git remote add origin <url>`. You can also specify the URL manually by passing `--repo`"
+/// Renders `sections` in the order of `section_names`, each at `fixed_level`
+/// if given, along with the link definitions their entries need, sorted by
+/// label. Entries keep their provenance comments if `provenance_comments`.
+fn render_sections(
+    section_names: &[String],
+    sections: &mut ChangelogSections,
+    config: &Config,
+    sort: Option<SortOrder>,
+    fixed_level: Option<u8>,
+    group_by: Grouping,
+    provenance_comments: bool,
+) -> (Vec<RenderedSection>, Vec<(String, String)>) {
+    let reference_links =
+        config.short_links || config.link_style == LinkStyle::Reference;
+    // Keyed by label, so that an entry appearing in several sections only gets
+    // one definition.
+    let mut link_definitions = HashMap::new();
+    let mut rendered_sections = Vec::new();
+    // Sections split up by the month their entries were merged in, which is
+    // `None` for entries without a merge date.
+    let mut months = BTreeMap::<_, Vec<RenderedSection>>::new();
+    // Who made their first contribution in which merge/pull request, keyed by
+    // its id so that they are listed in order.
+    let mut new_contributors = BTreeMap::new();
+    let new_contributor_badge = config
+        .new_contributors
+        .as_ref()
+        .and_then(|new_contributors| new_contributors.badge.as_deref());
+    for section in section_names {
+        if let Some((level, entries)) = sections.get_mut(section) {
+            let section_config = config.section(section);
+            let sort = sort
+                .or_else(|| {
+                    section_config
+                        .and_then(|section_config| section_config.sort)
+                })
+                .unwrap_or(config.sort);
+            sort_entries(entries, sort);
+            let level = fixed_level.unwrap_or_else(|| {
+                section_config
+                    .and_then(|section_config| section_config.level)
+                    .or(config.heading_level)
+                    .map_or(*level, |HeadingLevel(level)| level)
+            });
+            let name = match section_config
+                .and_then(|section_config| section_config.heading_case)
+                .or(config.heading_case)
+            {
+                Some(heading_case) => heading_case.apply(section),
+                None => section.clone(),
+            };
+            let heading = section_config
+                .and_then(|section_config| section_config.heading.as_ref())
+                .unwrap_or(&config.heading)
+                .replace("{name}", &name);
+            let emoji = section_config
+                .and_then(|section_config| section_config.emoji.as_ref());
+            let decorate = section_config
+                .and_then(|section_config| section_config.decorate)
+                .unwrap_or(config.decorate);
+            let heading = match emoji {
+                Some(emoji) if decorate == Decoration::Heading => {
+                    format!("{emoji} {heading}")
+                }
+                _ => heading,
+            };
+            let format = section_config
+                .and_then(|section_config| section_config.format.as_ref())
+                .unwrap_or(&config.format);
+            let format = if config.breaking.mode == BreakingMode::Annotate
+                && !format.contains("{breaking}")
+            {
+                format!("{{breaking}} {format}")
             } else {
-                "Remove the current remote origin with `git remote remove origin` and readd a correct one. You can also specify the URL manually by passing `--repo`"
+                format.clone()
+            };
+            let format = if new_contributor_badge.is_some()
+                && !format.contains("{new_contributor}")
+            {
+                format!("{{new_contributor}} {format}")
+            } else {
+                format
+            };
+            let format = match emoji {
+                Some(emoji) if decorate == Decoration::Entries => {
+                    format!("{emoji} {format}")
+                }
+                _ => format,
             };
-            miette!(
-                code = "main::parse_url",
-                labels = vec![LabeledSpan::at(
-                    (0, origin_string.len()),
-                    inner.to_string()
-                )],
-                help = help,
-                "Failed to parse {}origin URL",
-                if origin_string.is_empty() { "empty " } else { "" }
-            )
-            .with_source_code(NamedSource::new("url", origin_string))
-        })?
-    };
-    let host = match opts.host {
-        RepositoryHost::Infer => infer_host(&repo_url)?,
-        specified => specified,
-    };
-
-    let (repo_owner, repo_name) = parse_owner_and_name(repo_url, host)?;
-
-    let spinner = ProgressBar::new_spinner()
-        .with_message("Fetching information from remote repository")
-        .with_style(
-            ProgressStyle::default_spinner()
-                .tick_chars("⠁⠁⠉⠙⠚⠒⠂⠂⠒⠲⠴⠤⠄⠄⠤⠠⠠⠤⠦⠖⠒⠐⠐⠒⠓⠋⠉⠈⠈✓"),
-        );
-    spinner.enable_steady_tick(Duration::from_millis(100));
-    let pull_requests = fetch_merge_requests(&repo_owner, &repo_name, host)?;
-    spinner.finish_with_message(
-        "Fetched information from remote repository"
-            .green()
-            .to_string(),
-    );
-
-    let mut sections = HashMap::<String, (u8, Vec<(String, Link)>)>::new();
-    let mut current_section = None;
 
-    let arena = comrak::Arena::new();
-    if let Ok(read_dir) = opts.changelog_directory.read_dir_utf8() {
-        for entry in read_dir.flatten() {
-            if entry.path().is_file()
-                && entry
-                    .path()
-                    .extension()
-                    .map(|extension| extension == "md")
-                    .unwrap_or(false)
+            let mut items = Vec::new();
+            for Entry {
+                item,
+                details,
+                links,
+                breaking,
+                pull_request,
+                authors,
+                provenance,
+            } in entries.iter()
             {
-                let Some(file_stem) = entry.path().file_stem() else {
-                    continue;
+                let badge = if *breaking {
+                    config.breaking.badge.as_str()
+                } else {
+                    ""
                 };
-
-                let changelog_contents = fs::read_to_string(entry.path())
-                    .into_diagnostic()
-                    .whatever_context(miette!(
-                        code = "main::io_error",
-                        "Failed to read changelog at {}",
-                        entry.path()
-                    ))?;
-
-                let link = resolve_changelog_pr_interactive(
-                    file_stem,
-                    &changelog_contents,
-                    &pull_requests,
-                    &repo_owner,
-                    &repo_name,
-                    host,
-                )?;
-
-                for node in comrak::parse_document(
-                    &arena,
-                    &changelog_contents,
-                    &comrak::Options::default(),
-                )
-                .descendants()
-                {
-                    match node.data.borrow().value {
-                        comrak::nodes::NodeValue::Heading(heading) => {
-                            let mut heading_string = String::new();
-                            for descendant in node.children() {
-                                match descendant.data.borrow().value {
-                                    comrak::nodes::NodeValue::Text(
-                                        ref text,
-                                    ) => heading_string.push_str(text),
-                                    _ => todo!(),
-                                }
-                            }
-                            current_section = Some((
-                                heading_string.trim().to_string(),
-                                heading.level,
-                            ));
+                let link_short = links
+                    .iter()
+                    .map(|link| match config.link_style {
+                        LinkStyle::Inline => link.shorthand.clone(),
+                        LinkStyle::Reference => {
+                            format!("[{}]", escape_link_label(&link.shorthand))
                         }
-                        comrak::nodes::NodeValue::Item(_) => {
-                            let mut result = Vec::new();
-                            comrak::format_commonmark(
-                                node,
-                                &comrak::Options::default(),
-                                &mut result,
-                            )
-                            .into_diagnostic()
-                            .wrap_err("Failed to format document")?;
-                            let result = String::from_utf8(result)
-                                .into_diagnostic()
-                                .wrap_err(
-                                    "Markdown list item was not valid UTF-8",
-                                )?;
-                            if let Some(current_section) =
-                                current_section.as_ref()
-                            {
-                                sections
-                                    .entry(current_section.0.clone())
-                                    .or_insert((current_section.1, vec![]))
-                                    .1
-                                    .push((result, link.clone()));
-                            }
-                        }
-                        _ => {}
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let link_md = links
+                    .iter()
+                    .map(|link| {
+                        format!(
+                            "[{}]({})",
+                            escape_link_label(&link.shorthand),
+                            link.full
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let link_ref = links
+                    .iter()
+                    .map(|link| {
+                        format!("[{}]", escape_link_label(&link.shorthand))
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let first_contribution = pull_request
+                    .as_ref()
+                    .filter(|pull_request| pull_request.first_contribution);
+                if let Some(pull_request) = first_contribution {
+                    let link = links
+                        .iter()
+                        .find(|link| link.shorthand == pull_request.link)
+                        .or(links.first());
+                    if let Some((author, link)) =
+                        pull_request.author.as_ref().zip(link)
+                    {
+                        new_contributors.insert(
+                            pull_request.id,
+                            format!(
+                                "@{author} made their first contribution in [{}]({})",
+                                escape_link_label(&link.shorthand),
+                                link.full
+                            ),
+                        );
+                    }
+                }
+                let issues = pull_request
+                    .as_ref()
+                    .map(|pull_request| &pull_request.closes_issues)
+                    .filter(|issues| !issues.is_empty())
+                    .map(|issues| {
+                        format!(
+                            ", closes {}",
+                            issues
+                                .iter()
+                                .map(|id| format!("#{id}"))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        )
+                    })
+                    .unwrap_or_default();
+                let month = pull_request
+                    .as_ref()
+                    .and_then(|pull_request| pull_request.merged_at.as_deref())
+                    .and_then(merge_month);
+                let comment = provenance_comment(provenance)
+                    .filter(|_| provenance_comments)
+                    .map(|comment| format!(" {comment}"))
+                    .unwrap_or_default();
+                let subsection = config
+                    .subsections
+                    .as_ref()
+                    .zip(pull_request.as_ref())
+                    .and_then(|(subsections, pull_request)| {
+                        subsections.heading(pull_request)
+                    });
+                items.push((
+                    month,
+                    subsection,
+                    format
+                        .replace("{link_short}", &link_short)
+                        .replace("{link_md}", &link_md)
+                        .replace("{link_ref}", &link_ref)
+                        .replace(
+                            "{link}",
+                            &links
+                                .iter()
+                                .map(|link| link.full.as_str())
+                                .collect::<Vec<_>>()
+                                .join(", "),
+                        )
+                        .replace("{breaking}", badge)
+                        .replace("{section}", &name)
+                        .replace("{authors}", &authors.join(", "))
+                        .replace("{issues}", &issues)
+                        .replace(
+                            "{new_contributor}",
+                            first_contribution
+                                .and(new_contributor_badge)
+                                .unwrap_or_default(),
+                        )
+                        .replace("{item}", item)
+                        .trim()
+                        .to_string()
+                        + &comment
+                        + details,
+                ));
+                if reference_links || format.contains("{link_ref}") {
+                    for link in links {
+                        link_definitions
+                            .entry(escape_link_label(&link.shorthand))
+                            .or_insert_with(|| link.full.clone());
+                    }
+                }
+            }
+            if items.is_empty()
+                && config.empty_section(section) == EmptySection::Placeholder
+            {
+                items.push((None, None, "No changes".to_string()));
+            }
+            match group_by {
+                Grouping::Section => {
+                    rendered_sections.extend(RenderedSection::with_subsections(
+                        level,
+                        heading,
+                        items
+                            .into_iter()
+                            .map(|(_, subsection, item)| (subsection, item))
+                            .collect(),
+                    ))
+                }
+                Grouping::Month => {
+                    let mut by_month = BTreeMap::<_, Vec<_>>::new();
+                    for (month, subsection, item) in items {
+                        by_month
+                            .entry(month)
+                            .or_default()
+                            .push((subsection, item));
+                    }
+                    for (month, items) in by_month {
+                        months.entry(month).or_default().extend(
+                            RenderedSection::with_subsections(
+                                (level + 1).min(6),
+                                heading.clone(),
+                                items,
+                            ),
+                        );
                     }
                 }
             }
         }
     }
+    let month_level = months
+        .values()
+        .flatten()
+        .map(|section| section.level - 1)
+        .min()
+        .unwrap_or(2);
+    // Newest first, with undated entries last.
+    for (month, sections) in months.into_iter().rev() {
+        rendered_sections.push(RenderedSection {
+            level: month_level,
+            heading: month.map_or_else(
+                || "Undated".to_string(),
+                |(year, month)| {
+                    format!("{} {year}", MONTH_NAMES[month as usize - 1])
+                },
+            ),
+            items: Vec::new(),
+        });
+        rendered_sections.extend(sections);
+    }
+    if let Some(heading) = config
+        .new_contributors
+        .as_ref()
+        .and_then(|new_contributors| new_contributors.section.clone())
+        .filter(|_| !new_contributors.is_empty())
+    {
+        rendered_sections.push(RenderedSection {
+            level: rendered_sections
+                .iter()
+                .map(|section| section.level)
+                .min()
+                .unwrap_or(2),
+            heading,
+            items: new_contributors.into_values().collect(),
+        });
+    }
+    let mut link_definitions = link_definitions.into_iter().collect::<Vec<_>>();
+    link_definitions.sort_by(|lhs, rhs| natural_cmp(&lhs.0, &rhs.0));
+    (rendered_sections, link_definitions)
+}
 
-    let mut short_links_set = HashSet::new();
-    for (i, section) in opts.section.into_iter().enumerate() {
-        if i > 0 {
-            println!();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pull_request(id: u64, title: &str) -> PullRequest {
+        PullRequest {
+            id,
+            link: format!("!{id}"),
+            title: title.to_string(),
+            merged_at: None,
+            author: None,
+            labels: Vec::new(),
+            closes_issues: Vec::new(),
+            commits: Vec::new(),
+            first_contribution: false,
         }
-        if let Some((level, contents)) = sections.get_mut(&section) {
-            contents.sort_by(|lhs, rhs| lhs.1.shorthand.cmp(&rhs.1.shorthand));
-            println!("{} {}", "#".repeat(*level as usize), section);
-            for (content, link) in contents {
-                let item = content.trim();
-                let item = item.strip_prefix("-").unwrap_or(item).trim();
-                println!(
-                    "- {}",
-                    format
-                        .replace("{link_short}", &link.shorthand)
-                        .replace("{link}", &link.full)
-                        .replace("{item}", item)
-                );
-                if short_links {
-                    short_links_set
-                        .insert((link.shorthand.clone(), link.full.clone()));
-                }
-            }
+    }
+
+    #[test]
+    fn exact_title_beats_partial_match() {
+        let pull_requests = [
+            pull_request(1, "Fix parsing of generic arguments"),
+            pull_request(2, "Fix crash when parsing empty files"),
+            pull_request(3, "Document empty files"),
+        ];
+        let guesses = guess_pull_request(
+            "empty-files",
+            "## Fixed\n\n- Fix crash when parsing empty files\n",
+            &pull_requests,
+        )
+        .unwrap();
+        assert_eq!(
+            guesses.iter().map(|pr| pr.id).collect::<Vec<_>>(),
+            [2, 3, 1]
+        );
+    }
+
+    #[test]
+    fn exact_title_is_certain() {
+        let confidence = match_confidence(
+            "crash-on-empty-files",
+            "- Crash on empty files",
+            "Crash on empty files",
+        );
+        assert!((confidence - 1.0).abs() < 1e-9, "{confidence}");
+    }
+
+    #[test]
+    fn first_item_outweighs_name() {
+        let by_item = match_confidence(
+            "misc",
+            "- Support tuples in patterns",
+            "Support tuples in patterns",
+        );
+        let by_name = match_confidence(
+            "tuples-in-patterns",
+            "- Misc cleanup",
+            "Support tuples in patterns",
+        );
+        assert!(by_item > by_name, "{by_item} <= {by_name}");
+    }
+
+    #[test]
+    fn unrelated_titles_are_rejected() {
+        let pull_requests = [
+            pull_request(1, "Bump serde to 1.0.200"),
+            pull_request(2, "Rework the CI pipeline"),
+        ];
+        assert!(guess_pull_request(
+            "typecheck",
+            "- Typecheck generics eagerly",
+            &pull_requests
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn loose_matches_are_below_commit_confidence() {
+        let confidence = match_confidence(
+            "parser",
+            "- Speed up the lexer",
+            "Rewrite the parser in the lexer's style",
+        );
+        assert!(confidence > 0.0);
+        assert!(confidence < COMMIT_CONFIDENCE, "{confidence}");
+    }
+
+    #[test]
+    fn inflections_count_as_similar() {
+        assert!(word_coverage(&words("parsing"), &words("parse")) >= 0.85);
+        assert_eq!(word_coverage(&words("parser"), &words("lexer")), 0.0);
+    }
+
+    #[test]
+    fn common_words_say_nothing() {
+        assert_eq!(words("Fix the update of a thing"), ["thing"]);
+        assert_eq!(word_coverage(&[], &words("anything")), 0.0);
+    }
+
+    /// The text under each label of `report`, which was made for `source`.
+    fn labeled<'a>(report: &Report, source: &'a str) -> Vec<&'a str> {
+        report
+            .labels()
+            .into_iter()
+            .flatten()
+            .map(|label| &source[label.offset()..][..label.len()])
+            .collect()
+    }
+
+    #[test]
+    fn spans_skip_earlier_occurrences() {
+        let span = find_span("abcabc", "abc", 1).unwrap();
+        assert_eq!((span.offset(), span.len()), (3, 3));
+        assert!(find_span("abcabc", "abc", 4).is_none());
+        assert!(find_span("abc", "abc", 10).is_none());
+        // Not a character boundary.
+        assert!(find_span("é", "", 1).is_none());
+    }
+
+    #[test]
+    fn spans_land_on_later_lines() {
+        let source = "## Added\n\n- Add `foo`\n  which replaces `foo`\n";
+        let from = source.find("which").unwrap();
+        let span = find_span(source, "`foo`", from).unwrap();
+        assert_eq!(&source[span.offset()..][..span.len()], "`foo`");
+        assert_eq!(
+            source[..span.offset()].lines().last(),
+            Some("  which replaces ")
+        );
+    }
+
+    #[test]
+    fn unknown_domain_is_labeled_in_host() {
+        // The username spells the domain out too, before the host.
+        let url = Url::parse("https://example.org@example.org/a/b").unwrap();
+        let report = infer_host(&url).unwrap_err();
+        let labels = report.labels().unwrap().collect::<Vec<_>>();
+        assert_eq!(labels.len(), 1);
+        assert_eq!(
+            labels[0].offset(),
+            url.as_str().rfind("example.org").unwrap()
+        );
+        assert_eq!(labeled(&report, url.as_str()), ["example.org"]);
+    }
+
+    #[test]
+    fn missing_repository_name_is_labeled() {
+        let url = Url::parse("https://gitlab.com/spade").unwrap();
+        let report = parse_owner_and_name(url.clone(), RepositoryHost::GitLab)
+            .unwrap_err();
+        assert_eq!(labeled(&report, url.as_str()), ["spade"]);
+    }
+
+    #[test]
+    fn remotes_name_the_repository() {
+        for remote in [
+            "git@gitlab.com:spade-lang/spade.git",
+            "git@gitlab.com:spade-lang/spade",
+            "ssh://git@gitlab.com/spade-lang/spade.git",
+            "https://gitlab.com/spade-lang/spade.git",
+            "https://gitlab.com/spade-lang/spade",
+            "https://token@gitlab.com/spade-lang/spade.git",
+        ] {
+            let url = parse_git_url(remote).unwrap();
+            let host = infer_host(&url).unwrap();
+            assert_eq!(host, RepositoryHost::GitLab, "{remote}");
+            assert_eq!(
+                parse_owner_and_name(url, host).unwrap(),
+                ("spade-lang".to_string(), "spade".to_string()),
+                "{remote}"
+            );
         }
     }
-    if !short_links_set.is_empty() {
-        println!();
-        let mut short_links_list =
-            short_links_set.into_iter().collect::<Vec<_>>();
-        short_links_list.sort();
-        for (link, full_link) in short_links_list {
-            println!("[{link}]: {full_link}");
+
+    /// Resolves the changelog `name` with `contents` among a few merge
+    /// requests of spade-lang/spade, answering prompts with `answers`.
+    fn resolve_with(
+        name: &str,
+        contents: &str,
+        answers: &[&str],
+    ) -> Result<Link> {
+        let pull_requests = [
+            pull_request(390, "Replace __builtin__ with extern"),
+            pull_request(400, "Support generic arguments in type aliases"),
+            pull_request(401, "Generate if expressions in the backend"),
+        ];
+        resolve_changelog_pr_interactive(
+            Utf8Path::new(&format!("changelogs/{name}.md")),
+            contents,
+            &pull_requests,
+            Repository {
+                host: RepositoryHost::GitLab,
+                owner: "spade-lang",
+                name: "spade",
+                link_template: None,
+            },
+            false,
+            false,
+            &mut Scripted::new(answers.iter().map(|answer| answer.to_string())),
+        )
+    }
+
+    #[test]
+    fn changelogs_named_after_ids_are_not_asked_about() {
+        let link = resolve_with("390", "- Replace builtins", &[]).unwrap();
+        assert_eq!(link.shorthand, "!390");
+        assert_eq!(
+            link.full,
+            "https://gitlab.com/spade-lang/spade/-/merge_requests/390"
+        );
+    }
+
+    #[test]
+    fn unknown_ids_are_linked_without_asking() {
+        let link = resolve_with("999", "- Replace builtins", &[]).unwrap();
+        assert_eq!(link.shorthand, "!999");
+        assert_eq!(
+            link.full,
+            "https://gitlab.com/spade-lang/spade/-/merge_requests/999"
+        );
+    }
+
+    #[test]
+    fn accepts_an_entered_id() {
+        let link =
+            resolve_with("builtins", "- Replace builtins", &["!390"]).unwrap();
+        assert_eq!(link.shorthand, "!390");
+        assert_eq!(
+            link.full,
+            "https://gitlab.com/spade-lang/spade/-/merge_requests/390"
+        );
+    }
+
+    #[test]
+    fn accepts_a_search_result() {
+        let link = resolve_with(
+            "aliases",
+            "- Generic aliases",
+            &["type aliases", "1"],
+        )
+        .unwrap();
+        assert_eq!(link.shorthand, "!400");
+    }
+
+    #[test]
+    fn skipped_search_results_are_searched_again() {
+        let link = resolve_with(
+            "if",
+            "- Generate if",
+            &["type aliases", "", "backend", "1"],
+        )
+        .unwrap();
+        assert_eq!(link.shorthand, "!401");
+    }
+
+    #[test]
+    fn accepts_a_manually_entered_link() {
+        let link = resolve_with(
+            "docs",
+            "- Document the standard library",
+            &["https://docs.example.com/stdlib", "stdlib docs"],
+        )
+        .unwrap();
+        assert_eq!(link.shorthand, "stdlib docs");
+        assert_eq!(link.full, "https://docs.example.com/stdlib");
+    }
+
+    #[test]
+    fn running_out_of_answers_fails() {
+        let report =
+            resolve_with("docs", "- Document it", &["zzz"]).unwrap_err();
+        assert_eq!(
+            report.code().map(|code| code.to_string()).as_deref(),
+            Some("main::prompt_eof")
+        );
+    }
+
+    #[test]
+    fn fenced_headings_are_code() {
+        let contents = "# Changelog\n\
+                        \n\
+                        ## [1.1.0] - 2024-02-01\n\
+                        \n\
+                        ### Changed\n\
+                        \n\
+                        - Render examples like this:\n\
+                        \n\
+                        ```markdown\n\
+                        ## [9.9.9] - 2099-01-01\n\
+                        ```\n\
+                        \n\
+                        ~~~\n\
+                        ### Removed\n\
+                        ~~~\n\
+                        \n\
+                        ## [1.0.0] - 2024-01-01\n\
+                        \n\
+                        ### Added\n\
+                        \n\
+                        - Everything\n";
+        let changelog = Changelog::parse(contents);
+        assert_eq!(
+            changelog
+                .releases()
+                .map(|release| release.version())
+                .collect::<Vec<_>>(),
+            ["1.1.0", "1.0.0"]
+        );
+        let latest = changelog.latest().unwrap();
+        assert!(
+            changelog.notes(latest).contains("## [9.9.9] - 2099-01-01"),
+            "{}",
+            changelog.notes(latest)
+        );
+        assert_eq!(changelog.to_string(), contents);
+    }
+
+    /// The repository `o/r` on `host`.
+    fn repository(
+        host: RepositoryHost,
+        link_template: Option<&str>,
+    ) -> Repository<'_> {
+        Repository {
+            host,
+            owner: "o",
+            name: "r",
+            link_template,
         }
     }
 
-    Ok(())
+    #[test]
+    fn github_links_point_at_github() {
+        let repo = repository(RepositoryHost::GitHub, None);
+        assert_eq!(
+            make_pull_request_link("12".into(), "#12".into(), repo).full,
+            "https://github.com/o/r/pull/12"
+        );
+        assert_eq!(
+            make_commit_link("0123456789abcdef", repo),
+            Link {
+                shorthand: "0123456".into(),
+                full: "https://github.com/o/r/commit/0123456789abcdef".into(),
+            }
+        );
+        assert_eq!(
+            make_compare_link(Some("v1.0.0"), "v1.1.0", repo),
+            "https://github.com/o/r/compare/v1.0.0...v1.1.0"
+        );
+    }
+
+    #[test]
+    fn link_templates_move_every_link() {
+        let repo = repository(
+            RepositoryHost::GitLab,
+            Some(
+                "https://git.example.com/{owner}/{repo}/-/merge_requests/{id}",
+            ),
+        );
+        assert_eq!(repo.url(), "https://git.example.com/o/r");
+        assert_eq!(
+            make_pull_request_link("12".into(), "!12".into(), repo).full,
+            "https://git.example.com/o/r/-/merge_requests/12"
+        );
+        assert_eq!(
+            make_commit_link("0123456789abcdef", repo).full,
+            "https://git.example.com/o/r/-/commit/0123456789abcdef"
+        );
+        assert_eq!(
+            make_compare_link(Some("v1.0.0"), "v1.1.0", repo),
+            "https://git.example.com/o/r/-/compare/v1.0.0...v1.1.0"
+        );
+        assert_eq!(
+            make_compare_link(None, "v1.0.0", repo),
+            "https://git.example.com/o/r/-/tree/v1.0.0"
+        );
+    }
+
+    #[test]
+    fn tracker_templates_leave_other_links_on_host() {
+        let repo = repository(
+            RepositoryHost::GitLab,
+            Some("https://jira.example.com/browse/PROJ-{id}"),
+        );
+        assert_eq!(
+            make_pull_request_link("12".into(), "!12".into(), repo).full,
+            "https://jira.example.com/browse/PROJ-12"
+        );
+        assert_eq!(repo.url(), "https://gitlab.com/o/r");
+        assert_eq!(
+            make_commit_link("0123456789abcdef", repo).full,
+            "https://gitlab.com/o/r/-/commit/0123456789abcdef"
+        );
+        assert_eq!(
+            make_compare_link(Some("v1.0.0"), "v1.1.0", repo),
+            "https://gitlab.com/o/r/-/compare/v1.0.0...v1.1.0"
+        );
+    }
 }
@@ -0,0 +1,173 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The man page, generated from the command line definitions, including their
+//! examples, so that it says the same as `--help`.
+
+use std::fmt::Write;
+
+use argh::{CommandInfoWithArgs, FlagInfoKind, Optionality};
+
+/// The man page for the command line described by `info`, in roff.
+pub fn generate(info: &CommandInfoWithArgs) -> String {
+    let mut page = String::new();
+    writeln!(
+        page,
+        ".TH MERGELOG 1 \"\" \"mergelog {}\" \"User Commands\"",
+        env!("CARGO_PKG_VERSION")
+    )
+    .unwrap();
+    writeln!(page, ".SH NAME\nmergelog \\- {}", escape(info.description))
+        .unwrap();
+    page.push_str(".SH SYNOPSIS\n");
+    writeln!(page, "{}", synopsis(&["mergelog"], info)).unwrap();
+    page.push_str(".SH OPTIONS\n");
+    options(&mut page, info);
+    examples(&mut page, &["mergelog"], info);
+    page.push_str(".SH COMMANDS\n");
+    let mut commands = info
+        .commands
+        .iter()
+        .map(|subcommand| {
+            (vec!["mergelog", subcommand.name], &subcommand.command)
+        })
+        .collect::<Vec<_>>();
+    commands.reverse();
+    while let Some((path, command)) = commands.pop() {
+        writeln!(page, ".SS \"{}\"", path.join(" ")).unwrap();
+        writeln!(page, "{}", synopsis(&path, command)).unwrap();
+        writeln!(page, ".PP\n{}", escape(command.description)).unwrap();
+        options(&mut page, command);
+        examples(&mut page, &path, command);
+        for subcommand in command.commands.iter().rev() {
+            let mut path = path.clone();
+            path.push(subcommand.name);
+            commands.push((path, &subcommand.command));
+        }
+    }
+    page
+}
+
+/// The usage line of the command at `path`, e.g., `mergelog new [options]
+/// <description>`.
+fn synopsis(path: &[&str], command: &CommandInfoWithArgs) -> String {
+    let mut synopsis = format!(".B {}", path.join(" "));
+    if command.flags.iter().any(|flag| !flag.hidden) {
+        synopsis.push_str("\n[\\fIoptions\\fR]");
+    }
+    for positional in command
+        .positionals
+        .iter()
+        .filter(|positional| !positional.hidden)
+    {
+        let name = format!("\\fI{}\\fR", positional.name.replace('_', "-"));
+        let argument = match positional.optionality {
+            Optionality::Required => name,
+            Optionality::Optional => format!("[{}]", name),
+            Optionality::Repeating | Optionality::Greedy => {
+                format!("[{}...]", name)
+            }
+        };
+        write!(synopsis, "\n{}", argument).unwrap();
+    }
+    if !command.commands.is_empty() {
+        synopsis.push_str("\n\\fIcommand\\fR [\\fIargs\\fR]");
+    }
+    synopsis
+}
+
+/// Describes each option and positional argument of `command`.
+fn options(page: &mut String, command: &CommandInfoWithArgs) {
+    for positional in command
+        .positionals
+        .iter()
+        .filter(|positional| !positional.hidden)
+    {
+        writeln!(
+            page,
+            ".TP\n\\fI{}\\fR\n{}",
+            positional.name.replace('_', "-"),
+            escape(positional.description)
+        )
+        .unwrap();
+    }
+    for flag in command.flags.iter().filter(|flag| !flag.hidden) {
+        let mut names = escape(flag.long);
+        if let Some(short) = flag.short {
+            names = format!("\\-{}, {}", short, names);
+        }
+        let value = match flag.kind {
+            FlagInfoKind::Switch => String::new(),
+            FlagInfoKind::Option { arg_name } => {
+                format!(" \\fI{}\\fR", arg_name)
+            }
+        };
+        writeln!(
+            page,
+            ".TP\n\\fB{}\\fR{}\n{}",
+            names,
+            value,
+            escape(flag.description)
+        )
+        .unwrap();
+    }
+    if !command.commands.is_empty() {
+        page.push_str(".PP\nCommands:\n");
+        for subcommand in &command.commands {
+            writeln!(
+                page,
+                ".TP\n\\fB{}\\fR\n{}",
+                subcommand.name,
+                escape(subcommand.command.description)
+            )
+            .unwrap();
+        }
+    }
+}
+
+/// Lists the examples of the command at `path`, each a description followed by
+/// the command lines it explains.
+fn examples(page: &mut String, path: &[&str], command: &CommandInfoWithArgs) {
+    if command.examples.is_empty() {
+        return;
+    }
+    page.push_str(".PP\nExamples:\n");
+    for example in command.examples {
+        // Examples are written as format strings for argh's help.
+        let example = example
+            .replace("{command_name}", &path.join(" "))
+            .replace("{{", "{")
+            .replace("}}", "}");
+        for line in example.lines() {
+            match line.strip_prefix("$ ") {
+                Some(command_line) => writeln!(
+                    page,
+                    ".RS\n.EX\n$ {}\n.EE\n.RE",
+                    escape(command_line)
+                ),
+                None => writeln!(page, ".PP\n{}", escape(line)),
+            }
+            .unwrap();
+        }
+    }
+}
+
+/// Escapes `text` so that roff prints it as is.
+fn escape(text: &str) -> String {
+    let text = text.replace('\\', "\\e").replace('-', "\\-");
+    if text.starts_with(['.', '\'']) {
+        format!("\\&{}", text)
+    } else {
+        text
+    }
+}
@@ -0,0 +1,531 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Renders merged changelogs in markup languages other than markdown, for
+//! projects whose documentation is written in them, and as plain text for
+//! release announcements.
+
+use std::str::FromStr;
+
+use comrak::{
+    nodes::{AstNode, ListType, NodeValue},
+    Arena, Options,
+};
+use miette::{miette, Report};
+
+/// A markup language the merged changelog can be written in.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MarkupFormat {
+    Markdown,
+    AsciiDoc,
+    /// reStructuredText.
+    Rst,
+    /// Plain text for announcements by email, with links as footnotes.
+    Text,
+}
+
+impl FromStr for MarkupFormat {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "markdown" | "md" => Ok(Self::Markdown),
+            "asciidoc" | "adoc" => Ok(Self::AsciiDoc),
+            "rst" => Ok(Self::Rst),
+            "text" => Ok(Self::Text),
+            other => Err(miette!("Failed to parse '{other}' as an output format. Options include 'markdown', 'asciidoc', 'rst', and 'text'"))
+        }
+    }
+}
+
+/// Converts `markdown` to `format`.
+pub fn render(markdown: &str, format: MarkupFormat) -> String {
+    let arena = Arena::new();
+    let root = comrak::parse_document(&arena, markdown, &Options::default());
+    let block = match format {
+        MarkupFormat::Markdown => return markdown.to_string(),
+        MarkupFormat::AsciiDoc => asciidoc_block,
+        MarkupFormat::Rst => rst_block,
+        MarkupFormat::Text => return render_text(root),
+    };
+    let blocks = root
+        .children()
+        .map(block)
+        .filter(|block| !block.is_empty())
+        .collect::<Vec<_>>();
+    if blocks.is_empty() {
+        String::new()
+    } else {
+        blocks.join("\n\n") + "\n"
+    }
+}
+
+/// The children of `node` rendered with `block`, one after another.
+fn child_blocks<'a>(
+    node: &'a AstNode<'a>,
+    block: fn(&'a AstNode<'a>) -> String,
+) -> String {
+    node.children()
+        .map(block)
+        .filter(|block| !block.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Indents every line of `text` but blank ones by `width` spaces.
+fn indent(text: &str, width: usize) -> String {
+    text.lines()
+        .map(|line| {
+            if line.is_empty() {
+                String::new()
+            } else {
+                format!("{}{}", " ".repeat(width), line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn asciidoc_block<'a>(node: &'a AstNode<'a>) -> String {
+    match &node.data.borrow().value {
+        NodeValue::Heading(heading) => {
+            let mut text = String::new();
+            asciidoc_inlines(node, &mut text);
+            format!("{} {}", "=".repeat(heading.level as usize), text)
+        }
+        NodeValue::Paragraph => {
+            let mut text = String::new();
+            asciidoc_inlines(node, &mut text);
+            text
+        }
+        NodeValue::List(list) => {
+            let marker = match list.list_type {
+                ListType::Bullet => '*',
+                ListType::Ordered => '.',
+            };
+            // Nesting is written by repeating the marker.
+            let depth = node
+                .ancestors()
+                .filter(|ancestor| {
+                    matches!(ancestor.data.borrow().value, NodeValue::List(_))
+                })
+                .count();
+            node.children()
+                .map(|item| {
+                    let mut blocks = item.children();
+                    let mut text = format!(
+                        "{} {}",
+                        marker.to_string().repeat(depth),
+                        blocks.next().map(asciidoc_block).unwrap_or_default()
+                    );
+                    let mut after_list = false;
+                    for block in blocks {
+                        let rendered = asciidoc_block(block);
+                        if rendered.is_empty() {
+                            continue;
+                        }
+                        let nested = matches!(
+                            block.data.borrow().value,
+                            NodeValue::List(_)
+                        );
+                        // Other blocks are attached to the item with `+`,
+                        // after a blank line if they would otherwise be
+                        // attached to the last item of a nested list.
+                        text.push_str(match (nested, after_list) {
+                            (true, _) => "\n",
+                            (false, false) => "\n+\n",
+                            (false, true) => "\n\n+\n",
+                        });
+                        text.push_str(&rendered);
+                        after_list = nested;
+                    }
+                    text
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+        NodeValue::CodeBlock(code_block) => format!(
+            "[source{}]\n----\n{}----",
+            code_block
+                .info
+                .split_whitespace()
+                .next()
+                .map(|language| format!(",{language}"))
+                .unwrap_or_default(),
+            code_block.literal
+        ),
+        NodeValue::BlockQuote => {
+            format!("____\n{}\n____", child_blocks(node, asciidoc_block))
+        }
+        NodeValue::ThematicBreak => "'''".to_string(),
+        // Such as the comments left by `--provenance`.
+        NodeValue::HtmlBlock(_) => String::new(),
+        _ => child_blocks(node, asciidoc_block),
+    }
+}
+
+fn asciidoc_inlines<'a>(node: &'a AstNode<'a>, out: &mut String) {
+    for child in node.children() {
+        match &child.data.borrow().value {
+            NodeValue::Text(text) => out.push_str(text),
+            NodeValue::Code(code) => {
+                out.push_str(&format!("`+{}+`", code.literal))
+            }
+            NodeValue::SoftBreak => out.push(' '),
+            NodeValue::LineBreak => out.push_str(" +\n"),
+            NodeValue::Strong => {
+                out.push('*');
+                asciidoc_inlines(child, out);
+                out.push('*');
+            }
+            NodeValue::Emph => {
+                out.push('_');
+                asciidoc_inlines(child, out);
+                out.push('_');
+            }
+            NodeValue::Strikethrough => {
+                out.push_str("[.line-through]#");
+                asciidoc_inlines(child, out);
+                out.push('#');
+            }
+            NodeValue::Link(link) => {
+                let mut text = String::new();
+                asciidoc_inlines(child, &mut text);
+                out.push_str(&format!(
+                    "{}[{}]",
+                    link.url,
+                    text.replace(']', "\\]")
+                ));
+            }
+            NodeValue::Image(image) => {
+                let mut text = String::new();
+                asciidoc_inlines(child, &mut text);
+                out.push_str(&format!(
+                    "image:{}[{}]",
+                    image.url,
+                    text.replace(']', "\\]")
+                ));
+            }
+            NodeValue::HtmlInline(_) => {}
+            _ => asciidoc_inlines(child, out),
+        }
+    }
+}
+
+/// The characters headings are underlined with, by level. reStructuredText
+/// infers the levels from the order they are first used in.
+const RST_UNDERLINES: [char; 6] = ['=', '-', '~', '^', '"', '\''];
+
+/// How many columns `text` takes up, counting wide characters such as emoji
+/// and CJK ideographs twice, so that underlines are never too short.
+fn rst_width(text: &str) -> usize {
+    text.chars()
+        .map(|c| if c as u32 >= 0x1100 { 2 } else { 1 })
+        .sum()
+}
+
+fn rst_block<'a>(node: &'a AstNode<'a>) -> String {
+    match &node.data.borrow().value {
+        NodeValue::Heading(heading) => {
+            let mut text = String::new();
+            rst_inlines(node, &mut text);
+            let underline = RST_UNDERLINES[heading.level as usize - 1];
+            format!(
+                "{}\n{}",
+                text,
+                underline.to_string().repeat(rst_width(&text))
+            )
+        }
+        NodeValue::Paragraph => {
+            let mut text = String::new();
+            rst_inlines(node, &mut text);
+            text
+        }
+        NodeValue::List(list) => {
+            let marker = match list.list_type {
+                ListType::Bullet => "- ",
+                ListType::Ordered => "#. ",
+            };
+            let items = node
+                .children()
+                .map(|item| {
+                    let blocks = child_blocks(item, rst_block);
+                    let mut lines = blocks.lines();
+                    let first = lines.next().unwrap_or_default();
+                    let rest = lines.collect::<Vec<_>>().join("\n");
+                    if rest.is_empty() {
+                        format!("{marker}{first}")
+                    } else {
+                        format!(
+                            "{marker}{first}\n{}",
+                            indent(&rest, marker.len())
+                        )
+                    }
+                })
+                .collect::<Vec<_>>();
+            // Items with nested blocks have to be separated by blank lines.
+            if items.iter().any(|item| item.contains('\n')) {
+                items.join("\n\n")
+            } else {
+                items.join("\n")
+            }
+        }
+        NodeValue::CodeBlock(code_block) => format!(
+            ".. code-block::{}\n\n{}",
+            code_block
+                .info
+                .split_whitespace()
+                .next()
+                .map(|language| format!(" {language}"))
+                .unwrap_or_default(),
+            indent(code_block.literal.trim_end(), 3)
+        ),
+        NodeValue::BlockQuote => indent(&child_blocks(node, rst_block), 4),
+        NodeValue::ThematicBreak => "----".to_string(),
+        // Such as the comments left by `--provenance`.
+        NodeValue::HtmlBlock(_) => String::new(),
+        _ => child_blocks(node, rst_block),
+    }
+}
+
+/// Escapes the characters that start or end inline markup in
+/// reStructuredText.
+fn rst_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '`' | '|' | '_') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn rst_inlines<'a>(node: &'a AstNode<'a>, out: &mut String) {
+    for child in node.children() {
+        match &child.data.borrow().value {
+            NodeValue::Text(text) => out.push_str(&rst_escape(text)),
+            NodeValue::Code(code) => {
+                out.push_str(&format!("``{}``", code.literal))
+            }
+            NodeValue::SoftBreak | NodeValue::LineBreak => out.push(' '),
+            NodeValue::Strong => {
+                out.push_str("**");
+                rst_inlines(child, out);
+                out.push_str("**");
+            }
+            NodeValue::Emph => {
+                out.push('*');
+                rst_inlines(child, out);
+                out.push('*');
+            }
+            // Anonymous, so that links with the same text do not clash.
+            NodeValue::Link(link) => {
+                let mut text = String::new();
+                rst_inlines(child, &mut text);
+                out.push_str(&format!(
+                    "`{} <{}>`__",
+                    text.replace('<', "\\<"),
+                    link.url
+                ));
+            }
+            NodeValue::HtmlInline(_) => {}
+            _ => rst_inlines(child, out),
+        }
+    }
+}
+
+/// The column plain text is wrapped at, as is customary for email.
+const TEXT_WIDTH: usize = 72;
+
+/// Renders the document at `root` as plain text wrapped at [`TEXT_WIDTH`],
+/// with each link numbered like `[1]` and listed after it.
+fn render_text<'a>(root: &'a AstNode<'a>) -> String {
+    let mut text = PlainText::default();
+    let mut blocks = root
+        .children()
+        .map(|block| text.block(block, ""))
+        .filter(|block| !block.is_empty())
+        .collect::<Vec<_>>();
+    if !text.links.is_empty() {
+        blocks.push(
+            text.links
+                .iter()
+                .enumerate()
+                .map(|(index, url)| format!("[{}] {}", index + 1, url))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        );
+    }
+    if blocks.is_empty() {
+        String::new()
+    } else {
+        blocks.join("\n\n") + "\n"
+    }
+}
+
+#[derive(Default)]
+struct PlainText {
+    /// The URLs of the links so far, numbered from 1 in order.
+    links: Vec<String>,
+}
+
+impl PlainText {
+    /// Renders `node` with its lines indented by `indent`.
+    fn block<'a>(&mut self, node: &'a AstNode<'a>, indent: &str) -> String {
+        match &node.data.borrow().value {
+            NodeValue::Heading(heading) => {
+                let text = self.inlines(node);
+                let underline = if heading.level == 1 { '=' } else { '-' };
+                format!(
+                    "{indent}{}\n{indent}{}",
+                    text,
+                    underline.to_string().repeat(rst_width(&text))
+                )
+            }
+            NodeValue::Paragraph => {
+                let text = self.inlines(node);
+                wrap_text(&text, indent, indent, TEXT_WIDTH)
+            }
+            NodeValue::List(list) => {
+                let mut items = Vec::new();
+                for (index, item) in node.children().enumerate() {
+                    let marker = match list.list_type {
+                        ListType::Bullet => "* ".to_string(),
+                        ListType::Ordered => {
+                            format!("{}. ", list.start + index)
+                        }
+                    };
+                    let hang = format!("{indent}{}", " ".repeat(marker.len()));
+                    let mut rendered_item = String::new();
+                    for (position, block) in item.children().enumerate() {
+                        let rendered = if position == 0
+                            && matches!(
+                                block.data.borrow().value,
+                                NodeValue::Paragraph
+                            ) {
+                            let text = self.inlines(block);
+                            wrap_text(
+                                &text,
+                                &format!("{indent}{marker}"),
+                                &hang,
+                                TEXT_WIDTH,
+                            )
+                        } else {
+                            self.block(block, &hang)
+                        };
+                        if rendered.is_empty() {
+                            continue;
+                        }
+                        // Nested lists stay tight, but anything else in an
+                        // item gets a blank line before it, like a paragraph.
+                        if position > 0 {
+                            let is_list = matches!(
+                                block.data.borrow().value,
+                                NodeValue::List(_)
+                            );
+                            rendered_item.push_str(if is_list {
+                                "\n"
+                            } else {
+                                "\n\n"
+                            });
+                        }
+                        rendered_item.push_str(&rendered);
+                    }
+                    items.push(rendered_item);
+                }
+                items.join("\n")
+            }
+            NodeValue::CodeBlock(code_block) => code_block
+                .literal
+                .trim_end()
+                .lines()
+                .map(|line| {
+                    format!("{indent}    {line}").trim_end().to_string()
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            NodeValue::BlockQuote => {
+                let quote = format!("{indent}> ");
+                self.blocks(node, &quote)
+            }
+            NodeValue::ThematicBreak => format!("{indent}{}", "-".repeat(20)),
+            // Such as the comments left by `--provenance`.
+            NodeValue::HtmlBlock(_) => String::new(),
+            _ => self.blocks(node, indent),
+        }
+    }
+
+    /// Renders the children of `node` one after another.
+    fn blocks<'a>(&mut self, node: &'a AstNode<'a>, indent: &str) -> String {
+        node.children()
+            .map(|block| self.block(block, indent))
+            .filter(|block| !block.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    fn inlines<'a>(&mut self, node: &'a AstNode<'a>) -> String {
+        let mut out = String::new();
+        for child in node.children() {
+            match &child.data.borrow().value {
+                NodeValue::Text(text) => out.push_str(text),
+                NodeValue::Code(code) => out.push_str(&code.literal),
+                NodeValue::SoftBreak | NodeValue::LineBreak => out.push(' '),
+                NodeValue::Link(link) => {
+                    let text = self.inlines(child);
+                    out.push_str(&text);
+                    if text != link.url {
+                        let number = match self
+                            .links
+                            .iter()
+                            .position(|url| *url == link.url)
+                        {
+                            Some(index) => index + 1,
+                            None => {
+                                self.links.push(link.url.clone());
+                                self.links.len()
+                            }
+                        };
+                        out.push_str(&format!(" [{number}]"));
+                    }
+                }
+                NodeValue::HtmlInline(_) => {}
+                _ => out.push_str(&self.inlines(child)),
+            }
+        }
+        out
+    }
+}
+
+/// Wraps `text` at spaces to fit within `width` columns where possible,
+/// starting its first line with `first` and the others with `rest`.
+pub fn wrap_text(text: &str, first: &str, rest: &str, width: usize) -> String {
+    let mut lines = Vec::new();
+    let mut line = first.to_string();
+    let mut empty = true;
+    for word in text.split_whitespace() {
+        if !empty && rst_width(&line) + 1 + rst_width(word) > width {
+            lines.push(line);
+            line = rest.to_string();
+            empty = true;
+        }
+        if !empty {
+            line.push(' ');
+        }
+        line.push_str(word);
+        empty = false;
+    }
+    lines.push(line);
+    lines.join("\n")
+}
@@ -0,0 +1,259 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Renders merged changelogs as the changelogs of distribution packages: a
+//! stanza of `debian/changelog` or an entry of the `%changelog` of an RPM spec.
+
+use std::str::FromStr;
+
+use comrak::{
+    nodes::{AstNode, NodeValue},
+    Arena, Options,
+};
+use miette::{miette, Report};
+
+use crate::{civil_from_days, markup::wrap_text};
+
+/// The column both formats are wrapped at, which `lintian` and `rpmlint` warn
+/// about going past.
+const WIDTH: usize = 80;
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct",
+    "Nov", "Dec",
+];
+
+/// A packaging changelog format.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PackageFormat {
+    Debian,
+    Rpm,
+}
+
+impl FromStr for PackageFormat {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "debian" => Ok(Self::Debian),
+            "rpm" => Ok(Self::Rpm),
+            other => Err(miette!("Failed to parse '{other}' as a packaging format. Options include 'debian' and 'rpm'"))
+        }
+    }
+}
+
+/// What the entries are released as.
+pub struct Release {
+    pub package: String,
+    /// The upstream version, without a leading `v`.
+    pub version: String,
+    /// The packaging revision, appended to the version like `1.4.0-1`.
+    pub revision: String,
+    pub distribution: String,
+    pub urgency: String,
+    /// Like `Jane Doe <jane@example.com>`.
+    pub maintainer: String,
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+/// An entry along with the text of the entries nested under it.
+struct Entry {
+    text: String,
+    nested: Vec<String>,
+}
+
+/// Converts `markdown` to `format` for `release`.
+pub fn render(
+    markdown: &str,
+    format: PackageFormat,
+    release: &Release,
+) -> String {
+    let arena = Arena::new();
+    let root = comrak::parse_document(&arena, markdown, &Options::default());
+    let sections = sections(root);
+    match format {
+        PackageFormat::Debian => debian(&sections, release),
+        PackageFormat::Rpm => rpm(&sections, release),
+    }
+}
+
+fn debian(
+    sections: &[(Option<String>, Vec<Entry>)],
+    release: &Release,
+) -> String {
+    let mut changes = Vec::new();
+    for (section, entries) in sections {
+        // Entries go directly in the stanza without a section to group them.
+        let indent = match section {
+            Some(section) => {
+                changes.push(format!("  * {}:", section));
+                "    - "
+            }
+            None => "  * ",
+        };
+        let hang = " ".repeat(indent.len());
+        for entry in entries {
+            changes.push(wrap_text(&entry.text, indent, &hang, WIDTH));
+            for nested in &entry.nested {
+                changes.push(wrap_text(
+                    nested,
+                    &format!("{hang}+ "),
+                    &format!("{hang}  "),
+                    WIDTH,
+                ));
+            }
+        }
+    }
+    // A stanza needs at least one change.
+    if changes.is_empty() {
+        changes.push("  * New upstream release.".to_string());
+    }
+    let (year, month, day) =
+        civil_from_days((release.timestamp / 86400) as i64);
+    let seconds = release.timestamp % 86400;
+    format!(
+        "{} ({}-{}) {}; urgency={}\n\n{}\n\n -- {}  {}, {:02} {} {} {:02}:{:02}:{:02} +0000\n",
+        release.package,
+        release.version,
+        release.revision,
+        release.distribution,
+        release.urgency,
+        changes.join("\n"),
+        release.maintainer,
+        weekday(release.timestamp),
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+fn rpm(sections: &[(Option<String>, Vec<Entry>)], release: &Release) -> String {
+    let (year, month, day) =
+        civil_from_days((release.timestamp / 86400) as i64);
+    let mut lines = vec![format!(
+        "* {} {} {:02} {} {} - {}-{}",
+        weekday(release.timestamp),
+        MONTHS[month as usize - 1],
+        day,
+        year,
+        release.maintainer,
+        release.version,
+        release.revision
+    )];
+    for (section, entries) in sections {
+        for entry in entries {
+            let text = match section {
+                Some(section) => format!("{}: {}", section, entry.text),
+                None => entry.text.clone(),
+            };
+            lines.push(wrap_text(&text, "- ", "  ", WIDTH));
+            for nested in &entry.nested {
+                lines.push(wrap_text(nested, "  - ", "    ", WIDTH));
+            }
+        }
+    }
+    if lines.len() == 1 {
+        lines.push(format!("- Update to {}", release.version));
+    }
+    lines.join("\n") + "\n"
+}
+
+/// The abbreviated day of the week at `timestamp`.
+fn weekday(timestamp: u64) -> &'static str {
+    // The Unix epoch was a Thursday.
+    WEEKDAYS[((timestamp / 86400 + 4) % 7) as usize]
+}
+
+/// The entries of the document at `root` under the heading they are in, if
+/// any, leaving out anything that is not text, like code blocks.
+fn sections<'a>(root: &'a AstNode<'a>) -> Vec<(Option<String>, Vec<Entry>)> {
+    let mut sections: Vec<(Option<String>, Vec<Entry>)> = Vec::new();
+    // The headings the following entries are under, by level.
+    let mut headings: Vec<(u8, String)> = Vec::new();
+    for block in root.children() {
+        let entries = match &block.data.borrow().value {
+            NodeValue::Heading(heading) => {
+                headings.retain(|(level, _)| *level < heading.level);
+                headings.push((heading.level, plain(block)));
+                continue;
+            }
+            NodeValue::List(_) => block
+                .children()
+                .map(|item| {
+                    let mut nested = Vec::new();
+                    for list in item.children().skip(1) {
+                        nested_text(list, &mut nested);
+                    }
+                    Entry {
+                        text: item.first_child().map(plain).unwrap_or_default(),
+                        nested,
+                    }
+                })
+                .collect(),
+            NodeValue::Paragraph => vec![Entry {
+                text: plain(block),
+                nested: Vec::new(),
+            }],
+            _ => continue,
+        };
+        let section = (!headings.is_empty()).then(|| {
+            headings
+                .iter()
+                .map(|(_, heading)| heading.as_str())
+                .collect::<Vec<_>>()
+                .join(" / ")
+        });
+        match sections.last_mut() {
+            Some((last, last_entries)) if *last == section => {
+                last_entries.extend(entries)
+            }
+            _ => sections.push((section, entries)),
+        }
+    }
+    sections
+}
+
+/// Collects the text of the list items in `node`, however deeply nested.
+fn nested_text<'a>(node: &'a AstNode<'a>, texts: &mut Vec<String>) {
+    match &node.data.borrow().value {
+        NodeValue::Paragraph => texts.push(plain(node)),
+        NodeValue::List(_) | NodeValue::Item(_) | NodeValue::BlockQuote => {
+            for child in node.children() {
+                nested_text(child, texts);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The text of `node` without any markup, keeping the text of links but not
+/// where they point.
+fn plain<'a>(node: &'a AstNode<'a>) -> String {
+    let mut out = String::new();
+    for child in node.children() {
+        match &child.data.borrow().value {
+            NodeValue::Text(text) => out.push_str(text),
+            NodeValue::Code(code) => out.push_str(&code.literal),
+            NodeValue::SoftBreak | NodeValue::LineBreak => out.push(' '),
+            NodeValue::HtmlInline(_) => {}
+            _ => out.push_str(&plain(child)),
+        }
+    }
+    out.trim().to_string()
+}
@@ -0,0 +1,229 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! A terminal UI for picking the merge/pull request a changelog belongs to.
+//! It draws on standard error, since standard output is the changelog.
+
+use std::io::{self, Stderr};
+
+use miette::{miette, IntoDiagnostic, Result};
+use ratatui::{
+    backend::CrosstermBackend,
+    crossterm::{
+        event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+        execute,
+        terminal::{
+            disable_raw_mode, enable_raw_mode, EnterAlternateScreen,
+            LeaveAlternateScreen,
+        },
+    },
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, List, ListItem, ListState, Paragraph, Wrap},
+    Frame, Terminal,
+};
+
+use crate::{fuzzy_search, PullRequest};
+
+/// The changelog being resolved and the candidates the picker offers for it.
+struct Picker<'a> {
+    name: &'a str,
+    contents: &'a str,
+    pull_requests: &'a [PullRequest],
+    /// The candidates to offer before anything is typed.
+    guessed: Vec<usize>,
+    query: String,
+    /// Indices into `pull_requests` of the candidates currently listed.
+    candidates: Vec<usize>,
+    state: ListState,
+}
+
+impl<'a> Picker<'a> {
+    fn new(
+        name: &'a str,
+        contents: &'a str,
+        pull_requests: &'a [PullRequest],
+        guessed: Vec<usize>,
+    ) -> Self {
+        let mut picker = Self {
+            name,
+            contents,
+            pull_requests,
+            guessed,
+            query: String::new(),
+            candidates: Vec::new(),
+            state: ListState::default(),
+        };
+        picker.filter();
+        picker
+    }
+
+    /// Lists the guesses if nothing is typed, otherwise every merge/pull
+    /// request matching the query, best first.
+    fn filter(&mut self) {
+        self.candidates = if self.query.is_empty() {
+            self.guessed.clone()
+        } else {
+            fuzzy_search(&self.query, self.pull_requests)
+        };
+        self.state
+            .select((!self.candidates.is_empty()).then_some(0));
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, help] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)])
+                .areas(frame.area());
+        let [fragment, search] = Layout::horizontal([
+            Constraint::Percentage(50),
+            Constraint::Percentage(50),
+        ])
+        .areas(main);
+        let [input, list] =
+            Layout::vertical([Constraint::Length(3), Constraint::Min(0)])
+                .areas(search);
+
+        frame.render_widget(
+            Paragraph::new(self.contents)
+                .wrap(Wrap { trim: false })
+                .block(Block::bordered().title(format!(" {} ", self.name))),
+            fragment,
+        );
+        frame.render_widget(
+            Paragraph::new(self.query.as_str())
+                .block(Block::bordered().title(" Search ")),
+            input,
+        );
+        frame.set_cursor_position((
+            input.x + 1 + self.query.chars().count() as u16,
+            input.y + 1,
+        ));
+
+        let items = self
+            .candidates
+            .iter()
+            .map(|index| {
+                let pr = &self.pull_requests[*index];
+                ListItem::new(Line::from(vec![
+                    Span::styled(
+                        pr.link.clone(),
+                        Style::default().fg(Color::Cyan),
+                    ),
+                    Span::raw(" "),
+                    Span::raw(pr.title.clone()),
+                ]))
+            })
+            .collect::<Vec<_>>();
+        let title = if self.query.is_empty() {
+            " Best guesses ".to_string()
+        } else {
+            format!(" {} matching ", self.candidates.len())
+        };
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(Block::bordered().title(title))
+                .highlight_style(
+                    Style::default().add_modifier(Modifier::REVERSED),
+                )
+                .highlight_symbol("› "),
+            list,
+            &mut self.state,
+        );
+
+        frame.render_widget(
+            Paragraph::new(
+                "↑/↓ select · enter confirm · esc enter a link manually · ctrl-c quit",
+            )
+            .style(Style::default().fg(Color::DarkGray)),
+            help,
+        );
+    }
+
+    /// Handles key presses until a merge/pull request is picked, returning its
+    /// index, or `None` if the user would rather enter a link.
+    fn run(
+        &mut self,
+        terminal: &mut Terminal<CrosstermBackend<Stderr>>,
+    ) -> Result<Option<usize>> {
+        loop {
+            terminal.draw(|frame| self.draw(frame)).into_diagnostic()?;
+            let Event::Key(key) = event::read().into_diagnostic()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let control = key.modifiers.contains(KeyModifiers::CONTROL);
+            match key.code {
+                KeyCode::Char('c') if control => {
+                    return Err(miette!(
+                        code = "picker::cancelled",
+                        "Cancelled resolving changelog '{}'",
+                        self.name
+                    ));
+                }
+                KeyCode::Up => self.state.select_previous(),
+                KeyCode::Char('p') if control => self.state.select_previous(),
+                KeyCode::Down => self.state.select_next(),
+                KeyCode::Char('n') if control => self.state.select_next(),
+                KeyCode::Enter => {
+                    if let Some(selected) = self.state.selected() {
+                        return Ok(Some(self.candidates[selected]));
+                    }
+                }
+                KeyCode::Esc => return Ok(None),
+                KeyCode::Backspace => {
+                    self.query.pop();
+                    self.filter();
+                }
+                KeyCode::Char(c) if !control => {
+                    self.query.push(c);
+                    self.filter();
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Lets the user pick which of `pull_requests` the changelog `file_name` with
+/// `contents` belongs to, starting from the `guessed` ones, or returns `None`
+/// if they would rather enter a link by hand.
+pub fn pick_pull_request<'a>(
+    file_name: &str,
+    contents: &str,
+    pull_requests: &'a [PullRequest],
+    guessed: &[&PullRequest],
+) -> Result<Option<&'a PullRequest>> {
+    let guessed = guessed
+        .iter()
+        .filter_map(|guess| {
+            pull_requests.iter().position(|pr| pr.id == guess.id)
+        })
+        .collect();
+    let mut picker = Picker::new(file_name, contents, pull_requests, guessed);
+
+    enable_raw_mode().into_diagnostic()?;
+    let mut stderr = io::stderr();
+    let result = execute!(stderr, EnterAlternateScreen)
+        .into_diagnostic()
+        .and_then(|_| {
+            Terminal::new(CrosstermBackend::new(io::stderr())).into_diagnostic()
+        })
+        .and_then(|mut terminal| picker.run(&mut terminal));
+    // Restore the terminal even if the picker failed, so the error is legible.
+    execute!(stderr, LeaveAlternateScreen).into_diagnostic()?;
+    disable_raw_mode().into_diagnostic()?;
+    Ok(result?.map(|index| &pull_requests[index]))
+}
@@ -0,0 +1,101 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Where questions are shown and answered, so that resolving changelogs does
+//! not depend on standard input and standard error.
+
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+};
+
+use camino::Utf8Path;
+use miette::{Context, IntoDiagnostic, Result};
+
+use crate::read_file;
+
+/// Shows questions and the context they need, and gets the answers.
+pub trait Prompter {
+    /// Shows `line`, which explains the questions that follow.
+    fn show(&mut self, line: &str);
+
+    /// Shows `question`, which ends where the answer goes, and returns the
+    /// answer, or `None` if no more answers will come.
+    fn ask(&mut self, question: &str) -> Result<Option<String>>;
+}
+
+/// Asks on standard error and reads answers from standard input.
+pub struct Terminal;
+
+impl Prompter for Terminal {
+    fn show(&mut self, line: &str) {
+        eprintln!("{line}");
+    }
+
+    fn ask(&mut self, question: &str) -> Result<Option<String>> {
+        eprint!("{question}");
+        io::stdout()
+            .flush()
+            .into_diagnostic()
+            .wrap_err("Failed to flush standard output")?;
+        io::stderr()
+            .flush()
+            .into_diagnostic()
+            .wrap_err("Failed to flush standard error")?;
+        let mut answer = String::new();
+        let read = io::stdin()
+            .read_line(&mut answer)
+            .into_diagnostic()
+            .wrap_err("Failed to read user input")?;
+        if read == 0 {
+            // The answer would otherwise follow on the same line.
+            eprintln!();
+            return Ok(None);
+        }
+        Ok(Some(answer.trim_end_matches(['\n', '\r']).to_string()))
+    }
+}
+
+/// Answers questions with answers given up front, in order, showing them
+/// after their questions as if they had been typed.
+pub struct Scripted {
+    answers: VecDeque<String>,
+}
+
+impl Scripted {
+    pub fn new(answers: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            answers: answers.into_iter().collect(),
+        }
+    }
+
+    /// Reads the answers from the lines of the file at `path`.
+    pub fn read(path: &Utf8Path) -> Result<Self> {
+        let contents = read_file(path)
+            .into_diagnostic()
+            .wrap_err(format!("Failed to read answers from {path}"))?;
+        Ok(Self::new(contents.lines().map(str::to_string)))
+    }
+}
+
+impl Prompter for Scripted {
+    fn show(&mut self, line: &str) {
+        eprintln!("{line}");
+    }
+
+    fn ask(&mut self, question: &str) -> Result<Option<String>> {
+        let answer = self.answers.pop_front();
+        eprintln!("{question}{}", answer.as_deref().unwrap_or_default());
+        Ok(answer)
+    }
+}
@@ -0,0 +1,545 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Publishing the notes of a release in the changelog as the release for its
+//! tag on the repository host, so that the two say the same.
+
+use std::{collections::HashMap, env};
+
+use miette::{miette, Context, IntoDiagnostic, Result};
+use reqwest::{
+    blocking::RequestBuilder,
+    header::{CONTENT_TYPE, USER_AGENT},
+    Method, StatusCode,
+};
+use serde_json::{json, Value as JsonValue};
+use tracing::{debug, info, trace};
+
+use crate::{Api, RepositoryHost};
+
+/// How requests to the repository host are authenticated.
+enum Token {
+    /// A GitLab access token from `GITLAB_TOKEN`.
+    Private(String),
+    /// The token of the running GitLab CI job, from `CI_JOB_TOKEN`.
+    Job(String),
+    /// A GitHub access token from `GITHUB_TOKEN`, which GitHub Actions sets
+    /// in its jobs.
+    Bearer(String),
+}
+
+impl Token {
+    fn from_env(host: RepositoryHost) -> Option<Self> {
+        let var =
+            |name: &str| env::var(name).ok().filter(|value| !value.is_empty());
+        match host {
+            RepositoryHost::GitHub => var("GITHUB_TOKEN").map(Self::Bearer),
+            RepositoryHost::GitLab => var("GITLAB_TOKEN")
+                .map(Self::Private)
+                .or_else(|| var("CI_JOB_TOKEN").map(Self::Job)),
+            RepositoryHost::Infer => unreachable!(),
+        }
+    }
+
+    fn authenticate(&self, request: RequestBuilder) -> RequestBuilder {
+        match self {
+            Self::Private(token) => request.header("PRIVATE-TOKEN", token),
+            Self::Job(token) => request.header("JOB-TOKEN", token),
+            Self::Bearer(token) => request.bearer_auth(token),
+        }
+    }
+}
+
+/// Sends `request`, authenticated by `token` if there is one.
+fn send(
+    request: RequestBuilder,
+    token: Option<&Token>,
+) -> Result<(StatusCode, String)> {
+    // GitHub rejects requests without a user agent.
+    let request = request.header(USER_AGENT, "mergelog");
+    let request = match token {
+        Some(token) => token.authenticate(request),
+        None => request,
+    };
+    let response = request
+        .send()
+        .into_diagnostic()
+        .wrap_err("Failed to send API request")?;
+    let status = response.status();
+    let text = response
+        .text()
+        .into_diagnostic()
+        .wrap_err("Failed to extract API response text")?;
+    Ok((status, text))
+}
+
+/// The error message in an API `response` with `status`, or the status and
+/// the whole response if it has none.
+fn error_message(status: StatusCode, response: String) -> String {
+    serde_json::from_str::<JsonValue>(&response)
+        .ok()
+        .and_then(|response| {
+            response.get("message").map(|message| match message {
+                JsonValue::String(message) => message.clone(),
+                message => message.to_string(),
+            })
+        })
+        .unwrap_or_else(|| format!("{status} {response}"))
+}
+
+/// Something linked in a release besides its notes.
+pub enum Asset {
+    /// A file, e.g., a binary or its checksums, that is uploaded as a generic
+    /// package file of the repository and linked from there on GitLab, or
+    /// uploaded to the release on GitHub.
+    File { name: String, contents: Vec<u8> },
+    /// A link to something hosted elsewhere, e.g., documentation.
+    Link { name: String, url: String },
+}
+
+impl Asset {
+    fn name(&self) -> &str {
+        match self {
+            Self::File { name, .. } | Self::Link { name, .. } => name,
+        }
+    }
+}
+
+fn encode(component: &str) -> String {
+    url::form_urlencoded::byte_serialize(component.as_bytes()).collect()
+}
+
+/// Prints `method` `url` with `body` as the request that would be sent.
+fn print_request(method: &Method, url: &str, body: &str) {
+    println!("{} {}", method, url);
+    println!("{}", body);
+}
+
+/// The ids of the assets in the release described by `response`, by name,
+/// from the array at `pointer` in it, e.g., `/assets/links` on GitLab.
+fn existing_assets(response: &str, pointer: &str) -> HashMap<String, u64> {
+    serde_json::from_str::<JsonValue>(response)
+        .ok()
+        .and_then(|release| {
+            Some(
+                release
+                    .pointer(pointer)?
+                    .as_array()?
+                    .iter()
+                    .filter_map(|link| {
+                        Some((
+                            link.get("name")?.as_str()?.to_string(),
+                            link.get("id")?.as_u64()?,
+                        ))
+                    })
+                    .collect(),
+            )
+        })
+        .unwrap_or_default()
+}
+
+/// Creates the release for `tag` in the repository `owner/name` with `notes`
+/// as its description, or updates the description of the release if it
+/// exists, and attaches `assets` to it, replacing those with the same names.
+/// If `dry_run`, prints the requests that would do so instead of sending
+/// them.
+pub fn publish(
+    api: &Api,
+    (host, owner, name): (RepositoryHost, &str, &str),
+    tag: &str,
+    notes: &str,
+    assets: &[Asset],
+    dry_run: bool,
+) -> Result<()> {
+    let token = Token::from_env(host);
+    if token.is_none() && !dry_run {
+        return Err(miette!(
+            code = "publish::missing_token",
+            help = match host {
+                RepositoryHost::GitHub => "Set `GITHUB_TOKEN` to an access token that may write contents, or run in a GitHub Actions job with `contents: write` permissions.",
+                _ => "Set `GITLAB_TOKEN` to an access token with the `api` scope, or run in a GitLab CI job, which has a `CI_JOB_TOKEN`.",
+            },
+            "No token to publish the release with"
+        ));
+    }
+    let release = Release {
+        api,
+        token: token.as_ref(),
+        tag,
+        dry_run,
+    };
+    match host {
+        RepositoryHost::GitHub => {
+            release.publish_github((owner, name), notes, assets)
+        }
+        RepositoryHost::GitLab => {
+            release.publish_gitlab((owner, name), notes, assets)
+        }
+        RepositoryHost::Infer => unreachable!(),
+    }
+}
+
+/// A release being published, and how.
+struct Release<'a> {
+    api: &'a Api,
+    token: Option<&'a Token>,
+    tag: &'a str,
+    dry_run: bool,
+}
+
+impl Release<'_> {
+    /// Looks up the release at `url`, returning its description if it exists.
+    fn look_up(&self, url: &str) -> Result<Option<String>> {
+        self.api.check_online(url)?;
+        debug!("GET {}", url);
+        let (status, response) = send(self.api.client.get(url), self.token)?;
+        trace!("Response to {}: {}", url, response);
+        match status {
+            StatusCode::OK => Ok(Some(response)),
+            StatusCode::NOT_FOUND => Ok(None),
+            status => Err(miette!(
+                code = "publish::api_error",
+                "Failed to look up release {}: {}",
+                self.tag,
+                error_message(status, response)
+            )),
+        }
+    }
+
+    /// Creates or updates the release with `method` `url` and `payload`,
+    /// returning the response.
+    fn save(
+        &self,
+        exists: bool,
+        method: Method,
+        url: &str,
+        payload: &JsonValue,
+    ) -> Result<String> {
+        let response = send_json(
+            self.api,
+            self.token,
+            method,
+            url,
+            payload,
+            self.dry_run,
+        )?
+        .map_err(|message| {
+            miette!(
+                code = "publish::request_failed",
+                help = if exists {
+                    "Check that the token may update releases."
+                } else {
+                    "Check that the tag is pushed and that the token may create releases."
+                },
+                "Failed to {} release {}: {}",
+                if exists { "update" } else { "create" },
+                self.tag,
+                message
+            )
+        })?;
+        if !self.dry_run {
+            info!(
+                "{} release {}",
+                if exists { "Updated" } else { "Created" },
+                self.tag
+            );
+        }
+        Ok(response)
+    }
+
+    /// Uploads `contents` as `file` with `method` `url`, suggesting `help` if
+    /// that fails.
+    fn upload(
+        &self,
+        file: &str,
+        (method, url): (Method, &str),
+        contents: &[u8],
+        help: &'static str,
+    ) -> Result<()> {
+        upload(self.api, self.token, method, url, contents, self.dry_run)?
+            .map_err(|message| {
+                miette!(
+                    code = "publish::upload_failed",
+                    help = help,
+                    "Failed to upload asset {}: {}",
+                    file,
+                    message
+                )
+            })
+    }
+
+    fn publish_gitlab(
+        &self,
+        (owner, name): (&str, &str),
+        notes: &str,
+        assets: &[Asset],
+    ) -> Result<()> {
+        let tag = self.tag;
+        let project =
+            format!("{}/projects/{}%2F{}", self.api.gitlab_url, owner, name);
+        let releases = format!("{}/releases", project);
+        let release = format!("{}/{}", releases, encode(tag));
+        let existing = self.look_up(&release)?;
+        let links = existing
+            .as_deref()
+            .map(|response| existing_assets(response, "/assets/links"))
+            .unwrap_or_default();
+        if existing.is_some() {
+            self.save(
+                true,
+                Method::PUT,
+                &release,
+                &json!({ "description": notes }),
+            )?;
+        } else {
+            self.save(
+                false,
+                Method::POST,
+                &releases,
+                &json!({ "tag_name": tag, "name": tag, "description": notes }),
+            )?;
+        }
+
+        for asset in assets {
+            let url = match asset {
+                Asset::File {
+                    name: file,
+                    contents,
+                } => {
+                    let url = format!(
+                        "{}/packages/generic/{}/{}/{}",
+                        project,
+                        encode(name),
+                        encode(tag),
+                        encode(file)
+                    );
+                    self.upload(
+                        file,
+                        (Method::PUT, &url),
+                        contents,
+                        "Check that the token may publish packages.",
+                    )?;
+                    url
+                }
+                Asset::Link { url, .. } => url.clone(),
+            };
+            let (method, link_url, payload) = match links.get(asset.name()) {
+                Some(id) => (
+                    Method::PUT,
+                    format!("{}/assets/links/{}", release, id),
+                    json!({ "url": url }),
+                ),
+                None => (
+                    Method::POST,
+                    format!("{}/assets/links", release),
+                    json!({
+                        "name": asset.name(),
+                        "url": url,
+                        "link_type": match asset {
+                            Asset::File { .. } => "package",
+                            Asset::Link { .. } => "other",
+                        },
+                    }),
+                ),
+            };
+            send_json(
+                self.api,
+                self.token,
+                method,
+                &link_url,
+                &payload,
+                self.dry_run,
+            )?
+            .map_err(|message| {
+                miette!(
+                    code = "publish::request_failed",
+                    help = "Check that the token may update releases.",
+                    "Failed to link asset {} in release {}: {}",
+                    asset.name(),
+                    tag,
+                    message
+                )
+            })?;
+            if !self.dry_run {
+                info!("Linked asset {} in release {}", asset.name(), tag);
+            }
+        }
+        Ok(())
+    }
+
+    fn publish_github(
+        &self,
+        (owner, name): (&str, &str),
+        notes: &str,
+        assets: &[Asset],
+    ) -> Result<()> {
+        // Releases on GitHub hold uploaded files alone.
+        if let Some(link) = assets
+            .iter()
+            .find(|asset| matches!(asset, Asset::Link { .. }))
+        {
+            return Err(miette!(
+                code = "publish::unsupported_link",
+                help = "Link it from the changelog instead, or leave `publish.links` out of the config.",
+                "Cannot link {} in a GitHub release",
+                link.name()
+            ));
+        }
+
+        let tag = self.tag;
+        let releases = format!(
+            "{}/repos/{}/{}/releases",
+            self.api.github_url, owner, name
+        );
+        let existing =
+            self.look_up(&format!("{}/tags/{}", releases, encode(tag)))?;
+        let id = |response: &str| {
+            serde_json::from_str::<JsonValue>(response)
+                .ok()?
+                .get("id")?
+                .as_u64()
+        };
+        let response = match existing.as_deref().and_then(id) {
+            Some(id) => self.save(
+                true,
+                Method::PATCH,
+                &format!("{}/{}", releases, id),
+                &json!({ "body": notes }),
+            )?,
+            None => self.save(
+                false,
+                Method::POST,
+                &releases,
+                &json!({ "tag_name": tag, "name": tag, "body": notes }),
+            )?,
+        };
+        if assets.is_empty() {
+            return Ok(());
+        }
+
+        let release = existing.unwrap_or(response);
+        let uploaded = existing_assets(&release, "/assets");
+        // Like `https://uploads.github.com/repos/o/r/releases/1/assets{?name,label}`.
+        let upload_url = serde_json::from_str::<JsonValue>(&release)
+            .ok()
+            .and_then(|release| {
+                let url = release.get("upload_url")?.as_str()?;
+                Some(url.split('{').next().unwrap_or(url).to_string())
+            })
+            // The release is not created on a dry run.
+            .unwrap_or_else(|| format!("{}/{{id}}/assets", releases));
+        for asset in assets {
+            let Asset::File {
+                name: file,
+                contents,
+            } = asset
+            else {
+                continue;
+            };
+            // GitHub rejects a second asset with the same name.
+            if let Some(id) = uploaded.get(file) {
+                let url = format!("{}/assets/{}", releases, id);
+                upload(
+                    self.api,
+                    self.token,
+                    Method::DELETE,
+                    &url,
+                    &[],
+                    self.dry_run,
+                )?
+                .map_err(|message| {
+                    miette!(
+                        code = "publish::request_failed",
+                        help = "Check that the token may update releases.",
+                        "Failed to replace asset {} in release {}: {}",
+                        file,
+                        tag,
+                        message
+                    )
+                })?;
+            }
+            self.upload(
+                file,
+                (
+                    Method::POST,
+                    &format!("{}?name={}", upload_url, encode(file)),
+                ),
+                contents,
+                "Check that the token may write contents.",
+            )?;
+            if !self.dry_run {
+                info!("Uploaded asset {} to release {}", file, tag);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sends `method` `url` with the JSON `payload`, or prints it instead if
+/// `dry_run`, returning the response, which is empty on a dry run, or its
+/// error message if it was not successful.
+fn send_json(
+    api: &Api,
+    token: Option<&Token>,
+    method: Method,
+    url: &str,
+    payload: &JsonValue,
+    dry_run: bool,
+) -> Result<Result<String, String>> {
+    if dry_run {
+        print_request(
+            &method,
+            url,
+            &serde_json::to_string_pretty(payload)
+                .expect("JSON values always serialize"),
+        );
+        return Ok(Ok(String::new()));
+    }
+    debug!("{} {}", method, url);
+    let (status, response) =
+        send(api.client.request(method, url).json(payload), token)?;
+    trace!("Response to {}: {}", url, response);
+    if !status.is_success() {
+        return Ok(Err(error_message(status, response)));
+    }
+    Ok(Ok(response))
+}
+
+/// Sends `method` `url` with `contents` as the body, or prints the request
+/// instead if `dry_run`, returning the error message of the response if it
+/// was not successful.
+fn upload(
+    api: &Api,
+    token: Option<&Token>,
+    method: Method,
+    url: &str,
+    contents: &[u8],
+    dry_run: bool,
+) -> Result<Result<(), String>> {
+    if dry_run {
+        print_request(&method, url, &format!("<{} bytes>", contents.len()));
+        return Ok(Ok(()));
+    }
+    debug!("{} {}", method, url);
+    let request = api
+        .client
+        .request(method, url)
+        .header(CONTENT_TYPE, "application/octet-stream")
+        .body(contents.to_vec());
+    let (status, response) = send(request, token)?;
+    trace!("Response to {}: {}", url, response);
+    if !status.is_success() {
+        return Ok(Err(error_message(status, response)));
+    }
+    Ok(Ok(()))
+}
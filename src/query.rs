@@ -0,0 +1,280 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{cmp::Ordering, str::FromStr};
+
+use comrak::nodes::NodeValue;
+use miette::{miette, Context, IntoDiagnostic, Report, Result};
+use serde_json::json;
+
+use crate::{
+    canonical_section, headings_match, item_text, make_pull_request_link,
+    natural_cmp, node_text, strip_provenance, Config, Entry, PullRequest,
+    QueryOpts, Repository,
+};
+
+/// How `mergelog query` prints the matching entries.
+#[derive(Clone, Copy)]
+pub enum QueryFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for QueryFormat {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(miette!("Failed to parse '{other}' as a query format. Options include 'text' and 'json'"))
+        }
+    }
+}
+
+/// An entry together with where it sits in the changelog.
+pub struct Record {
+    /// The release the entry is in, or `None` if it is unreleased.
+    pub version: Option<String>,
+    pub section: String,
+    pub entry: Entry,
+}
+
+/// Compares versions like `v1.10.0` and `1.9.0` by their numeric parts.
+fn compare_versions(lhs: &str, rhs: &str) -> Ordering {
+    natural_cmp(lhs.trim_start_matches('v'), rhs.trim_start_matches('v'))
+}
+
+/// Whether `record` passes every filter in `opts`.
+pub fn matches(opts: &QueryOpts, record: &Record) -> bool {
+    let pull_request = record.entry.pull_request.as_ref();
+    (opts.section.is_empty()
+        || opts
+            .section
+            .iter()
+            .any(|section| headings_match(section, &record.section)))
+        && opts.author.as_ref().is_none_or(|author| {
+            pull_request
+                .and_then(|pull_request| pull_request.author.as_ref())
+                .is_some_and(|username| username.eq_ignore_ascii_case(author))
+        })
+        && opts.label.iter().all(|label| {
+            pull_request.is_some_and(|pull_request| {
+                pull_request.labels.iter().any(|other| other == label)
+            })
+        })
+        && opts.since.as_ref().is_none_or(|since| {
+            record.version.as_ref().is_none_or(|version| {
+                compare_versions(version, since) == Ordering::Greater
+            })
+        })
+        && opts.until.as_ref().is_none_or(|until| {
+            record.version.as_ref().is_some_and(|version| {
+                compare_versions(version, until) != Ordering::Greater
+            })
+        })
+}
+
+/// Recognizes release headings like `[1.2.0] - 2024-01-01`, `v1.2.0`, or
+/// `Unreleased`, returning the version, which is `None` for unreleased
+/// changes.
+pub fn release_heading(heading: &str) -> Option<Option<String>> {
+    let first = heading
+        .split_whitespace()
+        .next()?
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    if first.eq_ignore_ascii_case("unreleased") {
+        return Some(None);
+    }
+    let version = first.trim_start_matches('v');
+    (version.contains('.') && version.starts_with(|c: char| c.is_ascii_digit()))
+        .then(|| Some(first.to_string()))
+}
+
+/// Finds the merge/pull request ids an item links to, written either as
+/// shorthands like `!30` or as links ending in `/merge_requests/30`.
+fn referenced_ids(item: &str) -> Vec<u64> {
+    let mut ids = Vec::new();
+    for marker in ["!", "/merge_requests/"] {
+        for (start, _) in item.match_indices(marker) {
+            let digits = item[start + marker.len()..]
+                .split(|c: char| !c.is_ascii_digit())
+                .next()
+                .unwrap_or_default();
+            if let Ok(id) = digits.parse::<u64>() {
+                if !ids.contains(&id) {
+                    ids.push(id);
+                }
+            }
+        }
+    }
+    ids
+}
+
+/// Reads the entries of an existing changelog, in which each release has a
+/// heading with its version and sections are headings below that.
+pub fn parse_released(
+    contents: &str,
+    config: &Config,
+    pull_requests: &[PullRequest],
+    repo: Repository,
+) -> Result<Vec<Record>> {
+    let arena = comrak::Arena::new();
+    let options = comrak::Options::default();
+    let section_names = config.section_names();
+    let mut records = Vec::new();
+    let mut release = None;
+    let mut section = None;
+    for node in comrak::parse_document(&arena, contents, &options).descendants()
+    {
+        match node.data.borrow().value {
+            NodeValue::Heading(heading) => {
+                let text = node_text(node);
+                let text = text.trim();
+                match (release_heading(text), &release) {
+                    (Some(version), _) => {
+                        release = Some((version, heading.level));
+                        section = None;
+                    }
+                    (None, Some((_, level))) if heading.level > *level => {
+                        section = Some(
+                            canonical_section(text, &section_names, config)
+                                .unwrap_or_else(|| text.to_string()),
+                        );
+                    }
+                    _ => section = None,
+                }
+            }
+            NodeValue::Item(_) => {
+                let nested = node.ancestors().skip(1).any(|ancestor| {
+                    matches!(ancestor.data.borrow().value, NodeValue::Item(_))
+                });
+                let (Some((version, _)), Some(section), false) =
+                    (&release, &section, nested)
+                else {
+                    continue;
+                };
+
+                let mut result = Vec::new();
+                comrak::format_commonmark(node, &options, &mut result)
+                    .into_diagnostic()
+                    .wrap_err("Failed to format document")?;
+                let result = String::from_utf8(result)
+                    .into_diagnostic()
+                    .wrap_err("Markdown list item was not valid UTF-8")?;
+                let item = strip_provenance(item_text(&result));
+                let item = item.as_str();
+                let ids = referenced_ids(item);
+                let (item, breaking) = match item
+                    .strip_prefix(&config.breaking.prefix)
+                {
+                    Some(rest) => (rest.trim_start_matches(':').trim(), true),
+                    None => (
+                        item,
+                        *section == config.breaking.heading
+                            || *section == config.breaking.section,
+                    ),
+                };
+                records.push(Record {
+                    version: version.clone(),
+                    section: section.clone(),
+                    entry: Entry {
+                        item: item.to_string(),
+                        details: String::new(),
+                        links: ids
+                            .iter()
+                            .map(|id| {
+                                make_pull_request_link(
+                                    id.to_string(),
+                                    format!("!{id}"),
+                                    repo,
+                                )
+                            })
+                            .collect(),
+                        breaking,
+                        pull_request: ids.iter().find_map(|id| {
+                            pull_requests
+                                .iter()
+                                .find(|pull_request| pull_request.id == *id)
+                                .cloned()
+                        }),
+                        authors: Vec::new(),
+                        provenance: Vec::new(),
+                    },
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(records)
+}
+
+/// Prints `records` to standard output in `format`.
+/// The `records` in `format`, each on a line of its own for text.
+pub fn render(records: &[Record], format: QueryFormat) -> String {
+    match format {
+        QueryFormat::Text => records
+            .iter()
+            .map(|record| {
+                let links = record
+                    .entry
+                    .links
+                    .iter()
+                    .map(|link| link.shorthand.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!(
+                    "[{}] {}: {}{}\n",
+                    record.version.as_deref().unwrap_or("Unreleased"),
+                    record.section,
+                    record.entry.item,
+                    if links.is_empty() {
+                        String::new()
+                    } else {
+                        format!(" ({links})")
+                    }
+                )
+            })
+            .collect(),
+        QueryFormat::Json => {
+            let records = records
+                .iter()
+                .map(|record| {
+                    let pull_request = record.entry.pull_request.as_ref();
+                    json!({
+                        "version": record.version,
+                        "section": record.section,
+                        "item": record.entry.item,
+                        "breaking": record.entry.breaking,
+                        "links": record.entry.links.iter().map(|link| json!({
+                            "shorthand": link.shorthand,
+                            "url": link.full,
+                        })).collect::<Vec<_>>(),
+                        "title": pull_request.map(|pull_request| &pull_request.title),
+                        "author": pull_request.and_then(|pull_request| pull_request.author.as_ref()),
+                        "labels": pull_request.map(|pull_request| pull_request.labels.as_slice()).unwrap_or_default(),
+                        "merged_at": pull_request.and_then(|pull_request| pull_request.merged_at.as_ref()),
+                    })
+                })
+                .collect::<Vec<_>>();
+            serde_json::to_string_pretty(&records)
+                .expect("JSON values always serialize")
+                + "\n"
+        }
+    }
+}
+
+pub fn print(records: &[Record], format: QueryFormat) {
+    print!("{}", render(records, format));
+}
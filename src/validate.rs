@@ -0,0 +1,783 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Checks a config for mistakes that parsing it alone lets through, pointing
+//! each at where it is in the TOML source.
+
+use std::{collections::HashMap, ops::Range};
+
+use camino::Utf8Path;
+use miette::{
+    miette, Context, IntoDiagnostic, LabeledSpan, NamedSource, Report, Result,
+};
+use strsim::jaro_winkler;
+use toml_edit::{ImDocument, Item, TableLike, Value};
+use tracing::info;
+
+use crate::{annotate, load_config, read_file};
+
+/// The placeholders that entry formats may use.
+const FORMAT_PLACEHOLDERS: &[&str] = &[
+    "item",
+    "link",
+    "link_short",
+    "link_md",
+    "link_ref",
+    "breaking",
+    "section",
+    "authors",
+    "issues",
+    "new_contributor",
+];
+
+/// The placeholders that section headings may use.
+const HEADING_PLACEHOLDERS: &[&str] = &["name"];
+
+/// The placeholders that link templates may use.
+const LINK_PLACEHOLDERS: &[&str] = &["id", "owner", "repo"];
+
+/// The placeholders that release commit messages may use.
+const RELEASE_PLACEHOLDERS: &[&str] = &["version", "tag"];
+
+/// The keys a table in the config may have.
+enum Schema {
+    /// Anything goes, either because it is not a table or because its keys are
+    /// chosen by the user.
+    Any,
+    /// A table with exactly these keys.
+    Table(&'static [(&'static str, Schema)]),
+    /// A table whose keys are chosen by the user, each with the same schema.
+    Map(&'static Schema),
+    /// An array of values with the same schema.
+    Array(&'static Schema),
+}
+
+const SECTION: Schema = Schema::Table(&[
+    ("name", Schema::Any),
+    ("level", Schema::Any),
+    ("emoji", Schema::Any),
+    ("prefix", Schema::Any),
+    ("decorate", Schema::Any),
+    ("empty", Schema::Any),
+    ("sort", Schema::Any),
+    ("format", Schema::Any),
+    ("heading", Schema::Any),
+    ("heading-case", Schema::Any),
+]);
+
+const ANNOUNCE_TARGET: Schema = Schema::Table(&[
+    ("kind", Schema::Any),
+    ("url", Schema::Any),
+    ("url-env", Schema::Any),
+    ("room", Schema::Any),
+    ("token-env", Schema::Any),
+    ("max-length", Schema::Any),
+    ("changelog-url", Schema::Any),
+]);
+
+const PACKAGE: Schema =
+    Schema::Table(&[("directory", Schema::Any), ("changelog", Schema::Any)]);
+
+const VERSION_FILE: Schema =
+    Schema::Table(&[("key", Schema::Any), ("pattern", Schema::Any)]);
+
+const CONFIG: Schema = Schema::Table(&[
+    ("sections", Schema::Array(&SECTION)),
+    ("directory", Schema::Any),
+    ("format", Schema::Any),
+    ("heading", Schema::Any),
+    ("heading-case", Schema::Any),
+    ("heading-level", Schema::Any),
+    ("decorate", Schema::Any),
+    ("empty", Schema::Any),
+    ("section-order", Schema::Any),
+    ("sort", Schema::Any),
+    ("short-links", Schema::Any),
+    ("link-style", Schema::Any),
+    ("link-template", Schema::Any),
+    (
+        "link-templates",
+        Schema::Table(&[("github", Schema::Any), ("gitlab", Schema::Any)]),
+    ),
+    ("fragments", Schema::Any),
+    ("aliases", Schema::Any),
+    ("labels", Schema::Any),
+    ("highlights", Schema::Any),
+    (
+        "bump",
+        Schema::Table(&[("major", Schema::Any), ("minor", Schema::Any)]),
+    ),
+    (
+        "breaking",
+        Schema::Table(&[
+            ("heading", Schema::Any),
+            ("prefix", Schema::Any),
+            ("mode", Schema::Any),
+            ("section", Schema::Any),
+            ("badge", Schema::Any),
+        ]),
+    ),
+    ("duplicates", Schema::Any),
+    (
+        "list",
+        Schema::Table(&[
+            ("bullet", Schema::Any),
+            ("indent", Schema::Any),
+            ("wrap", Schema::Any),
+        ]),
+    ),
+    (
+        "packaging",
+        Schema::Table(&[
+            ("name", Schema::Any),
+            ("maintainer", Schema::Any),
+            ("revision", Schema::Any),
+            ("distribution", Schema::Any),
+            ("urgency", Schema::Any),
+        ]),
+    ),
+    ("announce", Schema::Array(&ANNOUNCE_TARGET)),
+    ("release-config", Schema::Any),
+    ("release", Schema::Table(&[("commit-message", Schema::Any)])),
+    (
+        "publish",
+        Schema::Table(&[("assets", Schema::Any), ("links", Schema::Any)]),
+    ),
+    ("version-files", Schema::Map(&VERSION_FILE)),
+    (
+        "hooks",
+        Schema::Table(&[
+            ("pre-merge", Schema::Any),
+            ("post-resolve", Schema::Any),
+            ("post-render", Schema::Any),
+        ]),
+    ),
+    (
+        "subsections",
+        Schema::Table(&[("label-prefix", Schema::Any), ("names", Schema::Any)]),
+    ),
+    (
+        "new-contributors",
+        Schema::Table(&[("badge", Schema::Any), ("section", Schema::Any)]),
+    ),
+    ("packages", Schema::Map(&PACKAGE)),
+    ("stale-after", Schema::Any),
+    ("exclude", Schema::Any),
+    ("exclude-labels", Schema::Any),
+    ("exclude-title-pattern", Schema::Any),
+    ("preserve-formatting", Schema::Any),
+    ("preserve_formatting", Schema::Any),
+    ("prose", Schema::Any),
+]);
+
+/// A TOML value along with where it is in the source, which [`toml::Value`]
+/// does not keep.
+struct Node {
+    span: Range<usize>,
+    value: NodeValue,
+}
+
+enum NodeValue {
+    Table(Vec<(Key, Node)>),
+    Array(Vec<Node>),
+    String(String),
+    /// A number, boolean, or date, none of which are checked beyond parsing.
+    Other,
+}
+
+/// A key in a table, along with where it is in the source.
+struct Key {
+    name: String,
+    span: Range<usize>,
+}
+
+impl Node {
+    /// Converts `item`, using `fallback` as its span if it has none of its
+    /// own, as with tables only implied by a dotted header.
+    fn from_item(item: &Item, fallback: Range<usize>) -> Self {
+        let span = item.span().unwrap_or(fallback);
+        let value = match item {
+            Item::None => NodeValue::Other,
+            Item::Value(value) => return Self::from_value(value, span),
+            Item::Table(table) => Self::table(table, &span),
+            Item::ArrayOfTables(array) => NodeValue::Array(
+                array
+                    .iter()
+                    .map(|table| Self {
+                        span: table.span().unwrap_or(span.clone()),
+                        value: Self::table(table, &span),
+                    })
+                    .collect(),
+            ),
+        };
+        Self { span, value }
+    }
+
+    fn from_value(value: &Value, fallback: Range<usize>) -> Self {
+        let span = value.span().unwrap_or(fallback);
+        let value = match value {
+            Value::String(string) => NodeValue::String(string.value().clone()),
+            Value::Array(array) => NodeValue::Array(
+                array
+                    .iter()
+                    .map(|element| Self::from_value(element, span.clone()))
+                    .collect(),
+            ),
+            Value::InlineTable(table) => Self::table(table, &span),
+            _ => NodeValue::Other,
+        };
+        Self { span, value }
+    }
+
+    fn table(table: &dyn TableLike, fallback: &Range<usize>) -> NodeValue {
+        NodeValue::Table(
+            table
+                .iter()
+                .filter_map(|(name, _)| table.get_key_value(name))
+                .map(|(key, item)| {
+                    let span = key.span().unwrap_or(fallback.clone());
+                    (
+                        Key {
+                            name: key.get().to_string(),
+                            span: span.clone(),
+                        },
+                        Self::from_item(item, span),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    fn get(&self, key: &str) -> Option<&Node> {
+        self.entries()
+            .iter()
+            .find(|(name, _)| name.name == key)
+            .map(|(_, node)| node)
+    }
+
+    fn entries(&self) -> &[(Key, Node)] {
+        match &self.value {
+            NodeValue::Table(entries) => entries,
+            _ => &[],
+        }
+    }
+
+    fn elements(&self) -> &[Node] {
+        match &self.value {
+            NodeValue::Array(elements) => elements,
+            _ => &[],
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match &self.value {
+            NodeValue::String(string) => Some(string),
+            _ => None,
+        }
+    }
+}
+
+/// A mistake in a file, with the spans it is about.
+pub struct Problem {
+    code: &'static str,
+    message: String,
+    labels: Vec<LabeledSpan>,
+    help: String,
+}
+
+impl Problem {
+    pub fn new(
+        code: &'static str,
+        message: String,
+        labels: Vec<LabeledSpan>,
+        help: String,
+    ) -> Self {
+        Self {
+            code,
+            message,
+            labels,
+            help,
+        }
+    }
+}
+
+/// Checks each table in `node` against `schema`, reporting keys it does not
+/// have.
+fn check_keys(
+    node: &Node,
+    schema: &Schema,
+    path: &str,
+    problems: &mut Vec<Problem>,
+) {
+    match schema {
+        Schema::Any => {}
+        Schema::Table(keys) => {
+            for (key, value) in node.entries() {
+                match keys.iter().find(|(name, _)| name == &key.name) {
+                    Some((name, schema)) => check_keys(
+                        value,
+                        schema,
+                        &join_key(path, name),
+                        problems,
+                    ),
+                    None => {
+                        let suggestion = keys
+                            .iter()
+                            .map(|(name, _)| {
+                                (jaro_winkler(name, &key.name), name)
+                            })
+                            .filter(|(similarity, _)| *similarity >= 0.8)
+                            .max_by(|lhs, rhs| lhs.0.total_cmp(&rhs.0));
+                        let help = match suggestion {
+                            Some((_, name)) => {
+                                format!("Did you mean `{}`?", name)
+                            }
+                            None => format!(
+                                "{} takes the keys {}.",
+                                if path.is_empty() {
+                                    "The config".to_string()
+                                } else {
+                                    format!("`{}`", path)
+                                },
+                                keys.iter()
+                                    .map(|(name, _)| format!("`{}`", name))
+                                    .collect::<Vec<_>>()
+                                    .join(", ")
+                            ),
+                        };
+                        problems.push(Problem::new(
+                            "config::unknown_key",
+                            format!(
+                                "Unknown config option `{}`",
+                                join_key(path, &key.name)
+                            ),
+                            vec![LabeledSpan::at(
+                                key.span.clone(),
+                                "not a config option",
+                            )],
+                            help,
+                        ));
+                    }
+                }
+            }
+        }
+        Schema::Map(schema) => {
+            for (key, value) in node.entries() {
+                check_keys(value, schema, &join_key(path, &key.name), problems);
+            }
+        }
+        Schema::Array(schema) => {
+            for (index, element) in node.elements().iter().enumerate() {
+                check_keys(
+                    element,
+                    schema,
+                    &format!("{}[{}]", path, index),
+                    problems,
+                );
+            }
+        }
+    }
+}
+
+fn join_key(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+/// The `{placeholder}`s in `template`, with their byte ranges in it.
+fn placeholders(template: &str) -> Vec<(&str, Range<usize>)> {
+    let mut placeholders = Vec::new();
+    let mut rest = template;
+    let mut offset = 0;
+    while let Some(start) = rest.find('{') {
+        let after = &rest[start + 1..];
+        let name_length = after
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(after.len());
+        let consumed =
+            if name_length > 0 && after[name_length..].starts_with('}') {
+                let range = offset + start..offset + start + name_length + 2;
+                placeholders.push((&after[..name_length], range));
+                start + name_length + 2
+            } else {
+                start + 1
+            };
+        rest = &rest[consumed..];
+        offset += consumed;
+    }
+    placeholders
+}
+
+/// Reports placeholders in the template at `node` that are not among `known`.
+fn check_template(
+    node: &Node,
+    key: &str,
+    known: &[&str],
+    contents: &str,
+    problems: &mut Vec<Problem>,
+) {
+    let Some(template) = node.as_str() else {
+        return;
+    };
+    // Only point into the string when it is written as-is, since escapes
+    // shift where each character is.
+    let literal = &contents[node.span.clone()];
+    let inner = literal
+        .strip_prefix(['"', '\''])
+        .and_then(|literal| literal.strip_suffix(['"', '\'']))
+        .filter(|inner| *inner == template);
+    for (name, range) in placeholders(template) {
+        if known.contains(&name) {
+            continue;
+        }
+        let span = match inner {
+            Some(_) => {
+                node.span.start + 1 + range.start
+                    ..node.span.start + 1 + range.end
+            }
+            None => node.span.clone(),
+        };
+        problems.push(Problem::new(
+            "config::unknown_placeholder",
+            format!("Unknown placeholder `{{{}}}` in `{}`", name, key),
+            vec![LabeledSpan::at(span, "not a placeholder")],
+            format!(
+                "`{}` can use {}.",
+                key,
+                known
+                    .iter()
+                    .map(|name| format!("`{{{}}}`", name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        ));
+    }
+}
+
+/// Reports the templates in `config` that use placeholders mergelog does not
+/// fill in.
+fn check_templates(config: &Node, contents: &str, problems: &mut Vec<Problem>) {
+    let tables = [(config, String::new())].into_iter().chain(
+        config
+            .get("sections")
+            .map(Node::elements)
+            .unwrap_or_default()
+            .iter()
+            .enumerate()
+            .map(|(index, section)| (section, format!("sections[{}].", index))),
+    );
+    for (table, prefix) in tables {
+        for (key, known) in [
+            ("format", FORMAT_PLACEHOLDERS),
+            ("heading", HEADING_PLACEHOLDERS),
+        ] {
+            if let Some(template) = table.get(key) {
+                check_template(
+                    template,
+                    &format!("{}{}", prefix, key),
+                    known,
+                    contents,
+                    problems,
+                );
+            }
+        }
+    }
+    let link_templates = config
+        .get("link-templates")
+        .map(Node::entries)
+        .unwrap_or_default()
+        .iter()
+        .map(|(host, template)| {
+            (format!("link-templates.{}", host.name), template)
+        });
+    for (key, template) in config
+        .get("link-template")
+        .map(|template| ("link-template".to_string(), template))
+        .into_iter()
+        .chain(link_templates)
+    {
+        check_template(template, &key, LINK_PLACEHOLDERS, contents, problems);
+    }
+    if let Some(template) = config
+        .get("release")
+        .and_then(|release| release.get("commit-message"))
+    {
+        check_template(
+            template,
+            "release.commit-message",
+            RELEASE_PLACEHOLDERS,
+            contents,
+            problems,
+        );
+    }
+}
+
+/// Reports options in `config` that contradict one another.
+fn check_conflicts(config: &Node, problems: &mut Vec<Problem>) {
+    let sections = config
+        .get("sections")
+        .map(Node::elements)
+        .unwrap_or_default()
+        .iter()
+        // Sections are either a name or a table with one.
+        .filter_map(|section| {
+            let name = section.get("name").unwrap_or(section);
+            Some((name.as_str()?, name))
+        })
+        .collect::<Vec<_>>();
+
+    let mut first_by_name = HashMap::new();
+    for (name, node) in &sections {
+        let first = *first_by_name.entry(*name).or_insert(*node);
+        if !std::ptr::eq(first, *node) {
+            problems.push(Problem::new(
+                "config::duplicate_section",
+                format!("Section `{}` is listed twice", name),
+                vec![
+                    LabeledSpan::at(first.span.clone(), "first listed here"),
+                    LabeledSpan::at(node.span.clone(), "listed again here"),
+                ],
+                "Remove one of them.".into(),
+            ));
+        }
+    }
+
+    let section_list = || {
+        sections
+            .iter()
+            .filter(|(name, node)| std::ptr::eq(first_by_name[name], *node))
+            .map(|(name, _)| format!("`{}`", name))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    if let Some(aliases) = config.get("aliases") {
+        for (alias, target) in aliases.entries() {
+            if let Some(section) = first_by_name.get(&alias.name.as_str()) {
+                problems.push(Problem::new(
+                    "config::alias_shadowed",
+                    format!(
+                        "Alias `{}` is also the name of a section",
+                        &alias.name
+                    ),
+                    vec![
+                        LabeledSpan::at(alias.span.clone(), "never used"),
+                        LabeledSpan::at(
+                            section.span.clone(),
+                            "since headings match this section first",
+                        ),
+                    ],
+                    "Remove the alias.".into(),
+                ));
+            }
+            if let Some(name) = target.as_str() {
+                if !sections.is_empty() && !first_by_name.contains_key(name) {
+                    problems.push(Problem::new(
+                        "config::unknown_alias_target",
+                        format!(
+                            "Alias `{}` maps to `{}`, which is not a section",
+                            &alias.name, name
+                        ),
+                        vec![LabeledSpan::at(
+                            target.span.clone(),
+                            "not in `sections`",
+                        )],
+                        format!("Map it to one of {}.", section_list()),
+                    ));
+                }
+            }
+        }
+    }
+
+    if let Some(labels) = config.get("labels") {
+        for (label, target) in labels.entries() {
+            if let Some(name) = target.as_str() {
+                if !sections.is_empty() && !first_by_name.contains_key(name) {
+                    problems.push(Problem::new(
+                        "config::unknown_label_target",
+                        format!(
+                            "Label `{}` maps to `{}`, which is not a section",
+                            &label.name, name
+                        ),
+                        vec![LabeledSpan::at(
+                            target.span.clone(),
+                            "not in `sections`",
+                        )],
+                        format!("Map it to one of {}.", section_list()),
+                    ));
+                }
+            }
+        }
+    }
+
+    if let Some(bump) = config.get("bump") {
+        let minor = bump.get("minor").map(Node::elements).unwrap_or_default();
+        for major in bump.get("major").map(Node::elements).unwrap_or_default() {
+            let Some(name) = major.as_str() else {
+                continue;
+            };
+            if let Some(also_minor) =
+                minor.iter().find(|minor| minor.as_str() == Some(name))
+            {
+                problems.push(Problem::new(
+                    "config::conflicting_bump",
+                    format!(
+                        "Section `{}` bumps both the major and minor version",
+                        name
+                    ),
+                    vec![
+                        LabeledSpan::at(major.span.clone(), "major"),
+                        LabeledSpan::at(also_minor.span.clone(), "minor"),
+                    ],
+                    "List it under only one of `bump.major` and `bump.minor`."
+                        .into(),
+                ));
+            }
+        }
+    }
+
+    if let Some(packages) = config.get("packages") {
+        let mut first_by_changelog = HashMap::new();
+        for (package, table) in packages.entries() {
+            let Some(changelog) = table.get("changelog") else {
+                continue;
+            };
+            let Some(path) = changelog.as_str() else {
+                continue;
+            };
+            let (first_package, first) = *first_by_changelog
+                .entry(path)
+                .or_insert((package, changelog));
+            if !std::ptr::eq(first, changelog) {
+                problems.push(Problem::new(
+                    "config::shared_changelog",
+                    format!(
+                        "Packages `{}` and `{}` write the same changelog",
+                        &first_package.name, &package.name
+                    ),
+                    vec![
+                        LabeledSpan::at(first.span.clone(), "written here"),
+                        LabeledSpan::at(
+                            changelog.span.clone(),
+                            "and overwritten here",
+                        ),
+                    ],
+                    "Give each package its own `changelog`.".into(),
+                ));
+            }
+        }
+    }
+
+    if let Some(version_files) = config.get("version-files") {
+        for (path, table) in version_files.entries() {
+            if let (Some(key), Some(pattern)) =
+                (table.get("key"), table.get("pattern"))
+            {
+                problems.push(Problem::new(
+                    "config::conflicting_version_target",
+                    format!(
+                        "Version file `{}` has both a key and a pattern",
+                        &path.name
+                    ),
+                    vec![
+                        LabeledSpan::at(key.span.clone(), "key"),
+                        LabeledSpan::at(pattern.span.clone(), "pattern"),
+                    ],
+                    "Keep only one of `key` and `pattern`.".into(),
+                ));
+            }
+        }
+    }
+}
+
+/// Checks the config at `path` for unknown keys, unknown placeholders, and
+/// conflicting options, printing each problem found.
+pub fn validate(path: &Utf8Path) -> Result<()> {
+    // Parsing first reports malformed TOML and values of the wrong type.
+    if load_config(path)?.is_none() {
+        return Err(miette!(
+            code = "config::missing_table",
+            help = "Put the config under `[tool.mergelog]` in a `pyproject.toml`, or under `[workspace.metadata.mergelog]` or `[package.metadata.mergelog]` in a `Cargo.toml`.",
+            "{} has no mergelog config",
+            path
+        ));
+    }
+    let contents = read_file(path)
+        .into_diagnostic()
+        .wrap_err(format!("Failed to read config file from {}", path))?;
+    let document = ImDocument::parse(contents.as_str())
+        .into_diagnostic()
+        .wrap_err(format!("Failed to parse config file {}", path))?;
+    let document = Node::from_item(document.as_item(), 0..contents.len());
+    let keys: &[&[&str]] = match path.file_name() {
+        Some("pyproject.toml") => &[&["tool", "mergelog"]],
+        Some("Cargo.toml") => &[
+            &["workspace", "metadata", "mergelog"],
+            &["package", "metadata", "mergelog"],
+        ],
+        _ => &[&[]],
+    };
+    let config = keys
+        .iter()
+        .find_map(|keys| {
+            keys.iter().try_fold(&document, |node, key| node.get(key))
+        })
+        .expect("the config was just loaded from here");
+
+    let mut problems = Vec::new();
+    check_keys(config, &CONFIG, "", &mut problems);
+    check_templates(config, &contents, &mut problems);
+    check_conflicts(config, &mut problems);
+
+    if problems.is_empty() {
+        info!("{} is valid", path);
+        return Ok(());
+    }
+    Err(report(path, &contents, "toml", problems, "config::invalid"))
+}
+
+/// Prints each of `problems` in `contents`, the contents of `path` written in
+/// `language`, returning an error with `code` that sums them up.
+pub fn report(
+    path: &Utf8Path,
+    contents: &str,
+    language: &str,
+    problems: Vec<Problem>,
+    code: &'static str,
+) -> Report {
+    let count = problems.len();
+    for problem in problems {
+        let report = miette!(
+            code = problem.code,
+            labels = problem.labels,
+            help = problem.help,
+            "{}",
+            problem.message
+        );
+        let report = report.with_source_code(
+            NamedSource::new(path, contents.to_string())
+                .with_language(language),
+        );
+        annotate::record(&report);
+        eprintln!("{:?}", report);
+    }
+    miette!(
+        code = code,
+        "{} has {} problem{}",
+        path,
+        count,
+        if count == 1 { "" } else { "s" }
+    )
+}
@@ -0,0 +1,112 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Sets the version in the files that spell it out, e.g., `Cargo.toml` or a
+//! `VERSION` file, leaving the rest of each file as it was.
+
+use miette::{miette, IntoDiagnostic, Result};
+use regex::Regex;
+use toml_edit::{DocumentMut, Item};
+
+/// Where the version is in a file.
+pub enum Target<'a> {
+    /// The string at a dotted key of a TOML file, e.g., `package.version`.
+    Key(&'a str),
+    /// The first group of the first match of a regular expression, or the
+    /// whole match if it has no groups.
+    Pattern(&'a Regex),
+    /// The whole file, apart from the whitespace around it.
+    Whole,
+}
+
+/// `contents` with the version at `target` set to `version`.
+pub fn set_version(
+    contents: &str,
+    target: &Target,
+    version: &str,
+) -> Result<String> {
+    match target {
+        Target::Key(key) => set_toml_key(contents, key, version),
+        Target::Pattern(pattern) => {
+            let captures = pattern.captures(contents).ok_or_else(|| {
+                miette!(
+                    code = "release::version_not_found",
+                    help = "Check that the pattern matches the line with the version.",
+                    "Pattern `{}` matches nothing",
+                    pattern
+                )
+            })?;
+            let found = captures
+                .get(1)
+                .or_else(|| captures.get(0))
+                .expect("a match has a whole match");
+            Ok(format!(
+                "{}{}{}",
+                &contents[..found.start()],
+                version,
+                &contents[found.end()..]
+            ))
+        }
+        Target::Whole => {
+            let start = contents.len() - contents.trim_start().len();
+            let end = contents.trim_end().len().max(start);
+            Ok(format!(
+                "{}{}{}",
+                &contents[..start],
+                version,
+                &contents[end..]
+            ))
+        }
+    }
+}
+
+/// Sets the string at the dotted `key` of a TOML document, following a
+/// package that inherits it like `version.workspace = true` to the same key
+/// under `workspace`.
+fn set_toml_key(contents: &str, key: &str, version: &str) -> Result<String> {
+    let mut document = contents.parse::<DocumentMut>().into_diagnostic()?;
+    let inherited = lookup(document.as_item(), key)
+        .and_then(|item| item.get("workspace"))
+        .and_then(Item::as_bool)
+        .unwrap_or(false);
+    let key = if inherited {
+        format!("workspace.{key}")
+    } else {
+        key.to_string()
+    };
+    let field = lookup_mut(document.as_item_mut(), &key)
+        .filter(|item| item.is_str())
+        .ok_or_else(|| {
+            miette!(
+                code = "release::version_not_found",
+                help = "Point `key` at the string holding the version, e.g., `package.version`.",
+                "There is no version at `{}`",
+                key
+            )
+        })?;
+    let decor = field.as_value().map(|value| value.decor().clone());
+    *field = toml_edit::value(version);
+    if let (Some(decor), Some(value)) = (decor, field.as_value_mut()) {
+        *value.decor_mut() = decor;
+    }
+    Ok(document.to_string())
+}
+
+fn lookup<'a>(item: &'a Item, key: &str) -> Option<&'a Item> {
+    key.split('.').try_fold(item, |item, part| item.get(part))
+}
+
+fn lookup_mut<'a>(item: &'a mut Item, key: &str) -> Option<&'a mut Item> {
+    key.split('.')
+        .try_fold(item, |item, part| item.get_mut(part))
+}
@@ -0,0 +1,90 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+mod common;
+
+use std::fs;
+
+use common::{MockApi, Workspace};
+use serde_json::{json, Value as JsonValue};
+
+#[test]
+fn annotates_config_problems_for_github() {
+    let api = MockApi::serve(200, "[]".to_string());
+    let workspace = Workspace::copy("annotate");
+    let output = workspace
+        .command(&api)
+        .args(["--annotate", "github", "config", "validate"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("::error file=mergelog.toml,line=2,title=config%3A%3Aunknown_key::Unknown config option `fragment`%0ADid you mean `fragments`?\n"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn writes_code_quality_report_for_gitlab() {
+    let api = MockApi::serve(200, "[]".to_string());
+    let workspace = Workspace::copy("annotate");
+    let output = workspace
+        .command(&api)
+        .env("GITLAB_CI", "true")
+        .args([
+            "--annotate",
+            "ci",
+            "--non-interactive",
+            "merge",
+            "changelogs",
+        ])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let report = fs::read_to_string(
+        workspace.path().join("gl-code-quality-report.json"),
+    )
+    .unwrap();
+    let mut issues = serde_json::from_str::<JsonValue>(&report).unwrap();
+    let issue = issues[0].as_object_mut().unwrap();
+    assert!(issue.remove("fingerprint").is_some());
+    assert_eq!(
+        issues,
+        json!([{
+            "description": "Changelog changelogs/400.md contributed nothing because it has no list items\nIts entries will not appear in the merged changelog.",
+            "check_name": "main::unused_changelog",
+            "severity": "minor",
+            "location": { "path": "changelogs/400.md", "lines": { "begin": 1 } },
+        }])
+    );
+}
+
+#[test]
+fn refuses_to_guess_the_ci_service() {
+    let api = MockApi::serve(200, "[]".to_string());
+    let workspace = Workspace::copy("annotate");
+    let output = workspace
+        .command(&api)
+        .args(["--annotate", "ci", "config", "validate"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("annotate::unknown_ci"), "{}", stderr);
+}
@@ -0,0 +1,156 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+mod common;
+
+use std::process::Command;
+
+use common::{assert_snapshot, read_fixture, MockApi, Workspace};
+
+/// Copies the backfill fixture into a repository with releases v0.1.0, v0.2.0,
+/// and v0.3.0 tagged on 2024-09-01, 2024-10-01, and 2024-10-15.
+fn tagged_workspace() -> Workspace {
+    let workspace = Workspace::copy("backfill");
+    workspace.git(&["init", "--quiet"]);
+    for (tag, date) in [
+        ("v0.1.0", "2024-09-01T12:00:00Z"),
+        ("v0.2.0", "2024-10-01T12:00:00Z"),
+        ("v0.3.0", "2024-10-15T12:00:00Z"),
+    ] {
+        let output = Command::new("git")
+            .current_dir(workspace.path())
+            .env("GIT_AUTHOR_DATE", date)
+            .env("GIT_COMMITTER_DATE", date)
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(["commit", "--quiet", "--allow-empty", "-m", tag])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        workspace.git(&["tag", tag]);
+    }
+    workspace
+}
+
+#[test]
+fn backfills_every_tagged_release() {
+    let api = MockApi::merge_requests("backfill");
+    let workspace = tagged_workspace();
+    let output = workspace.run(&api, &["backfill"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(output.status.success(), "{}", stderr);
+    assert_snapshot(
+        "backfill",
+        "expected.md",
+        &String::from_utf8(output.stdout).unwrap(),
+    );
+    assert_eq!(
+        api.requests(),
+        ["GET /api/v4/projects/spade-lang%2Fspade/merge_requests?state=merged&per_page=100"]
+    );
+    assert!(
+        stderr.contains("!9 'Bump the CI image' has no label naming a section"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn backfills_from_every_page() {
+    let merge_requests = serde_json::from_str::<Vec<serde_json::Value>>(
+        &read_fixture("backfill", "merge_requests.json"),
+    )
+    .unwrap();
+    let (newest, oldest) = merge_requests.split_at(2);
+    let api = MockApi::serve_pages(vec![vec![
+        serde_json::to_string(newest).unwrap(),
+        serde_json::to_string(oldest).unwrap(),
+    ]]);
+    let workspace = tagged_workspace();
+    let output = workspace.run(&api, &["backfill"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_snapshot(
+        "backfill",
+        "expected.md",
+        &String::from_utf8(output.stdout).unwrap(),
+    );
+    assert_eq!(
+        api.requests(),
+        [
+            "GET /api/v4/projects/spade-lang%2Fspade/merge_requests?state=merged&per_page=100",
+            "GET /api/v4/projects/spade-lang%2Fspade/merge_requests?state=merged&per_page=100&page=2",
+        ]
+    );
+}
+
+#[test]
+fn backfills_range_with_default_section() {
+    let api = MockApi::merge_requests("backfill");
+    let workspace = tagged_workspace();
+    let output = workspace.run(
+        &api,
+        &[
+            "backfill",
+            "--from",
+            "v0.2.0",
+            "--to",
+            "v0.2.0",
+            "--default-section",
+            "Changed",
+            "--output",
+            "CHANGELOG.md",
+        ],
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(output.stdout.is_empty());
+    assert_snapshot(
+        "backfill",
+        "expected_range.md",
+        &std::fs::read_to_string(workspace.path().join("CHANGELOG.md"))
+            .unwrap(),
+    );
+}
+
+#[test]
+fn rejects_unknown_and_reversed_tags() {
+    for (range, code) in [
+        (["--from", "v0.9.0"], "backfill::unknown_tag"),
+        (["--to", "v0.1.0-rc.1"], "backfill::unknown_tag"),
+    ] {
+        let api = MockApi::merge_requests("backfill");
+        let workspace = tagged_workspace();
+        let mut args = vec!["backfill"];
+        args.extend(range);
+        let output = workspace.run(&api, &args);
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(code), "{}", stderr);
+        assert!(api.requests().is_empty());
+    }
+
+    let api = MockApi::merge_requests("backfill");
+    let workspace = tagged_workspace();
+    let output = workspace
+        .run(&api, &["backfill", "--from", "v0.3.0", "--to", "v0.1.0"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("backfill::reversed_range"), "{}", stderr);
+    assert!(api.requests().is_empty());
+}
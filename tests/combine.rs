@@ -0,0 +1,54 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+mod common;
+
+use common::{assert_snapshot, MockApi, Workspace};
+
+#[test]
+fn combines_releases_sections_and_entries() {
+    let api = MockApi::serve(500, String::new());
+    let workspace = Workspace::copy("combine");
+    let output = workspace.run(&api, &["combine", "CHANGELOG.md", "fork.md"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("combine::conflicting_link"), "{}", stderr);
+    assert_snapshot(
+        "combine",
+        "expected.md",
+        &String::from_utf8(output.stdout).unwrap(),
+    );
+}
+
+#[test]
+fn combining_a_changelog_with_itself_changes_nothing() {
+    let api = MockApi::serve(500, String::new());
+    let workspace = Workspace::copy("combine");
+    let output = workspace.run(
+        &api,
+        &["combine", "fork.md", "fork.md", "--output", "combined.md"],
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        std::fs::read_to_string(workspace.path().join("combined.md")).unwrap(),
+        std::fs::read_to_string(workspace.path().join("fork.md")).unwrap()
+    );
+}
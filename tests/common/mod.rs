@@ -0,0 +1,330 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Runs mergelog against fixture changelogs and a fake GitLab API.
+
+// Each test crate uses only some of the helpers.
+#![allow(dead_code)]
+
+use std::{
+    env, fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+/// A request received by a [`MockApi`].
+#[derive(Debug, PartialEq)]
+pub struct Request {
+    pub method: String,
+    /// The path and query.
+    pub target: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Request {
+    /// The value of the header `name`, which is case-insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(other, _)| other.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// A response of a [`MockApi`].
+struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+/// A GitLab API on localhost that answers requests with canned responses, and
+/// remembers what was requested.
+pub struct MockApi {
+    url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl MockApi {
+    /// Serves `body` as JSON with the HTTP status `status`.
+    pub fn serve(status: u16, body: String) -> Self {
+        Self::serve_each(vec![(status, body)])
+    }
+
+    /// Answers the requests with `responses`, given as the HTTP status and the
+    /// JSON body, in order, repeating the last one once they run out.
+    pub fn serve_each(responses: Vec<(u16, String)>) -> Self {
+        Self::serve_each_with(|_| responses)
+    }
+
+    /// Like [`MockApi::serve_each`], but with the responses made from the
+    /// base URL, for those that link back to the API.
+    pub fn serve_each_with(
+        responses: impl FnOnce(&str) -> Vec<(u16, String)>,
+    ) -> Self {
+        Self::serve_responses(|url| {
+            responses(url)
+                .into_iter()
+                .map(|(status, body)| Response {
+                    status,
+                    headers: Vec::new(),
+                    body,
+                })
+                .collect()
+        })
+    }
+
+    /// Serves each of `arrays`, given as its pages, one after another, as
+    /// GitLab does, with each page pointing to the next with an `x-next-page`
+    /// header.
+    pub fn serve_pages(arrays: Vec<Vec<String>>) -> Self {
+        Self::serve_responses(|_| {
+            arrays
+                .into_iter()
+                .flat_map(|pages| {
+                    let count = pages.len();
+                    pages.into_iter().enumerate().map(move |(index, body)| {
+                        let next_page = if index + 1 < count {
+                            (index + 2).to_string()
+                        } else {
+                            String::new()
+                        };
+                        Response {
+                            status: 200,
+                            headers: vec![(
+                                "x-next-page".to_string(),
+                                next_page,
+                            )],
+                            body,
+                        }
+                    })
+                })
+                .collect()
+        })
+    }
+
+    /// Answers the requests with `responses`, made from the base URL, in
+    /// order, repeating the last one once they run out.
+    fn serve_responses(responses: impl FnOnce(&str) -> Vec<Response>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/api/v4", listener.local_addr().unwrap());
+        let responses = responses(&url);
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let requested = requests.clone();
+        thread::spawn(move || {
+            for (index, stream) in listener.incoming().enumerate() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let mut reader = BufReader::new(&stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut headers = Vec::new();
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    let Some((name, value)) = header.split_once(':') else {
+                        break;
+                    };
+                    headers.push((name.to_string(), value.trim().to_string()));
+                }
+                let length = headers
+                    .iter()
+                    .find(|(name, _)| {
+                        name.eq_ignore_ascii_case("content-length")
+                    })
+                    .map_or(0, |(_, value)| value.parse().unwrap());
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let mut parts = request_line.split(' ');
+                requested.lock().unwrap().push(Request {
+                    method: parts.next().unwrap_or_default().to_string(),
+                    target: parts.next().unwrap_or_default().to_string(),
+                    headers,
+                    body: String::from_utf8(body).unwrap(),
+                });
+
+                let Response {
+                    status,
+                    headers,
+                    body,
+                } = &responses[index.min(responses.len() - 1)];
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+                    status,
+                    body.len()
+                )
+                .unwrap();
+                for (name, value) in headers {
+                    write!(stream, "{name}: {value}\r\n").unwrap();
+                }
+                write!(stream, "\r\n{body}").unwrap();
+            }
+        });
+        Self { url, requests }
+    }
+
+    /// Serves the merge requests in `fixtures/<fixture>/merge_requests.json`.
+    pub fn merge_requests(fixture: &str) -> Self {
+        Self::serve(200, read_fixture(fixture, "merge_requests.json"))
+    }
+
+    /// The base URL to pass as `--api-url`.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The method and the path and query of each request received so far, in
+    /// order.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| format!("{} {}", request.method, request.target))
+            .collect()
+    }
+
+    /// Takes the requests received so far, in order.
+    pub fn take_requests(&self) -> Vec<Request> {
+        std::mem::take(&mut self.requests.lock().unwrap())
+    }
+}
+
+/// A copy of a fixture directory that is deleted when dropped.
+pub struct Workspace {
+    path: PathBuf,
+}
+
+impl Workspace {
+    /// Copies `fixtures/<fixture>` to a fresh temporary directory, which is
+    /// outside of any git repository so that mergelog does not lock or read
+    /// this one.
+    pub fn copy(fixture: &str) -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = env::temp_dir().join(format!(
+            "mergelog-test-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        copy_dir(&fixture_path(fixture), &path);
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// A command that runs mergelog in the workspace against `api` and a
+    /// repository on gitlab.com, with the workspace's config.
+    pub fn command(&self, api: &MockApi) -> Command {
+        let mut command = self.command_inferring_repo(api);
+        command.args(["--repo", "https://gitlab.com/spade-lang/spade"]);
+        command
+    }
+
+    /// Like [`Workspace::command`], but leaves mergelog to infer the
+    /// repository from the git remotes of the workspace.
+    pub fn command_inferring_repo(&self, api: &MockApi) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_mergelog"));
+        command
+            .current_dir(&self.path)
+            .env_clear()
+            .env("PATH", env::var_os("PATH").unwrap_or_default())
+            .env("NO_COLOR", "1")
+            .args(["--api-url", api.url(), "--config", "mergelog.toml"]);
+        command
+    }
+
+    /// Runs git with `args` in the workspace, returning what it printed.
+    pub fn git(&self, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .current_dir(&self.path)
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    }
+
+    /// Like [`Workspace::command`], but runs `args` without prompting.
+    pub fn run(&self, api: &MockApi, args: &[&str]) -> Output {
+        self.command(api)
+            .arg("--non-interactive")
+            .args(args)
+            .output()
+            .unwrap()
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Checks that `actual` matches the snapshot `fixtures/<fixture>/<name>`, or
+/// overwrites the snapshot with it if `MERGELOG_UPDATE_SNAPSHOTS` is set.
+pub fn assert_snapshot(fixture: &str, name: &str, actual: &str) {
+    let path = fixture_path(fixture).join(name);
+    if env::var_os("MERGELOG_UPDATE_SNAPSHOTS").is_some() {
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!(
+            "Missing snapshot {}; run with MERGELOG_UPDATE_SNAPSHOTS=1 to create it",
+            path.display()
+        )
+    });
+    assert_eq!(
+        expected,
+        actual,
+        "Output differs from snapshot {}",
+        path.display()
+    );
+}
+
+/// The contents of `fixtures/<fixture>/<name>`.
+pub fn read_fixture(fixture: &str, name: &str) -> String {
+    fs::read_to_string(fixture_path(fixture).join(name)).unwrap()
+}
+
+fn fixture_path(fixture: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(fixture)
+}
+
+fn copy_dir(from: &Path, to: &Path) {
+    fs::create_dir_all(to).unwrap();
+    for entry in fs::read_dir(from).unwrap() {
+        let entry = entry.unwrap();
+        let to = to.join(entry.file_name());
+        if entry.file_type().unwrap().is_dir() {
+            copy_dir(&entry.path(), &to);
+        } else {
+            fs::copy(entry.path(), to).unwrap();
+        }
+    }
+}
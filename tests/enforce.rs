@@ -0,0 +1,129 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+mod common;
+
+use common::{MockApi, Workspace};
+
+fn merge_request(labels: &str) -> (u16, String) {
+    (
+        200,
+        format!(r#"{{"iid":1234,"title":"Add traits","labels":{}}}"#, labels),
+    )
+}
+
+#[test]
+fn passes_when_a_changelog_is_added() {
+    let api = MockApi::serve_each(vec![
+        merge_request("[]"),
+        (
+            200,
+            r#"[
+                {"new_path":"src/traits.rs","new_file":true},
+                {"new_path":"changelogs/1234.md","new_file":true}
+            ]"#
+            .to_string(),
+        ),
+    ]);
+    let workspace = Workspace::copy("enforce");
+    let output = workspace
+        .command(&api)
+        .args(["enforce", "--pr", "1234"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        api.requests(),
+        [
+            "GET /api/v4/projects/spade-lang%2Fspade/merge_requests/1234",
+            "GET /api/v4/projects/spade-lang%2Fspade/merge_requests/1234/diffs?per_page=100",
+        ]
+    );
+}
+
+#[test]
+fn finds_changelog_on_later_page_of_changes() {
+    let api = MockApi::serve_pages(vec![
+        vec![merge_request("[]").1],
+        vec![
+            r#"[{"new_path":"src/traits.rs","new_file":true}]"#.to_string(),
+            r#"[{"new_path":"changelogs/1234.md","new_file":true}]"#
+                .to_string(),
+        ],
+    ]);
+    let workspace = Workspace::copy("enforce");
+    let output = workspace
+        .command(&api)
+        .args(["enforce", "--pr", "1234"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        api.requests(),
+        [
+            "GET /api/v4/projects/spade-lang%2Fspade/merge_requests/1234",
+            "GET /api/v4/projects/spade-lang%2Fspade/merge_requests/1234/diffs?per_page=100",
+            "GET /api/v4/projects/spade-lang%2Fspade/merge_requests/1234/diffs?per_page=100&page=2",
+        ]
+    );
+}
+
+#[test]
+fn fails_when_no_changelog_is_added() {
+    let api = MockApi::serve_each(vec![
+        merge_request(r#"["enhancement"]"#),
+        (
+            200,
+            r#"[
+                {"new_path":"src/traits.rs","new_file":true},
+                {"new_path":"changelogs/1200.md","new_file":false},
+                {"new_path":"docs/1234.md","new_file":true}
+            ]"#
+            .to_string(),
+        ),
+    ]);
+    let workspace = Workspace::copy("enforce");
+    let output = workspace
+        .command(&api)
+        .args(["enforce", "--pr", "1234"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("enforce::missing_changelog"), "{}", stderr);
+}
+
+#[test]
+fn skips_labeled_merge_requests() {
+    let api = MockApi::serve_each(vec![merge_request(r#"["no-changelog"]"#)]);
+    let workspace = Workspace::copy("enforce");
+    let output = workspace
+        .command(&api)
+        .args(["enforce", "--pr", "1234", "--skip-label", "no-changelog"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(api.requests().len(), 1);
+}
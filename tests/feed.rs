@@ -0,0 +1,117 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+mod common;
+
+use common::{assert_snapshot, MockApi, Workspace};
+
+#[test]
+fn writes_releases_as_atom_feed() {
+    let api = MockApi::serve(500, String::new());
+    let workspace = Workspace::copy("feed");
+    let output = workspace
+        .command(&api)
+        .env("SOURCE_DATE_EPOCH", "1729000000")
+        .args(["feed", "--url", "https://spade-lang.org/releases.xml"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("feed::undated_release"));
+    assert_snapshot(
+        "feed",
+        "expected.xml",
+        &String::from_utf8(output.stdout).unwrap(),
+    );
+    assert!(api.requests().is_empty());
+}
+
+#[test]
+fn limits_feed_to_latest_releases() {
+    let api = MockApi::serve(500, String::new());
+    let workspace = Workspace::copy("feed");
+    let output = workspace
+        .command(&api)
+        .args(["feed", "--limit", "1", "--output", "releases.xml"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let feed =
+        std::fs::read_to_string(workspace.path().join("releases.xml")).unwrap();
+    assert_eq!(feed.matches("<entry>").count(), 1);
+    assert!(feed.contains("<title>spade 0.2.0</title>"));
+    assert!(feed
+        .contains("<id>https://gitlab.com/spade-lang/spade/-/releases</id>"));
+}
+
+#[test]
+fn links_github_releases() {
+    let api = MockApi::serve(500, String::new());
+    let workspace = Workspace::copy("feed");
+    let output = workspace
+        .command_inferring_repo(&api)
+        .args(["--repo", "https://github.com/spade-lang/spade"])
+        .args(["feed", "--limit", "1"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let feed = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        feed.contains("<id>https://github.com/spade-lang/spade/releases</id>")
+    );
+    assert!(feed.contains(
+        "<id>https://github.com/spade-lang/spade/releases/tag/0.2.0</id>"
+    ));
+}
+
+#[test]
+fn dates_release_with_invalid_date_today() {
+    let api = MockApi::serve(500, String::new());
+    let workspace = Workspace::copy("feed");
+    let changelog = workspace.path().join("CHANGELOG.md");
+    let contents = std::fs::read_to_string(&changelog).unwrap();
+    std::fs::write(
+        &changelog,
+        contents.replace("[0.2.0] - 2024-09-20", "[0.2.0] - 2024-13-45"),
+    )
+    .unwrap();
+    let output = workspace
+        .command(&api)
+        .env("SOURCE_DATE_EPOCH", "1729000000")
+        .args(["feed", "--limit", "1"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("feed::invalid_date")
+    );
+    let feed = String::from_utf8(output.stdout).unwrap();
+    assert!(feed.contains("<updated>2024-10-15T00:00:00Z</updated>"));
+    assert!(!feed.contains("2024-13-45"));
+}
@@ -0,0 +1,128 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+mod common;
+
+use std::process::Output;
+
+use common::{MockApi, Workspace};
+
+/// Merges the changelogs of the merge fixture with `hooks` as the `hooks`
+/// config table.
+fn merge_with_hooks(hooks: &str) -> Output {
+    let api = MockApi::merge_requests("merge");
+    let workspace = Workspace::copy("merge");
+    let config = workspace.path().join("mergelog.toml");
+    let contents = std::fs::read_to_string(&config).unwrap();
+    std::fs::write(&config, format!("{contents}\n[hooks]\n{hooks}\n")).unwrap();
+    workspace.run(&api, &["merge", "changelogs"])
+}
+
+#[test]
+fn runs_each_hook_on_its_data() {
+    let output = merge_with_hooks(
+        r#"pre-merge = "sed 's/gen if/generate if/'"
+post-resolve = "sed 's/\"section\": \"Fixed\"/\"section\": \"Added\"/'"
+post-render = "sed 's/\"heading\": \"Added\"/\"heading\": \"Changes\"/'""#,
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "## Changes\n\
+         - Type level `if` with `generate if` (!385)\n\
+         - Report conditions that are not `bool` (!385)\n\
+         - Replace `__builtin__` with `extern` (!390)\n\
+         - Add the `no_mangle(all)` attribute (!400)\n"
+    );
+}
+
+#[test]
+fn drops_entries_left_out_by_hook() {
+    // The fourth entry given, counting Added before Fixed, is that of !390.
+    let output = merge_with_hooks(
+        r#"post-resolve = "cat > /dev/null; echo '[{\"id\": 3}]'""#,
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "## Fixed\n- Replace `__builtin__` with `extern` (!390)\n"
+    );
+}
+
+#[test]
+fn passes_large_input_through_hook() {
+    let api = MockApi::merge_requests("merge");
+    let workspace = Workspace::copy("merge");
+    let config = workspace.path().join("mergelog.toml");
+    let contents = std::fs::read_to_string(&config).unwrap();
+    // The entries are alike enough to be collapsed as duplicates otherwise.
+    std::fs::write(
+        &config,
+        format!(
+            "duplicates = \"keep\"\n{contents}\n[hooks]\npre-merge = \"cat\"\n"
+        ),
+    )
+    .unwrap();
+    // Far more than a pipe holds, so the hook blocks on printing unless its
+    // output is read while it is given its input.
+    let entries = (0..10000)
+        .map(|index| {
+            format!("- Support the {index}th kind of generic argument\n")
+        })
+        .collect::<String>();
+    assert!(entries.len() > 64 * 1024);
+    std::fs::write(
+        workspace.path().join("changelogs/400.md"),
+        format!("## Added\n\n{entries}"),
+    )
+    .unwrap();
+    let output = workspace.run(&api, &["merge", "changelogs"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("- Support the 9999th kind of generic argument (!400)"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn fails_on_failing_or_invalid_hooks() {
+    for (hooks, code) in [
+        ("pre-merge = \"exit 3\"", "hooks::failed"),
+        ("post-render = \"echo nope\"", "hooks::invalid_json"),
+        ("post-resolve = \"echo '[{}]'\"", "hooks::invalid_output"),
+        (
+            "post-resolve = \"sed 's/\\\"section\\\": \\\"Fixed\\\"/\\\"section\\\": \\\"Removed\\\"/'\"",
+            "hooks::unknown_section",
+        ),
+    ] {
+        let output = merge_with_hooks(hooks);
+        assert!(!output.status.success(), "{}", hooks);
+        assert!(output.stdout.is_empty());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(code), "{}", stderr);
+    }
+}
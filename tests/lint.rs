@@ -0,0 +1,73 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+mod common;
+
+use common::{MockApi, Workspace};
+
+#[test]
+fn accepts_clean_changelog() {
+    let api = MockApi::serve(500, String::new());
+    let workspace = Workspace::copy("release");
+    let output = workspace.run(&api, &["lint"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(
+        stderr.contains("CHANGELOG.md has no problems"),
+        "{}",
+        stderr
+    );
+    assert!(api.requests().is_empty());
+}
+
+#[test]
+fn reports_problems_at_their_lines() {
+    let api = MockApi::serve(500, String::new());
+    let workspace = Workspace::copy("lint");
+    let output = workspace
+        .command(&api)
+        .args(["--annotate", "github", "lint", "CHANGELOG.md"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let mut problems = stderr
+        .lines()
+        .filter_map(|line| line.strip_prefix("::error file=CHANGELOG.md,"))
+        .filter_map(|line| line.split_once("::"))
+        .map(|(properties, _)| properties)
+        .collect::<Vec<_>>();
+    problems.sort();
+    assert_eq!(
+        problems,
+        [
+            "line=10,title=lint%3A%3Aout_of_order_date",
+            "line=12,title=lint%3A%3Aunknown_section",
+            "line=14,title=lint%3A%3Amissing_link_definition",
+            "line=16,title=lint%3A%3Aduplicate_version",
+            "line=28,title=lint%3A%3Amissing_link_definition",
+            "line=28,title=lint%3A%3Anon_semver_version",
+            "line=36,title=lint%3A%3Aduplicate_link_definition",
+            "line=39,title=lint%3A%3Abroken_link",
+            "line=40,title=lint%3A%3Abroken_link",
+            "line=8,title=lint%3A%3Aentry_without_link",
+        ],
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("CHANGELOG.md has 10 problems"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,139 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+mod common;
+
+use common::{assert_snapshot, read_fixture, MockApi, Workspace};
+
+/// Serves the merge requests and then the issues of the milestone fixture.
+fn milestone_api() -> MockApi {
+    MockApi::serve_each(vec![
+        (200, read_fixture("milestone", "merge_requests.json")),
+        (200, read_fixture("milestone", "issues.json")),
+    ])
+}
+
+#[test]
+fn lists_milestone_by_label() {
+    let api = milestone_api();
+    let workspace = Workspace::copy("milestone");
+    let output = workspace.run(&api, &["merge", "--milestone", "v1.4 beta"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(output.status.success(), "{}", stderr);
+    assert_snapshot(
+        "milestone",
+        "expected.md",
+        &String::from_utf8(output.stdout).unwrap(),
+    );
+    assert_eq!(
+        api.requests(),
+        [
+            "GET /api/v4/projects/spade-lang%2Fspade/merge_requests?state=merged&milestone=v1.4+beta&per_page=100",
+            "GET /api/v4/projects/spade-lang%2Fspade/issues?state=closed&milestone=v1.4+beta&per_page=100",
+        ]
+    );
+    assert!(
+        stderr
+            .contains("!395 'Bump the CI image' has no label naming a section"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn lists_milestone_from_every_page() {
+    let merge_requests = serde_json::from_str::<Vec<serde_json::Value>>(
+        &read_fixture("milestone", "merge_requests.json"),
+    )
+    .unwrap();
+    let (first, second) = merge_requests.split_at(1);
+    let api = MockApi::serve_pages(vec![
+        vec![
+            serde_json::to_string(first).unwrap(),
+            serde_json::to_string(second).unwrap(),
+        ],
+        vec![read_fixture("milestone", "issues.json")],
+    ]);
+    let workspace = Workspace::copy("milestone");
+    let output = workspace.run(&api, &["merge", "--milestone", "v1.4 beta"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_snapshot(
+        "milestone",
+        "expected.md",
+        &String::from_utf8(output.stdout).unwrap(),
+    );
+    assert_eq!(
+        api.requests(),
+        [
+            "GET /api/v4/projects/spade-lang%2Fspade/merge_requests?state=merged&milestone=v1.4+beta&per_page=100",
+            "GET /api/v4/projects/spade-lang%2Fspade/merge_requests?state=merged&milestone=v1.4+beta&per_page=100&page=2",
+            "GET /api/v4/projects/spade-lang%2Fspade/issues?state=closed&milestone=v1.4+beta&per_page=100",
+        ]
+    );
+}
+
+#[test]
+fn fails_on_unlabeled_items_when_strict() {
+    let api = milestone_api();
+    let workspace = Workspace::copy("milestone");
+    let output =
+        workspace.run(&api, &["merge", "--strict", "--milestone", "v1.4 beta"]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn rejects_milestone_with_changelogs() {
+    let api = milestone_api();
+    let workspace = Workspace::copy("milestone");
+    let output =
+        workspace.run(&api, &["merge", "--milestone", "v1.4", "changelogs"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("A milestone is listed without changelogs"),
+        "{}",
+        stderr
+    );
+    assert!(api.requests().is_empty());
+}
+
+#[test]
+fn leaves_out_excluded_merge_requests() {
+    for exclusion in [
+        "exclude-labels = [\"ci\"]",
+        "exclude-title-pattern = \"^Bump \"",
+    ] {
+        let api = milestone_api();
+        let workspace = Workspace::copy("milestone");
+        let config = workspace.path().join("mergelog.toml");
+        let contents = std::fs::read_to_string(&config).unwrap();
+        std::fs::write(&config, format!("{exclusion}\n{contents}")).unwrap();
+        let output = workspace
+            .run(&api, &["merge", "--strict", "--milestone", "v1.4 beta"]);
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_snapshot(
+            "milestone",
+            "expected.md",
+            &String::from_utf8(output.stdout).unwrap(),
+        );
+    }
+}
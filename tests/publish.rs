@@ -0,0 +1,324 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+mod common;
+
+use common::{MockApi, Workspace};
+use serde_json::{json, Value as JsonValue};
+
+const NOTES: &str = "### Fixed
+
+- Replace `__builtin__` with `extern` ([!390])
+
+[!390]: https://gitlab.com/spade-lang/spade/-/merge_requests/390
+";
+
+#[test]
+fn creates_release() {
+    let api = MockApi::serve_each(vec![
+        (404, r#"{"message":"404 Not Found"}"#.to_string()),
+        (201, "{}".to_string()),
+    ]);
+    let workspace = Workspace::copy("publish");
+    let output = workspace
+        .command(&api)
+        .env("GITLAB_TOKEN", "secret")
+        .args(["publish", "--tag", "v0.2.0"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let requests = api.take_requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(
+        (requests[0].method.as_str(), requests[0].target.as_str()),
+        ("GET", "/api/v4/projects/spade-lang%2Fspade/releases/v0.2.0")
+    );
+    assert_eq!(
+        (requests[1].method.as_str(), requests[1].target.as_str()),
+        ("POST", "/api/v4/projects/spade-lang%2Fspade/releases")
+    );
+    assert_eq!(requests[1].header("private-token"), Some("secret"));
+    assert_eq!(
+        serde_json::from_str::<JsonValue>(&requests[1].body).unwrap(),
+        json!({ "tag_name": "v0.2.0", "name": "v0.2.0", "description": NOTES })
+    );
+}
+
+#[test]
+fn updates_existing_release() {
+    let api = MockApi::serve_each(vec![
+        (200, r#"{"tag_name":"v0.2.0"}"#.to_string()),
+        (200, "{}".to_string()),
+    ]);
+    let workspace = Workspace::copy("publish");
+    let output = workspace
+        .command(&api)
+        .env("CI_JOB_TOKEN", "job")
+        .args(["publish", "--tag", "v0.2.0"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let requests = api.take_requests();
+    assert_eq!(
+        (requests[1].method.as_str(), requests[1].target.as_str()),
+        ("PUT", "/api/v4/projects/spade-lang%2Fspade/releases/v0.2.0")
+    );
+    assert_eq!(requests[1].header("job-token"), Some("job"));
+    assert_eq!(
+        serde_json::from_str::<JsonValue>(&requests[1].body).unwrap(),
+        json!({ "description": NOTES })
+    );
+}
+
+#[test]
+fn reports_rejected_release() {
+    let api = MockApi::serve_each(vec![
+        (404, r#"{"message":"404 Not Found"}"#.to_string()),
+        (422, r#"{"message":"Tag does not exist"}"#.to_string()),
+    ]);
+    let workspace = Workspace::copy("publish");
+    let output = workspace
+        .command(&api)
+        .env("GITLAB_TOKEN", "secret")
+        .args(["publish", "--tag", "v0.2.0"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("Failed to create release v0.2.0: Tag does not exist"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn requires_released_version() {
+    let api = MockApi::serve(500, "{}".to_string());
+    let workspace = Workspace::copy("publish");
+    let output = workspace
+        .command(&api)
+        .env("GITLAB_TOKEN", "secret")
+        .args(["publish", "--tag", "v0.3.0"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(api.requests().is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("Changelog CHANGELOG.md has no release for tag v0.3.0"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn links_assets() {
+    let api = MockApi::serve_each(vec![
+        (
+            200,
+            r#"{"tag_name":"v0.2.0","assets":{"links":[{"id":7,"name":"Documentation","url":"https://old.example.com"}]}}"#
+                .to_string(),
+        ),
+        (200, "{}".to_string()),
+    ]);
+    let workspace = Workspace::copy("publish");
+    std::fs::write(workspace.path().join("checksums.txt"), "abc  spade\n")
+        .unwrap();
+    std::fs::write(
+        workspace.path().join("mergelog.toml"),
+        "sections = [\"Added\", \"Fixed\"]\n[publish.links]\nDocumentation = \"https://docs.example.com\"\n",
+    )
+    .unwrap();
+    let output = workspace
+        .command(&api)
+        .env("GITLAB_TOKEN", "secret")
+        .args(["publish", "--tag", "v0.2.0", "--asset", "checksums.txt"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let requests = api.take_requests();
+    let package = "/api/v4/projects/spade-lang%2Fspade/packages/generic/spade/v0.2.0/checksums.txt";
+    assert_eq!(
+        requests
+            .iter()
+            .map(|request| format!("{} {}", request.method, request.target))
+            .collect::<Vec<_>>(),
+        [
+            "GET /api/v4/projects/spade-lang%2Fspade/releases/v0.2.0",
+            "PUT /api/v4/projects/spade-lang%2Fspade/releases/v0.2.0",
+            &format!("PUT {package}"),
+            "POST /api/v4/projects/spade-lang%2Fspade/releases/v0.2.0/assets/links",
+            "PUT /api/v4/projects/spade-lang%2Fspade/releases/v0.2.0/assets/links/7",
+        ]
+    );
+    assert_eq!(requests[2].body, "abc  spade\n");
+    assert_eq!(
+        serde_json::from_str::<JsonValue>(&requests[3].body).unwrap(),
+        json!({
+            "name": "checksums.txt",
+            "url": format!("{}{}", api.url().trim_end_matches("/api/v4"), package),
+            "link_type": "package",
+        })
+    );
+    assert_eq!(
+        serde_json::from_str::<JsonValue>(&requests[4].body).unwrap(),
+        json!({ "url": "https://docs.example.com" })
+    );
+}
+
+#[test]
+fn requires_readable_assets() {
+    let api = MockApi::serve(500, "{}".to_string());
+    let workspace = Workspace::copy("publish");
+    let output = workspace
+        .command(&api)
+        .env("GITLAB_TOKEN", "secret")
+        .args(["publish", "--tag", "v0.2.0", "--asset", "missing.tar.gz"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(api.requests().is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("Failed to read asset missing.tar.gz"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn creates_github_release() {
+    let api = MockApi::serve_each(vec![
+        (404, r#"{"message":"Not Found"}"#.to_string()),
+        (201, r#"{"id":1}"#.to_string()),
+    ]);
+    let workspace = Workspace::copy("publish");
+    let output = workspace
+        .command_inferring_repo(&api)
+        .args(["--repo", "https://github.com/spade-lang/spade"])
+        .env("GITHUB_TOKEN", "secret")
+        .args(["publish", "--tag", "v0.2.0"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let requests = api.take_requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(
+        (requests[0].method.as_str(), requests[0].target.as_str()),
+        ("GET", "/api/v4/repos/spade-lang/spade/releases/tags/v0.2.0")
+    );
+    assert_eq!(
+        (requests[1].method.as_str(), requests[1].target.as_str()),
+        ("POST", "/api/v4/repos/spade-lang/spade/releases")
+    );
+    assert_eq!(requests[1].header("authorization"), Some("Bearer secret"));
+    assert_eq!(requests[1].header("user-agent"), Some("mergelog"));
+    assert_eq!(
+        serde_json::from_str::<JsonValue>(&requests[1].body).unwrap(),
+        json!({ "tag_name": "v0.2.0", "name": "v0.2.0", "body": NOTES })
+    );
+}
+
+#[test]
+fn updates_github_release_assets() {
+    let api = MockApi::serve_each_with(|url| {
+        let release = json!({
+            "id": 5,
+            "tag_name": "v0.2.0",
+            "upload_url": format!("{url}/uploads/releases/5/assets{{?name,label}}"),
+            "assets": [{ "id": 9, "name": "checksums.txt" }],
+        });
+        vec![
+            (200, release.to_string()),
+            (200, release.to_string()),
+            (204, String::new()),
+            (201, "{}".to_string()),
+        ]
+    });
+    let workspace = Workspace::copy("publish");
+    std::fs::write(workspace.path().join("checksums.txt"), "abc  spade\n")
+        .unwrap();
+    let output = workspace
+        .command_inferring_repo(&api)
+        .args(["--repo", "https://github.com/spade-lang/spade"])
+        .env("GITHUB_TOKEN", "secret")
+        .args(["publish", "--tag", "v0.2.0", "--asset", "checksums.txt"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let requests = api.take_requests();
+    assert_eq!(
+        requests
+            .iter()
+            .map(|request| format!("{} {}", request.method, request.target))
+            .collect::<Vec<_>>(),
+        [
+            "GET /api/v4/repos/spade-lang/spade/releases/tags/v0.2.0",
+            "PATCH /api/v4/repos/spade-lang/spade/releases/5",
+            "DELETE /api/v4/repos/spade-lang/spade/releases/assets/9",
+            "POST /api/v4/uploads/releases/5/assets?name=checksums.txt",
+        ]
+    );
+    assert_eq!(
+        serde_json::from_str::<JsonValue>(&requests[1].body).unwrap(),
+        json!({ "body": NOTES })
+    );
+    assert_eq!(requests[3].body, "abc  spade\n");
+}
+
+#[test]
+fn rejects_github_links() {
+    let api = MockApi::serve(500, "{}".to_string());
+    let workspace = Workspace::copy("publish");
+    std::fs::write(
+        workspace.path().join("mergelog.toml"),
+        "sections = [\"Added\", \"Fixed\"]\n[publish.links]\nDocumentation = \"https://docs.example.com\"\n",
+    )
+    .unwrap();
+    let output = workspace
+        .command_inferring_repo(&api)
+        .args(["--repo", "https://github.com/spade-lang/spade"])
+        .env("GITHUB_TOKEN", "secret")
+        .args(["publish", "--tag", "v0.2.0"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(api.requests().is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("Cannot link Documentation in a GitHub release"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,218 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+mod common;
+
+use common::{assert_snapshot, MockApi, Workspace};
+
+#[test]
+fn releases_unreleased_section() {
+    let api = MockApi::serve(500, String::new());
+    let workspace = Workspace::copy("release");
+    let output = workspace
+        .command(&api)
+        .env("SOURCE_DATE_EPOCH", "1729000000")
+        .args(["release", "0.3.0"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_snapshot(
+        "release",
+        "expected.md",
+        &std::fs::read_to_string(workspace.path().join("CHANGELOG.md"))
+            .unwrap(),
+    );
+}
+
+#[test]
+fn requires_unreleased_section() {
+    let api = MockApi::serve(500, String::new());
+    let workspace = Workspace::copy("release");
+    std::fs::write(
+        workspace.path().join("CHANGELOG.md"),
+        "# Changelog\n\n## 0.1.0\n\n- Initial release\n",
+    )
+    .unwrap();
+    let output = workspace
+        .command(&api)
+        .args(["release", "0.2.0"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("release::missing_unreleased"));
+}
+
+/// Copies the release fixture, with a Cargo manifest at version 0.2.0, into a
+/// repository with everything committed.
+fn committed_workspace() -> Workspace {
+    let workspace = Workspace::copy("release");
+    std::fs::write(
+        workspace.path().join("Cargo.toml"),
+        "[package]\nname = \"spade\"\nversion = \"0.2.0\" # bumped on release\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    workspace.git(&["init", "--quiet"]);
+    workspace.git(&["config", "user.name", "Test"]);
+    workspace.git(&["config", "user.email", "test@example.com"]);
+    workspace.git(&["add", "."]);
+    workspace.git(&["commit", "--quiet", "-m", "Add the parser"]);
+    workspace
+}
+
+#[test]
+fn commits_and_tags_release() {
+    let api = MockApi::serve(500, String::new());
+    let workspace = committed_workspace();
+    let config = workspace.path().join("mergelog.toml");
+    let contents = std::fs::read_to_string(&config).unwrap();
+    std::fs::write(
+        &config,
+        format!("{contents}\n[release]\ncommit-message = \"chore: release {{tag}}\"\n"),
+    )
+    .unwrap();
+    let output = workspace
+        .command(&api)
+        .env("SOURCE_DATE_EPOCH", "1729000000")
+        .args(["release", "0.3.0", "--bump-cargo", "--commit", "--tag"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_snapshot(
+        "release",
+        "expected.md",
+        &std::fs::read_to_string(workspace.path().join("CHANGELOG.md"))
+            .unwrap(),
+    );
+    assert_eq!(
+        std::fs::read_to_string(workspace.path().join("Cargo.toml")).unwrap(),
+        "[package]\nname = \"spade\"\nversion = \"0.3.0\" # bumped on release\nedition = \"2021\"\n"
+    );
+    assert_eq!(
+        workspace.git(&["log", "-1", "--format=%s"]),
+        "chore: release v0.3.0"
+    );
+    // The config change was not part of the release.
+    assert_eq!(workspace.git(&["status", "--porcelain"]), "M mergelog.toml");
+    assert_eq!(
+        workspace.git(&["tag", "-l", "--format=%(contents)", "v0.3.0"]),
+        "### Added\n\n- Add the `no_mangle(all)` attribute ([!400])\n\n[!400]: https://gitlab.com/spade-lang/spade/-/merge_requests/400"
+    );
+    assert_eq!(
+        workspace.git(&["rev-parse", "v0.3.0^{commit}"]),
+        workspace.git(&["rev-parse", "HEAD"])
+    );
+}
+
+#[test]
+fn rejects_existing_tag() {
+    let api = MockApi::serve(500, String::new());
+    let workspace = committed_workspace();
+    workspace.git(&["tag", "v0.3.0"]);
+    let output = workspace
+        .command(&api)
+        .args(["release", "0.3.0", "--commit", "--tag"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("release::tag_exists")
+    );
+    assert_eq!(workspace.git(&["status", "--porcelain"]), "");
+}
+
+#[test]
+fn sets_version_in_version_files() {
+    let api = MockApi::serve(500, String::new());
+    let workspace = committed_workspace();
+    let path = workspace.path();
+    std::fs::write(
+        path.join("Cargo.toml"),
+        "[workspace.package]\nversion = \"0.2.0\"\n\n[package]\nname = \"spade\"\nversion.workspace = true\n",
+    )
+    .unwrap();
+    std::fs::write(
+        path.join("pyproject.toml"),
+        "[project]\nname = \"spade\"\nversion = \"0.2.0\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        path.join("package.json"),
+        "{\n  \"name\": \"spade\",\n  \"version\": \"0.2.0\"\n}\n",
+    )
+    .unwrap();
+    std::fs::write(path.join("VERSION"), "0.2.0\n").unwrap();
+    let config = path.join("mergelog.toml");
+    let contents = std::fs::read_to_string(&config).unwrap();
+    std::fs::write(
+        &config,
+        format!(
+            "{contents}\n[version-files]\n\"Cargo.toml\" = {{ key = \"package.version\" }}\n\"pyproject.toml\" = {{ key = \"project.version\" }}\n\"package.json\" = {{ pattern = '\"version\": \"(.*)\"' }}\n\"VERSION\" = {{}}\n"
+        ),
+    )
+    .unwrap();
+    let output = workspace
+        .command(&api)
+        .args(["release", "0.3.0"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let read = |name| std::fs::read_to_string(path.join(name)).unwrap();
+    assert_eq!(
+        read("Cargo.toml"),
+        "[workspace.package]\nversion = \"0.3.0\"\n\n[package]\nname = \"spade\"\nversion.workspace = true\n"
+    );
+    assert_eq!(
+        read("pyproject.toml"),
+        "[project]\nname = \"spade\"\nversion = \"0.3.0\"\n"
+    );
+    assert_eq!(
+        read("package.json"),
+        "{\n  \"name\": \"spade\",\n  \"version\": \"0.3.0\"\n}\n"
+    );
+    assert_eq!(read("VERSION"), "0.3.0\n");
+}
+
+#[test]
+fn leaves_changelog_when_version_is_missing() {
+    let api = MockApi::serve(500, String::new());
+    let workspace = committed_workspace();
+    let config = workspace.path().join("mergelog.toml");
+    let contents = std::fs::read_to_string(&config).unwrap();
+    std::fs::write(
+        &config,
+        format!("{contents}\n[version-files]\n\"Cargo.toml\" = {{ key = \"project.version\" }}\n"),
+    )
+    .unwrap();
+    let output = workspace
+        .command(&api)
+        .args(["release", "0.3.0"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("release::version_not_found"));
+    assert_eq!(workspace.git(&["status", "--porcelain"]), "M mergelog.toml");
+}
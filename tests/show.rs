@@ -0,0 +1,50 @@
+// Copyright (C) 2024 Ethan Uppal.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3 of the License only.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+// You should have received a copy of the GNU General Public License along with
+// this program.  If not, see <https://www.gnu.org/licenses/>.
+
+mod common;
+
+use common::{MockApi, Workspace};
+
+const NOTES: &str = "### Fixed
+
+- Replace `__builtin__` with `extern` ([!390])
+
+[!390]: https://gitlab.com/spade-lang/spade/-/merge_requests/390
+";
+
+#[test]
+fn prints_release_notes() {
+    let api = MockApi::serve(500, String::new());
+    let workspace = Workspace::copy("release");
+    for version in ["0.2.0", "v0.2.0", "latest"] {
+        let output = workspace.run(&api, &["show", version]);
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), NOTES);
+    }
+    assert!(api.requests().is_empty());
+}
+
+#[test]
+fn reports_missing_release() {
+    let api = MockApi::serve(500, String::new());
+    let workspace = Workspace::copy("release");
+    let output = workspace.run(&api, &["show", "0.3.0"]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("show::missing_release"));
+}